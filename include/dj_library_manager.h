@@ -0,0 +1,31 @@
+#ifndef DJ_LIBRARY_MANAGER_H
+#define DJ_LIBRARY_MANAGER_H
+
+/*
+ * C interface to dj-library-manager, available when the crate is built with
+ * `cargo build --release --features ffi`.
+ *
+ * Every function returning `char *` hands out a UTF-8 JSON document owned by
+ * the caller, which must be released with djlm_free_string(). On failure NULL
+ * is returned and djlm_last_error() describes what went wrong.
+ *
+ * The documents follow the JSON schemas in `schemas/v<N>/`, where N is the
+ * value returned by djlm_schema_version().
+ */
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+char *djlm_scan_directory(const char *path);
+char *djlm_find_duplicates(const char *path);
+char *djlm_analyze_bitrate(const char *path);
+unsigned int djlm_schema_version(void);
+char *djlm_last_error(void);
+void djlm_free_string(char *ptr);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DJ_LIBRARY_MANAGER_H */
@@ -0,0 +1,32 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::analyzers::duplicate::{DuplicateMatch, MatchClass};
+
+// Which matches an unattended run may act on. Matches of the classes listed
+// are moved as usual; the rest are only reported, for someone to review.
+// Read from JSON:
+//
+//   { "act_on": ["identical", "tags"] }
+//
+// Classes are `identical` (same bytes), `tags`, `filename`, `similar` and
+// `fingerprint`.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalList {
+    pub act_on: Vec<MatchClass>,
+}
+
+impl ApprovalList {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid approval list {}: {}", path.display(), e)))
+    }
+
+    pub fn approves(&self, dup_match: &DuplicateMatch) -> bool {
+        self.act_on.contains(&dup_match.match_class)
+    }
+}
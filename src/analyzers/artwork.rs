@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use image::{ImageFormat, ImageReader};
+use serde::Serialize;
+use symphonia::core::meta::{StandardVisualKey, Visual};
+use crate::{AudioError, AudioFile, Result};
+use crate::audio::decode;
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Embedded cover art audit. CDJs show covers at roughly 500x500, so anything
+// smaller looks blurry on the player; multi-megabyte covers just bloat every
+// copy of the file (and slow down USB exports).
+
+pub const DEFAULT_MIN_DIMENSION: u32 = 500;
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Artwork {
+    pub media_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size_bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "flag", rename_all = "snake_case")]
+pub enum ArtworkFlag {
+    Missing,
+    TooSmall { width: u32, height: u32 },
+    Oversized { size_bytes: usize },
+}
+
+impl std::fmt::Display for ArtworkFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "No embedded cover"),
+            Self::TooSmall { width, height } => write!(f, "Cover is only {}x{}", width, height),
+            Self::Oversized { size_bytes } => write!(f, "Cover takes {:.2} MB", *size_bytes as f64 / 1_048_576.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtworkReport {
+    pub path: PathBuf,
+    // The front cover, or the first picture if none is marked as such
+    pub artwork: Option<Artwork>,
+    pub flags: Vec<ArtworkFlag>,
+}
+
+pub struct ArtworkAnalyzer {
+    min_dimension: u32,
+    max_bytes: usize,
+}
+
+impl ParallelProcessor for ArtworkAnalyzer {}
+
+impl Default for ArtworkAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArtworkAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            min_dimension: DEFAULT_MIN_DIMENSION,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    // Covers narrower or shorter than this are flagged
+    pub fn with_min_dimension(mut self, pixels: u32) -> Self {
+        self.min_dimension = pixels;
+        self
+    }
+
+    // Covers larger than this many bytes are flagged
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<ArtworkReport> {
+        println!("Auditing artwork of {} files using {} threads",
+            files.len(),
+            parallel::current_threads()
+        );
+
+        let results = Self::parallel_map(files, |file| self.check(&file.path));
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error reading artwork: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn check(&self, path: &Path) -> Result<ArtworkReport> {
+        let artwork = read_cover(path)?.map(|cover| describe(&cover.media_type, &cover.data));
+        let mut flags = Vec::new();
+        match &artwork {
+            None => flags.push(ArtworkFlag::Missing),
+            Some(art) => {
+                if let (Some(width), Some(height)) = (art.width, art.height) {
+                    if width < self.min_dimension || height < self.min_dimension {
+                        flags.push(ArtworkFlag::TooSmall { width, height });
+                    }
+                }
+                if art.size_bytes > self.max_bytes {
+                    flags.push(ArtworkFlag::Oversized { size_bytes: art.size_bytes });
+                }
+            }
+        }
+
+        Ok(ArtworkReport {
+            path: path.to_path_buf(),
+            artwork,
+            flags,
+        })
+    }
+}
+
+pub struct Cover {
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+// Front cover (or first picture) from the file's tags
+pub fn read_cover(path: &Path) -> Result<Option<Cover>> {
+    let mut probed = decode::probe(path)?;
+
+    // ID3v2 tags are read before the container; FLAC pictures live in it
+    let mut visuals: Vec<Visual> = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            visuals.extend(revision.visuals().iter().cloned());
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        visuals.extend(revision.visuals().iter().cloned());
+    }
+
+    let front = visuals.iter()
+        .position(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .unwrap_or(0);
+    Ok((!visuals.is_empty()).then(|| {
+        let visual = visuals.swap_remove(front);
+        Cover {
+            media_type: visual.media_type,
+            data: visual.data.into_vec(),
+        }
+    }))
+}
+
+fn describe(media_type: &str, data: &[u8]) -> Artwork {
+    let dimensions = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    Artwork {
+        media_type: media_type.to_string(),
+        width: dimensions.map(|(w, _)| w),
+        height: dimensions.map(|(_, h)| h),
+        size_bytes: data.len(),
+    }
+}
+
+// Scales the cover down to fit within `max_dimension` and re-encodes it as
+// JPEG. Returns the new image data, or None if it's already small enough
+// in both dimensions and bytes.
+pub fn shrink_cover(cover: &Cover, max_dimension: u32, max_bytes: usize) -> Result<Option<Vec<u8>>> {
+    let image = ImageReader::new(Cursor::new(&cover.data))
+        .with_guessed_format()
+        .map_err(|e| AudioError::Metadata(format!("Unreadable cover image: {}", e)))?
+        .decode()
+        .map_err(|e| AudioError::Metadata(format!("Unreadable cover image: {}", e)))?;
+
+    let too_large = image.width() > max_dimension || image.height() > max_dimension;
+    if !too_large && cover.data.len() <= max_bytes {
+        return Ok(None);
+    }
+
+    let resized = if too_large {
+        image.resize(max_dimension, max_dimension, image::imageops::FilterType::Lanczos3)
+    } else {
+        image
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    resized.to_rgb8()
+        .write_to(&mut encoded, ImageFormat::Jpeg)
+        .map_err(|e| AudioError::Metadata(format!("Failed to encode cover: {}", e)))?;
+    Ok(Some(encoded.into_inner()))
+}
+
+// Replaces the front cover in the file's tags. Supported for MP3 (ID3v2)
+// and FLAC; other formats return UnsupportedFormat.
+pub fn embed_cover(path: &Path, data: &[u8], media_type: &str) -> Result<()> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = id3::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+            tag.add_frame(id3::frame::Picture {
+                mime_type: media_type.to_string(),
+                picture_type: id3::frame::PictureType::CoverFront,
+                description: String::new(),
+                data: data.to_vec(),
+            });
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            tag.remove_picture_type(metaflac::block::PictureType::CoverFront);
+            tag.add_picture(media_type, metaflac::block::PictureType::CoverFront, data.to_vec());
+            tag.save().map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't rewrite artwork in {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FolderCoverStatus {
+    Written,
+    // Dry run: would have been written
+    Planned,
+    // The folder already has a cover file
+    Exists,
+    // No file in the folder has embedded artwork
+    NoArtwork,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderCover {
+    pub directory: PathBuf,
+    pub destination: PathBuf,
+    // Track the cover was taken from
+    pub source: Option<PathBuf>,
+    pub status: FolderCoverStatus,
+}
+
+// Writes each folder's embedded cover to a picture file next to the tracks
+// (`folder.jpg` by default), for file managers and players that only look
+// for a file. A folder counts as an album; the first track in it (by path)
+// with embedded artwork provides the cover.
+pub struct CoverExtractor {
+    file_name: String,
+    overwrite: bool,
+    dry_run: bool,
+}
+
+impl ParallelProcessor for CoverExtractor {}
+
+impl CoverExtractor {
+    pub fn new(file_name: impl Into<String>) -> Self {
+        Self {
+            file_name: file_name.into(),
+            overwrite: false,
+            dry_run: false,
+        }
+    }
+
+    // Replace existing cover files instead of skipping their folders
+    pub fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn extract(&self, files: &[AudioFile]) -> Vec<Result<FolderCover>> {
+        let mut folders: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+        for file in files {
+            if let Some(directory) = file.path.parent() {
+                folders.entry(directory).or_default().push(&file.path);
+            }
+        }
+        let folders: Vec<(&Path, Vec<&Path>)> = folders.into_iter()
+            .map(|(directory, mut tracks)| {
+                tracks.sort();
+                (directory, tracks)
+            })
+            .collect();
+
+        println!("Extracting covers for {} folders using {} threads", folders.len(), parallel::current_threads());
+        Self::parallel_map(&folders, |(directory, tracks)| self.extract_folder(directory, tracks))
+    }
+
+    fn extract_folder(&self, directory: &Path, tracks: &[&Path]) -> Result<FolderCover> {
+        let destination = directory.join(&self.file_name);
+        let mut result = FolderCover {
+            directory: directory.to_path_buf(),
+            destination: destination.clone(),
+            source: None,
+            status: FolderCoverStatus::Exists,
+        };
+        if destination.exists() && !self.overwrite {
+            return Ok(result);
+        }
+
+        let found = tracks.iter().find_map(|track| match read_cover(track) {
+            Ok(Some(cover)) => Some((*track, cover)),
+            _ => None,
+        });
+        let Some((track, cover)) = found else {
+            result.status = FolderCoverStatus::NoArtwork;
+            return Ok(result);
+        };
+        result.source = Some(track.to_path_buf());
+
+        let data = encode_for(&cover, &destination)?;
+        if self.dry_run {
+            result.status = FolderCoverStatus::Planned;
+            return Ok(result);
+        }
+        crate::utils::file_ops::ensure_writable(&destination)?;
+        fs::write(&destination, data).map_err(|e| AudioError::io(&destination, e))?;
+        result.status = FolderCoverStatus::Written;
+        Ok(result)
+    }
+}
+
+// Cover data in the format the destination's extension calls for,
+// re-encoding only when the embedded image is in another format
+fn encode_for(cover: &Cover, destination: &Path) -> Result<Vec<u8>> {
+    let format = ImageFormat::from_path(destination)
+        .ok()
+        .filter(|format| matches!(format, ImageFormat::Jpeg | ImageFormat::Png))
+        .ok_or_else(|| AudioError::UnsupportedFormat(format!(
+            "Can't write covers as {} (use a .jpg or .png name)", destination.display()
+        )))?;
+    let reader = ImageReader::new(Cursor::new(&cover.data))
+        .with_guessed_format()
+        .map_err(|e| AudioError::Metadata(format!("Unreadable cover image: {}", e)))?;
+    if reader.format() == Some(format) {
+        return Ok(cover.data.clone());
+    }
+
+    let image = reader.decode()
+        .map_err(|e| AudioError::Metadata(format!("Unreadable cover image: {}", e)))?;
+    let mut encoded = Cursor::new(Vec::new());
+    image.to_rgb8()
+        .write_to(&mut encoded, format)
+        .map_err(|e| AudioError::Metadata(format!("Failed to encode cover: {}", e)))?;
+    Ok(encoded.into_inner())
+}
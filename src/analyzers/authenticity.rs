@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use serde::Serialize;
+use crate::{AudioFile, Result};
+use crate::audio::decode::{self, DecodedAudio};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Checks whether files sold as hi-res actually carry hi-res content. Two
+// tell-tales of a CD master dressed up as 24/96:
+// - padded bit depth: the low 8 bits of every 24-bit sample are zero
+// - upsampling: nothing in the spectrum above ~22 kHz, where a 44.1/48 kHz
+//   source had its anti-aliasing cutoff
+
+const FFT_SIZE: usize = 4096;
+// Bins below the spectrum's peak by more than this are treated as empty
+const NOISE_FLOOR_DB: f64 = 90.0;
+// Genuine content extends above what a 48 kHz source could hold
+const LOW_RATE_CUTOFF_HZ: f64 = 24_000.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "flag", rename_all = "snake_case")]
+pub enum AuthenticityFlag {
+    PaddedBitDepth { claimed_bits: u32, effective_bits: u32 },
+    Upsampled { sample_rate: u32, cutoff_hz: u32 },
+}
+
+impl std::fmt::Display for AuthenticityFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PaddedBitDepth { claimed_bits, effective_bits } => {
+                write!(f, "{}-bit file only uses {} bits", claimed_bits, effective_bits)
+            }
+            Self::Upsampled { sample_rate, cutoff_hz } => {
+                write!(f, "{} Hz file has no content above {} Hz", sample_rate, cutoff_hz)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthenticityReport {
+    pub path: PathBuf,
+    pub sample_rate: u32,
+    pub claimed_bits: Option<u32>,
+    pub effective_bits: Option<u32>,
+    // Highest frequency with content above the noise floor
+    pub cutoff_hz: Option<u32>,
+    pub flags: Vec<AuthenticityFlag>,
+}
+
+impl AuthenticityReport {
+    pub fn is_suspicious(&self) -> bool {
+        !self.flags.is_empty()
+    }
+}
+
+pub struct AuthenticityAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for AuthenticityAnalyzer {}
+
+impl Default for AuthenticityAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthenticityAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    // Hi-res is sold as lossless PCM; lossy files can't claim a bit depth
+    pub fn is_candidate(file: &AudioFile) -> bool {
+        file.path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "flac" | "wav" | "aif" | "aiff"))
+    }
+
+    // Checks every candidate; files that aren't hi-res by their own claims
+    // come back with no flags
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<AuthenticityReport> {
+        let candidates: Vec<&AudioFile> = files.iter().filter(|f| Self::is_candidate(f)).collect();
+        println!("Checking {} lossless files for hi-res authenticity using {} decode threads",
+            candidates.len(),
+            parallel::decode_threads()
+        );
+
+        let total = candidates.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(&candidates, |file| {
+            let result = self.check(&file.path);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error checking file: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn check(&self, path: &Path) -> Result<AuthenticityReport> {
+        let audio = decode::decode(path, Some(self.max_duration))?;
+        Ok(Self::inspect(&audio))
+    }
+
+    pub fn inspect(audio: &DecodedAudio) -> AuthenticityReport {
+        let effective_bits = effective_bits(audio);
+        let cutoff_hz = spectral_cutoff(audio).map(|hz| hz.round() as u32);
+        let mut flags = Vec::new();
+
+        if let (Some(claimed), Some(effective)) = (audio.bits_per_sample, effective_bits) {
+            if claimed > 16 && effective <= 16 {
+                flags.push(AuthenticityFlag::PaddedBitDepth { claimed_bits: claimed, effective_bits: effective });
+            }
+        }
+        if let Some(cutoff) = cutoff_hz {
+            if audio.sample_rate > 48_000 && (cutoff as f64) < LOW_RATE_CUTOFF_HZ {
+                flags.push(AuthenticityFlag::Upsampled { sample_rate: audio.sample_rate, cutoff_hz: cutoff });
+            }
+        }
+
+        AuthenticityReport {
+            path: audio.path.clone(),
+            sample_rate: audio.sample_rate,
+            claimed_bits: audio.bits_per_sample,
+            effective_bits,
+            cutoff_hz,
+            flags,
+        }
+    }
+}
+
+// Bits actually used: declared depth minus bits that are zero in every sample
+fn effective_bits(audio: &DecodedAudio) -> Option<u32> {
+    let claimed = audio.bits_per_sample?;
+    if audio.sample_bits == 0 {
+        // Digital silence says nothing about the depth
+        return None;
+    }
+    let unused = audio.sample_bits.trailing_zeros().saturating_sub(32 - claimed.min(32));
+    Some(claimed.saturating_sub(unused))
+}
+
+// Averages Hann-windowed power spectra over the decoded audio and returns the
+// highest frequency still within NOISE_FLOOR_DB of the peak
+fn spectral_cutoff(audio: &DecodedAudio) -> Option<f64> {
+    if audio.samples.len() < FFT_SIZE || audio.sample_rate == 0 {
+        return None;
+    }
+
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+
+    let bins = FFT_SIZE / 2;
+    let mut power = vec![0.0f64; bins];
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+    for chunk in audio.samples.chunks_exact(FFT_SIZE) {
+        for ((slot, &sample), &w) in buffer.iter_mut().zip(chunk).zip(&window) {
+            *slot = Complex::new(sample * w, 0.0);
+        }
+        fft.process(&mut buffer);
+        for (total, value) in power.iter_mut().zip(&buffer[..bins]) {
+            *total += value.norm_sqr() as f64;
+        }
+    }
+
+    let peak = power.iter().copied().fold(0.0, f64::max);
+    if peak <= 0.0 {
+        return None;
+    }
+    let floor = peak * 10f64.powf(-NOISE_FLOOR_DB / 10.0);
+    let highest = power.iter().rposition(|&p| p > floor)?;
+    Some(highest as f64 * audio.sample_rate as f64 / FFT_SIZE as f64)
+}
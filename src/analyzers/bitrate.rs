@@ -1,11 +1,11 @@
-use std::collections::HashMap;
-use crate::AudioFile;
-use crate::utils::parallel::ParallelProcessor;
+use std::collections::{BTreeMap, HashMap};
+use crate::{AudioFile, UnknownBitrate};
+use crate::utils::parallel::{self, ParallelProcessor};
 use serde::Serialize;
 use std::fmt;
-use rayon::prelude::*;
 
 #[derive(Debug, Serialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum BitrateCategory {
     HighRes,       // 1500+ kbps
     Lossless,      // 700-1499 kbps
@@ -46,18 +46,75 @@ impl fmt::Display for BitrateCategory {
 }
 
 #[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct BitrateStats {
     pub file_count: usize,
     pub category_distribution: HashMap<BitrateCategory, usize>,
+    // Files without a bitrate, by why it is missing
+    pub unknown_causes: BTreeMap<UnknownBitrate, usize>,
     pub average_bitrate: f64,
     pub min_bitrate: u32,
     pub max_bitrate: u32,
+    pub total_size_bytes: u64,
+    pub total_allocated_bytes: u64,
+}
+
+// Below this a file is worth re-sourcing
+pub const LOW_QUALITY_KBPS: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Grouping {
+    Artist,
+    Label,
+}
+
+impl Grouping {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Grouping::Artist => "Artist",
+            Grouping::Label => "Label",
+        }
+    }
+
+    fn value<'a>(&self, file: &'a AudioFile) -> Option<&'a str> {
+        match self {
+            Grouping::Artist => file.artist.as_deref(),
+            Grouping::Label => file.label.as_deref(),
+        }
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+    }
+}
+
+// Quality of everything one artist or label sent
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupQuality {
+    // As first seen; files are grouped case-insensitively
+    pub name: String,
+    pub file_count: usize,
+    pub average_bitrate: f64,
+    pub min_bitrate: u32,
+    // Files below LOW_QUALITY_KBPS
+    pub low_count: usize,
+    pub lossless_count: usize,
+}
+
+impl GroupQuality {
+    pub fn low_percent(&self) -> f64 {
+        self.low_count as f64 / self.file_count as f64 * 100.0
+    }
 }
 
 pub struct BitrateAnalyzer;
 
 impl ParallelProcessor for BitrateAnalyzer {}
 
+impl Default for BitrateAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BitrateAnalyzer {
     pub fn new() -> Self {
         println!("Initializing BitrateAnalyzer");
@@ -76,11 +133,15 @@ impl BitrateAnalyzer {
                 };
                 (file1_better, format!("Bitrate difference: {} vs {} kbps", b1, b2))
             },
+            // Audio stream sizes, so a bigger cover doesn't make a copy "better"
+            _ if file1.audio_size() != file2.audio_size() => {
+                let file1_better = file1.audio_size() > file2.audio_size();
+                let size1_mb = file1.audio_size() as f64 / 1_048_576.0;
+                let size2_mb = file2.audio_size() as f64 / 1_048_576.0;
+                (file1_better, format!("Audio size difference: {:.2} MB vs {:.2} MB", size1_mb, size2_mb))
+            },
             _ if file1.size_bytes != file2.size_bytes => {
-                let file1_better = file1.size_bytes > file2.size_bytes;
-                let size1_mb = file1.size_bytes as f64 / 1_048_576.0;
-                let size2_mb = file2.size_bytes as f64 / 1_048_576.0;
-                (file1_better, format!("Size difference: {:.2} MB vs {:.2} MB", size1_mb, size2_mb))
+                (true, "Same audio; files differ only in tags or artwork".to_string())
             },
             _ => (true, "Files are identical in size and bitrate".to_string())
         }
@@ -89,31 +150,34 @@ impl BitrateAnalyzer {
     pub fn analyze(&self, files: &[AudioFile]) -> BitrateStats {
         println!("Starting bitrate analysis of {} files using {} threads", 
             files.len(), 
-            rayon::current_num_threads()
+            parallel::current_threads()
         );
         
-        let progress = Self::get_progress_counter();
         let total_files = files.len();
+        let progress = Self::get_progress_counter(total_files);
 
         // Process files in parallel
-        let results: Vec<_> = files.par_iter()
-            .filter_map(|file| file.bitrate.map(|b| (file, b)))
-            .inspect(|(file, bitrate)| {
-                let processed = progress.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
-                if processed % 100 == 0 || processed == total_files {
-                    println!("Progress: {}/{} files ({:.1}%)", 
-                        processed, total_files,
-                        (processed as f64 / total_files as f64) * 100.0
-                    );
-                }
-                
-                println!("Processed '{}' - {} kbps ({})", 
-                    file.file_name, 
-                    bitrate,
-                    BitrateCategory::from_bitrate(*bitrate).as_str()
+        let results: Vec<_> = Self::parallel_map(files, |file| {
+            let bitrate = file.bitrate?;
+            let processed = progress.advance(1);
+            if processed % 100 == 0 || processed == total_files {
+                println!("Progress: {}/{} files ({:.1}%){}", 
+                    processed, total_files,
+                    (processed as f64 / total_files as f64) * 100.0,
+                    progress.rate(processed)
                 );
-            })
-            .collect();
+            }
+
+            println!("Processed '{}' - {} kbps ({})", 
+                file.file_name, 
+                bitrate,
+                BitrateCategory::from_bitrate(bitrate).as_str()
+            );
+            Some(bitrate)
+        })
+        .into_iter()
+        .flatten()
+        .collect();
 
         // Calculate statistics
         let mut category_distribution: HashMap<BitrateCategory, usize> = HashMap::new();
@@ -121,17 +185,25 @@ impl BitrateAnalyzer {
         let mut min_bitrate = u32::MAX;
         let mut max_bitrate = 0;
 
-        for (_, bitrate) in &results {
-            let category = BitrateCategory::from_bitrate(*bitrate);
+        for &bitrate in &results {
+            let category = BitrateCategory::from_bitrate(bitrate);
             *category_distribution.entry(category).or_insert(0) += 1;
-            total_bitrate += *bitrate as f64;
-            min_bitrate = min_bitrate.min(*bitrate);
-            max_bitrate = max_bitrate.max(*bitrate);
+            total_bitrate += bitrate as f64;
+            min_bitrate = min_bitrate.min(bitrate);
+            max_bitrate = max_bitrate.max(bitrate);
+        }
+
+        let mut unknown_causes = BTreeMap::new();
+        for file in files.iter().filter(|file| file.bitrate.is_none()) {
+            if let Some(cause) = file.bitrate_unknown {
+                *unknown_causes.entry(cause).or_insert(0) += 1;
+            }
         }
 
         let stats = BitrateStats {
             file_count: files.len(),
             category_distribution,
+            unknown_causes,
             average_bitrate: if !results.is_empty() {
                 total_bitrate / results.len() as f64
             } else {
@@ -139,12 +211,58 @@ impl BitrateAnalyzer {
             },
             min_bitrate: if min_bitrate == u32::MAX { 0 } else { min_bitrate },
             max_bitrate,
+            total_size_bytes: files.iter().map(|f| f.size_bytes).sum(),
+            total_allocated_bytes: files.iter().map(|f| f.size_on_disk()).sum(),
         };
 
         Self::print_summary(&stats);
         stats
     }
 
+    // Files with a known bitrate per artist or label (untagged ones under
+    // "Unknown"), worst first: highest share of low-quality files, then
+    // lowest average
+    pub fn quality_by(files: &[AudioFile], grouping: Grouping) -> Vec<GroupQuality> {
+        let mut groups: HashMap<String, GroupQuality> = HashMap::new();
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        for file in files {
+            let Some(bitrate) = file.bitrate else { continue };
+            let name = grouping.value(file).unwrap_or("Unknown");
+            let key = name.to_lowercase();
+            let group = groups.entry(key.clone()).or_insert_with(|| GroupQuality {
+                name: name.to_string(),
+                file_count: 0,
+                average_bitrate: 0.0,
+                min_bitrate: u32::MAX,
+                low_count: 0,
+                lossless_count: 0,
+            });
+            group.file_count += 1;
+            group.min_bitrate = group.min_bitrate.min(bitrate);
+            if bitrate < LOW_QUALITY_KBPS {
+                group.low_count += 1;
+            }
+            if matches!(BitrateCategory::from_bitrate(bitrate), BitrateCategory::HighRes | BitrateCategory::Lossless) {
+                group.lossless_count += 1;
+            }
+            *totals.entry(key).or_insert(0) += bitrate as u64;
+        }
+
+        let mut groups: Vec<GroupQuality> = groups.into_iter()
+            .map(|(key, mut group)| {
+                group.average_bitrate = totals[&key] as f64 / group.file_count as f64;
+                group
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.low_percent().total_cmp(&a.low_percent())
+                .then(a.average_bitrate.total_cmp(&b.average_bitrate))
+                .then(b.file_count.cmp(&a.file_count))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        groups
+    }
+
     fn print_summary(stats: &BitrateStats) {
         println!("\nBitrate Analysis Summary:");
         println!("Total files: {}", stats.file_count);
@@ -152,6 +270,9 @@ impl BitrateAnalyzer {
         println!("Average bitrate: {:.1} kbps", stats.average_bitrate);
         println!("Min bitrate: {} kbps", stats.min_bitrate);
         println!("Max bitrate: {} kbps", stats.max_bitrate);
+        println!("Total size: {:.2} MB ({:.2} MB on disk)",
+            stats.total_size_bytes as f64 / 1_048_576.0,
+            stats.total_allocated_bytes as f64 / 1_048_576.0);
         println!("\nBitrate Distribution:");
         
         let total_processed = stats.category_distribution.values().sum::<usize>();
@@ -162,5 +283,12 @@ impl BitrateAnalyzer {
             let percentage = (**count as f64 / total_processed as f64 * 100.0).round();
             println!("{}: {} files ({:.1}%)", category.as_str(), count, percentage);
         }
+
+        if !stats.unknown_causes.is_empty() {
+            println!("\nUnknown Bitrate:");
+            for (cause, count) in &stats.unknown_causes {
+                println!("{}: {} files", cause.label(), count);
+            }
+        }
     }
 }
\ No newline at end of file
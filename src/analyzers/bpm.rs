@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::Serialize;
+use crate::{AudioFile, Result};
+use crate::analyzers::tempo::TempoAnalyzer;
+use crate::audio::decode::{self, DecodedAudio};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// BPM estimation for tagging. The tempo comes from the same onset envelope
+// and autocorrelation as tempo stability, refined by the static grid fitted
+// there; tracks too sparse for a grid (ambient, long breakdowns), or whose
+// grid locked onto every other beat, keep the raw autocorrelation tempo.
+// Autocorrelation can't tell a tempo from its
+// half or double, so the result is folded by octaves into a range, the way
+// DJ software shows drum & bass at 174 rather than 87.
+
+pub const DEFAULT_MIN_BPM: f64 = 70.0;
+pub const DEFAULT_MAX_BPM: f64 = 180.0;
+// Detected and tagged tempos this close count as the same
+const TAG_TOLERANCE_BPM: f64 = 1.0;
+// The grid only corrects the autocorrelation tempo by a fraction of a BPM;
+// a grid further off than this share is trusted less than the raw tempo
+const MAX_GRID_CORRECTION: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BpmStatus {
+    // No BPM tag to compare with
+    Detected,
+    MatchesTag,
+    // Includes a tag at half or double the tempo
+    DiffersFromTag,
+    NoBeat,
+}
+
+impl BpmStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Detected => "Detected",
+            Self::MatchesTag => "Matches Tag",
+            Self::DiffersFromTag => "Differs From Tag",
+            Self::NoBeat => "No Beat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BpmReport {
+    pub path: PathBuf,
+    pub bpm: Option<f64>,
+    pub tag_bpm: Option<f64>,
+}
+
+impl BpmReport {
+    pub fn status(&self) -> BpmStatus {
+        match (self.bpm, self.tag_bpm) {
+            (None, _) => BpmStatus::NoBeat,
+            (Some(_), None) => BpmStatus::Detected,
+            (Some(bpm), Some(tag)) if (bpm - tag).abs() <= TAG_TOLERANCE_BPM => BpmStatus::MatchesTag,
+            (Some(_), Some(_)) => BpmStatus::DiffersFromTag,
+        }
+    }
+}
+
+pub struct BpmAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+    min_bpm: f64,
+    max_bpm: f64,
+}
+
+impl ParallelProcessor for BpmAnalyzer {}
+
+impl Default for BpmAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BpmAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(3 * 60),
+            min_bpm: DEFAULT_MIN_BPM,
+            max_bpm: DEFAULT_MAX_BPM,
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    // Range results are folded into; it must span at least an octave
+    // (max >= 2 * min) for every tempo to have a place in it
+    pub fn with_range(mut self, min_bpm: f64, max_bpm: f64) -> Self {
+        self.min_bpm = min_bpm;
+        self.max_bpm = max_bpm;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<BpmReport> {
+        println!("Detecting BPM of {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.detect(file);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error detecting BPM: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn detect(&self, file: &AudioFile) -> Result<BpmReport> {
+        let audio = decode::decode(&file.path, Some(self.max_duration))?;
+        Ok(BpmReport {
+            path: file.path.clone(),
+            bpm: self.estimate(&audio),
+            tag_bpm: file.bpm,
+        })
+    }
+
+    pub fn estimate(&self, audio: &DecodedAudio) -> Option<f64> {
+        let envelope_bpm = TempoAnalyzer::envelope_bpm(audio)?;
+        let bpm = TempoAnalyzer::stability(audio).bpm
+            .filter(|grid_bpm| (grid_bpm / envelope_bpm - 1.0).abs() <= MAX_GRID_CORRECTION)
+            .unwrap_or(envelope_bpm);
+        Some((self.fold(bpm) * 100.0).round() / 100.0)
+    }
+
+    fn fold(&self, mut bpm: f64) -> f64 {
+        while bpm < self.min_bpm && bpm * 2.0 <= self.max_bpm {
+            bpm *= 2.0;
+        }
+        while bpm > self.max_bpm && bpm / 2.0 >= self.min_bpm {
+            bpm /= 2.0;
+        }
+        bpm
+    }
+}
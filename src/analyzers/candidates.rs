@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::utils::unix_timestamp_millis;
+
+// Number of shard files buckets are spread over once spilled. Each shard is
+// loaded on its own, so peak memory after spilling is roughly 1/SHARDS of
+// the candidate set.
+const SPILL_SHARDS: usize = 64;
+
+pub const DEFAULT_MEMORY_BUDGET: usize = 512 * 1024 * 1024;
+
+// Candidate files grouped by a match key (normalized artist + title). Only
+// files within the same bucket can be duplicates of each other, so buckets
+// are the unit of comparison. When the estimated size of the held files
+// exceeds the memory budget, buckets are written out to a temporary on-disk
+// store and read back one shard at a time.
+pub struct CandidateBuckets {
+    memory_budget: usize,
+    buckets: HashMap<String, Vec<AudioFile>>,
+    held_bytes: usize,
+    spill: Option<SpillStore>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpilledCandidate {
+    key: String,
+    file: AudioFile,
+}
+
+struct SpillStore {
+    dir: PathBuf,
+    shards: Vec<Option<BufWriter<File>>>,
+}
+
+impl CandidateBuckets {
+    pub fn new(memory_budget: usize) -> Self {
+        Self {
+            memory_budget,
+            buckets: HashMap::new(),
+            held_bytes: 0,
+            spill: None,
+        }
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    pub fn insert(&mut self, key: String, file: AudioFile) {
+        self.held_bytes += estimated_size(&key, &file);
+        self.buckets.entry(key).or_default().push(file);
+
+        if self.held_bytes > self.memory_budget {
+            if let Err(e) = self.spill_to_disk() {
+                // Keep going in memory rather than failing the whole analysis
+                eprintln!("Could not spill duplicate candidates to disk: {}", e);
+                self.memory_budget = usize::MAX;
+            }
+        }
+    }
+
+    // Hands every bucket to `f`, one shard at a time when spilled
+    pub fn for_each_shard<F>(mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Vec<Vec<AudioFile>>),
+    {
+        let Some(mut spill) = self.spill.take() else {
+            f(self.buckets.into_values().collect());
+            return Ok(());
+        };
+
+        let remaining = std::mem::take(&mut self.buckets);
+        spill.write(remaining)?;
+        spill.finish()?;
+
+        for shard in 0..SPILL_SHARDS {
+            let buckets = spill.read_shard(shard)?;
+            if !buckets.is_empty() {
+                f(buckets.into_values().collect());
+            }
+        }
+        Ok(())
+    }
+
+    fn spill_to_disk(&mut self) -> Result<()> {
+        if self.spill.is_none() {
+            let store = SpillStore::create()?;
+            println!("Candidate set exceeds memory budget ({} MB), spilling to {}",
+                self.memory_budget / (1024 * 1024),
+                store.dir.display()
+            );
+            self.spill = Some(store);
+        }
+
+        let buckets = std::mem::take(&mut self.buckets);
+        self.held_bytes = 0;
+        if let Some(spill) = self.spill.as_mut() {
+            spill.write(buckets)?;
+        }
+        Ok(())
+    }
+}
+
+impl SpillStore {
+    fn create() -> Result<Self> {
+        let millis = unix_timestamp_millis();
+        let dir = std::env::temp_dir()
+            .join(format!("dj-library-manager-candidates-{}-{}", std::process::id(), millis));
+        fs::create_dir_all(&dir).map_err(|e| AudioError::io(&dir, e))?;
+        Ok(Self {
+            dir,
+            shards: (0..SPILL_SHARDS).map(|_| None).collect(),
+        })
+    }
+
+    fn shard_path(&self, shard: usize) -> PathBuf {
+        self.dir.join(format!("shard-{:02}.jsonl", shard))
+    }
+
+    fn write(&mut self, buckets: HashMap<String, Vec<AudioFile>>) -> Result<()> {
+        for (key, files) in buckets {
+            let shard = shard_of(&key);
+            if self.shards[shard].is_none() {
+                let path = self.shard_path(shard);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| AudioError::io(&path, e))?;
+                self.shards[shard] = Some(BufWriter::new(file));
+            }
+            let path = self.shard_path(shard);
+            let Some(writer) = self.shards[shard].as_mut() else { continue };
+
+            for file in files {
+                let record = SpilledCandidate { key: key.clone(), file };
+                let line = serde_json::to_string(&record)
+                    .map_err(|e| AudioError::Metadata(e.to_string()))?;
+                writeln!(writer, "{}", line).map_err(|e| AudioError::io(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for shard in 0..SPILL_SHARDS {
+            if let Some(mut writer) = self.shards[shard].take() {
+                writer.flush().map_err(|e| AudioError::io(self.shard_path(shard), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_shard(&self, shard: usize) -> Result<HashMap<String, Vec<AudioFile>>> {
+        let path = self.shard_path(shard);
+        let mut buckets: HashMap<String, Vec<AudioFile>> = HashMap::new();
+        if !path.exists() {
+            return Ok(buckets);
+        }
+
+        let reader = BufReader::new(File::open(&path).map_err(|e| AudioError::io(&path, e))?);
+        for line in reader.lines() {
+            let line = line.map_err(|e| AudioError::io(&path, e))?;
+            let record: SpilledCandidate = serde_json::from_str(&line)
+                .map_err(|e| AudioError::Metadata(format!("Corrupt spill file {}: {}", path.display(), e)))?;
+            buckets.entry(record.key).or_default().push(record.file);
+        }
+        Ok(buckets)
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        self.shards.clear();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn shard_of(key: &str) -> usize {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % SPILL_SHARDS as u64) as usize
+}
+
+// Rough heap + inline footprint of a held candidate
+fn estimated_size(key: &str, file: &AudioFile) -> usize {
+    let strings = [&file.artist, &file.title, &file.album]
+        .iter()
+        .map(|s| s.as_ref().map_or(0, |s| s.len()))
+        .sum::<usize>();
+    std::mem::size_of::<AudioFile>()
+        + key.len()
+        + file.path.as_os_str().len()
+        + file.file_name.len()
+        + strings
+}
@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use regex::Regex;
+use serde::Serialize;
+use crate::AudioFile;
+use crate::utils::file_ops::FileOperation;
+
+// Multi-disc albums ripped or downloaded into one folder per disc, either
+// side by side ("Album (Disc 1)", "Album (Disc 2)") or nested ("Album/CD1",
+// "Album/CD2"). Each disc on its own looks like an incomplete album, and
+// tracks from different discs with the same number sort together. Merging
+// moves every disc's tracks into one album folder, prefixing file names with
+// the disc number ("2-05 Title.mp3") so the order survives.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscFolder {
+    pub path: PathBuf,
+    pub disc: u32,
+    pub tracks: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitAlbum {
+    // Folder name without the disc marker; the enclosing folder's name for
+    // nested discs
+    pub name: String,
+    // Folder the merged album ends up in
+    pub target: PathBuf,
+    // Ordered by disc number
+    pub discs: Vec<DiscFolder>,
+}
+
+impl SplitAlbum {
+    pub fn track_count(&self) -> usize {
+        self.discs.iter().map(|disc| disc.tracks.len()).sum()
+    }
+
+    // Moves bringing every disc's tracks into the target folder
+    pub fn merge_operations(&self) -> Vec<FileOperation> {
+        self.discs.iter()
+            .flat_map(|disc| disc.tracks.iter().map(move |track| (disc.disc, track)))
+            .filter_map(|(disc, track)| {
+                let file_name = track.file_name()?.to_string_lossy();
+                Some(FileOperation::Move {
+                    from: track.clone(),
+                    to: self.target.join(disc_file_name(disc, &file_name)),
+                })
+            })
+            .collect()
+    }
+}
+
+// Splits a folder name into the album part and the disc number:
+// "Album (Disc 2)" -> ("Album", 2), "CD1" -> ("", 1)
+pub fn parse_disc_folder(name: &str) -> Option<(String, u32)> {
+    static DISC: OnceLock<Regex> = OnceLock::new();
+    let regex = DISC.get_or_init(|| {
+        Regex::new(r"(?i)^(.*?)[\s._\-(\[]*\b(?:disc|disk|cd)[\s._\-]*(\d{1,2})[)\]]?\s*$").unwrap()
+    });
+    let captures = regex.captures(name.trim())?;
+    let disc = captures[2].parse().ok().filter(|disc| *disc > 0)?;
+    Some((captures[1].trim().to_string(), disc))
+}
+
+// "05 Title.mp3" on disc 2 -> "2-05 Title.mp3"; names that already carry
+// the disc number are kept
+fn disc_file_name(disc: u32, file_name: &str) -> String {
+    let prefix = format!("{}-", disc);
+    if file_name.starts_with(&prefix) {
+        file_name.to_string()
+    } else {
+        format!("{}{}", prefix, file_name)
+    }
+}
+
+// A folder whose name carries a disc marker
+struct DiscMember<'a> {
+    folder: &'a Path,
+    album: String,
+    disc: u32,
+}
+
+pub struct DiscAnalyzer;
+
+impl DiscAnalyzer {
+    // Sibling disc folders with the same album part and distinct disc
+    // numbers. Groups whose album tags disagree, or that have two folders for
+    // the same disc, are left out rather than guessed at.
+    pub fn find_split_albums(files: &[AudioFile]) -> Vec<SplitAlbum> {
+        let mut folders: BTreeMap<&Path, Vec<&AudioFile>> = BTreeMap::new();
+        for file in files {
+            if let Some(folder) = file.path.parent() {
+                folders.entry(folder).or_default().push(file);
+            }
+        }
+
+        // (parent, lowercased album part) -> disc folders
+        let mut groups: BTreeMap<(&Path, String), Vec<DiscMember>> = BTreeMap::new();
+        for folder in folders.keys() {
+            let (Some(parent), Some(name)) = (folder.parent(), folder.file_name()) else { continue };
+            if let Some((album, disc)) = parse_disc_folder(&name.to_string_lossy()) {
+                groups.entry((parent, album.to_lowercase())).or_default().push(DiscMember { folder, album, disc });
+            }
+        }
+
+        groups.into_iter()
+            .filter_map(|((parent, _), mut members)| {
+                members.sort_by_key(|member| member.disc);
+                let distinct = members.windows(2).all(|pair| pair[0].disc != pair[1].disc);
+                if members.len() < 2 || !distinct
+                    || !same_album_tags(members.iter().map(|member| folders[member.folder].as_slice())) {
+                    return None;
+                }
+
+                let discs: Vec<DiscFolder> = members.iter()
+                    .map(|member| {
+                        let mut tracks: Vec<PathBuf> = folders[member.folder].iter().map(|file| file.path.clone()).collect();
+                        tracks.sort();
+                        DiscFolder { path: member.folder.to_path_buf(), disc: member.disc, tracks }
+                    })
+                    .collect();
+                let album = &members[0].album;
+                let (name, target) = if album.is_empty() {
+                    (parent.file_name().unwrap_or_default().to_string_lossy().into_owned(), parent.to_path_buf())
+                } else {
+                    (album.clone(), parent.join(album))
+                };
+                Some(SplitAlbum { name, target, discs })
+            })
+            .collect()
+    }
+}
+
+// Whether the discs' most common album tags (disc marker removed) agree;
+// discs without album tags don't object
+fn same_album_tags<'a>(discs: impl Iterator<Item = &'a [&'a AudioFile]>) -> bool {
+    let mut albums = discs.filter_map(|files| {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for album in files.iter().filter_map(|file| file.album.as_deref()) {
+            let album = parse_disc_folder(album).map_or_else(|| album.trim().to_string(), |(album, _)| album);
+            *counts.entry(album.to_lowercase()).or_default() += 1;
+        }
+        counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0))).map(|(album, _)| album)
+    });
+    match albums.next() {
+        Some(first) => albums.all(|album| album == first),
+        None => true,
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::AudioFile;
+use crate::exporters::CollectionTrack;
+
+// Tracks whose file no longer has the length DJ software recorded when it
+// analyzed them: re-exported edits, a different master dropped in under the
+// same name, a trimmed intro. The saved beatgrid and cues are then offset
+// from the audio, which only shows up on the decks.
+
+// Rekordbox stores whole seconds and decoders disagree by a few frames on
+// MP3s with encoder padding, so small differences are normal
+pub const DEFAULT_TOLERANCE_SECS: f64 = 1.5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LengthMismatch {
+    pub path: PathBuf,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub collection_secs: f64,
+    pub file_secs: f64,
+}
+
+impl LengthMismatch {
+    // Positive when the file got longer
+    pub fn difference_secs(&self) -> f64 {
+        self.file_secs - self.collection_secs
+    }
+}
+
+pub struct DriftChecker {
+    tolerance_secs: f64,
+}
+
+impl Default for DriftChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DriftChecker {
+    pub fn new() -> Self {
+        Self { tolerance_secs: DEFAULT_TOLERANCE_SECS }
+    }
+
+    pub fn with_tolerance(mut self, secs: f64) -> Self {
+        self.tolerance_secs = secs.max(0.0);
+        self
+    }
+
+    // None when either length is unknown or they agree within the tolerance
+    pub fn check(&self, track: &CollectionTrack, file: &AudioFile) -> Option<LengthMismatch> {
+        let collection_secs = track.duration_secs.filter(|&secs| secs > 0.0)?;
+        let file_secs = file.duration_secs?;
+        if (file_secs - collection_secs).abs() <= self.tolerance_secs {
+            return None;
+        }
+        Some(LengthMismatch {
+            path: file.path.clone(),
+            artist: track.artist.clone(),
+            title: track.title.clone(),
+            collection_secs,
+            file_secs,
+        })
+    }
+
+    // Largest differences first
+    pub fn find_mismatches<'a>(&self, pairs: impl IntoIterator<Item = (&'a CollectionTrack, &'a AudioFile)>) -> Vec<LengthMismatch> {
+        let mut mismatches: Vec<LengthMismatch> = pairs.into_iter()
+            .filter_map(|(track, file)| self.check(track, file))
+            .collect();
+        mismatches.sort_by(|a, b| b.difference_secs().abs().total_cmp(&a.difference_secs().abs()));
+        mismatches
+    }
+}
@@ -1,187 +1,334 @@
 use crate::AudioFile;
-use crate::utils::parallel::ParallelProcessor;
+use crate::utils::parallel::{self, ParallelProcessor};
 use crate::analyzers::bitrate::BitrateAnalyzer;
-use std::sync::atomic::Ordering;
-use regex::Regex;
+use crate::analyzers::candidates::{CandidateBuckets, DEFAULT_MEMORY_BUDGET};
+#[cfg(feature = "decode")]
+use crate::analyzers::fingerprint::{Fingerprint, Fingerprinter};
+use crate::analyzers::ignore::IgnoreList;
+use crate::analyzers::prefilter::Prefilter;
+use crate::analyzers::roots::RootPriority;
+use crate::analyzers::title::{KnownArtists, ParsedTitle, TitleParser};
+use crate::utils::hashing;
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+
+// What makes two files duplicates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    // Same artist, title and version in the file name
+    #[default]
+    Name,
+    // Same audio, whatever the files are called
+    #[cfg(feature = "decode")]
+    Fingerprint,
+}
 
-#[derive(Debug)]
-pub struct DuplicateMatch {
-    pub higher_quality: AudioFile,
-    pub lower_quality: AudioFile,
-    pub match_reason: String,
-    pub quality_difference: String
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" | "title" => Ok(Self::Name),
+            #[cfg(feature = "decode")]
+            "fingerprint" | "audio" => Ok(Self::Fingerprint),
+            other => Err(format!("Unknown match mode: {}", other)),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct DuplicateResults {
-    pub matches: Vec<DuplicateMatch>,
-    pub total_files_scanned: usize,
+// Where a file's artist and title are taken from in name mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchSource {
+    // ISRC tag: the recording itself rather than its artist and title.
+    // Only ever matches the same code, never fuzzily.
+    Isrc,
+    // Artist and title tags, when the file has both
+    Tags,
+    // "Artist - Title" file name, track numbers and copy markers stripped
+    Filename,
+}
+
+impl MatchSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Isrc => "ISRC",
+            Self::Tags => "tags",
+            Self::Filename => "file name",
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
-enum VersionType {
-    None,
-    WithMarkers(Vec<String>),
+// How sure a match is, from surest to least sure; an approval list decides
+// which classes unattended runs act on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MatchClass {
+    // Same bytes (SHA-256 of the whole file)
+    Identical,
+    // Same ISRC tag on both files
+    Isrc,
+    // Same artist and title tags on both files
+    Tags,
+    // Same artist and title, from the file name of at least one file
+    Filename,
+    // Artist and title alike but not equal (`--similarity`, `fuzzy`), or
+    // matched only through another copy. Also what documents without a
+    // class read as.
+    #[default]
+    Similar,
+    // Same audio fingerprint
+    Fingerprint,
 }
 
-impl VersionType {
-    fn from_str(text: Option<&str>) -> Self {
-        let markers = [
-            // Remix and edit types
-            "remix", "mix", "rmx", "rework", "edit", "reconstruction",
-            "bootleg", "mashup", "flip", "recut", "reprise",
-            // Version types
-            "version", "radio", "club", "special", "extended",
-            // DJ markers
-            "dj", "vs", "presents", 
-            // Release types
-            "remaster", "master", "remastered",
-            // Mix types
-            "dub", "instrumental", "acapella", "acoustic", "live",
-            // Length markers
-            "long", "short", "full", "cut", "original",
-            // Regional markers
-            "us", "uk", "euro", "italian", "spanish", "dutch",
-            // Special combinations
-            "radio edit", "club mix", "dance mix", "extended mix"
-        ];
-
-        match text {
-            None => Self::None,
-            Some(text) => {
-                let text_lower = text.to_lowercase();
-                let found_markers: Vec<String> = markers.iter()
-                    .filter(|&&m| text_lower.contains(m))
-                    .map(|&s| s.to_string())
-                    .collect();
+impl MatchClass {
+    pub const ALL: [Self; 6] = [Self::Identical, Self::Isrc, Self::Tags, Self::Filename, Self::Similar, Self::Fingerprint];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::Isrc => "isrc",
+            Self::Tags => "tags",
+            Self::Filename => "filename",
+            Self::Similar => "similar",
+            Self::Fingerprint => "fingerprint",
+        }
+    }
+}
+
+impl FromStr for MatchClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter()
+            .find(|class| class.as_str() == s.trim().to_lowercase())
+            .ok_or_else(|| format!("Unknown match class: {} (use identical, isrc, tags, filename, similar or fingerprint)", s))
+    }
+}
 
-                if found_markers.is_empty() && text_lower.chars()
-                    .filter(|c| c.is_ascii_digit())
-                    .count() >= 4 {
-                    Self::WithMarkers(vec!["year".to_string()])
-                } else if !found_markers.is_empty() {
-                    Self::WithMarkers(found_markers)
-                } else {
-                    Self::None
+// Similarity `fuzzy` in the match chain uses when the analyzer was given no
+// threshold below 1.0
+pub const DEFAULT_SIMILARITY: f64 = 0.9;
+
+// Sources tried in order for each file, the first one that has an artist
+// and title deciding what track the file is; with `fuzzy`, tracks whose
+// artist and title are a typo or two apart match too
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchChain {
+    pub sources: Vec<MatchSource>,
+    pub fuzzy: bool,
+}
+
+impl Default for MatchChain {
+    fn default() -> Self {
+        Self {
+            sources: vec![MatchSource::Tags, MatchSource::Filename],
+            fuzzy: false,
+        }
+    }
+}
+
+// Comma-separated, e.g. `isrc,tags,filename,fuzzy`
+impl FromStr for MatchChain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chain = Self { sources: Vec::new(), fuzzy: false };
+        for part in s.split(',').map(|part| part.trim().to_lowercase()) {
+            if chain.fuzzy {
+                return Err("fuzzy must come last".to_string());
+            }
+            let source = match part.as_str() {
+                "isrc" => MatchSource::Isrc,
+                "tags" | "tag" => MatchSource::Tags,
+                "filename" | "file" | "name" => MatchSource::Filename,
+                "fuzzy" => {
+                    chain.fuzzy = true;
+                    continue;
                 }
+                other => return Err(format!("Unknown match source: {} (use isrc, tags, filename or fuzzy)", other)),
+            };
+            if chain.sources.contains(&source) {
+                return Err(format!("{} is listed twice", part));
             }
+            chain.sources.push(source);
         }
+        if chain.sources.is_empty() {
+            return Err("List isrc, tags or filename before fuzzy".to_string());
+        }
+        Ok(chain)
     }
+}
 
-    fn share_markers(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::WithMarkers(m1), Self::WithMarkers(m2)) => 
-                m1.iter().any(|m| m2.contains(m)),
-            _ => false
-        }
+// Files are shared with the bucket they came from rather than cloned, so a
+// file appearing in several matches is only held once
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DuplicateMatch {
+    pub higher_quality: Arc<AudioFile>,
+    pub lower_quality: Arc<AudioFile>,
+    pub match_reason: String,
+    pub quality_difference: String,
+    #[serde(default)]
+    pub match_class: MatchClass,
+}
+
+// Two files of a bucket by index, with the reason and class of their match
+type MatchedPair = (usize, usize, String, MatchClass);
+
+// Every copy of one track: the best one, kept, and the rest, each matched
+// against it so `higher_quality` is always `original`
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DuplicateGroup {
+    pub original: Arc<AudioFile>,
+    pub duplicates: Vec<DuplicateMatch>,
+}
+
+impl DuplicateGroup {
+    // On-disk bytes freed by removing the duplicates
+    fn waste(&self) -> u64 {
+        self.duplicates.iter().map(|m| m.lower_quality.size_on_disk()).sum()
     }
 }
 
-#[derive(Debug)]
-struct ParsedTitle {
-    artist: String,
-    title: String,
-    version: Option<String>,
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DuplicateResults {
+    pub groups: Vec<DuplicateGroup>,
+    pub total_files_scanned: usize,
+}
+
+impl DuplicateResults {
+    // Each duplicate paired with the original of its group
+    pub fn matches(&self) -> impl Iterator<Item = &DuplicateMatch> {
+        self.groups.iter().flat_map(|group| &group.duplicates)
+    }
+
+    // Files that would be removed, i.e. every copy but the originals
+    pub fn duplicate_count(&self) -> usize {
+        self.groups.iter().map(|group| group.duplicates.len()).sum()
+    }
+
+    // Space freed by removing every lower-quality copy: (logical, on disk)
+    pub fn reclaimable_bytes(&self) -> (u64, u64) {
+        self.matches().fold((0, 0), |(logical, on_disk), m| {
+            (logical + m.lower_quality.size_bytes, on_disk + m.lower_quality.size_on_disk())
+        })
+    }
+
+    // Group freeing the most space first
+    pub fn sort_by_waste(&mut self) {
+        self.groups.sort_by_key(|group| std::cmp::Reverse(group.waste()));
+    }
+
+    // Keeps `limit` groups (all when None) starting at `offset`
+    pub fn paginate(&mut self, offset: usize, limit: Option<usize>) {
+        self.groups.drain(..offset.min(self.groups.len()));
+        if let Some(limit) = limit {
+            self.groups.truncate(limit);
+        }
+    }
 }
 
 pub struct DuplicateAnalyzer {
-    title_regex: Arc<Regex>,
+    parser: TitleParser,
+    chain: MatchChain,
+    // Minimum similarity of artist and title for fuzzy matches; 1.0 only
+    // matches them exactly
+    similarity: f64,
+    memory_budget: usize,
+    prefilter: Option<Prefilter>,
+    root_priority: Option<RootPriority>,
+    // Pairs reviewed as different tracks
+    ignored: IgnoreList,
+    // Set in fingerprint mode
+    #[cfg(feature = "decode")]
+    fingerprinter: Option<Fingerprinter>,
 }
 
 impl ParallelProcessor for DuplicateAnalyzer {}
 
 impl DuplicateAnalyzer {
-    pub fn new(_threshold: f64) -> Self {
+    // `threshold` is the similarity (0.0 to 1.0) artist and title need to
+    // match despite typos or punctuation; 1.0 matches exact titles only
+    pub fn new(threshold: f64) -> Self {
         Self::init_parallel_processing();
         println!("Initializing DuplicateAnalyzer");
         Self {
-            title_regex: Arc::new(Regex::new(r"^\d+\.?\s*").unwrap()),
+            parser: TitleParser::new(),
+            chain: MatchChain::default(),
+            similarity: threshold.clamp(0.0, 1.0),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            prefilter: None,
+            root_priority: None,
+            ignored: IgnoreList::default(),
+            #[cfg(feature = "decode")]
+            fingerprinter: None,
         }
     }
 
-    fn normalize_artist(artist: &str) -> String {
-        let normalized = artist
-            .to_lowercase()
-            .replace("feat.", "featuring")
-            .replace("ft.", "featuring")
-            .replace(" x ", " featuring ");
-
-        let mut artists: Vec<_> = normalized
-            .split(',')
-            .map(|s| {
-                let artist_name = s.trim();
-                // Remove any parenthetical content from artist names
-                artist_name
-                    .split('(')
-                    .next()
-                    .unwrap_or(artist_name)
-                    .trim()
-                    .to_string()
-            })
-            .collect();
+    pub fn with_match_mode(self, mode: MatchMode) -> Self {
+        match mode {
+            MatchMode::Name => Self {
+                #[cfg(feature = "decode")]
+                fingerprinter: None,
+                ..self
+            },
+            #[cfg(feature = "decode")]
+            MatchMode::Fingerprint => self.with_fingerprinter(Fingerprinter::new()),
+        }
+    }
 
-        artists.sort();
-        artists.join(", ")
+    // Fingerprint mode with custom settings. Files are compared when their
+    // durations agree within the prefilter's tolerance (2 s by default).
+    #[cfg(feature = "decode")]
+    pub fn with_fingerprinter(mut self, fingerprinter: Fingerprinter) -> Self {
+        self.fingerprinter = Some(fingerprinter);
+        self
     }
 
-    fn extract_version(text: &str) -> (String, Option<String>) {
-        match (text.rfind('('), text[..].rfind(')')) {
-            (Some(start), Some(end)) if start < end => {
-                let version_text = text[start + 1..end].trim();
-                match VersionType::from_str(Some(version_text)) {
-                    VersionType::None => (text.trim().to_string(), None),
-                    VersionType::WithMarkers(_) => (
-                        text[..start].trim().to_string(),
-                        Some(version_text.to_lowercase())
-                    ),
-                }
-            },
-            _ => (text.trim().to_string(), None)
-        }
+    // Approximate bytes of candidate files held in RAM before spilling to disk
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
     }
 
-    fn clean_title(&self, filename: &str) -> ParsedTitle {
-        let without_ext = filename.rfind('.').map_or(filename, |i| &filename[..i]);
-        
-        let clean_name = without_ext
-            .replace(|c| c == '[' || c == ']', "")
-            .replace('_', " ")
-            .trim()
-            .to_string();
+    // Skip title comparison for pairs whose duration or size rule them out
+    pub fn with_prefilter(mut self, prefilter: Prefilter) -> Self {
+        self.prefilter = Some(prefilter);
+        self
+    }
 
-        let without_numbers = self.title_regex.replace(&clean_name, "").to_string();
 
-        let parts: Vec<&str> = without_numbers.split(" - ").collect();
-        if parts.len() < 2 {
-            return ParsedTitle {
-                artist: without_numbers.clone(),
-                title: without_numbers,
-                version: None,
-            };
-        }
+    // Artists to recognize "Title - Artist" names of untagged files by
+    pub fn with_known_artists(self, known: KnownArtists) -> Self {
+        Self { parser: self.parser.with_known_artists(known), ..self }
+    }
 
-        let artist = Self::normalize_artist(parts[0].trim());
-        let title_parts = parts[1..].join(" - ");
-        let (clean_title, version) = Self::extract_version(&title_parts);
+    // Where artist and title come from in name mode
+    pub fn with_match_chain(mut self, chain: MatchChain) -> Self {
+        self.chain = chain;
+        self
+    }
 
-        ParsedTitle {
-            artist,
-            title: clean_title.to_lowercase(),
-            version,
-        }
+    // Whether leading track numbers are dropped from file names before matching
+    pub fn with_track_number_stripping(self, strip: bool) -> Self {
+        Self { parser: self.parser.with_track_number_stripping(strip), ..self }
     }
 
-    fn are_different_versions(version1: Option<&str>, version2: Option<&str>) -> bool {
-        let v1_type = VersionType::from_str(version1);
-        let v2_type = VersionType::from_str(version2);
+    // When both copies carry the same audio, keep the one on the preferred root
+    pub fn with_root_priority(mut self, priority: RootPriority) -> Self {
+        self.root_priority = Some(priority);
+        self
+    }
 
-        match (v1_type, v2_type) {
-            (VersionType::None, VersionType::None) => false,
-            (VersionType::WithMarkers(_), VersionType::WithMarkers(_)) 
-                if version1 == version2 => false,
-            (v1, v2) => !v1.share_markers(&v2)
-        }
+    // Pairs never to match, whatever their names, tags or audio
+    pub fn with_ignored_pairs(mut self, ignored: IgnoreList) -> Self {
+        self.ignored = ignored;
+        self
     }
 
     fn get_formatted_reason(&self, parsed: &ParsedTitle, version: Option<&str>) -> String {
@@ -189,81 +336,366 @@ impl DuplicateAnalyzer {
         format!("Exact title match: '{} - {}{}'", parsed.artist, parsed.title, version_info)
     }
 
-    fn are_duplicates(&self, file1: &AudioFile, file2: &AudioFile) -> Option<DuplicateMatch> {
-        let parsed1 = self.clean_title(&file1.file_name);
-        let parsed2 = self.clean_title(&file2.file_name);
+    // Artist and title from the first source in the chain that has them. An
+    // ISRC comes back as the title, with no artist.
+    pub fn identify(&self, file: &AudioFile) -> Option<(ParsedTitle, MatchSource)> {
+        self.chain.sources.iter().find_map(|source| match source {
+            MatchSource::Isrc => {
+                let isrc = file.isrc.clone()?;
+                Some((ParsedTitle { artist: String::new(), title: isrc, version: None }, MatchSource::Isrc))
+            }
+            MatchSource::Tags => {
+                let artist = file.artist.as_deref().map(str::trim).filter(|artist| !artist.is_empty())?;
+                let title = file.title.as_deref().map(str::trim).filter(|title| !title.is_empty())?;
+                Some((TitleParser::parse_tags(artist, title), MatchSource::Tags))
+            }
+            MatchSource::Filename => Some((self.parser.parse_file(file), MatchSource::Filename)),
+        })
+    }
 
-        // Early returns for non-matches
-        if parsed1.artist != parsed2.artist || parsed1.title != parsed2.title {
+    // Similarity fuzzy matches need, if fuzzy matching is on
+    fn fuzzy_threshold(&self) -> Option<f64> {
+        if self.similarity < 1.0 {
+            Some(self.similarity)
+        } else {
+            self.chain.fuzzy.then_some(DEFAULT_SIMILARITY)
+        }
+    }
+
+    // Files are compared within a bucket. Fuzzy matching groups artists by
+    // their first few letters, so a typo there is the one it can't catch.
+    fn bucket_key(&self, file: &AudioFile) -> Option<String> {
+        const FUZZY_PREFIX: usize = 4;
+        let (parsed, source) = self.identify(file)?;
+        if source == MatchSource::Isrc {
+            return Some(format!("isrc\u{0}{}", parsed.title));
+        }
+        Some(match self.fuzzy_threshold() {
+            Some(_) => TitleParser::normalize_text(&parsed.artist)
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .take(FUZZY_PREFIX)
+                .collect(),
+            None => format!("{}\u{0}{}", parsed.artist, parsed.title),
+        })
+    }
+
+    // Pure pairwise check, usable without scanning a library (e.g. to test a
+    // single candidate track against an existing collection)
+    pub fn match_pair(&self, file1: &Arc<AudioFile>, file2: &Arc<AudioFile>) -> Option<DuplicateMatch> {
+        if self.ignored.contains(&file1.path, &file2.path) {
             return None;
         }
+        let (parsed1, source1) = self.identify(file1)?;
+        let (parsed2, source2) = self.identify(file2)?;
+        if source1 == MatchSource::Isrc || source2 == MatchSource::Isrc {
+            if source1 != source2 || parsed1.title != parsed2.title {
+                return None;
+            }
+            return Some(self.build_match(file1, file2, format!("Same ISRC: {}", parsed1.title), MatchClass::Isrc));
+        }
+
+        // Early returns for non-matches
+        let exact = parsed1.artist == parsed2.artist && parsed1.title == parsed2.title;
+        let similarity = match self.fuzzy_threshold() {
+            Some(threshold) if !exact => {
+                let similarity = TitleParser::similarity(
+                    &format!("{} {}", parsed1.artist, parsed1.title),
+                    &format!("{} {}", parsed2.artist, parsed2.title),
+                );
+                if similarity < threshold {
+                    return None;
+                }
+                similarity
+            }
+            _ if !exact => return None,
+            _ => 1.0,
+        };
 
         // Check for different versions
-        if Self::are_different_versions(parsed1.version.as_deref(), parsed2.version.as_deref()) {
+        if TitleParser::are_different_versions(parsed1.version.as_deref(), parsed2.version.as_deref()) {
             return None;
         }
 
-        // Use BitrateAnalyzer for quality comparison
-        let (file1_better, quality_difference) = BitrateAnalyzer::compare_quality(file1, file2);
-        let match_reason = self.get_formatted_reason(&parsed1, parsed1.version.as_deref());
-        
+        let mut match_reason = if exact {
+            self.get_formatted_reason(&parsed1, parsed1.version.as_deref())
+        } else {
+            format!("Similar title match ({:.0}%): '{} - {}' and '{} - {}'",
+                similarity * 100.0, parsed1.artist, parsed1.title, parsed2.artist, parsed2.title)
+        };
+        let class = match (exact, source1, source2) {
+            (false, ..) => MatchClass::Similar,
+            (true, MatchSource::Tags, MatchSource::Tags) => MatchClass::Tags,
+            (true, ..) => MatchClass::Filename,
+        };
+        // File names alone is how matching always worked; say so otherwise
+        match (source1, source2) {
+            (MatchSource::Filename, MatchSource::Filename) => {}
+            (a, b) if a == b => match_reason.push_str(&format!(" (from {})", a.label())),
+            (a, b) => match_reason.push_str(&format!(" (from {} and {})", a.label(), b.label())),
+        }
+        Some(self.build_match(file1, file2, match_reason, class))
+    }
+
+    // Whether `files[a]` and `files[b]` have the same bytes. Only files of
+    // equal size are hashed, each at most once for all the pairs of a group.
+    fn same_bytes(files: &[Arc<AudioFile>], a: usize, b: usize, hashes: &mut HashMap<usize, Option<String>>) -> bool {
+        if files[a].size_bytes != files[b].size_bytes {
+            return false;
+        }
+        let mut hash = |i: usize| hashes.entry(i)
+            .or_insert_with(|| hashing::sha256_file(&files[i].path).ok())
+            .clone();
+        matches!((hash(a), hash(b)), (Some(x), Some(y)) if x == y)
+    }
+
+    // The two files as a match, the better copy first
+    fn build_match(&self, file1: &Arc<AudioFile>, file2: &Arc<AudioFile>, match_reason: String, match_class: MatchClass) -> DuplicateMatch {
+        let (file1_better, quality_difference) = self.compare_quality(file1, file2);
         let (higher, lower) = if file1_better {
-            (file1.clone(), file2.clone())
+            (Arc::clone(file1), Arc::clone(file2))
         } else {
-            (file2.clone(), file1.clone())
+            (Arc::clone(file2), Arc::clone(file1))
         };
 
-        Some(DuplicateMatch {
+        DuplicateMatch {
             higher_quality: higher,
             lower_quality: lower,
             match_reason,
             quality_difference,
-        })
+            match_class,
+        }
+    }
+
+    // Whether file1 is the better copy, and how the two differ
+    fn compare_quality(&self, file1: &AudioFile, file2: &AudioFile) -> (bool, String) {
+        // Use BitrateAnalyzer for quality comparison
+        let (mut file1_better, mut quality_difference) = BitrateAnalyzer::compare_quality(file1, file2);
+        let same_audio = file1.bitrate == file2.bitrate && file1.audio_size() == file2.audio_size();
+        if let Some(priority) = self.root_priority.as_ref().filter(|_| same_audio) {
+            if priority.rank(&file1.path) != priority.rank(&file2.path) {
+                file1_better = priority.prefers(&file1.path, &file2.path);
+                quality_difference.push_str("; keeping the copy on the preferred root");
+            }
+        }
+        (file1_better, quality_difference)
+    }
+
+    // Joins matched pairs sharing a file into groups and keeps the best copy
+    // of each, so three copies of a track give one keeper and two duplicates
+    // rather than three overlapping pairs. A copy matched only through
+    // another one (fuzzy or fingerprint matches need not be transitive)
+    // keeps the reason of the pair that brought it in and counts as a similar
+    // match, unless it and the keeper are an ignored pair: then it stays.
+    // Copies with the keeper's bytes are `Identical` whatever matched them.
+    fn group_matches(&self, files: &[Arc<AudioFile>], pairs: Vec<MatchedPair>) -> Vec<DuplicateGroup> {
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        let mut parent: Vec<usize> = (0..files.len()).collect();
+        for (i, j, ..) in &pairs {
+            let (a, b) = (root(&mut parent, *i), root(&mut parent, *j));
+            parent[a.max(b)] = a.min(b);
+        }
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); files.len()];
+        for i in 0..files.len() {
+            let group = root(&mut parent, i);
+            members[group].push(i);
+        }
+
+        members.into_iter()
+            .filter(|group| group.len() > 1)
+            .filter_map(|group| {
+                let mut hashes = HashMap::new();
+                let keeper = group.iter().copied()
+                    .reduce(|best, i| if self.compare_quality(&files[best], &files[i]).0 { best } else { i })
+                    .expect("groups have members");
+                let duplicates: Vec<DuplicateMatch> = group.into_iter()
+                    .filter(|&i| i != keeper && !self.ignored.contains(&files[keeper].path, &files[i].path))
+                    .map(|i| {
+                        let touches = |&&(a, b, ..): &&MatchedPair| a == i || b == i;
+                        let (reason, class) = match pairs.iter().filter(touches).find(|&&(a, b, ..)| a == keeper || b == keeper) {
+                            Some((_, _, reason, class)) => (reason.clone(), *class),
+                            None => (pairs.iter().find(touches).map_or_else(String::new, |(_, _, reason, _)| reason.clone()), MatchClass::Similar),
+                        };
+                        DuplicateMatch {
+                            higher_quality: Arc::clone(&files[keeper]),
+                            lower_quality: Arc::clone(&files[i]),
+                            match_reason: reason,
+                            quality_difference: self.compare_quality(&files[keeper], &files[i]).1,
+                            match_class: if Self::same_bytes(files, keeper, i, &mut hashes) { MatchClass::Identical } else { class },
+                        }
+                    })
+                    .collect();
+                (!duplicates.is_empty()).then(|| DuplicateGroup { original: Arc::clone(&files[keeper]), duplicates })
+            })
+            .collect()
     }
 
     pub fn find_duplicates(&self, files: Vec<AudioFile>) -> DuplicateResults {
-        println!("Starting duplicate analysis with {} files using {} threads", 
-            files.len(), 
-            rayon::current_num_threads()
+        self.find_duplicates_in(files)
+    }
+
+    // Accepts any stream of files so callers don't need the whole library in
+    // memory at once; candidates beyond the memory budget spill to disk
+    pub fn find_duplicates_in(&self, files: impl IntoIterator<Item = AudioFile>) -> DuplicateResults {
+        #[cfg(feature = "decode")]
+        if let Some(fingerprinter) = &self.fingerprinter {
+            return self.find_by_fingerprint(files.into_iter().collect(), fingerprinter);
+        }
+
+        let mut buckets = CandidateBuckets::new(self.memory_budget);
+        let mut total_files = 0;
+        let mut unidentified = 0;
+        for file in files {
+            total_files += 1;
+            match self.bucket_key(&file) {
+                Some(key) => buckets.insert(key, file),
+                None => unidentified += 1,
+            }
+        }
+        if unidentified > 0 {
+            println!("{} files have no artist and title in any matched source and are skipped", unidentified);
+        }
+
+        println!("Starting duplicate analysis with {} files using {} threads",
+            total_files,
+            parallel::current_threads()
         );
 
-        if files.is_empty() {
+        if total_files == 0 {
             println!("No files to analyze!");
-            return DuplicateResults { matches: Vec::new(), total_files_scanned: 0 };
+            return DuplicateResults { groups: Vec::new(), total_files_scanned: 0 };
         }
 
-        let progress = Self::get_progress_counter();
-        let total_files = files.len();
+        // How many files share a bucket isn't known until every shard has
+        // been read, so there's a rate but no ETA
+        let progress = Self::get_progress_counter(0);
+        let mut groups = Vec::new();
 
-        // Use parallel comparison for finding duplicates
-        let matches = Self::parallel_compare(&files, |file1, file2| {
-            let result = self.are_duplicates(file1, file2);
-            
-            if result.is_some() {
-                let dup = result.as_ref().unwrap();
-                println!("\nFound duplicate:");
-                println!("  Higher quality: {} ({} kbps)", 
-                    dup.higher_quality.file_name, 
-                    dup.higher_quality.bitrate.unwrap_or(0));
-                println!("  Lower quality: {} ({} kbps)", 
-                    dup.lower_quality.file_name, 
-                    dup.lower_quality.bitrate.unwrap_or(0));
-                println!("  Reason: {}", dup.match_reason);
-                println!("  Quality difference: {}", dup.quality_difference);
-            }
+        let outcome = buckets.for_each_shard(|shard| {
+            // Only buckets with more than one file can contain duplicates
+            let candidates: Vec<Vec<Arc<AudioFile>>> = shard.into_iter()
+                .filter(|bucket| bucket.len() > 1)
+                .map(|bucket| bucket.into_iter().map(Arc::new).collect())
+                .collect();
 
-            let processed = progress.fetch_add(1, Ordering::SeqCst) + 1;
-            if processed % 1000 == 0 || processed == total_files {
-                println!("Progress: processed {} file comparisons", processed);
-            }
+            let shard_groups = Self::parallel_map(&candidates, |bucket| {
+                let bucket_groups = self.compare_bucket(bucket);
+
+                let processed = progress.advance(bucket.len());
+                if processed % 1000 < bucket.len() {
+                    println!("Progress: compared {} candidate files{}", processed, progress.rate(processed));
+                }
 
-            result
+                bucket_groups
+            });
+            groups.extend(shard_groups.into_iter().flatten());
         });
 
-        println!("\nFound {} duplicate matches", matches.len());
-        DuplicateResults {
-            matches,
+        if let Err(e) = outcome {
+            eprintln!("Error reading spilled duplicate candidates: {}", e);
+        }
+
+        let results = DuplicateResults {
+            groups,
             total_files_scanned: total_files
+        };
+        println!("\nFound {} duplicate groups", results.groups.len());
+        results
+    }
+
+    // Compares the audio of every pair of files whose durations agree. Files
+    // that can't be decoded are left out.
+    #[cfg(feature = "decode")]
+    fn find_by_fingerprint(&self, files: Vec<AudioFile>, fingerprinter: &Fingerprinter) -> DuplicateResults {
+        let total_files = files.len();
+        println!("Starting fingerprint duplicate analysis with {} files using {} threads",
+            total_files,
+            parallel::current_threads()
+        );
+        if total_files == 0 {
+            println!("No files to analyze!");
+            return DuplicateResults { groups: Vec::new(), total_files_scanned: 0 };
+        }
+
+        let fingerprints = fingerprinter.fingerprint_all(&files);
+        let mut candidates: Vec<(Arc<AudioFile>, Fingerprint)> = files.into_iter()
+            .zip(fingerprints)
+            .filter_map(|(file, fingerprint)| Some((Arc::new(file), fingerprint?)))
+            .collect();
+        // Shortest first, so each file only meets the few that follow it;
+        // files of unknown length come first and meet everything
+        candidates.sort_by(|a, b| a.0.duration_secs.unwrap_or(0.0).total_cmp(&b.0.duration_secs.unwrap_or(0.0)));
+        let tolerance = self.prefilter.unwrap_or_default().duration_tolerance_secs;
+
+        let indices: Vec<usize> = (0..candidates.len()).collect();
+        let pairs: Vec<MatchedPair> = Self::parallel_map(&indices, |&i| {
+            let (file1, fingerprint1) = &candidates[i];
+            candidates[i + 1..].iter()
+                .enumerate()
+                .take_while(|(_, (file2, _))| match (file1.duration_secs, file2.duration_secs) {
+                    (Some(d1), Some(d2)) => d2 - d1 <= tolerance,
+                    _ => true,
+                })
+                .filter(|(_, (file2, _))| !self.ignored.contains(&file1.path, &file2.path))
+                .filter_map(|(offset, (_, fingerprint2))| {
+                    let rate = fingerprinter.matches(fingerprint1, fingerprint2)?;
+                    Some((i, i + 1 + offset, format!("Audio fingerprint match ({:.1}% of bits differ)", rate * 100.0),
+                        MatchClass::Fingerprint))
+                })
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let files: Vec<Arc<AudioFile>> = candidates.into_iter().map(|(file, _)| file).collect();
+        let groups = self.group_matches(&files, pairs);
+        groups.iter().for_each(Self::print_group);
+
+        println!("\nFound {} duplicate groups", groups.len());
+        DuplicateResults {
+            groups,
+            total_files_scanned: total_files,
         }
     }
-}
\ No newline at end of file
+
+    fn print_group(group: &DuplicateGroup) {
+        println!("\nFound duplicate:");
+        println!("  Higher quality: {} ({} kbps)",
+            group.original.file_name,
+            group.original.bitrate.unwrap_or(0));
+        for dup in &group.duplicates {
+            println!("  Lower quality: {} ({} kbps)",
+                dup.lower_quality.file_name,
+                dup.lower_quality.bitrate.unwrap_or(0));
+            println!("    Reason: {}", dup.match_reason);
+            println!("    Quality difference: {}", dup.quality_difference);
+        }
+    }
+
+    fn compare_bucket(&self, bucket: &[Arc<AudioFile>]) -> Vec<DuplicateGroup> {
+        let pairs = match &self.prefilter {
+            Some(prefilter) => prefilter.candidate_pairs(bucket),
+            None => (0..bucket.len())
+                .flat_map(|i| (i + 1..bucket.len()).map(move |j| (i, j)))
+                .collect(),
+        };
+
+        let matched = pairs.into_iter()
+            .filter_map(|(i, j)| {
+                let dup = self.match_pair(&bucket[i], &bucket[j])?;
+                Some((i, j, dup.match_reason, dup.match_class))
+            })
+            .collect();
+        let groups = self.group_matches(bucket, matched);
+        groups.iter().for_each(Self::print_group);
+        groups
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::Serialize;
+use crate::{AudioError, AudioFile, Result};
+use crate::audio::tags::{self, TagMap};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Explicit-lyrics flags. The iTunes content advisory (ITUNESADVISORY: 1 or
+// 4 explicit, 2 clean, 0 not rated) is what stores and most taggers write;
+// it lives in a TXXX frame on MP3 and a Vorbis comment on FLAC. Tracks the
+// tags don't rate can optionally be run through an external transcriber
+// (e.g. a whisper wrapper printing the lyrics to stdout) and matched
+// against a word list.
+
+const ADVISORY_TAGS: [&str; 4] = ["ITUNESADVISORY", "TXXX:ITUNESADVISORY", "EXPLICIT", "TXXX:EXPLICIT"];
+
+// Entries ending in '*' match any word starting with the stem
+const DEFAULT_WORDS: &[&str] = &[
+    "fuck*", "motherfuck*", "shit*", "bullshit", "bitch*", "cunt*", "dick", "dicks",
+    "pussy", "cock", "cocks", "asshole*", "bastard*", "whore*", "slut*", "nigga*", "nigger*",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplicitStatus {
+    Explicit,
+    Clean,
+    // Not rated by the tags and not transcribed
+    Unknown,
+}
+
+impl ExplicitStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Explicit => "Explicit",
+            Self::Clean => "Clean",
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplicitSource {
+    Tag,
+    Transcript,
+}
+
+impl ExplicitSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Tag => "Tag",
+            Self::Transcript => "Transcript",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplicitReport {
+    pub path: PathBuf,
+    pub status: ExplicitStatus,
+    // Where the status came from; None when Unknown
+    pub source: Option<ExplicitSource>,
+    // Listed words heard in the transcript, each once
+    pub matched_words: Vec<String>,
+}
+
+// External speech-to-text command. `{}` in the arguments is replaced by the
+// track's path (appended when absent); the transcript is read from stdout.
+#[derive(Debug, Clone)]
+pub struct Transcriber {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Transcriber {
+    // Split on whitespace; programs or arguments containing spaces need a
+    // wrapper script
+    pub fn parse(command: &str) -> Result<Self> {
+        let mut parts = command.split_whitespace().map(str::to_string);
+        let program = parts.next()
+            .ok_or_else(|| AudioError::Metadata("Empty transcriber command".to_string()))?;
+        Ok(Self { program, args: parts.collect() })
+    }
+
+    pub fn transcribe(&self, path: &Path) -> Result<String> {
+        let mut command = Command::new(&self.program);
+        let mut substituted = false;
+        for arg in &self.args {
+            if arg.contains("{}") {
+                command.arg(arg.replace("{}", &path.to_string_lossy()));
+                substituted = true;
+            } else {
+                command.arg(arg);
+            }
+        }
+        if !substituted {
+            command.arg(path);
+        }
+
+        let output = command.output()
+            .map_err(|e| AudioError::Metadata(format!("Could not run transcriber {}: {}", self.program, e)))?;
+        if !output.status.success() {
+            return Err(AudioError::Metadata(format!(
+                "Transcriber failed on {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WordList {
+    words: Vec<String>,
+}
+
+impl Default for WordList {
+    fn default() -> Self {
+        Self { words: DEFAULT_WORDS.iter().map(|word| word.to_string()).collect() }
+    }
+}
+
+impl WordList {
+    // One word per line, '#' starts a comment
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        let words = contents.lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim().to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect();
+        Ok(Self { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // Distinct words of the text that are on the list, in order of first
+    // appearance
+    pub fn matches(&self, text: &str) -> Vec<String> {
+        let mut found: Vec<String> = Vec::new();
+        let text = text.to_lowercase();
+        let words = text.split(|c: char| !(c.is_alphanumeric() || c == '\''))
+            .map(|word| word.trim_matches('\''))
+            .filter(|word| !word.is_empty());
+        for word in words {
+            if !found.iter().any(|seen| seen == word) && self.contains(word) {
+                found.push(word.to_string());
+            }
+        }
+        found
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.words.iter().any(|entry| match entry.strip_suffix('*') {
+            Some(stem) => word.starts_with(stem),
+            None => entry == word,
+        })
+    }
+}
+
+pub struct ExplicitAnalyzer {
+    transcriber: Option<Transcriber>,
+    words: WordList,
+}
+
+impl ParallelProcessor for ExplicitAnalyzer {}
+
+impl Default for ExplicitAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExplicitAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            transcriber: None,
+            words: WordList::default(),
+        }
+    }
+
+    pub fn with_transcriber(mut self, transcriber: Transcriber) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    pub fn with_words(mut self, words: WordList) -> Self {
+        self.words = words;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<ExplicitReport> {
+        println!("Checking {} files for explicit content using {} threads", files.len(), parallel::current_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::parallel_map(files, |file| {
+            let result = self.check(&file.path);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error analyzing file: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // The tags decide when they rate the track; otherwise the transcript,
+    // if a transcriber is set
+    pub fn check(&self, path: &Path) -> Result<ExplicitReport> {
+        let mut report = ExplicitReport {
+            path: path.to_path_buf(),
+            status: ExplicitStatus::Unknown,
+            source: None,
+            matched_words: Vec::new(),
+        };
+
+        if let Some(status) = advisory(&tags::read_tags(path)?) {
+            report.status = status;
+            report.source = Some(ExplicitSource::Tag);
+        } else if let Some(transcriber) = &self.transcriber {
+            report.matched_words = self.words.matches(&transcriber.transcribe(path)?);
+            report.status = if report.matched_words.is_empty() { ExplicitStatus::Clean } else { ExplicitStatus::Explicit };
+            report.source = Some(ExplicitSource::Transcript);
+        }
+        Ok(report)
+    }
+}
+
+// Rating from the advisory tags; None when they're missing or say "not rated"
+pub fn advisory(tags: &TagMap) -> Option<ExplicitStatus> {
+    tags.iter()
+        .filter(|(field, _)| ADVISORY_TAGS.iter().any(|tag| field.eq_ignore_ascii_case(tag)))
+        .find_map(|(_, value)| match value.trim().to_lowercase().as_str() {
+            "1" | "4" | "explicit" | "yes" | "true" => Some(ExplicitStatus::Explicit),
+            "2" | "clean" | "no" | "false" => Some(ExplicitStatus::Clean),
+            _ => None,
+        })
+}
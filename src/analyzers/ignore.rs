@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::utils::{paths, unix_timestamp_millis};
+
+// Pairs of files reviewed and found to be different tracks, which duplicate
+// analysis never reports again. Kept by path, in either order, as
+// `ignored_pairs.json` in the data directory; a pair stops applying when
+// either file is moved or renamed.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IgnoreList {
+    pub pairs: Vec<IgnoredPair>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IgnoredPair {
+    pub first: PathBuf,
+    pub second: PathBuf,
+    pub added_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl IgnoredPair {
+    fn is(&self, a: &Path, b: &Path) -> bool {
+        (self.first == a && self.second == b) || (self.first == b && self.second == a)
+    }
+}
+
+impl IgnoreList {
+    pub fn default_path() -> PathBuf {
+        paths::data_dir().join("ignored_pairs.json")
+    }
+
+    // An empty list when the file doesn't exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid ignore list {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| AudioError::io(dir, e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        fs::write(path, json).map_err(|e| AudioError::io(path, e))
+    }
+
+    pub fn contains(&self, a: &Path, b: &Path) -> bool {
+        self.pairs.iter().any(|pair| pair.is(a, b))
+    }
+
+    // False when the pair is already listed
+    pub fn add(&mut self, a: impl Into<PathBuf>, b: impl Into<PathBuf>, note: Option<String>) -> bool {
+        let (first, second) = (a.into(), b.into());
+        if self.contains(&first, &second) {
+            return false;
+        }
+        self.pairs.push(IgnoredPair { first, second, added_at: unix_timestamp_millis(), note });
+        true
+    }
+
+    // False when the pair wasn't listed
+    pub fn remove(&mut self, a: &Path, b: &Path) -> bool {
+        let before = self.pairs.len();
+        self.pairs.retain(|pair| !pair.is(a, b));
+        self.pairs.len() < before
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
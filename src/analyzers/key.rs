@@ -0,0 +1,224 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rustfft::{num_complex::Complex, FftPlanner};
+use crate::{AudioFile, Result};
+use crate::audio::decode::{self, DecodedAudio};
+use crate::exporters::keys::MusicalKey;
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Musical key from the audio. Every frame's spectrum between MIN_HZ and
+// MAX_HZ is folded onto the 12 pitch classes (a chromagram), summed over the
+// track, and correlated with the Krumhansl-Kessler key profiles rotated to
+// each of the 24 keys; the best fit is the key. Relative keys (Am and C)
+// share their notes and differ only in weighting, so they are the most
+// common confusion, which on the Camelot wheel stays a safe mix.
+
+const FFT_SIZE: usize = 16384;
+const HOP_SIZE: usize = 8192;
+// C2 up to about C7: below that bins are wider than a semitone, above it
+// harmonics and hi-hats blur the pitch classes
+const MIN_HZ: f64 = 65.0;
+const MAX_HZ: f64 = 2100.0;
+// Frames this far below the loudest frame count as silence
+const SILENCE_DB: f64 = 40.0;
+// Correlation with the best key profile below which a track (drum tools,
+// noise) is taken to have no key
+const MIN_CORRELATION: f64 = 0.5;
+
+// Probe-tone ratings of how well each scale degree fits a key, tonic first
+const MAJOR_PROFILE: [f64; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f64; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    // No key tag to compare with
+    Detected,
+    MatchesTag,
+    // The tag names the relative key (Am for C); both mix the same
+    RelativeOfTag,
+    DiffersFromTag,
+    NoKey,
+}
+
+impl KeyStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Detected => "Detected",
+            Self::MatchesTag => "Matches Tag",
+            Self::RelativeOfTag => "Relative of Tag",
+            Self::DiffersFromTag => "Differs From Tag",
+            Self::NoKey => "No Key",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyReport {
+    pub path: PathBuf,
+    pub key: Option<MusicalKey>,
+    // Correlation with the key's profile in percent; higher is clearer
+    pub confidence: Option<f64>,
+    // Key tag, when it parses
+    pub tag_key: Option<MusicalKey>,
+}
+
+impl KeyReport {
+    pub fn status(&self) -> KeyStatus {
+        match (self.key, self.tag_key) {
+            (None, _) => KeyStatus::NoKey,
+            (Some(_), None) => KeyStatus::Detected,
+            (Some(key), Some(tag)) if key == tag => KeyStatus::MatchesTag,
+            (Some(key), Some(tag)) if key.minor != tag.minor && key.camelot_number() == tag.camelot_number() => {
+                KeyStatus::RelativeOfTag
+            }
+            (Some(_), Some(_)) => KeyStatus::DiffersFromTag,
+        }
+    }
+}
+
+pub struct KeyAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for KeyAnalyzer {}
+
+impl Default for KeyAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(5 * 60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn analyze(&self, files: &[&AudioFile]) -> Vec<KeyReport> {
+        println!("Detecting the key of {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.detect(&file.path);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result.map(|report| KeyReport {
+                tag_key: file.key.as_deref().and_then(MusicalKey::parse),
+                ..report
+            })
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error detecting key: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn detect(&self, path: &Path) -> Result<KeyReport> {
+        let audio = decode::decode(path, Some(self.max_duration))?;
+        let estimate = Self::estimate(&audio);
+        Ok(KeyReport {
+            path: path.to_path_buf(),
+            key: estimate.map(|(key, _)| key),
+            confidence: estimate.map(|(_, correlation)| (correlation * 1000.0).round() / 10.0),
+            tag_key: None,
+        })
+    }
+
+    // Best-fitting key and its profile correlation (-1..=1); None for
+    // silence and for tracks without a clear tonal centre
+    pub fn estimate(audio: &DecodedAudio) -> Option<(MusicalKey, f64)> {
+        let chroma = chromagram(audio)?;
+        (0..12u8)
+            .flat_map(|tonic| [(tonic, false), (tonic, true)])
+            .map(|(tonic, minor)| {
+                let profile = if minor { &MINOR_PROFILE } else { &MAJOR_PROFILE };
+                let rotated: Vec<f64> = (0..12).map(|pitch| profile[(pitch + 12 - tonic as usize) % 12]).collect();
+                (MusicalKey::new(tonic, minor), correlation(&chroma, &rotated))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, correlation)| *correlation >= MIN_CORRELATION)
+    }
+}
+
+// Energy per pitch class, C = 0 .. B = 11, over the audible frames
+fn chromagram(audio: &DecodedAudio) -> Option<[f64; 12]> {
+    if audio.samples.len() < FFT_SIZE || audio.sample_rate == 0 {
+        return None;
+    }
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let bin_hz = audio.sample_rate as f64 / FFT_SIZE as f64;
+    // Pitch class of every bin in range
+    let bins: Vec<(usize, usize)> = (1..FFT_SIZE / 2)
+        .filter(|&bin| (MIN_HZ..=MAX_HZ).contains(&(bin as f64 * bin_hz)))
+        .map(|bin| {
+            let midi = 69.0 + 12.0 * (bin as f64 * bin_hz / 440.0).log2();
+            (bin, (midi.round() as i64).rem_euclid(12) as usize)
+        })
+        .collect();
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+
+    let frames: Vec<([f64; 12], f64)> = (0..=audio.samples.len() - FFT_SIZE)
+        .step_by(HOP_SIZE)
+        .map(|offset| {
+            let frame = &audio.samples[offset..offset + FFT_SIZE];
+            for ((slot, &sample), &w) in buffer.iter_mut().zip(frame).zip(&window) {
+                *slot = Complex::new(sample * w, 0.0);
+            }
+            fft.process(&mut buffer);
+            let mut chroma = [0.0; 12];
+            for &(bin, pitch_class) in &bins {
+                chroma[pitch_class] += buffer[bin].norm() as f64;
+            }
+            let energy = chroma.iter().sum();
+            (chroma, energy)
+        })
+        .collect();
+
+    // Each audible frame weighs the same, so a loud drop doesn't outvote
+    // the rest of the track
+    let loudest = frames.iter().map(|(_, energy)| *energy).fold(0.0, f64::max);
+    if loudest <= 0.0 {
+        return None;
+    }
+    let floor = loudest * 10f64.powf(-SILENCE_DB / 20.0);
+    let mut total = [0.0; 12];
+    for (chroma, energy) in frames.iter().filter(|(_, energy)| *energy > floor) {
+        for (sum, value) in total.iter_mut().zip(chroma) {
+            *sum += value / energy;
+        }
+    }
+    Some(total)
+}
+
+// Pearson correlation of two equally long series
+fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let count = a.len() as f64;
+    let mean_a = a.iter().sum::<f64>() / count;
+    let mean_b = b.iter().sum::<f64>() / count;
+    let covariance: f64 = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let spread_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+    let spread_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+    if spread_a <= 0.0 || spread_b <= 0.0 {
+        return 0.0;
+    }
+    covariance / (spread_a * spread_b).sqrt()
+}
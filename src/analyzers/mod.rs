@@ -1,2 +1,37 @@
+pub mod approval;
+#[cfg(feature = "artwork")]
+pub mod artwork;
+#[cfg(feature = "decode")]
+pub mod authenticity;
 pub mod bitrate;
-pub mod duplicate;
\ No newline at end of file
+#[cfg(feature = "decode")]
+pub mod bpm;
+pub mod candidates;
+pub mod discs;
+pub mod duplicate;
+pub mod drift;
+#[cfg(feature = "decode")]
+pub mod explicit;
+#[cfg(feature = "decode")]
+pub mod fingerprint;
+pub mod ignore;
+#[cfg(feature = "decode")]
+pub mod key;
+#[cfg(feature = "decode")]
+pub mod phrasing;
+pub mod policy;
+pub mod pool;
+pub mod prefilter;
+pub mod relocate;
+pub mod roots;
+pub mod runs;
+pub mod storage;
+#[cfg(feature = "decode")]
+pub mod structure;
+#[cfg(feature = "decode")]
+pub mod tempo;
+pub mod title;
+#[cfg(feature = "decode")]
+pub mod vocals;
+#[cfg(feature = "reports-csv")]
+pub mod wishlist;
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::Serialize;
+use crate::{AudioFile, Result};
+use crate::analyzers::structure::StructureAnalyzer;
+use crate::analyzers::tempo::TempoAnalyzer;
+use crate::audio::decode::{self, DecodedAudio};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Phrase-length estimation. Dance music changes section every 16 or 32
+// bars, which is what lets a DJ line up one track's breakdown with the
+// next one's drop. The section boundaries found by structural segmentation
+// are counted in bars from where the music starts (BPM from the tag, else
+// measured); the longer phrase length most boundaries fall on is the
+// track's phrasing. Tracks whose boundaries mostly don't fall on a
+// 16-bar line are irregular and harder to mix.
+
+const PHRASE_LENGTHS: [u32; 3] = [32, 16, 8];
+// A boundary counts as on a phrase line within this many bars
+const BAR_TOLERANCE: f64 = 1.0;
+// Share of boundaries that must fall on a phrase length for it to count
+const MIN_PHRASE_SHARE: f64 = 0.75;
+// Fewer boundaries than this don't say anything about phrasing
+const MIN_BOUNDARIES: usize = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhraseAnalysis {
+    pub path: PathBuf,
+    pub bpm: Option<f64>,
+    // Longest phrase length in bars most section changes fall on
+    pub phrase_bars: Option<u32>,
+    // Percent of section changes on a 16-bar line; None when the track has
+    // too few sections (or no tempo) to tell
+    pub regularity: Option<f64>,
+    // Section changes in bars from the start of the music
+    pub boundaries_bars: Vec<f64>,
+}
+
+impl PhraseAnalysis {
+    // Follows 16/32-bar phrasing; None when it couldn't be judged
+    pub fn is_regular(&self) -> Option<bool> {
+        self.regularity.map(|_| self.phrase_bars.is_some_and(|bars| bars >= 16))
+    }
+}
+
+pub struct PhraseAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for PhraseAnalyzer {}
+
+impl Default for PhraseAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhraseAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(15 * 60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn analyze(&self, files: &[&AudioFile]) -> Vec<PhraseAnalysis> {
+        println!("Estimating phrasing of {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.estimate(file);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(analysis) => Some(analysis),
+                Err(e) => {
+                    eprintln!("Error analyzing file: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn estimate(&self, file: &AudioFile) -> Result<PhraseAnalysis> {
+        let audio = decode::decode(&file.path, Some(self.max_duration))?;
+        Ok(Self::phrasing(&audio, file.bpm))
+    }
+
+    pub fn phrasing(audio: &DecodedAudio, bpm: Option<f64>) -> PhraseAnalysis {
+        let bpm = bpm.filter(|bpm| *bpm > 0.0).or_else(|| TempoAnalyzer::stability(audio).bpm);
+        let mut analysis = PhraseAnalysis {
+            path: audio.path.clone(),
+            bpm,
+            phrase_bars: None,
+            regularity: None,
+            boundaries_bars: Vec::new(),
+        };
+        let Some(bpm) = bpm else { return analysis };
+        let starts = StructureAnalyzer::section_starts(audio);
+        let Some((&anchor, boundaries)) = starts.split_first() else { return analysis };
+
+        let bar_secs = 4.0 * 60.0 / bpm;
+        analysis.boundaries_bars = boundaries.iter()
+            .map(|secs| ((secs - anchor) / bar_secs * 10.0).round() / 10.0)
+            .collect();
+        if boundaries.len() < MIN_BOUNDARIES {
+            return analysis;
+        }
+
+        let share_on = |phrase: u32| {
+            let phrase = phrase as f64;
+            let on_line = analysis.boundaries_bars.iter()
+                .filter(|&&bars| (bars - (bars / phrase).round() * phrase).abs() <= BAR_TOLERANCE)
+                .count();
+            on_line as f64 / analysis.boundaries_bars.len() as f64
+        };
+        analysis.phrase_bars = PHRASE_LENGTHS.into_iter().find(|&phrase| share_on(phrase) >= MIN_PHRASE_SHARE);
+        analysis.regularity = Some((share_on(16) * 1000.0).round() / 10.0);
+        analysis
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::bitrate::{BitrateCategory, LOW_QUALITY_KBPS};
+
+// Minimum quality per folder or genre instead of one threshold for the
+// whole library: lossless for the classics, 320 for tools, whatever came
+// for the rest. Read from JSON:
+//
+//   {
+//     "default": 256,
+//     "rules": [
+//       { "folder": "/Music/Classics", "minimum": "lossless" },
+//       { "genre": "Tools", "minimum": 320 }
+//     ]
+//   }
+//
+// The first matching rule applies, so list specific folders before broad
+// ones.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Minimum {
+    Kbps(u32),
+    Level(QualityLevel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityLevel {
+    // Lossless bitrates (700+ kbps); catches lossy files only, not lossy
+    // files converted to WAV (see `authenticity` for those)
+    Lossless,
+    // 1500+ kbps
+    HiRes,
+}
+
+impl Minimum {
+    pub fn accepts(&self, bitrate: u32) -> bool {
+        match self {
+            Minimum::Kbps(kbps) => bitrate >= *kbps,
+            Minimum::Level(QualityLevel::Lossless) => matches!(
+                BitrateCategory::from_bitrate(bitrate), BitrateCategory::Lossless | BitrateCategory::HighRes
+            ),
+            Minimum::Level(QualityLevel::HiRes) => BitrateCategory::from_bitrate(bitrate) == BitrateCategory::HighRes,
+        }
+    }
+}
+
+impl fmt::Display for Minimum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Minimum::Kbps(kbps) => write!(f, "{} kbps", kbps),
+            Minimum::Level(QualityLevel::Lossless) => write!(f, "lossless"),
+            Minimum::Level(QualityLevel::HiRes) => write!(f, "hi-res"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    // Files under this folder
+    #[serde(default)]
+    pub folder: Option<PathBuf>,
+    // Files with this genre tag (case-insensitive)
+    #[serde(default)]
+    pub genre: Option<String>,
+    pub minimum: Minimum,
+}
+
+impl PolicyRule {
+    fn matches(&self, path: &Path, genre: Option<&str>) -> bool {
+        let folder_matches = self.folder.as_ref().is_none_or(|folder| path.starts_with(folder));
+        let genre_matches = self.genre.as_ref().is_none_or(|wanted| {
+            genre.is_some_and(|genre| genre.trim().eq_ignore_ascii_case(wanted.trim()))
+        });
+        folder_matches && genre_matches
+    }
+
+    pub fn describe(&self) -> String {
+        match (&self.folder, &self.genre) {
+            (Some(folder), Some(genre)) => format!("{} in {}", genre, folder.display()),
+            (Some(folder), None) => folder.display().to_string(),
+            (None, Some(genre)) => format!("genre {}", genre),
+            (None, None) => "all files".to_string(),
+        }
+    }
+}
+
+fn default_minimum() -> Minimum {
+    Minimum::Kbps(LOW_QUALITY_KBPS)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityPolicy {
+    #[serde(default = "default_minimum")]
+    pub default: Minimum,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+impl Default for QualityPolicy {
+    fn default() -> Self {
+        Self {
+            default: default_minimum(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyViolation {
+    pub path: PathBuf,
+    pub bitrate: u32,
+    pub minimum: Minimum,
+    // The rule that set the minimum; "default" when none matched
+    pub rule: String,
+}
+
+impl QualityPolicy {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid quality policy {}: {}", path.display(), e)))
+    }
+
+    // Reading genres means opening every file's tags, so callers can skip it
+    // when no rule needs them
+    pub fn uses_genres(&self) -> bool {
+        self.rules.iter().any(|rule| rule.genre.is_some())
+    }
+
+    // The minimum for a file and the rule it comes from
+    pub fn minimum_for(&self, path: &Path, genre: Option<&str>) -> (Minimum, Option<&PolicyRule>) {
+        match self.rules.iter().find(|rule| rule.matches(path, genre)) {
+            Some(rule) => (rule.minimum, Some(rule)),
+            None => (self.default, None),
+        }
+    }
+
+    // Files below their minimum, lowest bitrate first. Files without a known
+    // bitrate can't be judged and are left out.
+    pub fn evaluate(&self, files: &[AudioFile], genre_of: impl Fn(&AudioFile) -> Option<String>) -> Vec<PolicyViolation> {
+        let mut violations: Vec<PolicyViolation> = files.iter()
+            .filter_map(|file| {
+                let bitrate = file.bitrate?;
+                let genre = if self.uses_genres() { genre_of(file) } else { None };
+                let (minimum, rule) = self.minimum_for(&file.path, genre.as_deref());
+                (!minimum.accepts(bitrate)).then(|| PolicyViolation {
+                    path: file.path.clone(),
+                    bitrate,
+                    minimum,
+                    rule: rule.map_or_else(|| "default".to_string(), PolicyRule::describe),
+                })
+            })
+            .collect();
+        violations.sort_by(|a, b| a.bitrate.cmp(&b.bitrate).then_with(|| a.path.cmp(&b.path)));
+        violations
+    }
+}
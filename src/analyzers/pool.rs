@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::AudioFile;
+use crate::analyzers::title::{LyricVersion, PoolMarkers, TitleParser};
+
+// Promo pool versions of the same track, told apart by the clean/dirty and
+// intro markers in their file names. For gigs that require clean lyrics, the
+// tracks to worry about are the ones where only a dirty version was
+// downloaded.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolVersion {
+    pub path: PathBuf,
+    pub version: Option<String>,
+    pub markers: PoolMarkers,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirtyOnlyTrack {
+    // As parsed from the file names (lowercase)
+    pub artist: String,
+    pub title: String,
+    // Every dirty version in the library, by path
+    pub versions: Vec<PoolVersion>,
+}
+
+// Tracks with at least one dirty version and no clean one. Versions without
+// a clean/dirty marker don't count either way.
+pub fn find_dirty_only(files: &[AudioFile], parser: &TitleParser) -> Vec<DirtyOnlyTrack> {
+    let mut tracks: BTreeMap<(String, String), Vec<PoolVersion>> = BTreeMap::new();
+    for file in files {
+        let parsed = parser.parse_file(file);
+        let markers = parsed.pool_markers();
+        if markers.lyrics.is_none() {
+            continue;
+        }
+        tracks.entry((parsed.artist, parsed.title)).or_default().push(PoolVersion {
+            path: file.path.clone(),
+            version: parsed.version,
+            markers,
+        });
+    }
+
+    tracks.into_iter()
+        .filter(|(_, versions)| versions.iter().all(|v| v.markers.lyrics == Some(LyricVersion::Dirty)))
+        .map(|((artist, title), mut versions)| {
+            versions.sort_by(|a, b| a.path.cmp(&b.path));
+            DirtyOnlyTrack { artist, title, versions }
+        })
+        .collect()
+}
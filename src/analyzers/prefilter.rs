@@ -0,0 +1,96 @@
+use crate::AudioFile;
+
+// Cheap check run before the title comparison: two files can only hold the
+// same recording if their durations agree and their sizes are in proportion
+// to their bitrates. Anything unknown is let through rather than guessed.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Prefilter {
+    // Maximum difference in duration, in seconds
+    pub duration_tolerance_secs: f64,
+    // Allowed deviation of the size ratio from the bitrate ratio (0.5 = ±50%)
+    pub size_ratio_tolerance: f64,
+}
+
+impl Default for Prefilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Prefilter {
+    pub fn new() -> Self {
+        Self {
+            duration_tolerance_secs: 2.0,
+            size_ratio_tolerance: 0.5,
+        }
+    }
+
+    pub fn with_duration_tolerance(mut self, secs: f64) -> Self {
+        self.duration_tolerance_secs = secs;
+        self
+    }
+
+    pub fn accepts(&self, file1: &AudioFile, file2: &AudioFile) -> bool {
+        self.durations_match(file1, file2) && self.sizes_plausible(file1, file2)
+    }
+
+    pub fn durations_match(&self, file1: &AudioFile, file2: &AudioFile) -> bool {
+        match (file1.duration_secs, file2.duration_secs) {
+            (Some(d1), Some(d2)) => (d1 - d2).abs() <= self.duration_tolerance_secs,
+            _ => true,
+        }
+    }
+
+    // Same content at bitrates b1 and b2 takes roughly b1/b2 times the space
+    // (tags and artwork aside, hence the generous tolerance)
+    fn sizes_plausible(&self, file1: &AudioFile, file2: &AudioFile) -> bool {
+        let (Some(b1), Some(b2)) = (file1.bitrate, file2.bitrate) else { return true };
+        let (size1, size2) = (file1.audio_size(), file2.audio_size());
+        if b1 == 0 || b2 == 0 || size1 == 0 || size2 == 0 {
+            return true;
+        }
+        let expected = b1 as f64 / b2 as f64;
+        let actual = size1 as f64 / size2 as f64;
+        let deviation = actual / expected;
+        let lowest = (1.0 - self.size_ratio_tolerance).max(f64::EPSILON);
+        deviation >= lowest && deviation <= 1.0 / lowest
+    }
+
+    // Candidate pairs (as indices into `files`) that pass the filter. Files
+    // are sorted by duration so only neighbours within the tolerance are
+    // visited instead of every pair.
+    pub fn candidate_pairs(&self, files: &[impl AsRef<AudioFile>]) -> Vec<(usize, usize)> {
+        let mut timed: Vec<(usize, f64)> = Vec::new();
+        let mut untimed: Vec<usize> = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            match file.as_ref().duration_secs {
+                Some(duration) => timed.push((i, duration)),
+                None => untimed.push(i),
+            }
+        }
+        timed.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut pairs = Vec::new();
+        for (n, &(i, duration)) in timed.iter().enumerate() {
+            for &(j, other) in &timed[n + 1..] {
+                if other - duration > self.duration_tolerance_secs {
+                    break;
+                }
+                pairs.push((i.min(j), i.max(j)));
+            }
+        }
+        // Files without a duration are compared with everything
+        for &i in &untimed {
+            for (j, other) in files.iter().enumerate() {
+                if j != i && (other.as_ref().duration_secs.is_some() || j > i) {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+
+        pairs.retain(|&(i, j)| self.sizes_plausible(files[i].as_ref(), files[j].as_ref()));
+        pairs.sort_unstable();
+        pairs
+    }
+}
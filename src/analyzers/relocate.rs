@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::exporters::CollectionTrack;
+
+// Finds where tracks referenced by DJ software have moved to. Matching only
+// relies on file names and sizes so it works without decoding anything.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMethod {
+    NameAndSize,
+    Name,
+    Size,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Relocation {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    pub method: MatchMethod,
+}
+
+pub struct Relocator {
+    by_name: HashMap<String, Vec<(PathBuf, u64)>>,
+    by_size: HashMap<u64, Vec<PathBuf>>,
+}
+
+impl Relocator {
+    // `candidates` are the files currently in the library with their sizes
+    pub fn new(candidates: impl IntoIterator<Item = (PathBuf, u64)>) -> Self {
+        let mut by_name: HashMap<String, Vec<(PathBuf, u64)>> = HashMap::new();
+        let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        for (path, size) in candidates {
+            if let Some(name) = name_key(&path) {
+                by_name.entry(name).or_default().push((path.clone(), size));
+            }
+            by_size.entry(size).or_default().push(path);
+        }
+        Self { by_name, by_size }
+    }
+
+    // Ambiguous results (several equally good candidates) are not guessed
+    pub fn locate(&self, track: &CollectionTrack) -> Option<Relocation> {
+        let relocation = |new_path: &Path, method| Relocation {
+            old_path: track.location.clone(),
+            new_path: new_path.to_path_buf(),
+            method,
+        };
+
+        let same_name = name_key(&track.location)
+            .and_then(|name| self.by_name.get(&name))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        if let Some(size) = track.size_bytes {
+            let exact: Vec<&PathBuf> = same_name.iter()
+                .filter(|(_, candidate_size)| *candidate_size == size)
+                .map(|(path, _)| path)
+                .collect();
+            if let [path] = exact.as_slice() {
+                return Some(relocation(path, MatchMethod::NameAndSize));
+            }
+            if exact.len() > 1 {
+                return None;
+            }
+        }
+
+        if let [(path, _)] = same_name {
+            return Some(relocation(path, MatchMethod::Name));
+        }
+        if !same_name.is_empty() {
+            return None;
+        }
+
+        // Renamed files: fall back to a unique size match with the same extension
+        let size = track.size_bytes?;
+        let extension = extension_key(&track.location);
+        let same_size: Vec<&PathBuf> = self.by_size.get(&size)?
+            .iter()
+            .filter(|path| extension_key(path) == extension)
+            .collect();
+        match same_size.as_slice() {
+            [path] => Some(relocation(path, MatchMethod::Size)),
+            _ => None,
+        }
+    }
+}
+
+fn name_key(path: &Path) -> Option<String> {
+    path.file_name().map(|name| name.to_string_lossy().to_lowercase())
+}
+
+fn extension_key(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase())
+}
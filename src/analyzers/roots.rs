@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use crate::analyzers::duplicate::DuplicateMatch;
+
+// Library roots in order of preference, e.g. the SSD working set first, then
+// the archive drive, then the NAS. Files outside every root rank last.
+#[derive(Debug, Clone, Default)]
+pub struct RootPriority {
+    roots: Vec<PathBuf>,
+}
+
+impl RootPriority {
+    // Highest priority first
+    pub fn new(roots: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        let roots = roots.into_iter()
+            .map(|root| {
+                let root = root.into();
+                std::fs::canonicalize(&root).unwrap_or(root)
+            })
+            .collect();
+        Self { roots }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    // 0 for the first root; roots.len() for paths outside every root. Nested
+    // roots resolve to the most specific one.
+    pub fn rank(&self, path: &Path) -> usize {
+        self.root_of(path)
+            .and_then(|root| self.roots.iter().position(|r| r == root))
+            .unwrap_or(self.roots.len())
+    }
+
+    pub fn root_of(&self, path: &Path) -> Option<&Path> {
+        self.roots.iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+            .map(PathBuf::as_path)
+    }
+
+    // True if `a` sits on a more preferred root than `b`
+    pub fn prefers(&self, a: &Path, b: &Path) -> bool {
+        self.rank(a) < self.rank(b)
+    }
+
+    // When the kept copy lives on a less preferred root than the one being
+    // removed, the place it should move to: the removed copy's folder, under
+    // the kept copy's own file name
+    pub fn relocation_target(&self, dup_match: &DuplicateMatch) -> Option<PathBuf> {
+        let keep = &dup_match.higher_quality.path;
+        let remove = &dup_match.lower_quality.path;
+        if !self.prefers(remove, keep) {
+            return None;
+        }
+        Some(remove.with_file_name(keep.file_name()?))
+    }
+}
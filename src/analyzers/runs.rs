@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::analyzers::duplicate::DuplicateResults;
+use crate::utils::{paths, unix_timestamp_millis};
+
+// Record of one duplicate analysis, kept so later runs can be compared
+// against it. Stored as `<run_id>.json` in the runs directory.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateRun {
+    pub run_id: String,
+    pub started_at: u64,
+    pub inputs: Vec<PathBuf>,
+    pub total_files_scanned: usize,
+    pub matches: Vec<RunMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMatch {
+    pub higher_quality: PathBuf,
+    pub lower_quality: PathBuf,
+    pub match_reason: String,
+}
+
+impl RunMatch {
+    // A pair is the same duplicate whichever side is currently the keeper
+    fn key(&self) -> (&Path, &Path) {
+        let (a, b) = (self.higher_quality.as_path(), self.lower_quality.as_path());
+        if a <= b { (a, b) } else { (b, a) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunDiff<'a> {
+    pub old_run: &'a str,
+    pub new_run: &'a str,
+    // Duplicates found by the new run only
+    pub new_matches: Vec<&'a RunMatch>,
+    // Duplicates the new run no longer finds
+    pub resolved: Vec<&'a RunMatch>,
+    pub unchanged: usize,
+}
+
+impl DuplicateRun {
+    // Assigns the run ID; call before scanning so it can be shown up front
+    pub fn start(inputs: &[PathBuf]) -> Self {
+        let started_at = unix_timestamp_millis();
+        Self {
+            run_id: format!("run-{}", started_at),
+            started_at,
+            inputs: inputs.to_vec(),
+            total_files_scanned: 0,
+            matches: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, results: &DuplicateResults) {
+        self.total_files_scanned = results.total_files_scanned;
+        self.matches = results.matches()
+            .map(|m| RunMatch {
+                higher_quality: m.higher_quality.path.clone(),
+                lower_quality: m.lower_quality.path.clone(),
+                match_reason: m.match_reason.clone(),
+            })
+            .collect();
+    }
+
+    // Writes the record into the runs directory and returns its path
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = paths::runs_dir();
+        fs::create_dir_all(&dir).map_err(|e| AudioError::io(&dir, e))?;
+        let path = dir.join(format!("{}.json", self.run_id));
+        let json = serde_json::to_string_pretty(self).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        fs::write(&path, json).map_err(|e| AudioError::io(&path, e))?;
+        Ok(path)
+    }
+
+    // Accepts either a path to a run record or a run ID from the runs directory
+    pub fn load(run: &str) -> Result<Self> {
+        let direct = PathBuf::from(run);
+        let path = if direct.is_file() {
+            direct
+        } else {
+            paths::runs_dir().join(format!("{}.json", run))
+        };
+        let json = fs::read_to_string(&path).map_err(|e| AudioError::io(&path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid run record {}: {}", path.display(), e)))
+    }
+
+    // Run IDs of every stored run, oldest first
+    pub fn list() -> Result<Vec<String>> {
+        let dir = paths::runs_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut runs: Vec<(u64, String)> = fs::read_dir(&dir)
+            .map_err(|e| AudioError::io(&dir, e))?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let run_id = name.strip_suffix(".json")?;
+                let started_at = run_id.strip_prefix("run-")?.parse().ok()?;
+                Some((started_at, run_id.to_string()))
+            })
+            .collect();
+        runs.sort();
+        Ok(runs.into_iter().map(|(_, run_id)| run_id).collect())
+    }
+
+    pub fn diff<'a>(&'a self, newer: &'a DuplicateRun) -> RunDiff<'a> {
+        let old_keys: HashSet<(&Path, &Path)> = self.matches.iter().map(RunMatch::key).collect();
+        let new_keys: HashSet<(&Path, &Path)> = newer.matches.iter().map(RunMatch::key).collect();
+
+        RunDiff {
+            old_run: &self.run_id,
+            new_run: &newer.run_id,
+            new_matches: newer.matches.iter().filter(|m| !old_keys.contains(&m.key())).collect(),
+            resolved: self.matches.iter().filter(|m| !new_keys.contains(&m.key())).collect(),
+            unchanged: newer.matches.iter().filter(|m| old_keys.contains(&m.key())).count(),
+        }
+    }
+}
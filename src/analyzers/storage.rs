@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::utils::{paths, unix_timestamp_millis};
+
+// Storage planning: what the library takes per format, how fast it has been
+// growing, what that means for the next year, and how much converting WAV to
+// FLAC would give back. Growth comes from earlier `storage` runs once they
+// span long enough, otherwise from file modification dates.
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+const MONTH_MS: f64 = 30.44 * DAY_MS as f64;
+// History shorter than this says more about one shopping spree than a trend
+const MIN_HISTORY_DAYS: u64 = 30;
+// Typical FLAC size relative to PCM, used when the library has no FLAC files
+// to measure
+const DEFAULT_FLAC_RATIO: f64 = 0.6;
+// 16-bit/44.1 kHz stereo
+const CD_BITRATE_KBPS: f64 = 1411.2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSnapshot {
+    pub taken_at: u64,
+    pub inputs: Vec<PathBuf>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatUsage {
+    pub format: String,
+    pub files: usize,
+    pub bytes: u64,
+    pub average_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrowthSource {
+    // Earlier `storage` runs over the same inputs
+    History,
+    // Files modified during the last year
+    FileDates,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Projection {
+    pub months: u32,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageForecast {
+    pub files: usize,
+    pub bytes: u64,
+    pub formats: Vec<FormatUsage>,
+    pub growth_source: GrowthSource,
+    pub bytes_per_month: f64,
+    pub files_per_month: f64,
+    pub projections: Vec<Projection>,
+    pub wav_files: usize,
+    pub wav_bytes: u64,
+    // Estimated size of the WAV files as FLAC
+    pub flac_estimate_bytes: u64,
+    // FLAC size relative to the audio it encodes, measured on the library
+    // when possible
+    pub flac_ratio: f64,
+}
+
+impl StorageForecast {
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.wav_bytes.saturating_sub(self.flac_estimate_bytes)
+    }
+}
+
+pub struct StoragePlanner {
+    history: PathBuf,
+    horizons: Vec<u32>,
+}
+
+impl Default for StoragePlanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StoragePlanner {
+    pub fn new() -> Self {
+        Self {
+            history: paths::data_dir().join("storage-history.jsonl"),
+            horizons: vec![3, 6, 12],
+        }
+    }
+
+    pub fn with_history(mut self, history: impl Into<PathBuf>) -> Self {
+        self.history = history.into();
+        self
+    }
+
+    pub fn history_path(&self) -> &Path {
+        &self.history
+    }
+
+    // Earlier snapshots of exactly these inputs, oldest first. A missing
+    // history file is an empty history; unreadable lines are skipped.
+    pub fn load_history(&self, inputs: &[PathBuf]) -> Result<Vec<StorageSnapshot>> {
+        let file = match fs::File::open(&self.history) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(AudioError::io(&self.history, e)),
+        };
+        let mut snapshots: Vec<StorageSnapshot> = BufReader::new(file).lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str::<StorageSnapshot>(&line).ok())
+            .filter(|snapshot| snapshot.inputs == inputs)
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        Ok(snapshots)
+    }
+
+    pub fn record(&self, snapshot: &StorageSnapshot) -> Result<()> {
+        if let Some(dir) = self.history.parent() {
+            fs::create_dir_all(dir).map_err(|e| AudioError::io(dir, e))?;
+        }
+        let line = serde_json::to_string(snapshot).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.history)
+            .map_err(|e| AudioError::io(&self.history, e))?;
+        writeln!(file, "{}", line).map_err(|e| AudioError::io(&self.history, e))
+    }
+
+    pub fn snapshot(inputs: &[PathBuf], files: &[AudioFile]) -> StorageSnapshot {
+        StorageSnapshot {
+            taken_at: unix_timestamp_millis(),
+            inputs: inputs.to_vec(),
+            files: files.len(),
+            bytes: files.iter().map(|file| file.size_bytes).sum(),
+        }
+    }
+
+    pub fn forecast(&self, files: &[AudioFile], history: &[StorageSnapshot]) -> StorageForecast {
+        let bytes: u64 = files.iter().map(|file| file.size_bytes).sum();
+        let now = unix_timestamp_millis();
+
+        let (growth_source, bytes_per_month, files_per_month) = match history_growth(history, now, files.len(), bytes) {
+            Some((bytes_per_month, files_per_month)) => (GrowthSource::History, bytes_per_month, files_per_month),
+            None => {
+                let (bytes_per_month, files_per_month) = file_date_growth(files);
+                (GrowthSource::FileDates, bytes_per_month, files_per_month)
+            }
+        };
+        let projections = self.horizons.iter()
+            .map(|&months| Projection {
+                months,
+                bytes: bytes + (bytes_per_month.max(0.0) * months as f64) as u64,
+            })
+            .collect();
+
+        let wavs: Vec<&AudioFile> = files.iter().filter(|file| extension(&file.path) == "wav").collect();
+        let flac_ratio = measured_flac_ratio(files).unwrap_or(DEFAULT_FLAC_RATIO);
+        let flac_estimate_bytes = wavs.iter()
+            .map(|file| (file.audio_size() as f64 * flac_ratio) as u64 + file.size_bytes.saturating_sub(file.audio_size()))
+            .sum();
+
+        StorageForecast {
+            files: files.len(),
+            bytes,
+            formats: format_usage(files),
+            growth_source,
+            bytes_per_month,
+            files_per_month,
+            projections,
+            wav_files: wavs.len(),
+            wav_bytes: wavs.iter().map(|file| file.size_bytes).sum(),
+            flac_estimate_bytes,
+            flac_ratio,
+        }
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// Largest formats first
+fn format_usage(files: &[AudioFile]) -> Vec<FormatUsage> {
+    let mut formats: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for file in files {
+        let entry = formats.entry(extension(&file.path)).or_default();
+        entry.0 += 1;
+        entry.1 += file.size_bytes;
+    }
+    let mut usage: Vec<FormatUsage> = formats.into_iter()
+        .map(|(format, (files, bytes))| FormatUsage {
+            format,
+            files,
+            bytes,
+            average_bytes: bytes / files as u64,
+        })
+        .collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.format.cmp(&b.format)));
+    usage
+}
+
+// Monthly growth from the oldest snapshot to now, once the history spans
+// long enough
+fn history_growth(history: &[StorageSnapshot], now: u64, files: usize, bytes: u64) -> Option<(f64, f64)> {
+    let oldest = history.first()?;
+    let span = now.saturating_sub(oldest.taken_at);
+    if span < MIN_HISTORY_DAYS * DAY_MS {
+        return None;
+    }
+    let months = span as f64 / MONTH_MS;
+    Some((
+        (bytes as f64 - oldest.bytes as f64) / months,
+        (files as f64 - oldest.files as f64) / months,
+    ))
+}
+
+// Monthly average of what was added during the last year, taking each file's
+// modification time as the date it was added. Retagging touches that date
+// too, so this overestimates after a big tagging session.
+fn file_date_growth(files: &[AudioFile]) -> (f64, f64) {
+    let year_ago = SystemTime::now() - Duration::from_millis(365 * DAY_MS);
+    let recent: Vec<&AudioFile> = files.iter()
+        .filter(|file| {
+            fs::metadata(&file.path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= year_ago)
+        })
+        .collect();
+    let bytes: u64 = recent.iter().map(|file| file.size_bytes).sum();
+    (bytes as f64 / 12.0, recent.len() as f64 / 12.0)
+}
+
+// Average FLAC bitrate against CD-quality PCM, from the library's own FLAC
+// files. Hi-res files are left out: their ratio against 1411 kbps would be
+// meaningless.
+fn measured_flac_ratio(files: &[AudioFile]) -> Option<f64> {
+    let bitrates: Vec<f64> = files.iter()
+        .filter(|file| extension(&file.path) == "flac")
+        .filter_map(|file| file.bitrate)
+        .map(f64::from)
+        .filter(|&bitrate| bitrate > 0.0 && bitrate <= CD_BITRATE_KBPS)
+        .collect();
+    if bitrates.is_empty() {
+        return None;
+    }
+    Some(bitrates.iter().sum::<f64>() / bitrates.len() as f64 / CD_BITRATE_KBPS)
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+use rustfft::{num_complex::Complex, FftPlanner};
+use crate::{AudioFile, Result};
+use crate::audio::decode::{self, DecodedAudio};
+use crate::exporters::cues::{CueKind, CuePoint, TrackCues};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Structural segmentation for hot cue suggestions. The track is cut into
+// half-second blocks described by their loudness overall and in three bands
+// (bass, mids, highs). A novelty curve compares the blocks before and after
+// every point; its peaks are section boundaries. Sections are then judged by
+// loudness: the first step up into one of the loudest sections is the drop,
+// the first clear step down after it the breakdown, the next step back up
+// the second drop. A quiet last section is the outro.
+
+const FFT_SIZE: usize = 2048;
+const BLOCK_SECS: f64 = 0.5;
+// Novelty compares this much audio on either side of a point
+const NOVELTY_SPAN_SECS: f64 = 8.0;
+const MIN_SECTION_SECS: f64 = 8.0;
+// Sections within this many dB of the loudest one count as full sections;
+// a breakdown must be at least this much quieter than what came before
+const LOUD_SECTION_DB: f64 = 3.0;
+// The intro starts at the first block within this many dB of the loudest
+const SILENCE_DB: f64 = 30.0;
+const BASS_HZ: f64 = 150.0;
+const MID_HZ: f64 = 2500.0;
+
+// Level overall, then bass, mids and highs, all in dB
+type Features = [f64; 4];
+
+struct Section {
+    start: usize,
+    level: f64,
+}
+
+pub struct StructureAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for StructureAnalyzer {}
+
+impl Default for StructureAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StructureAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(15 * 60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<TrackCues> {
+        println!("Analyzing the structure of {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.suggest(file);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(cues) => Some(cues),
+                Err(e) => {
+                    eprintln!("Error analyzing file: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Decodes the file and suggests cues, snapped to bars when it has a BPM tag
+    pub fn suggest(&self, file: &AudioFile) -> Result<TrackCues> {
+        let audio = decode::decode(&file.path, Some(self.max_duration))?;
+        Ok(TrackCues {
+            path: file.path.clone(),
+            artist: file.artist.clone(),
+            title: file.title.clone(),
+            duration_secs: audio.duration_secs(),
+            bpm: file.bpm,
+            cues: Self::segment(&audio, file.bpm),
+        })
+    }
+
+    // Where the music starts, then every section boundary, in seconds
+    pub fn section_starts(audio: &DecodedAudio) -> Vec<f64> {
+        let Some((blocks, block_secs)) = block_features(audio) else { return Vec::new() };
+        let peak = blocks.iter().map(|b| b[0]).fold(f64::MIN, f64::max);
+        let Some(intro) = blocks.iter().position(|b| b[0] >= peak - SILENCE_DB) else { return Vec::new() };
+        std::iter::once(intro)
+            .chain(novelty_peaks(&blocks, block_secs).into_iter().filter(|&b| b > intro))
+            .map(|block| block as f64 * block_secs)
+            .collect()
+    }
+
+    pub fn segment(audio: &DecodedAudio, bpm: Option<f64>) -> Vec<CuePoint> {
+        let Some((blocks, block_secs)) = block_features(audio) else { return Vec::new() };
+        let peak = blocks.iter().map(|b| b[0]).fold(f64::MIN, f64::max);
+        let Some(intro) = blocks.iter().position(|b| b[0] >= peak - SILENCE_DB) else { return Vec::new() };
+
+        let boundaries = novelty_peaks(&blocks, block_secs);
+        let starts: Vec<usize> = std::iter::once(intro)
+            .chain(boundaries.into_iter().filter(|&b| b > intro))
+            .collect();
+        let sections: Vec<Section> = starts.iter().enumerate().map(|(index, &start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(blocks.len());
+            let level = blocks[start..end].iter().map(|b| b[0]).sum::<f64>() / (end - start) as f64;
+            Section { start, level }
+        }).collect();
+
+        let loudest = sections.iter().map(|s| s.level).fold(f64::MIN, f64::max);
+        let loud = |index: usize| sections[index].level >= loudest - LOUD_SECTION_DB;
+        let rise_after = |from: usize| (from..sections.len()).find(|&i| i > 0 && loud(i) && !loud(i - 1));
+
+        let mut found = vec![(CueKind::Intro, 0)];
+        if let Some(drop) = rise_after(1) {
+            found.push((CueKind::Drop, drop));
+            let breakdown = (drop + 1..sections.len())
+                .find(|&i| !loud(i) && sections[i].level <= sections[i - 1].level - LOUD_SECTION_DB);
+            if let Some(breakdown) = breakdown {
+                found.push((CueKind::Breakdown, breakdown));
+                if let Some(second) = rise_after(breakdown + 1) {
+                    found.push((CueKind::SecondDrop, second));
+                }
+            }
+        }
+        let last = sections.len() - 1;
+        let after_cues = found.last().is_some_and(|&(_, index)| index < last);
+        if found.len() < 4 && after_cues && !loud(last) {
+            found.push((CueKind::Outro, last));
+        }
+
+        let intro_secs = intro as f64 * block_secs;
+        found.into_iter()
+            .map(|(kind, index)| {
+                let secs = sections[index].start as f64 * block_secs;
+                CuePoint { kind, position_secs: snap_to_bar(secs, intro_secs, bpm) }
+            })
+            .collect()
+    }
+}
+
+// Features per block and the block length in seconds
+fn block_features(audio: &DecodedAudio) -> Option<(Vec<Features>, f64)> {
+    if audio.samples.len() < FFT_SIZE || audio.sample_rate == 0 {
+        return None;
+    }
+    let sample_rate = audio.sample_rate as f64;
+    let frames_per_block = (BLOCK_SECS * sample_rate / FFT_SIZE as f64).round().max(1.0) as usize;
+    let block_secs = (frames_per_block * FFT_SIZE) as f64 / sample_rate;
+    let bin_hz = sample_rate / FFT_SIZE as f64;
+    let bass_bin = (BASS_HZ / bin_hz).ceil() as usize;
+    let mid_bin = (MID_HZ / bin_hz).ceil() as usize;
+
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+
+    let to_db = |power: f64| 10.0 * (power + 1e-12).log10();
+    let blocks = audio.samples
+        .chunks_exact(FFT_SIZE)
+        .collect::<Vec<_>>()
+        .chunks(frames_per_block)
+        .map(|frames| {
+            let mut bands = [0.0f64; 3];
+            for frame in frames {
+                for ((slot, &sample), &w) in buffer.iter_mut().zip(*frame).zip(&window) {
+                    *slot = Complex::new(sample * w, 0.0);
+                }
+                fft.process(&mut buffer);
+                for (bin, value) in buffer[1..FFT_SIZE / 2].iter().enumerate() {
+                    let band = match bin + 1 {
+                        b if b < bass_bin => 0,
+                        b if b < mid_bin => 1,
+                        _ => 2,
+                    };
+                    bands[band] += value.norm_sqr() as f64;
+                }
+            }
+            let count = frames.len() as f64;
+            let total = bands.iter().sum::<f64>() / count;
+            [to_db(total), to_db(bands[0] / count), to_db(bands[1] / count), to_db(bands[2] / count)]
+        })
+        .collect();
+    Some((blocks, block_secs))
+}
+
+// Block indices where the music changes most, at least MIN_SECTION_SECS apart
+fn novelty_peaks(blocks: &[Features], block_secs: f64) -> Vec<usize> {
+    let span = (NOVELTY_SPAN_SECS / block_secs).round().max(1.0) as usize;
+    let min_gap = (MIN_SECTION_SECS / block_secs).round().max(1.0) as usize;
+    if blocks.len() < span * 2 + 1 {
+        return Vec::new();
+    }
+
+    // Prefix sums so each window mean is O(1)
+    let mut prefix = vec![[0.0f64; 4]; blocks.len() + 1];
+    for (index, block) in blocks.iter().enumerate() {
+        for feature in 0..4 {
+            prefix[index + 1][feature] = prefix[index][feature] + block[feature];
+        }
+    }
+    let mean = |from: usize, to: usize, feature: usize| (prefix[to][feature] - prefix[from][feature]) / (to - from) as f64;
+
+    let novelty: Vec<(usize, f64)> = (span..=blocks.len() - span)
+        .map(|index| {
+            let distance = (0..4)
+                .map(|f| (mean(index - span, index, f) - mean(index, index + span, f)).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            (index, distance)
+        })
+        .collect();
+
+    let average = novelty.iter().map(|n| n.1).sum::<f64>() / novelty.len() as f64;
+    let deviation = (novelty.iter().map(|n| (n.1 - average).powi(2)).sum::<f64>() / novelty.len() as f64).sqrt();
+    let threshold = average + deviation * 0.5;
+
+    let mut candidates: Vec<(usize, f64)> = novelty.iter()
+        .enumerate()
+        .filter(|&(position, &(_, value))| {
+            value > threshold
+                && novelty[position.saturating_sub(1)].1 <= value
+                && novelty.get(position + 1).is_none_or(|next| next.1 <= value)
+        })
+        .map(|(_, &peak)| peak)
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut peaks: Vec<usize> = Vec::new();
+    for (index, _) in candidates {
+        if peaks.iter().all(|&p| p.abs_diff(index) >= min_gap) {
+            peaks.push(index);
+        }
+    }
+    peaks.sort_unstable();
+    peaks
+}
+
+// Nearest bar line, counting bars from the intro
+fn snap_to_bar(secs: f64, anchor_secs: f64, bpm: Option<f64>) -> f64 {
+    let Some(bpm) = bpm.filter(|bpm| *bpm > 0.0) else { return secs };
+    let bar_secs = 4.0 * 60.0 / bpm;
+    let snapped = anchor_secs + ((secs - anchor_secs) / bar_secs).round() * bar_secs;
+    (snapped * 1000.0).round() / 1000.0
+}
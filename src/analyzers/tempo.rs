@@ -0,0 +1,302 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::Serialize;
+use crate::{AudioFile, Result};
+use crate::audio::decode::{self, DecodedAudio};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Tempo stability: how well a single static beatgrid fits a track. An onset
+// envelope gives the overall tempo by autocorrelation; then every window of
+// a few seconds is matched against a comb at that tempo to find where its
+// kicks fall. With a steady tempo the beat positions move in a straight line
+// against the grid (a slightly wrong BPM tilts the line, nothing more), so a
+// line is fitted and each window's distance from it is how far a static
+// grid would be off there. Stability is the share of windows where that
+// stays within GRID_TOLERANCE_MS.
+
+const FFT_SIZE: usize = 1024;
+const HOP_SIZE: usize = 256;
+const WINDOW_SECS: f64 = 8.0;
+const WINDOW_STEP_SECS: f64 = 4.0;
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+// Tempo prior centered here, one octave wide, so half/double tempo
+// candidates don't win on noise
+const PRIOR_BPM: f64 = 120.0;
+// A window needs its best beat alignment this far above the average one to
+// count as having a beat (breakdowns and ambient intros don't)
+const MIN_BEAT_CONTRAST: f64 = 2.0;
+const MIN_BEAT_WINDOWS: usize = 4;
+// Beats further off the grid than this are audible as flamming kicks
+const GRID_TOLERANCE_MS: f64 = 20.0;
+// Kick drums live below this
+const BASS_HZ: f64 = 150.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TempoReport {
+    pub path: PathBuf,
+    // Tempo of the best-fitting static grid
+    pub bpm: Option<f64>,
+    // Percent of beat windows a static grid stays on; None without a steady
+    // enough beat to judge
+    pub stability: Option<f64>,
+    // Furthest a static grid drifts from the beat anywhere in the track
+    pub max_drift_ms: Option<f64>,
+}
+
+impl TempoReport {
+    pub fn needs_manual_grid(&self) -> bool {
+        self.stability.is_some_and(|stability| stability < AudioFile::STEADY_TEMPO_PERCENT)
+    }
+}
+
+pub struct TempoAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for TempoAnalyzer {}
+
+impl Default for TempoAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TempoAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(10 * 60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<TempoReport> {
+        println!("Measuring tempo stability of {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.measure(file);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error measuring tempo: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn measure(&self, file: &AudioFile) -> Result<TempoReport> {
+        let audio = decode::decode(&file.path, Some(self.max_duration))?;
+        Ok(Self::stability(&audio))
+    }
+
+    // Tempo straight from the onset envelope's autocorrelation, without
+    // fitting a grid; still there for tracks whose beat is too sparse to fit one
+    pub fn envelope_bpm(audio: &DecodedAudio) -> Option<f64> {
+        let (envelope, _) = onset_envelopes(audio);
+        let hop_secs = HOP_SIZE as f64 / audio.sample_rate.max(1) as f64;
+        let period = beat_period(&envelope, hop_secs)?;
+        Some((60.0 / (period * hop_secs) * 100.0).round() / 100.0)
+    }
+
+    pub fn stability(audio: &DecodedAudio) -> TempoReport {
+        let mut report = TempoReport {
+            path: audio.path.clone(),
+            bpm: None,
+            stability: None,
+            max_drift_ms: None,
+        };
+        let (envelope, bass) = onset_envelopes(audio);
+        let hop_secs = HOP_SIZE as f64 / audio.sample_rate.max(1) as f64;
+        let Some(period) = beat_period(&envelope, hop_secs) else { return report };
+
+        // Where the kicks fall within the grid period, for each window that
+        // has them
+        let window = (WINDOW_SECS / hop_secs).round() as usize;
+        let step = (WINDOW_STEP_SECS / hop_secs).round() as usize;
+        let phases: Vec<(f64, f64)> = (0..bass.len().saturating_sub(window) + 1)
+            .step_by(step.max(1))
+            .filter_map(|start| {
+                let phase = beat_phase(&bass[start..start + window], period)?;
+                let centre = start as f64 + window as f64 / 2.0;
+                Some((centre, (start as f64 + phase) % period))
+            })
+            .collect();
+        if phases.len() < MIN_BEAT_WINDOWS {
+            return report;
+        }
+
+        // Unwrap so a beat sliding across the period boundary keeps moving
+        // the same way instead of jumping by a whole beat
+        let mut unwrapped = vec![phases[0]];
+        for pair in phases.windows(2) {
+            let mut delta = pair[1].1 - pair[0].1;
+            delta -= (delta / period).round() * period;
+            let previous = unwrapped[unwrapped.len() - 1].1;
+            unwrapped.push((pair[1].0, previous + delta));
+        }
+
+        let (slope, intercept) = fit_line(&unwrapped);
+        // Being a whole beat out still puts the grid on a beat
+        let drifts_ms: Vec<f64> = unwrapped.iter()
+            .map(|&(time, phase)| {
+                let offset = phase - (intercept + slope * time);
+                (offset - (offset / period).round() * period).abs() * hop_secs * 1000.0
+            })
+            .collect();
+        let on_grid = drifts_ms.iter().filter(|&&drift| drift <= GRID_TOLERANCE_MS).count();
+
+        // The fitted slope is how far the detected tempo was off
+        let grid_period = period / (1.0 - slope);
+        report.bpm = Some((60.0 / (grid_period * hop_secs) * 100.0).round() / 100.0);
+        report.stability = Some((on_grid as f64 / drifts_ms.len() as f64 * 1000.0).round() / 10.0);
+        report.max_drift_ms = Some(drifts_ms.iter().fold(0.0f64, |max, &drift| max.max(drift)).round());
+        report
+    }
+}
+
+// Spectral flux: how much louder each hop got than the one before, summed
+// over frequency on a log scale, with the local average taken out. Returned
+// for the whole spectrum and for the bass alone: hi-hats on the off-beat
+// make the full-band envelope ambiguous by half a beat, kicks don't.
+fn onset_envelopes(audio: &DecodedAudio) -> (Vec<f64>, Vec<f64>) {
+    if audio.samples.len() < FFT_SIZE || audio.sample_rate == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let bass_bins = (BASS_HZ * FFT_SIZE as f64 / audio.sample_rate as f64).ceil() as usize;
+    let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+    let mut previous = vec![0.0f64; FFT_SIZE / 2];
+
+    let (mut full, mut bass): (Vec<f64>, Vec<f64>) = (0..=audio.samples.len() - FFT_SIZE)
+        .step_by(HOP_SIZE)
+        .map(|offset| {
+            let frame = &audio.samples[offset..offset + FFT_SIZE];
+            for ((slot, &sample), &w) in buffer.iter_mut().zip(frame).zip(&window) {
+                *slot = Complex::new(sample * w, 0.0);
+            }
+            fft.process(&mut buffer);
+            let (mut rise, mut bass_rise) = (0.0, 0.0);
+            for (bin, (last, value)) in previous.iter_mut().zip(&buffer[..FFT_SIZE / 2]).enumerate() {
+                let magnitude = (1.0 + 100.0 * value.norm() as f64).ln();
+                let step = (magnitude - *last).max(0.0);
+                rise += step;
+                if bin > 0 && bin <= bass_bins {
+                    bass_rise += step;
+                }
+                *last = magnitude;
+            }
+            (rise, bass_rise)
+        })
+        .unzip();
+
+    // Subtract a moving average over roughly half a second
+    let span = (0.25 * audio.sample_rate as f64 / HOP_SIZE as f64).round().max(1.0) as usize;
+    for flux in [&mut full, &mut bass] {
+        flux[0] = 0.0;
+        let mut prefix = vec![0.0; flux.len() + 1];
+        for (index, value) in flux.iter().enumerate() {
+            prefix[index + 1] = prefix[index] + value;
+        }
+        for (index, value) in flux.iter_mut().enumerate() {
+            let from = index.saturating_sub(span);
+            let to = (index + span + 1).min(prefix.len() - 1);
+            let mean = (prefix[to] - prefix[from]) / (to - from) as f64;
+            *value = (*value - mean).max(0.0);
+        }
+    }
+    (full, bass)
+}
+
+// Beat period in hops from the envelope's autocorrelation
+fn beat_period(envelope: &[f64], hop_secs: f64) -> Option<f64> {
+    let min_lag = (60.0 / MAX_BPM / hop_secs).floor().max(1.0) as usize;
+    let max_lag = (60.0 / MIN_BPM / hop_secs).ceil() as usize;
+    if envelope.len() < max_lag * 4 {
+        return None;
+    }
+    let correlation: Vec<f64> = (min_lag - 1..=max_lag + 1)
+        .map(|lag| envelope.iter().zip(&envelope[lag..]).map(|(a, b)| a * b).sum())
+        .collect();
+    let weighted = |index: usize| {
+        let bpm = 60.0 / ((index + min_lag - 1) as f64 * hop_secs);
+        correlation[index] * (-0.5 * (bpm / PRIOR_BPM).log2().powi(2)).exp()
+    };
+    let best = (1..correlation.len() - 1).max_by(|&a, &b| weighted(a).total_cmp(&weighted(b)))?;
+    if correlation[best] <= 0.0 {
+        return None;
+    }
+    let lag = (best + min_lag - 1) as f64;
+    Some(lag + parabolic_offset(correlation[best - 1], correlation[best], correlation[best + 1]))
+}
+
+// Offset within one period, in hops, of the comb that lines up best with the
+// window's onsets; None when no offset stands out
+fn beat_phase(envelope: &[f64], period: f64) -> Option<f64> {
+    let sample = |position: f64| {
+        let index = position.floor() as usize;
+        let fraction = position - index as f64;
+        let next = envelope.get(index + 1).copied().unwrap_or(0.0);
+        envelope.get(index).map_or(0.0, |&value| value * (1.0 - fraction) + next * fraction)
+    };
+    let offsets = period.ceil() as usize;
+    let scores: Vec<f64> = (0..offsets)
+        .map(|offset| {
+            let mut position = offset as f64;
+            let mut score = 0.0;
+            while position < envelope.len() as f64 {
+                score += sample(position);
+                position += period;
+            }
+            score
+        })
+        .collect();
+
+    let average = scores.iter().sum::<f64>() / scores.len() as f64;
+    let (best, &peak) = scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+    if average <= 0.0 || peak < average * MIN_BEAT_CONTRAST {
+        return None;
+    }
+    let before = scores[(best + offsets - 1) % offsets];
+    let after = scores[(best + 1) % offsets];
+    Some(best as f64 + parabolic_offset(before, peak, after))
+}
+
+// Peak position between three evenly spaced values, relative to the middle one
+fn parabolic_offset(before: f64, peak: f64, after: f64) -> f64 {
+    let curvature = before - 2.0 * peak + after;
+    if curvature.abs() < f64::EPSILON {
+        return 0.0;
+    }
+    (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+}
+
+// Least-squares slope and intercept
+fn fit_line(points: &[(f64, f64)]) -> (f64, f64) {
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / count;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / count;
+    let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    (slope, mean_y - slope * mean_x)
+}
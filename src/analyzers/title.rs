@@ -0,0 +1,478 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use crate::AudioFile;
+
+// Pure filename/title parsing used by the duplicate matcher. Nothing in here
+// touches the filesystem or decoders, so it can be reused from any front-end.
+
+#[derive(Debug, PartialEq)]
+enum VersionType {
+    None,
+    WithMarkers(Vec<String>),
+}
+
+impl VersionType {
+    fn from_str(text: Option<&str>) -> Self {
+        let markers = [
+            // Remix and edit types
+            "remix", "mix", "rmx", "rework", "edit", "reconstruction",
+            "bootleg", "mashup", "flip", "recut", "reprise",
+            // Version types
+            "version", "radio", "club", "special", "extended",
+            // DJ markers
+            "dj", "vs", "presents",
+            // Release types
+            "remaster", "master", "remastered",
+            // Mix types
+            "dub", "instrumental", "acapella", "acoustic", "live",
+            // Length markers
+            "long", "short", "full", "cut", "original",
+            // Regional markers
+            "us", "uk", "euro", "italian", "spanish", "dutch",
+            // DJ pool markers
+            "clean", "dirty", "explicit", "intro", "quick hit",
+            // Special combinations
+            "radio edit", "club mix", "dance mix", "extended mix"
+        ];
+
+        match text {
+            None => Self::None,
+            Some(text) => {
+                let text_lower = text.to_lowercase();
+                let found_markers: Vec<String> = markers.iter()
+                    .filter(|&&m| text_lower.contains(m))
+                    .map(|&s| s.to_string())
+                    .collect();
+
+                if found_markers.is_empty() && text_lower.chars()
+                    .filter(|c| c.is_ascii_digit())
+                    .count() >= 4 {
+                    Self::WithMarkers(vec!["year".to_string()])
+                } else if !found_markers.is_empty() {
+                    Self::WithMarkers(found_markers)
+                } else {
+                    Self::None
+                }
+            }
+        }
+    }
+
+    fn share_markers(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::WithMarkers(m1), Self::WithMarkers(m2)) =>
+                m1.iter().any(|m| m2.contains(m)),
+            _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedTitle {
+    pub artist: String,
+    pub title: String,
+    pub version: Option<String>,
+}
+
+impl ParsedTitle {
+    pub fn pool_markers(&self) -> PoolMarkers {
+        PoolMarkers::from_version(self.version.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LyricVersion {
+    Clean,
+    Dirty,
+}
+
+impl LyricVersion {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Clean => "Clean",
+            Self::Dirty => "Dirty",
+        }
+    }
+}
+
+// Markers promo pools add to the version, e.g. "(Dirty Intro)" or
+// "(Clean) (Quick Hit)". Versions differing in any of them are different
+// tracks for the DJ: a clean edit can't stand in for the dirty one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PoolMarkers {
+    pub lyrics: Option<LyricVersion>,
+    pub intro: bool,
+    pub quick_hit: bool,
+}
+
+impl PoolMarkers {
+    pub fn from_version(version: Option<&str>) -> Self {
+        let Some(version) = version else { return Self::default() };
+        let words: Vec<String> = version.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect();
+        let has = |word: &str| words.iter().any(|w| w == word);
+
+        let lyrics = if has("dirty") || has("explicit") {
+            Some(LyricVersion::Dirty)
+        } else if has("clean") {
+            Some(LyricVersion::Clean)
+        } else {
+            None
+        };
+        Self {
+            lyrics,
+            intro: has("intro"),
+            quick_hit: words.windows(2).any(|pair| pair[0] == "quick" && pair[1] == "hit"),
+        }
+    }
+}
+
+// Artists known to exist in the library, normalized like parsed artists.
+// Tells "Title - Artist" rips apart from "Artist - Title" when a file has no
+// tags to go by.
+#[derive(Debug, Clone, Default)]
+pub struct KnownArtists {
+    names: HashSet<String>,
+}
+
+impl KnownArtists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Each credit counts on its own as well, so "A, B" teaches both A and B
+    pub fn insert(&mut self, artist: &str) {
+        let normalized = TitleParser::normalize_artist(artist);
+        if normalized.is_empty() {
+            return;
+        }
+        for name in normalized.split(", ") {
+            self.names.insert(name.to_string());
+        }
+        self.names.insert(normalized);
+    }
+
+    // The tagged artists of the given files
+    pub fn from_files<'a>(files: impl IntoIterator<Item = &'a AudioFile>) -> Self {
+        let mut known = Self::new();
+        for artist in files.into_iter().filter_map(|file| file.artist.as_deref()) {
+            known.insert(artist);
+        }
+        known
+    }
+
+    pub fn contains(&self, artist: &str) -> bool {
+        self.names.contains(&TitleParser::normalize_artist(artist))
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for KnownArtists {
+    fn from_iter<I: IntoIterator<Item = S>>(artists: I) -> Self {
+        let mut known = Self::new();
+        for artist in artists {
+            known.insert(artist.as_ref());
+        }
+        known
+    }
+}
+
+pub struct TitleParser {
+    strip_track_numbers: bool,
+    known_artists: Option<Arc<KnownArtists>>,
+}
+
+impl Default for TitleParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TitleParser {
+    pub fn new() -> Self {
+        Self {
+            strip_track_numbers: true,
+            known_artists: None,
+        }
+    }
+
+    // Off keeps leading numbers in parsed titles, for libraries without
+    // numbered files where they're always part of the name
+    pub fn with_track_number_stripping(mut self, strip: bool) -> Self {
+        self.strip_track_numbers = strip;
+        self
+    }
+
+    // Used by the `*_file` methods to spot "Title - Artist" names of untagged files
+    pub fn with_known_artists(mut self, known: KnownArtists) -> Self {
+        self.known_artists = Some(Arc::new(known));
+        self
+    }
+
+    pub fn normalize_artist(artist: &str) -> String {
+        let normalized = artist
+            .to_lowercase()
+            .replace("feat.", "featuring")
+            .replace("ft.", "featuring")
+            .replace(" x ", " featuring ");
+
+        let mut artists: Vec<_> = normalized
+            .split(',')
+            .map(|s| {
+                let artist_name = s.trim();
+                // Remove any parenthetical content from artist names
+                artist_name
+                    .split('(')
+                    .next()
+                    .unwrap_or(artist_name)
+                    .trim()
+                    .to_string()
+            })
+            .collect();
+
+        artists.sort();
+        artists.join(", ")
+    }
+
+    // The last parenthesized version, plus any version groups right before
+    // it: "Title (Extended Mix) (Clean)" -> ("Title", "extended mix clean")
+    fn extract_version(text: &str) -> (String, Option<String>) {
+        let (mut rest, mut versions) = match (text.rfind('('), text.rfind(')')) {
+            (Some(start), Some(end)) if start < end => {
+                let version_text = text[start + 1..end].trim();
+                match VersionType::from_str(Some(version_text)) {
+                    VersionType::None => return (text.trim().to_string(), None),
+                    VersionType::WithMarkers(_) => (text[..start].trim(), vec![version_text.to_lowercase()]),
+                }
+            },
+            _ => return (text.trim().to_string(), None),
+        };
+
+        while let (Some(start), true) = (rest.rfind('('), rest.ends_with(')')) {
+            let version_text = rest[start + 1..rest.len() - 1].trim();
+            if VersionType::from_str(Some(version_text)) == VersionType::None {
+                break;
+            }
+            versions.push(version_text.to_lowercase());
+            rest = rest[..start].trim();
+        }
+        versions.reverse();
+        (rest.to_string(), Some(versions.join(" ")))
+    }
+
+    // Drops the markers Explorer, Finder and browsers add to copies, however
+    // often the copy was copied again: "Title - Copy (2)", "Title copy copy",
+    // "Title (1)". A capitalized "Copy" without the dash is kept, as it's
+    // more likely part of the title ("Carbon Copy").
+    pub fn strip_copy_markers(stem: &str) -> &str {
+        static COPY: OnceLock<Regex> = OnceLock::new();
+        let regex = COPY.get_or_init(|| {
+            Regex::new(r"(?:\s+-\s+(?i:copy)|\s+copy)(?:\s+\d+|\s*\(\d+\))?$|\s*\(\d+\)$").unwrap()
+        });
+        let mut stem = stem.trim_end();
+        while let Some(marker) = regex.find(stem) {
+            stem = stem[..marker.start()].trim_end();
+        }
+        stem
+    }
+
+    // Leading track or promo pool number with its separator, or "" when the
+    // name doesn't start with one. Only the usual numbering formats count:
+    // "01 - ", "01. ", "1) ", "[01] ", "01 " (zero-padded), "1-03 " (disc and
+    // track) and pool IDs of five or more digits ("123456 - "). A number
+    // that's part of the name ("1999 - Prince", "2 Unlimited - ...") stays.
+    pub fn track_number_prefix<'a>(&self, stem: &'a str) -> &'a str {
+        static NUMBER: OnceLock<Regex> = OnceLock::new();
+        let regex = NUMBER.get_or_init(|| {
+            Regex::new(concat!(
+                r"^(?:[\[(]\d{1,3}[\])][\s_]*",
+                r"|\d{1,3}(?:\s*[.)]|\s+-)[\s_]+",
+                r"|0\d{1,2}(?:[\s_]+|[-.]_*)",
+                r"|\d{1,2}-\d{2,3}[\s_]+",
+                r"|\d{5,}\s*-[\s_]+)",
+            )).unwrap()
+        });
+        if !self.strip_track_numbers {
+            return "";
+        }
+        regex.find(stem).map_or("", |m| m.as_str())
+    }
+
+    // File name without extension, copy markers, brackets and leading track
+    // or pool number, split at its first " - "
+    fn split_name(&self, filename: &str) -> (String, Option<String>) {
+        let without_ext = filename.rfind('.').map_or(filename, |i| &filename[..i]);
+        let without_ext = Self::strip_copy_markers(without_ext).trim();
+        let without_number = &without_ext[self.track_number_prefix(without_ext).len()..];
+
+        let clean_name = without_number
+            .replace(['[', ']'], "")
+            .replace('_', " ")
+            .trim()
+            .to_string();
+
+        match clean_name.split_once(" - ") {
+            Some((head, rest)) => (head.to_string(), Some(rest.to_string())),
+            None => (clean_name, None),
+        }
+    }
+
+    fn build(artist: &str, title: &str) -> ParsedTitle {
+        let (clean_title, version) = Self::extract_version(title);
+        ParsedTitle {
+            artist: Self::normalize_artist(artist.trim()),
+            title: clean_title.to_lowercase(),
+            version,
+        }
+    }
+
+    // Artist and title tags, normalized like the parts of a file name so
+    // either can be compared with the other
+    pub fn parse_tags(artist: &str, title: &str) -> ParsedTitle {
+        Self::build(artist, title)
+    }
+
+    pub fn parse(&self, filename: &str) -> ParsedTitle {
+        match self.split_name(filename) {
+            (head, Some(rest)) => Self::build(&head, &rest),
+            (name, None) => ParsedTitle {
+                artist: name.clone(),
+                title: name,
+                version: None,
+            },
+        }
+    }
+
+    // Whether a file is named "Title - Artist" rather than "Artist - Title".
+    // Tags decide when the file has them; otherwise the name is swapped when
+    // only its last part is a known artist.
+    pub fn is_swapped(&self, filename: &str, tag_artist: Option<&str>, tag_title: Option<&str>) -> bool {
+        let (head, Some(rest)) = self.split_name(filename) else { return false };
+        let tail = rest.rsplit(" - ").next().unwrap_or(&rest);
+
+        if let Some(artist) = tag_artist.map(Self::normalize_artist).filter(|artist| !artist.is_empty()) {
+            return artist != Self::normalize_artist(&head) && artist == Self::normalize_artist(tail);
+        }
+        if let Some(title) = tag_title.map(Self::normalize_text).filter(|title| !title.is_empty()) {
+            let head_title = Self::normalize_text(&Self::extract_version(&head).0);
+            let rest_title = Self::normalize_text(&Self::extract_version(&rest).0);
+            if head_title == title || rest_title == title {
+                return head_title == title && rest_title != title;
+            }
+        }
+        match &self.known_artists {
+            Some(known) => known.contains(tail) && !known.contains(&head),
+            None => false,
+        }
+    }
+
+    // Like `parse`, but reads "Title - Artist" names the right way round
+    pub fn parse_file(&self, file: &AudioFile) -> ParsedTitle {
+        if !self.is_swapped(&file.file_name, file.artist.as_deref(), file.title.as_deref()) {
+            return self.parse(&file.file_name);
+        }
+        let (head, rest) = self.split_name(&file.file_name);
+        let rest = rest.unwrap_or_default();
+        let (middle, artist) = match rest.rsplit_once(" - ") {
+            Some((middle, artist)) => (format!("{} - {}", head, middle), artist.to_string()),
+            None => (head, rest),
+        };
+        Self::build(&artist, &middle)
+    }
+
+    // "Artist - Title" file name for a file named the other way round, with
+    // its track number and extension kept; None when the name is fine
+    pub fn normalized_file_name(&self, file: &AudioFile) -> Option<String> {
+        if !self.is_swapped(&file.file_name, file.artist.as_deref(), file.title.as_deref()) {
+            return None;
+        }
+        let name = &file.file_name;
+        let (stem, extension) = name.rfind('.').map_or((name.as_str(), ""), |i| name.split_at(i));
+        let number = self.track_number_prefix(stem);
+        let (title, artist) = stem[number.len()..].rsplit_once(" - ")?;
+        Some(format!("{}{} - {}{}", number, artist.trim(), title.trim(), extension))
+    }
+
+    // Files can only be duplicates when artist and title agree; this key
+    // groups them for bucketed comparison
+    pub fn match_key(&self, filename: &str) -> String {
+        let parsed = self.parse(filename);
+        format!("{}\u{0}{}", parsed.artist, parsed.title)
+    }
+
+    // `match_key` with swapped "Title - Artist" names put the right way round
+    pub fn match_key_file(&self, file: &AudioFile) -> String {
+        let parsed = self.parse_file(file);
+        format!("{}\u{0}{}", parsed.artist, parsed.title)
+    }
+
+    // Lowercase letters and digits only, words separated by single spaces,
+    // for comparing titles typed or tagged slightly differently
+    pub fn normalize_text(text: &str) -> String {
+        text.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Levenshtein distance in characters
+    pub fn edit_distance(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
+        let mut current = vec![0; b.len() + 1];
+        for (i, a_char) in a.chars().enumerate() {
+            current[0] = i + 1;
+            for (j, b_char) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(a_char != *b_char);
+                current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        previous[b.len()]
+    }
+
+    // How alike two texts are from 0.0 to 1.0: one minus the edit distance
+    // over the longer length, after normalizing case and punctuation.
+    // Numbers must agree, or the texts count as unrelated, so "Part 1" and
+    // "Part 2" stay different tracks however alike the rest is.
+    pub fn similarity(a: &str, b: &str) -> f64 {
+        let (a, b) = (Self::normalize_text(a), Self::normalize_text(b));
+        let digits = |text: &str| text.chars().filter(char::is_ascii_digit).collect::<String>();
+        if digits(&a) != digits(&b) {
+            return 0.0;
+        }
+        let longest = a.chars().count().max(b.chars().count());
+        if longest == 0 {
+            return 1.0;
+        }
+        1.0 - Self::edit_distance(&a, &b) as f64 / longest as f64
+    }
+
+    pub fn are_different_versions(version1: Option<&str>, version2: Option<&str>) -> bool {
+        if PoolMarkers::from_version(version1) != PoolMarkers::from_version(version2) {
+            return true;
+        }
+        let v1_type = VersionType::from_str(version1);
+        let v2_type = VersionType::from_str(version2);
+
+        match (v1_type, v2_type) {
+            (VersionType::None, VersionType::None) => false,
+            (VersionType::WithMarkers(_), VersionType::WithMarkers(_))
+                if version1 == version2 => false,
+            (v1, v2) => !v1.share_markers(&v2)
+        }
+    }
+}
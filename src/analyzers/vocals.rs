@@ -0,0 +1,296 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::Serialize;
+use crate::{AudioFile, Result};
+#[cfg(feature = "tags")]
+use crate::AudioError;
+use crate::audio::decode::{self, DecodedAudio};
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Vocal presence. Sung notes never hold a perfectly steady pitch: vibrato,
+// scoops and the small wander of a human voice move every harmonic by tens
+// of cents, where synths, pianos and guitars hold theirs within a few. The
+// spectral peaks in the vocal range are followed from frame to frame; a
+// track of peaks whose pitch wobbles around its trend by more than
+// MIN_WOBBLE_CENTS is taken as sung. The share of the track's audible frames
+// covered by such tracks is its vocal score.
+
+const FFT_SIZE: usize = 4096;
+const HOP_SIZE: usize = 512;
+const MIN_HZ: f64 = 200.0;
+const MAX_HZ: f64 = 2000.0;
+// Peaks considered per frame, loudest first
+const MAX_PEAKS: usize = 10;
+// Peaks quieter than the frame's loudest by more than this are ignored
+const PEAK_RANGE_DB: f64 = 30.0;
+// A peak must stand this far above the spectrum three bins either side;
+// two partials closer than that beat against each other, which reads as a
+// wobbling pitch
+const MIN_ISOLATION_DB: f64 = 10.0;
+// Frames this far below the loudest frame count as silence
+const SILENCE_DB: f64 = 40.0;
+// A peak continues a track when within this many cents of its last pitch
+const MAX_STEP_CENTS: f64 = 50.0;
+const MIN_TRACK_SECS: f64 = 0.2;
+// Spread around the track's trend (as a standard deviation); above the
+// ceiling it's not one sound any more
+const MIN_WOBBLE_CENTS: f64 = 8.0;
+const MAX_WOBBLE_CENTS: f64 = 80.0;
+// Percent of audible frames that must carry singing for a track to be vocal
+pub const VOCAL_SCORE: f64 = 10.0;
+// Custom tag written by `write_vocal_tag`
+pub const VOCALS_TAG: &str = "VOCALS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VocalClass {
+    Vocal,
+    Instrumental,
+}
+
+impl VocalClass {
+    pub fn from_score(score: f64) -> Self {
+        if score >= VOCAL_SCORE { Self::Vocal } else { Self::Instrumental }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Vocal => "Vocal",
+            Self::Instrumental => "Instrumental",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VocalReport {
+    pub path: PathBuf,
+    // Percent of audible frames with singing, 0..=100
+    pub score: f64,
+    pub class: VocalClass,
+}
+
+// A run of spectral peaks followed across frames
+struct PartialTrack {
+    start: usize,
+    cents: Vec<f64>,
+}
+
+pub struct VocalAnalyzer {
+    // How much audio to decode per file
+    max_duration: Duration,
+}
+
+impl ParallelProcessor for VocalAnalyzer {}
+
+impl Default for VocalAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VocalAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            max_duration: Duration::from_secs(10 * 60),
+        }
+    }
+
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    pub fn analyze(&self, files: &[AudioFile]) -> Vec<VocalReport> {
+        println!("Listening for vocals in {} files using {} decode threads", files.len(), parallel::decode_threads());
+
+        let total = files.len();
+        let progress = Self::get_progress_counter(total);
+        let results = Self::decode_map(files, |file| {
+            let result = self.detect(&file.path);
+            let processed = progress.advance(1);
+            if processed % 50 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        results.into_iter()
+            .filter_map(|result| match result {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    eprintln!("Error analyzing file: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn detect(&self, path: &Path) -> Result<VocalReport> {
+        let audio = decode::decode(path, Some(self.max_duration))?;
+        let score = Self::vocal_score(&audio);
+        Ok(VocalReport {
+            path: path.to_path_buf(),
+            score,
+            class: VocalClass::from_score(score),
+        })
+    }
+
+    pub fn vocal_score(audio: &DecodedAudio) -> f64 {
+        if audio.samples.len() < FFT_SIZE || audio.sample_rate == 0 {
+            return 0.0;
+        }
+        let bin_hz = audio.sample_rate as f64 / FFT_SIZE as f64;
+        let min_bin = (MIN_HZ / bin_hz).floor().max(3.0) as usize;
+        let max_bin = ((MAX_HZ / bin_hz).ceil() as usize).min(FFT_SIZE / 2 - 4);
+        let min_frames = (MIN_TRACK_SECS * audio.sample_rate as f64 / HOP_SIZE as f64).ceil() as usize;
+
+        let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        let mut buffer = vec![Complex::new(0.0f32, 0.0); FFT_SIZE];
+
+        // Peak pitches (in cents) and overall level of every frame
+        let frames: Vec<(Vec<f64>, f64)> = (0..=audio.samples.len() - FFT_SIZE)
+            .step_by(HOP_SIZE)
+            .map(|offset| {
+                let frame = &audio.samples[offset..offset + FFT_SIZE];
+                for ((slot, &sample), &w) in buffer.iter_mut().zip(frame).zip(&window) {
+                    *slot = Complex::new(sample * w, 0.0);
+                }
+                fft.process(&mut buffer);
+                let db: Vec<f64> = buffer[..FFT_SIZE / 2].iter()
+                    .map(|value| 10.0 * (value.norm_sqr() as f64 + 1e-12).log10())
+                    .collect();
+                let level = 10.0 * (buffer[..FFT_SIZE / 2].iter().map(|v| v.norm_sqr() as f64).sum::<f64>() + 1e-12).log10();
+                (spectral_peaks(&db, min_bin, max_bin, bin_hz), level)
+            })
+            .collect();
+
+        let loudest = frames.iter().map(|f| f.1).fold(f64::MIN, f64::max);
+        let audible = frames.iter().filter(|f| f.1 >= loudest - SILENCE_DB).count();
+        if audible == 0 {
+            return 0.0;
+        }
+
+        let mut sung = vec![false; frames.len()];
+        let mut mark = |track: &PartialTrack| {
+            if track.cents.len() >= min_frames && is_sung(&track.cents) {
+                sung[track.start..track.start + track.cents.len()].fill(true);
+            }
+        };
+        let mut active: Vec<PartialTrack> = Vec::new();
+        for (index, (peaks, _)) in frames.iter().enumerate() {
+            let mut continued = Vec::with_capacity(peaks.len());
+            let mut taken = vec![false; peaks.len()];
+            for track in active.drain(..) {
+                let last = track.cents[track.cents.len() - 1];
+                let nearest = peaks.iter().enumerate()
+                    .filter(|&(peak, cents)| !taken[peak] && (cents - last).abs() <= MAX_STEP_CENTS)
+                    .min_by(|a, b| (a.1 - last).abs().total_cmp(&(b.1 - last).abs()));
+                match nearest {
+                    Some((peak, &cents)) => {
+                        taken[peak] = true;
+                        let mut track = track;
+                        track.cents.push(cents);
+                        continued.push(track);
+                    }
+                    None => mark(&track),
+                }
+            }
+            for (peak, &cents) in peaks.iter().enumerate() {
+                if !taken[peak] {
+                    continued.push(PartialTrack { start: index, cents: vec![cents] });
+                }
+            }
+            active = continued;
+        }
+        for track in &active {
+            mark(track);
+        }
+
+        let sung_frames = sung.iter().zip(&frames)
+            .filter(|&(&sung, frame)| sung && frame.1 >= loudest - SILENCE_DB)
+            .count();
+        (sung_frames as f64 / audible as f64 * 1000.0).round() / 10.0
+    }
+}
+
+// The loudest local maxima between the two bins, as pitches in cents
+// relative to A440, refined between bins by parabolic interpolation
+fn spectral_peaks(db: &[f64], min_bin: usize, max_bin: usize, bin_hz: f64) -> Vec<f64> {
+    let floor = db[min_bin..=max_bin].iter().fold(f64::MIN, |max, &v| max.max(v)) - PEAK_RANGE_DB;
+    let mut peaks: Vec<(f64, f64)> = (min_bin..=max_bin)
+        .filter(|&bin| {
+            db[bin] >= floor
+                && db[bin] > db[bin - 1] && db[bin] >= db[bin + 1]
+                && db[bin] > db[bin - 2] && db[bin] >= db[bin + 2]
+                && db[bin] - db[bin - 3].max(db[bin + 3]) >= MIN_ISOLATION_DB
+        })
+        .map(|bin| {
+            let (before, peak, after) = (db[bin - 1], db[bin], db[bin + 1]);
+            let curvature = before - 2.0 * peak + after;
+            let offset = if curvature.abs() < f64::EPSILON { 0.0 } else { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) };
+            let hz = (bin as f64 + offset) * bin_hz;
+            (1200.0 * (hz / 440.0).log2(), peak)
+        })
+        .collect();
+    peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+    peaks.truncate(MAX_PEAKS);
+    peaks.into_iter().map(|(cents, _)| cents).collect()
+}
+
+// Whether the pitch wanders around its trend like a voice does
+fn is_sung(cents: &[f64]) -> bool {
+    let count = cents.len() as f64;
+    let mean_x = (count - 1.0) / 2.0;
+    let mean_y = cents.iter().sum::<f64>() / count;
+    let covariance: f64 = cents.iter().enumerate().map(|(x, y)| (x as f64 - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = (0..cents.len()).map(|x| (x as f64 - mean_x).powi(2)).sum();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    // Median deviation, so drum hits smearing a frame or two don't count
+    let mut deviations: Vec<f64> = cents.iter().enumerate()
+        .map(|(x, y)| (y - (mean_y + slope * (x as f64 - mean_x))).abs())
+        .collect();
+    deviations.sort_by(f64::total_cmp);
+    let wobble = deviations[deviations.len() / 2] * 1.4826;
+    (MIN_WOBBLE_CENTS..=MAX_WOBBLE_CENTS).contains(&wobble)
+}
+
+// Records the classification in a custom VOCALS tag (ID3 TXXX frame or
+// Vorbis comment) so it can be filtered on elsewhere. MP3 and FLAC only.
+#[cfg(feature = "tags")]
+pub fn write_vocal_tag(path: &Path, class: VocalClass) -> Result<()> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(tag_error(e.to_string())),
+            };
+            tag.remove_extended_text(Some(VOCALS_TAG), None);
+            tag.add_frame(id3::frame::ExtendedText {
+                description: VOCALS_TAG.to_string(),
+                value: class.label().to_string(),
+            });
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            tag.set_vorbis(VOCALS_TAG, vec![class.label()]);
+            tag.save().map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't write tags to {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
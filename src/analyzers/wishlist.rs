@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::bitrate::BitrateAnalyzer;
+use crate::analyzers::title::TitleParser;
+
+// The reverse of duplicate detection: given tracks someone wants (a CSV of
+// artists and titles, or a Spotify or Apple Music playlist export), which of
+// them are already in the library, and in what quality? What's left is the
+// shopping list.
+//
+// Streaming services and stores spell versions differently ("Title - Extended
+// Mix" vs "Title (Extended Mix)") and list every featured artist, so entries
+// match on any shared artist plus the title without its version and
+// featuring credits; the version then decides between "owned" and "owned in
+// another version".
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WishlistEntry {
+    pub artist: String,
+    pub title: String,
+    // Spotify URI or store link, when the list has one
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WishStatus {
+    Owned,
+    // The library has the track, but not the version asked for
+    OtherVersion,
+    Missing,
+}
+
+impl WishStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WishStatus::Owned => "Owned",
+            WishStatus::OtherVersion => "Other version in library",
+            WishStatus::Missing => "Missing",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WishMatch {
+    pub entry: WishlistEntry,
+    pub status: WishStatus,
+    // The best library copy found, for owned tracks and other versions
+    pub library_copy: Option<AudioFile>,
+}
+
+impl WishMatch {
+    pub fn library_path(&self) -> Option<&Path> {
+        self.library_copy.as_ref().map(|file| file.path.as_path())
+    }
+}
+
+// Artist, title and version reduced to what two spellings of the same track
+// have in common
+#[derive(Debug, Clone, PartialEq)]
+struct TrackKey {
+    artists: Vec<String>,
+    title: String,
+    version: Option<String>,
+}
+
+struct KeyParser {
+    // "feat. X", "ft X", "featuring X" up to the end or a bracket
+    featuring: Regex,
+    credit_prefix: Regex,
+    // Bracketed groups, and " - Suffix" versions as streaming services write them
+    groups: Regex,
+    artist_separators: Regex,
+}
+
+impl KeyParser {
+    fn new() -> Self {
+        Self {
+            featuring: Regex::new(r"(?i)[(\[]?\b(feat\.?|ft\.?|featuring)\s[^)\]]*[)\]]?").unwrap(),
+            credit_prefix: Regex::new(r"(?i)^(feat\.?|ft\.?|featuring)\s").unwrap(),
+            groups: Regex::new(r"[(\[]([^)\]]*)[)\]]|\s-\s(.*)$").unwrap(),
+            artist_separators: Regex::new(r"(?i)\s*[,&;]\s*|\s+(and|x|vs\.?|feat\.?|ft\.?|featuring)\s+").unwrap(),
+        }
+    }
+
+    fn key(&self, artist: &str, title: &str) -> TrackKey {
+        let mut artists: Vec<String> = self.artist_separators.split(artist)
+            .map(TitleParser::normalize_text)
+            .filter(|artist| !artist.is_empty())
+            .collect();
+        // "Title (feat. X)": X counts as an artist too
+        for credit in self.featuring.find_iter(title) {
+            let names = credit.as_str().trim_matches(['(', ')', '[', ']']);
+            let names = self.credit_prefix.replace(names, "");
+            artists.extend(self.artist_separators.split(&names)
+                .map(TitleParser::normalize_text)
+                .filter(|artist| !artist.is_empty()));
+        }
+        artists.sort();
+        artists.dedup();
+
+        let without_featuring = self.featuring.replace_all(title, "");
+        let versions: Vec<String> = self.groups.captures_iter(&without_featuring)
+            .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .map(|version| TitleParser::normalize_text(version.as_str()))
+            // Remasters are the same recording as far as a DJ is concerned
+            .filter(|version| !version.is_empty() && !matches!(version.as_str(), "original mix" | "original") && !version.contains("remaster"))
+            .collect();
+        let base = self.groups.replace_all(&without_featuring, "");
+
+        TrackKey {
+            artists,
+            title: TitleParser::normalize_text(&base),
+            version: (!versions.is_empty()).then(|| versions.join(" ")),
+        }
+    }
+}
+
+pub struct WishlistMatcher<'a> {
+    parser: KeyParser,
+    // Library tracks by base title
+    library: HashMap<String, Vec<(TrackKey, &'a AudioFile)>>,
+}
+
+impl<'a> WishlistMatcher<'a> {
+    // Indexes each file under its tags and, when the name reads
+    // "Artist - Title", its file name
+    pub fn new(files: &'a [AudioFile]) -> Self {
+        let parser = KeyParser::new();
+        let title_parser = TitleParser::new();
+        let mut library: HashMap<String, Vec<(TrackKey, &'a AudioFile)>> = HashMap::new();
+
+        for file in files {
+            let mut keys = Vec::new();
+            if let (Some(artist), Some(title)) = (&file.artist, &file.title) {
+                keys.push(parser.key(artist, title));
+            }
+            let stem = Path::new(&file.file_name).file_stem()
+                .map(|stem| stem.to_string_lossy().replace('_', " "))
+                .unwrap_or_default();
+            let stem = TitleParser::strip_copy_markers(stem.trim());
+            let stem = &stem[title_parser.track_number_prefix(stem).len()..];
+            if let Some((artist, title)) = stem.split_once(" - ") {
+                keys.push(parser.key(artist, title));
+            }
+
+            keys.dedup();
+            for key in keys {
+                if key.title.is_empty() || key.artists.is_empty() {
+                    continue;
+                }
+                library.entry(key.title.clone()).or_default().push((key, file));
+            }
+        }
+
+        Self { parser, library }
+    }
+
+    pub fn check(&self, entry: &WishlistEntry) -> WishMatch {
+        let wanted = self.parser.key(&entry.artist, &entry.title);
+        let candidates: Vec<&(TrackKey, &AudioFile)> = self.library.get(&wanted.title)
+            .map(|candidates| candidates.iter()
+                .filter(|(key, _)| key.artists.iter().any(|artist| wanted.artists.contains(artist)))
+                .collect())
+            .unwrap_or_default();
+
+        let owned: Vec<&AudioFile> = candidates.iter()
+            .filter(|(key, _)| key.version == wanted.version)
+            .map(|(_, file)| *file)
+            .collect();
+        let others: Vec<&AudioFile> = candidates.iter().map(|(_, file)| *file).collect();
+        let (status, library_copy) = match (best_copy(&owned), best_copy(&others)) {
+            (Some(file), _) => (WishStatus::Owned, Some(file.clone())),
+            (None, Some(file)) => (WishStatus::OtherVersion, Some(file.clone())),
+            (None, None) => (WishStatus::Missing, None),
+        };
+        WishMatch { entry: entry.clone(), status, library_copy }
+    }
+
+    pub fn check_all(&self, entries: &[WishlistEntry]) -> Vec<WishMatch> {
+        entries.iter().map(|entry| self.check(entry)).collect()
+    }
+}
+
+// Highest quality of several copies (a file indexed under both its tags and
+// its name shows up twice, which doesn't matter here)
+fn best_copy<'f>(copies: &[&'f AudioFile]) -> Option<&'f AudioFile> {
+    copies.iter().copied().reduce(|best, file| {
+        if BitrateAnalyzer::compare_quality(file, best).0 && file.path != best.path { file } else { best }
+    })
+}
+
+// Spotify's account data export (`YourLibrary.json`)
+#[derive(Deserialize)]
+struct SpotifyLibrary {
+    tracks: Vec<SpotifyTrack>,
+}
+
+#[derive(Deserialize)]
+struct SpotifyTrack {
+    artist: String,
+    track: String,
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+// Reads a wish-list or streaming playlist: Spotify's `YourLibrary.json`, or
+// a table with a header row naming the artist and title columns: CSV
+// ("Artist"/"Title", or the "Artist Name(s)"/"Track Name" of Exportify) or
+// the tab-separated, usually UTF-16 text Apple Music exports playlists as
+// ("Name"/"Artist"). Rows without both are skipped.
+pub fn read_wishlist(path: impl AsRef<Path>) -> Result<Vec<WishlistEntry>> {
+    let path = path.as_ref();
+    let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if is_json {
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        let library: SpotifyLibrary = serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Not a Spotify library export {}: {}", path.display(), e)))?;
+        return Ok(library.tracks.into_iter()
+            .map(|track| WishlistEntry { artist: track.artist, title: track.track, link: track.uri })
+            .collect());
+    }
+
+    let text = decode_text(&fs::read(path).map_err(|e| AudioError::io(path, e))?);
+    let tab_separated = text.lines().next().is_some_and(|header| header.contains('\t'));
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .delimiter(if tab_separated { b'\t' } else { b',' })
+        .from_reader(text.as_bytes());
+    let headers: Vec<String> = reader.headers()
+        .map_err(|e| AudioError::Metadata(format!("Can't read {}: {}", path.display(), e)))?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| headers.iter().position(|header| names.contains(&header.as_str()));
+    let (Some(artist_column), Some(title_column)) = (
+        column(&["artist", "artists", "artist name", "artist name(s)"]),
+        column(&["title", "track", "track name", "name", "song"]),
+    ) else {
+        return Err(AudioError::Metadata(format!(
+            "{} needs a header row with artist and title columns", path.display()
+        )));
+    };
+    let link_column = column(&["link", "url", "uri", "track uri", "spotify uri"]);
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| AudioError::Metadata(format!("Can't read {}: {}", path.display(), e)))?;
+        let field = |index: usize| record.get(index).map(str::trim).filter(|value| !value.is_empty());
+        let (Some(artist), Some(title)) = (field(artist_column), field(title_column)) else { continue };
+        entries.push(WishlistEntry {
+            artist: artist.to_string(),
+            title: title.to_string(),
+            link: link_column.and_then(field).map(str::to_string),
+        });
+    }
+    Ok(entries)
+}
+
+// UTF-8, or UTF-16 when the text starts with a byte order mark
+fn decode_text(bytes: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| from([pair[0], pair[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
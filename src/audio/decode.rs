@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::{Hint, ProbeResult};
+use crate::{AudioError, Result};
+
+// Decoded PCM for analyses that need the actual signal rather than tags
+#[derive(Debug, Clone)]
+pub struct DecodedAudio {
+    pub path: PathBuf,
+    pub sample_rate: u32,
+    pub channels: usize,
+    // Bit depth the container declares, for integer formats
+    pub bits_per_sample: Option<u32>,
+    // Channels mixed down to mono, scaled to -1.0..1.0
+    pub samples: Vec<f32>,
+    // Bitwise OR of every decoded sample left-aligned to 32 bits; trailing
+    // zeros here are bits no sample ever used
+    pub sample_bits: u32,
+}
+
+impl DecodedAudio {
+    pub fn duration_secs(&self) -> f64 {
+        if self.sample_rate == 0 {
+            return 0.0;
+        }
+        self.samples.len() as f64 / self.sample_rate as f64
+    }
+}
+
+// Opens the container, using the extension as a hint. Tags read before the
+// container (ID3v2) end up in `metadata`, the rest in `format.metadata()`.
+pub fn probe(path: &Path) -> Result<ProbeResult> {
+    let file = std::fs::File::open(path).map_err(|e| AudioError::io(path, e))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    symphonia::default::get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| symphonia_error(path, e))
+}
+
+// Decodes the default track, stopping after `max_duration` if given
+pub fn decode(path: impl AsRef<Path>, max_duration: Option<Duration>) -> Result<DecodedAudio> {
+    let path = path.as_ref();
+    let mut format = probe(path)?.format;
+
+    let track = format.default_track().ok_or_else(|| AudioError::Probe { path: path.to_path_buf() })?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .map_err(|e| symphonia_error(path, e))?;
+
+    let sample_rate = params.sample_rate.unwrap_or(0);
+    let max_frames = max_duration.map(|d| (d.as_secs_f64() * sample_rate as f64) as usize);
+    let mut audio = DecodedAudio {
+        path: path.to_path_buf(),
+        sample_rate,
+        channels: params.channels.map(|c| c.count()).unwrap_or(0),
+        bits_per_sample: params.bits_per_sample,
+        samples: Vec::new(),
+        sample_bits: 0,
+    };
+    let mut buffer: Option<SampleBuffer<i32>> = None;
+
+    loop {
+        if max_frames.is_some_and(|max| audio.samples.len() >= max) {
+            break;
+        }
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(symphonia_error(path, e)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupt packets are skipped, as players do
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(symphonia_error(path, e)),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        audio.channels = channels;
+        if audio.sample_rate == 0 {
+            audio.sample_rate = spec.rate;
+        }
+
+        let buffer = match &mut buffer {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+            slot => slot.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks_exact(channels) {
+            let mut sum = 0.0;
+            for &sample in frame {
+                audio.sample_bits |= sample as u32;
+                sum += sample as f32 / i32::MAX as f32;
+            }
+            audio.samples.push(sum / channels as f32);
+        }
+    }
+
+    if let Some(max) = max_frames {
+        audio.samples.truncate(max);
+    }
+    Ok(audio)
+}
+
+pub(crate) fn symphonia_error(path: &Path, error: SymphoniaError) -> AudioError {
+    match error {
+        SymphoniaError::Unsupported(_) => AudioError::Probe { path: path.to_path_buf() },
+        SymphoniaError::IoError(source) => AudioError::io(path, source),
+        source => AudioError::Decode { path: path.to_path_buf(), source },
+    }
+}
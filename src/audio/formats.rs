@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use crate::{AudioError, AudioFile, Result, UnknownBitrate};
+use crate::utils::disk;
+
+// Lightweight readers for formats the decoder doesn't know (DSD, WMA,
+// tracker modules). They only pull out what indexing needs: size, duration, bitrate
+// and whatever title the container carries. Register more with
+// `FormatRegistry::register`.
+pub trait FormatParser: Send + Sync {
+    // Short name for logs, e.g. "DSF"
+    fn name(&self) -> &'static str;
+
+    // Lowercase extensions this parser handles
+    fn extensions(&self) -> &'static [&'static str];
+
+    // Fills in what the format provides; size, file name and allocation are
+    // already set on `audio_file`
+    fn read(&self, file: &mut File, audio_file: &mut AudioFile) -> Result<()>;
+}
+
+#[derive(Clone)]
+pub struct FormatRegistry {
+    parsers: HashMap<String, Arc<dyn FormatParser>>,
+}
+
+impl fmt::Debug for FormatRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut extensions: Vec<&str> = self.parsers.keys().map(String::as_str).collect();
+        extensions.sort_unstable();
+        f.debug_struct("FormatRegistry").field("extensions", &extensions).finish()
+    }
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        Self::builtin().clone()
+    }
+}
+
+impl FormatRegistry {
+    pub fn empty() -> Self {
+        Self { parsers: HashMap::new() }
+    }
+
+    // DSF, DSDIFF, WMA and the common tracker module formats
+    pub fn builtin() -> &'static Self {
+        static BUILTIN: OnceLock<FormatRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            let mut registry = Self::empty();
+            registry.register(Arc::new(DsfParser));
+            registry.register(Arc::new(DffParser));
+            registry.register(Arc::new(AsfParser));
+            registry.register(Arc::new(ModuleParser));
+            registry
+        })
+    }
+
+    // Later registrations replace earlier ones for the same extension
+    pub fn register(&mut self, parser: Arc<dyn FormatParser>) {
+        for ext in parser.extensions() {
+            self.parsers.insert(ext.to_string(), Arc::clone(&parser));
+        }
+    }
+
+    pub fn extensions(&self) -> Vec<String> {
+        let mut extensions: Vec<String> = self.parsers.keys().cloned().collect();
+        extensions.sort_unstable();
+        extensions
+    }
+
+    // `ext=parser` for every registered extension, sorted; tells two
+    // registries apart that parse the same extensions differently
+    pub fn parser_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.parsers.iter().map(|(ext, parser)| format!("{}={}", ext, parser.name())).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn parser_for(&self, path: &Path) -> Option<&dyn FormatParser> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.parsers.get(&ext).map(|parser| parser.as_ref())
+    }
+
+    // None when no parser is registered for the file's extension
+    pub fn extract(&self, path: &Path) -> Option<Result<AudioFile>> {
+        let parser = self.parser_for(path)?;
+        Some(Self::extract_with(parser, path))
+    }
+
+    fn extract_with(parser: &dyn FormatParser, path: &Path) -> Result<AudioFile> {
+        let mut file = File::open(path).map_err(|e| AudioError::io(path, e))?;
+        let metadata = file.metadata().map_err(|e| AudioError::io(path, e))?;
+        if path.file_name().and_then(|n| n.to_str()).is_none() {
+            return Err(AudioError::InvalidFileName { path: path.to_path_buf() });
+        }
+
+        let mut audio_file = AudioFile::new(path, metadata.len());
+        audio_file.allocated_bytes = disk::allocated_size(path, &metadata);
+        parser.read(&mut file, &mut audio_file).map_err(|e| match e {
+            AudioError::Io(source) => AudioError::io(path, source),
+            other => other,
+        })?;
+        Ok(audio_file)
+    }
+}
+
+fn set_duration(audio_file: &mut AudioFile, samples: u64, sample_rate: u64) {
+    if sample_rate == 0 {
+        return;
+    }
+    audio_file.duration_secs = Some(samples as f64 / sample_rate as f64);
+    audio_file.duration_ms = Some((samples * 1000 + sample_rate / 2) / sample_rate);
+}
+
+// Nominal stream bitrate for 1-bit DSD; unknown rather than 0 kbps when the
+// header gives no sample rate or channels
+fn set_dsd_bitrate(audio_file: &mut AudioFile, sample_rate: u64, channels: u64) {
+    audio_file.bitrate_unknown = match (sample_rate, channels) {
+        (0, _) => Some(UnknownBitrate::NoTimeBase),
+        (_, 0) => Some(UnknownBitrate::NoAudioTrack),
+        _ => {
+            audio_file.bitrate = Some((sample_rate * channels / 1000) as u32);
+            None
+        }
+    };
+}
+
+fn malformed(format: &str) -> AudioError {
+    AudioError::Metadata(format!("Malformed {} header", format))
+}
+
+// Sony DSF: little-endian "DSD " chunk followed by a "fmt " chunk
+struct DsfParser;
+
+impl FormatParser for DsfParser {
+    fn name(&self) -> &'static str {
+        "DSF"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["dsf"]
+    }
+
+    fn read(&self, file: &mut File, audio_file: &mut AudioFile) -> Result<()> {
+        let mut header = [0u8; 28 + 52];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != b"DSD " || &header[28..32] != b"fmt " {
+            return Err(malformed(self.name()));
+        }
+
+        let le_u32 = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap_or_default()) as u64;
+        let channels = le_u32(28 + 24);
+        let sample_rate = le_u32(28 + 28);
+        let samples = u64::from_le_bytes(header[28 + 36..28 + 44].try_into().unwrap_or_default());
+
+        set_duration(audio_file, samples, sample_rate);
+        set_dsd_bitrate(audio_file, sample_rate, channels);
+        Ok(())
+    }
+}
+
+// Philips DSDIFF: big-endian IFF-style "FRM8" form with a PROP chunk holding
+// the sample rate and channel count, and a "DSD " chunk with the samples
+struct DffParser;
+
+impl FormatParser for DffParser {
+    fn name(&self) -> &'static str {
+        "DSDIFF"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["dff"]
+    }
+
+    fn read(&self, file: &mut File, audio_file: &mut AudioFile) -> Result<()> {
+        let mut form = [0u8; 16];
+        file.read_exact(&mut form)?;
+        if &form[0..4] != b"FRM8" || &form[12..16] != b"DSD " {
+            return Err(malformed(self.name()));
+        }
+
+        let mut sample_rate = None;
+        let mut channels = None;
+        let mut sound_bytes = None;
+        while let Some((id, size)) = read_dff_chunk(file)? {
+            let next = file.stream_position()? + size + (size & 1);
+            match &id {
+                b"PROP" => {
+                    let mut kind = [0u8; 4];
+                    file.read_exact(&mut kind)?;
+                    while file.stream_position()? < next {
+                        let Some((sub_id, sub_size)) = read_dff_chunk(file)? else { break };
+                        let sub_next = file.stream_position()? + sub_size + (sub_size & 1);
+                        match &sub_id {
+                            b"FS  " => {
+                                let mut rate = [0u8; 4];
+                                file.read_exact(&mut rate)?;
+                                sample_rate = Some(u32::from_be_bytes(rate) as u64);
+                            }
+                            b"CHNL" => {
+                                let mut count = [0u8; 2];
+                                file.read_exact(&mut count)?;
+                                channels = Some(u16::from_be_bytes(count) as u64);
+                            }
+                            _ => {}
+                        }
+                        file.seek(SeekFrom::Start(sub_next))?;
+                    }
+                }
+                b"DSD " => sound_bytes = Some(size),
+                _ => {}
+            }
+            if sound_bytes.is_some() && sample_rate.is_some() {
+                break;
+            }
+            file.seek(SeekFrom::Start(next))?;
+        }
+
+        let (Some(sample_rate), Some(channels)) = (sample_rate, channels) else {
+            return Err(malformed(self.name()));
+        };
+        if let Some(bytes) = sound_bytes.filter(|_| channels > 0) {
+            set_duration(audio_file, bytes * 8 / channels, sample_rate);
+        }
+        set_dsd_bitrate(audio_file, sample_rate, channels);
+        Ok(())
+    }
+}
+
+// Chunk id and payload size, or None at end of file
+fn read_dff_chunk(file: &mut File) -> Result<Option<([u8; 4], u64)>> {
+    let mut header = [0u8; 12];
+    match file.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let id: [u8; 4] = header[0..4].try_into().unwrap_or_default();
+    let size = u64::from_be_bytes(header[4..12].try_into().unwrap_or_default());
+    Ok(Some((id, size)))
+}
+
+// Tracker modules have no fixed duration without playing the pattern data,
+// so only the song title is read and the bitrate is reported as unknown
+struct ModuleParser;
+
+impl FormatParser for ModuleParser {
+    fn name(&self) -> &'static str {
+        "tracker module"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["mod", "xm", "s3m", "it"]
+    }
+
+    fn read(&self, file: &mut File, audio_file: &mut AudioFile) -> Result<()> {
+        let mut header = [0u8; 64];
+        let read = file.read(&mut header)?;
+        let header = &header[..read];
+
+        let title = if header.starts_with(b"Extended Module: ") {
+            header.get(17..37)
+        } else if header.starts_with(b"IMPM") {
+            header.get(4..30)
+        } else if header.get(44..48) == Some(b"SCRM") {
+            header.get(0..28)
+        } else {
+            // ProTracker MOD has no magic at the start; the title comes first
+            header.get(0..20)
+        };
+
+        audio_file.title = title
+            .map(|bytes| {
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+            })
+            .filter(|title| !title.is_empty());
+        audio_file.bitrate_unknown = Some(UnknownBitrate::NoStreamBitrate);
+        Ok(())
+    }
+}
+
+// Windows Media (ASF): a header object of GUID-tagged objects in front of
+// the packets. The file properties give the duration, the audio stream's
+// WAVEFORMATEX the nominal bitrate (the file size would include the index
+// and any embedded artwork), and the content description title and author.
+struct AsfParser;
+
+const ASF_HEADER: [u8; 16] = [0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C];
+const ASF_FILE_PROPERTIES: [u8; 16] = [0xA1, 0xDC, 0xAB, 0x8C, 0x47, 0xA9, 0xCF, 0x11, 0x8E, 0xE4, 0x00, 0xC0, 0x0C, 0x20, 0x53, 0x65];
+const ASF_STREAM_PROPERTIES: [u8; 16] = [0x91, 0x07, 0xDC, 0xB7, 0xB7, 0xA9, 0xCF, 0x11, 0x8E, 0xE6, 0x00, 0xC0, 0x0C, 0x20, 0x53, 0x65];
+const ASF_AUDIO_MEDIA: [u8; 16] = [0x40, 0x9E, 0x69, 0xF8, 0x4D, 0x5B, 0xCF, 0x11, 0xA8, 0xFD, 0x00, 0x80, 0x5F, 0x5C, 0x44, 0x2B];
+const ASF_CONTENT_DESCRIPTION: [u8; 16] = [0x33, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C];
+
+impl FormatParser for AsfParser {
+    fn name(&self) -> &'static str {
+        "WMA"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["wma"]
+    }
+
+    fn read(&self, file: &mut File, audio_file: &mut AudioFile) -> Result<()> {
+        let mut header = [0u8; 30];
+        file.read_exact(&mut header)?;
+        if header[0..16] != ASF_HEADER {
+            return Err(malformed(self.name()));
+        }
+        let header_size = u64::from_le_bytes(header[16..24].try_into().unwrap_or_default());
+        // The header holds no audio, so reading it whole is cheap; cap it in
+        // case of a corrupt size
+        if !(30..=16 * 1024 * 1024).contains(&header_size) {
+            return Err(malformed(self.name()));
+        }
+        let mut objects = vec![0u8; header_size as usize - 30];
+        file.read_exact(&mut objects)?;
+
+        let le_u16 = |bytes: &[u8], at: usize| bytes.get(at..at + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]) as usize);
+        let le_u32 = |bytes: &[u8], at: usize| bytes.get(at..at + 4)
+            .and_then(|b| b.try_into().ok())
+            .map_or(0, u32::from_le_bytes) as u64;
+        let le_u64 = |bytes: &[u8], at: usize| bytes.get(at..at + 8)
+            .and_then(|b| b.try_into().ok())
+            .map_or(0, u64::from_le_bytes);
+
+        let mut offset = 0;
+        while offset + 24 <= objects.len() {
+            let size = le_u64(&objects, offset + 16) as usize;
+            let Some(object) = objects.get(offset..offset.saturating_add(size)).filter(|_| size >= 24) else { break };
+            let guid = &object[0..16];
+            if guid == ASF_FILE_PROPERTIES {
+                // Play duration in 100 ns units, including the preroll in ms
+                let duration = le_u64(object, 64) as f64 / 10_000_000.0 - le_u64(object, 80) as f64 / 1000.0;
+                if duration > 0.0 {
+                    audio_file.duration_secs = Some(duration);
+                    audio_file.duration_ms = Some((duration * 1000.0).round() as u64);
+                }
+            } else if guid == ASF_STREAM_PROPERTIES && object.get(24..40) == Some(&ASF_AUDIO_MEDIA[..]) {
+                // WAVEFORMATEX: tag, channels, sample rate, average bytes per second
+                let bytes_per_sec = le_u32(object, 78 + 8);
+                if bytes_per_sec > 0 {
+                    audio_file.bitrate = Some((bytes_per_sec * 8 / 1000) as u32);
+                }
+            } else if guid == ASF_CONTENT_DESCRIPTION {
+                // Lengths of title, author, copyright, description and
+                // rating, then the UTF-16LE strings in that order
+                let title_len = le_u16(object, 24);
+                let author_len = le_u16(object, 26);
+                let text = |at: usize, len: usize| object.get(at..at + len).map(|bytes| {
+                    let units: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+                    String::from_utf16_lossy(&units).trim_end_matches('\0').trim().to_string()
+                }).filter(|text| !text.is_empty());
+                audio_file.title = text(34, title_len);
+                audio_file.artist = text(34 + title_len, author_len);
+            }
+            offset += size;
+        }
+
+        if audio_file.bitrate.is_none() {
+            return Err(malformed(self.name()));
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,204 @@
-use std::path::Path;
-use symphonia::core::formats::FormatOptions;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use symphonia::core::codecs::CODEC_TYPE_MP3;
+use symphonia::core::formats::{FormatOptions, FormatReader};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
-use crate::{AudioFile, Result, AudioError};
-use crate::utils::parallel::ParallelProcessor;
-use rayon::prelude::*;
+use crate::{AudioFile, Result, AudioError, UnknownBitrate};
+use crate::utils::disk;
+use crate::utils::parallel::{self, AdaptiveThreads, ParallelProcessor};
+use crate::utils::hashing;
+use crate::utils::pipeline;
+use crate::utils::progress::{self, PhaseTimer, Progress};
+use crate::utils::scan_cache::ScanCache;
+#[cfg(feature = "index")]
+use crate::index::incremental::IncrementalScan;
+use crate::utils::scan_log::{LogEvent, ScanLog};
+use crate::audio::decode;
+use crate::audio::formats::FormatRegistry;
+use crate::audio::source::MetadataSource;
+use crate::audio::stream;
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // How many extra passes to make over files that failed with transient errors
+    pub retry_count: u32,
+    pub retry_delay: Duration,
+    // Descend into subdirectories
+    pub recursive: bool,
+    // Wildcard patterns (`*`, `?`) matched against file/directory names and
+    // against the path relative to the scan root
+    pub excludes: Vec<String>,
+    // Directories left out wherever they turn up, as canonical paths (the
+    // duplicates quarantine when it lies inside an input)
+    pub exclude_dirs: Vec<PathBuf>,
+    // Lowercase extensions to treat as audio
+    pub extensions: Vec<String>,
+    // Parsers for formats the decoder doesn't handle, tried before it
+    pub formats: FormatRegistry,
+    // Every processed file is appended here as it completes
+    pub log: Option<Arc<ScanLog>>,
+    // Skip the folders DJ software and sync tools keep their own data in
+    // (see `SOFTWARE_FOLDERS`) and macOS `._` resource files
+    pub skip_software_folders: bool,
+    // Reuse the previous results for roots whose fingerprint is unchanged
+    pub cache: Option<ScanCache>,
+    // Read through every frame to measure the duration of files whose header
+    // has no frame count, which would otherwise have no duration or bitrate,
+    // and of MP3s, whose duration is estimated from the first frames when
+    // there is no Xing or VBRI header (wrong for VBR). Much slower.
+    pub accurate_duration: bool,
+    // Time spent walking folders and extracting metadata is added here
+    pub phases: Option<Arc<PhaseTimer>>,
+    // Fewer workers extract at once while throughput has collapsed
+    pub adaptive: Option<Arc<AdaptiveThreads>>,
+    // Reuse indexed metadata of files unchanged since the last scan, and
+    // index what gets extracted
+    #[cfg(feature = "index")]
+    pub index: Option<IncrementalScan>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            retry_count: 1,
+            retry_delay: Duration::from_secs(2),
+            recursive: true,
+            excludes: Vec::new(),
+            exclude_dirs: Vec::new(),
+            extensions: decoder_extensions().into_iter()
+                .map(String::from)
+                .chain(FormatRegistry::builtin().extensions())
+                .collect(),
+            formats: FormatRegistry::default(),
+            log: None,
+            skip_software_folders: true,
+            cache: None,
+            accurate_duration: false,
+            phases: None,
+            adaptive: None,
+            #[cfg(feature = "index")]
+            index: None,
+        }
+    }
+}
+
+// Folders written by DJ software and sync tools rather than by the user:
+// analysis data, databases, crates, backups and sync state. Some hold files
+// with audio extensions (samplers, recordings, backups), so scanning them
+// inflates size statistics and produces false duplicates. Matched on the
+// folder name, case-insensitively.
+pub const SOFTWARE_FOLDERS: &[&str] = &[
+    // Serato (crates, database, backups)
+    "_Serato_",
+    "_Serato_Backup",
+    // Rekordbox USB exports and analysis files
+    "PIONEER",
+    "USBANLZ",
+    "rekordbox",
+    // Engine DJ database
+    "Engine Library",
+    // Traktor
+    "Native Instruments",
+    // Syncthing state and file versions
+    ".stfolder",
+    ".stversions",
+];
+
+// Extensions the decoder reads with the codecs this build includes
+pub fn decoder_extensions() -> Vec<&'static str> {
+    let mut extensions = Vec::new();
+    if cfg!(feature = "codecs-mp3") {
+        extensions.push("mp3");
+    }
+    if cfg!(feature = "codecs-wav") {
+        extensions.push("wav");
+    }
+    if cfg!(feature = "codecs-flac") {
+        extensions.push("flac");
+    }
+    // MP4 audio holds AAC or ALAC; bare AAC comes as an ADTS stream
+    if cfg!(feature = "codecs-aac") {
+        extensions.extend(["m4a", "aac"]);
+    }
+    // Opus is probed for its duration but can't be decoded, so analyses
+    // that listen to the audio skip it
+    if cfg!(feature = "codecs-ogg") {
+        extensions.extend(["ogg", "oga", "opus"]);
+    }
+    if cfg!(feature = "codecs-aiff") {
+        extensions.extend(["aiff", "aif"]);
+    }
+    extensions
+}
+
+// Files smaller than this can't hold a second of audio even at 32 kbps, the
+// lowest MP3 bitrate; they are failed or interrupted downloads and are left
+// out of the scan instead of turning up with a bitrate of 0
+pub const PLACEHOLDER_BYTES: u64 = 4096;
+
+// Paths the walk may find ahead of extraction before it waits
+const WALK_QUEUE: usize = 1024;
+// Paths looked up in the incremental index at a time
+const INDEX_BATCH: usize = 256;
+
+pub fn is_software_folder(name: &str) -> bool {
+    SOFTWARE_FOLDERS.iter().any(|folder| folder.eq_ignore_ascii_case(name))
+}
+
+// macOS writes `._Track.mp3` resource files next to every file on drives
+// without extended attributes (exFAT/FAT32 USB sticks)
+pub fn is_resource_file(name: &str) -> bool {
+    name.starts_with("._")
+}
 
 pub struct MetadataExtractor;
 
 impl ParallelProcessor for MetadataExtractor {}
 
+impl MetadataSource for MetadataExtractor {
+    fn extract(&self, path: &Path) -> Result<AudioFile> {
+        Self::extract_metadata(path)
+    }
+
+    fn scan(&self, root: &Path) -> Result<Vec<AudioFile>> {
+        Self::process_directory(root)
+    }
+}
+
 impl MetadataExtractor {
     pub fn extract_metadata(path: impl AsRef<Path>) -> Result<AudioFile> {
         let path = path.as_ref();
-        let file = std::fs::File::open(path)?;
+        Self::extract_with(path, FormatRegistry::builtin(), false)
+    }
+
+    fn extract_with(path: &Path, formats: &FormatRegistry, accurate_duration: bool) -> Result<AudioFile> {
+        formats.extract(path).unwrap_or_else(|| Self::decode_metadata(path, accurate_duration))
+    }
+
+    // Sums the durations of every packet of the track, in its time base.
+    // Only demuxes (frame headers on MP3), nothing is decoded.
+    fn count_frames(format: &mut dyn FormatReader, track_id: u32) -> Option<u64> {
+        let mut frames = 0;
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() == track_id {
+                frames += packet.dur;
+            }
+        }
+        (frames > 0).then_some(frames)
+    }
+
+    fn decode_metadata(path: &Path, accurate_duration: bool) -> Result<AudioFile> {
+        let file = std::fs::File::open(path).map_err(|e| AudioError::io(path, e))?;
         
         // Get basic file info
-        let file_metadata = file.metadata()?;
+        let file_metadata = file.metadata().map_err(|e| AudioError::io(path, e))?;
         let file_name = path.file_name()
             .and_then(|n| n.to_str())
-            .ok_or_else(|| AudioError::Metadata("Invalid filename".into()))?
+            .ok_or_else(|| AudioError::InvalidFileName { path: path.to_path_buf() })?
             .to_string();
 
         // Create media source stream
@@ -33,70 +211,126 @@ impl MetadataExtractor {
         }
 
         // Probe the media source
-        let probed = symphonia::default::get_probe()
+        let mut probed = symphonia::default::get_probe()
             .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
-            .map_err(|e| AudioError::Metadata(e.to_string()))?;
+            .map_err(|e| decode::symphonia_error(path, e))?;
 
         let mut format = probed.format;
         
         // Extract metadata
-        let mut audio_file = AudioFile {
-            path: path.to_path_buf(),
-            file_name,
-            size_bytes: file_metadata.len(),
-            duration_secs: None,
-            bitrate: None,
-            artist: None,
-            title: None,
-            album: None,
-        };
+        let mut audio_file = AudioFile::new(path, file_metadata.len());
+        audio_file.file_name = file_name;
+        audio_file.allocated_bytes = disk::allocated_size(path, &file_metadata);
+        audio_file.audio_bytes = stream::audio_stream_bytes(path);
 
         // Try to get format info
-        if let Some(track) = format.default_track() {
-            let params = &track.codec_params;
-            
-            // Get duration if available
-            if let Some(time_base) = params.time_base {
-                if let Some(n_frames) = params.n_frames {
+        audio_file.bitrate_unknown = Some(UnknownBitrate::NoAudioTrack);
+        if let Some((track_id, params)) = format.default_track().map(|track| (track.id, track.codec_params.clone())) {
+            // Get duration if available, counting frames when asked to
+            let measure = accurate_duration && (params.n_frames.is_none() || params.codec == CODEC_TYPE_MP3);
+            let n_frames = measure.then(|| Self::count_frames(format.as_mut(), track_id))
+                .flatten()
+                .or(params.n_frames);
+            if let Some(n_frames) = n_frames {
+                if let Some(sample_rate) = params.sample_rate.filter(|&rate| rate > 0) {
+                    // Sample-accurate: frames / rate, without going through a time base
+                    let rate = sample_rate as u64;
+                    audio_file.duration_secs = Some(n_frames as f64 / rate as f64);
+                    audio_file.duration_ms = Some((n_frames * 1000 + rate / 2) / rate);
+                } else if let Some(time_base) = params.time_base {
+                    // `frac` is already a fraction of a second
                     let time = time_base.calc_time(n_frames);
-                    audio_file.duration_secs = Some(time.seconds as f64 + time.frac as f64 / 1_000_000_000.0);
+                    let secs = time.seconds as f64 + time.frac;
+                    audio_file.duration_secs = Some(secs);
+                    audio_file.duration_ms = Some((secs * 1000.0).round() as u64);
                 }
             }
             
-            // Calculate bitrate from file size and duration
-            if let Some(duration) = audio_file.duration_secs {
-                if duration > 0.0 {
-                    let bitrate = (file_metadata.len() * 8) as f64 / duration;
+            // Calculate bitrate from the audio size and duration
+            audio_file.bitrate_unknown = match (n_frames, audio_file.duration_secs) {
+                (None, _) => Some(UnknownBitrate::NoFrameCount),
+                (Some(_), None) => Some(UnknownBitrate::NoTimeBase),
+                (Some(_), Some(duration)) if duration <= 0.0 => Some(UnknownBitrate::ZeroDuration),
+                (Some(_), Some(duration)) => {
+                    let bitrate = (audio_file.audio_size() * 8) as f64 / duration;
                     audio_file.bitrate = Some((bitrate / 1000.0) as u32); // Convert to kbps
+                    None
                 }
-            }
+            };
         }
 
-        // Get additional metadata if available
+        // Get additional metadata if available. Tags ahead of the container
+        // (ID3v2 on MP3) are found by the probe, the rest by the format
+        // reader; container tags come last so they win.
+        let mut tags = Vec::new();
+        if let Some(metadata) = probed.metadata.get() {
+            tags.extend(metadata.current().map(|revision| revision.tags().to_vec()).unwrap_or_default());
+        }
         if let Some(metadata) = format.metadata().current() {
-            for tag in metadata.tags() {
-                match tag.std_key {
-                    Some(symphonia::core::meta::StandardTagKey::Artist) => {
-                        audio_file.artist = Some(tag.value.to_string());
-                    }
-                    Some(symphonia::core::meta::StandardTagKey::TrackTitle) => {
-                        audio_file.title = Some(tag.value.to_string());
-                    }
-                    Some(symphonia::core::meta::StandardTagKey::Album) => {
-                        audio_file.album = Some(tag.value.to_string());
-                    }
-                    _ => {}
+            tags.extend(metadata.tags().iter().cloned());
+        }
+        for tag in &tags {
+            match tag.std_key {
+                Some(symphonia::core::meta::StandardTagKey::Artist) => {
+                    audio_file.artist = Some(tag.value.to_string());
+                }
+                Some(symphonia::core::meta::StandardTagKey::TrackTitle) => {
+                    audio_file.title = Some(tag.value.to_string());
                 }
+                Some(symphonia::core::meta::StandardTagKey::Album) => {
+                    audio_file.album = Some(tag.value.to_string());
+                }
+                Some(symphonia::core::meta::StandardTagKey::Label) => {
+                    audio_file.label = Some(tag.value.to_string());
+                }
+                // ID3 TSRC, Vorbis ISRC; written with or without hyphens
+                Some(symphonia::core::meta::StandardTagKey::IdentIsrc) => {
+                    let code: String = tag.value.to_string().chars()
+                        .filter(|c| !matches!(c, '-' | ' '))
+                        .map(|c| c.to_ascii_uppercase())
+                        .collect();
+                    audio_file.isrc = (code.len() == 12 && code.chars().all(|c| c.is_ascii_alphanumeric())).then_some(code);
+                }
+                // Some taggers write "128.00 BPM" or a comma decimal
+                Some(symphonia::core::meta::StandardTagKey::Bpm) => {
+                    let value = tag.value.to_string().replace(',', ".");
+                    audio_file.bpm = value.split_whitespace().next()
+                        .and_then(|bpm| bpm.parse::<f64>().ok())
+                        .filter(|bpm| *bpm > 0.0);
+                }
+                // Symphonia has no standard key for the musical key;
+                // ID3 uses TKEY, Vorbis comments INITIALKEY
+                None if matches!(tag.key.to_ascii_uppercase().as_str(), "TKEY" | "INITIALKEY" | "KEY") => {
+                    audio_file.key = Some(tag.value.to_string());
+                }
+                _ => {}
             }
         }
 
         Ok(audio_file)
     }
 
-    fn collect_audio_files(dir_path: &Path) -> Vec<walkdir::DirEntry> {
-        walkdir::WalkDir::new(dir_path)
-            .follow_links(true)
+    // Lazily, so the walk can feed extraction as it goes
+    fn walk_audio_files<'a>(dir_path: &'a Path, options: &'a ScanOptions) -> impl Iterator<Item = walkdir::DirEntry> + Send + 'a {
+        let mut walker = walkdir::WalkDir::new(dir_path).follow_links(true);
+        if !options.recursive {
+            walker = walker.max_depth(1);
+        }
+
+        walker
             .into_iter()
+            .filter_entry(|e| {
+                if e.depth() > 0 && Self::is_software_artifact(e, options) {
+                    let kind = if e.file_type().is_dir() { "software data folder" } else { "macOS resource file" };
+                    println!("Skipping {}: {}", kind, e.path().display());
+                    return false;
+                }
+                let excluded = e.depth() > 0 && Self::is_excluded(dir_path, e.path(), options);
+                if excluded {
+                    println!("Excluding: {}", e.path().display());
+                }
+                !excluded
+            })
             .filter_map(|e| match e {
                 Ok(entry) => Some(entry),
                 Err(err) => {
@@ -107,7 +341,7 @@ impl MetadataExtractor {
             .filter(|e| {
                 let is_file = e.file_type().is_file();
                 let has_valid_ext = if let Some(ext) = e.path().extension().and_then(|e| e.to_str()) {
-                    matches!(ext.to_lowercase().as_str(), "mp3" | "wav" | "flac")
+                    options.extensions.contains(&ext.to_lowercase())
                 } else {
                     false
                 };
@@ -116,18 +350,61 @@ impl MetadataExtractor {
                 }
                 is_file && has_valid_ext
             })
+    }
+
+    fn is_software_artifact(entry: &walkdir::DirEntry, options: &ScanOptions) -> bool {
+        if !options.skip_software_folders {
+            return false;
+        }
+        let name = entry.file_name().to_string_lossy();
+        if entry.file_type().is_dir() {
+            is_software_folder(&name)
+        } else {
+            is_resource_file(&name)
+        }
+    }
+
+    fn is_excluded(root: &Path, path: &Path, options: &ScanOptions) -> bool {
+        if options.exclude_dirs.iter().any(|dir| dir == path) {
+            return true;
+        }
+        if options.excludes.is_empty() {
+            return false;
+        }
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        options.excludes.iter().any(|pattern| wildcard_match(pattern, &name) || wildcard_match(pattern, &relative))
+    }
+
+    // Audio files under `dirs` honoring the recursion/exclude/format options,
+    // without extracting any metadata
+    pub fn list_audio_files(dirs: &[impl AsRef<Path>], options: &ScanOptions) -> Vec<PathBuf> {
+        let mut seen = HashSet::new();
+        dirs.iter()
+            .flat_map(|dir| {
+                let dir = std::fs::canonicalize(dir.as_ref()).unwrap_or_else(|_| dir.as_ref().to_path_buf());
+                Self::walk_audio_files(&dir, options).collect::<Vec<_>>()
+            })
+            .map(|entry| entry.into_path())
+            .filter(|path| seen.insert(path.clone()))
             .collect()
     }
 
     pub fn process_directories(dirs: &[impl AsRef<Path>]) -> Result<Vec<AudioFile>> {
+        Self::process_directories_with(dirs, &ScanOptions::default())
+    }
+
+    pub fn process_directories_with(dirs: &[impl AsRef<Path>], options: &ScanOptions) -> Result<Vec<AudioFile>> {
         Self::init_parallel_processing();
         let mut all_files = Vec::new();
+        let mut seen = HashSet::new();
         
         for dir in dirs {
             println!("Processing directory: {}", dir.as_ref().display());
-            let files = Self::process_directory(dir)?;
+            let files = Self::process_directory_with(dir, options)?;
             println!("Found {} valid audio files in directory", files.len());
-            all_files.extend(files);
+            // Overlapping roots must not make a file a duplicate of itself
+            all_files.extend(files.into_iter().filter(|f| seen.insert(f.path.clone())));
         }
         
         println!("Total audio files found: {}", all_files.len());
@@ -135,6 +412,10 @@ impl MetadataExtractor {
     }
 
     pub fn process_directory(dir: impl AsRef<Path>) -> Result<Vec<AudioFile>> {
+        Self::process_directory_with(dir, &ScanOptions::default())
+    }
+
+    pub fn process_directory_with(dir: impl AsRef<Path>, options: &ScanOptions) -> Result<Vec<AudioFile>> {
         let dir_ref = dir.as_ref();
         
         // Try to get canonical path
@@ -144,54 +425,276 @@ impl MetadataExtractor {
             dir_ref.to_path_buf()
         };
 
+        let timed = |phase: &'static str, started: std::time::Instant| {
+            if let Some(phases) = &options.phases {
+                phases.add(phase, started.elapsed());
+            }
+        };
+        let walk_started = std::time::Instant::now();
+        let fingerprint = options.cache.as_ref().map(|_| Self::fingerprint(&dir_path, options));
+        if let (Some(cache), Some(fingerprint)) = (&options.cache, &fingerprint) {
+            if let Some(files) = cache.lookup(&dir_path, fingerprint) {
+                timed(progress::WALKING, walk_started);
+                println!("Unchanged since the last scan, reusing {} cached files: {}", files.len(), dir_path.display());
+                // The log and the index get the files as if they had been scanned
+                if let Some(log) = options.log.as_deref() {
+                    files.iter().for_each(|file| log.record(&LogEvent::Scanned { file }));
+                }
+                #[cfg(feature = "index")]
+                if let Some(index) = &options.index {
+                    if let Err(e) = index.record(&files) {
+                        eprintln!("Error recording scanned files in the index: {}", e);
+                    }
+                }
+                return Ok(files);
+            }
+        }
+
         println!("Scanning directory structure: {}", dir_path.display());
 
-        // Collect all potential audio files
-        let entries = Self::collect_audio_files(&dir_path);
-        println!("Found {} potential audio files", entries.len());
+        // The walk and extraction run as two stages: the walk on a thread of
+        // its own, feeding paths to the extraction workers through a bounded
+        // queue as it finds them. With an incremental index, paths are looked
+        // up in batches on the walk's thread and only the changed ones passed on.
+        let counter = Progress::counting();
+        let reused = Mutex::new(Vec::new());
+        let batch_size = if Self::uses_index(options) { INDEX_BATCH } else { 1 };
+        let mut walk = Self::walk_audio_files(&dir_path, options).map(|entry| entry.into_path()).fuse();
+        let mut walked = 0;
+        let mut pending = Vec::new().into_iter();
+        let paths = std::iter::from_fn(|| loop {
+            if let Some(path) = pending.next() {
+                return Some(path);
+            }
+            let batch: Vec<PathBuf> = walk.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                counter.counted();
+                println!("Found {} potential audio files", walked);
+                timed(progress::WALKING, walk_started);
+                return None;
+            }
+            walked += batch.len();
+            pending = Self::skip_indexed(batch, options, &reused).into_iter();
+            counter.found(pending.len());
+        });
+
+        println!("Processing files using {} threads...", parallel::current_threads());
+        let extract_started = std::time::Instant::now();
+        let (mut files, mut retry_queue) = Self::extract_all(paths, &counter, options);
 
-        if entries.is_empty() {
-            return Ok(Vec::new());
+        // Files on slow or sleeping drives often fail the first pass with
+        // timeouts; give them another chance once the drive has spun up
+        let mut attempt = 0;
+        while !retry_queue.is_empty() && attempt < options.retry_count {
+            attempt += 1;
+            println!("\nRetrying {} files with transient errors (attempt {}/{}) in {:.1}s...",
+                retry_queue.len(),
+                attempt,
+                options.retry_count,
+                options.retry_delay.as_secs_f64()
+            );
+            std::thread::sleep(options.retry_delay);
+
+            let counter = Self::get_progress_counter(retry_queue.len());
+            let (recovered, still_failing) = Self::extract_all(std::mem::take(&mut retry_queue), &counter, options);
+            println!("Recovered {} files on retry", recovered.len());
+            files.extend(recovered);
+            retry_queue = still_failing;
         }
 
-        let progress = Self::get_progress_counter();
-        let total_files = entries.len();
+        for path in &retry_queue {
+            eprintln!("Giving up on file after {} retries: {}", options.retry_count, path.display());
+        }
 
-        // Process files in parallel using rayon
-        println!("Processing files using {} threads...", rayon::current_num_threads());
-        let files: Vec<AudioFile> = entries.par_iter()
-            .map(|entry| {
-                let result = Self::extract_metadata(entry.path());
-                
-                if let Ok(ref file) = result {
-                    println!("Processed file: {} (Size: {} bytes, Duration: {:?}s, Bitrate: {:?}kbps)",
-                        file.file_name,
-                        file.size_bytes,
-                        file.duration_secs,
-                        file.bitrate
-                    );
-                }
+        #[cfg(feature = "index")]
+        if let Some(index) = &options.index {
+            let reused = reused.into_inner().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = index.record(&files) {
+                eprintln!("Error recording scanned files in the index: {}", e);
+            }
+            println!("Reused {} unchanged files from the index, extracted {}", reused.len(), files.len());
+            files.extend(reused);
+        }
 
-                let processed = progress.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
-                if processed % 100 == 0 || processed == total_files {
-                    println!("Progress: {}/{} files ({:.1}%)", 
-                        processed,
-                        total_files,
-                        (processed as f64 / total_files as f64) * 100.0
-                    );
+        // A scan that gave up on files isn't complete enough to reuse
+        if let (Some(cache), Some(fingerprint)) = (&options.cache, &fingerprint) {
+            if retry_queue.is_empty() {
+                if let Err(e) = cache.store(&dir_path, fingerprint, &files) {
+                    eprintln!("Error caching scan results: {}", e);
                 }
+            }
+        }
 
-                result
+        timed(progress::EXTRACTING, extract_started);
+        Ok(files)
+    }
+
+    // Hash over the scan options and the name, size and modification time of
+    // every entry the scan would walk. Only listing and stat calls; no file
+    // is opened.
+    fn fingerprint(dir_path: &Path, options: &ScanOptions) -> String {
+        let mut walker = walkdir::WalkDir::new(dir_path).follow_links(true).sort_by_file_name();
+        if !options.recursive {
+            walker = walker.max_depth(1);
+        }
+
+        let mut listing = format!("{}\n{}\n{}\n{:?}\n{:?}\n{:?}\n{}\n{:?}\n",
+            env!("CARGO_PKG_VERSION"), options.recursive, options.skip_software_folders, options.excludes,
+            options.exclude_dirs, options.extensions, options.accurate_duration, options.formats.parser_ids());
+        let entries = walker.into_iter()
+            .filter_entry(|e| {
+                e.depth() == 0
+                    || !(Self::is_software_artifact(e, options) || Self::is_excluded(dir_path, e.path(), options))
             })
-            .filter_map(|result| match result {
-                Ok(file) => Some(file),
+            .filter_map(|e| e.ok());
+        for entry in entries {
+            let relative = entry.path().strip_prefix(dir_path).unwrap_or(entry.path());
+            let (size, modified) = entry.metadata()
+                .map(|metadata| {
+                    let modified = metadata.modified().ok()
+                        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map_or(0, |since| since.as_nanos());
+                    (metadata.len(), modified)
+                })
+                .unwrap_or_default();
+            listing.push_str(&format!("{}\t{}\t{}\t{}\n", relative.display(), entry.file_type().is_dir(), size, modified));
+        }
+        hashing::sha256_bytes(listing.as_bytes())
+    }
+
+    fn uses_index(options: &ScanOptions) -> bool {
+        #[cfg(feature = "index")]
+        return options.index.is_some();
+        #[cfg(not(feature = "index"))]
+        let _ = options;
+        #[cfg(not(feature = "index"))]
+        return false;
+    }
+
+    // The paths of `batch` to extract; files the incremental index has
+    // current records for go to `reused` instead
+    fn skip_indexed(batch: Vec<PathBuf>, options: &ScanOptions, reused: &Mutex<Vec<AudioFile>>) -> Vec<PathBuf> {
+        #[cfg(feature = "index")]
+        if let Some(index) = &options.index {
+            return match index.split(&batch) {
+                Ok((current, changed)) => {
+                    reused.lock().unwrap_or_else(|e| e.into_inner()).extend(current);
+                    changed
+                }
                 Err(e) => {
-                    eprintln!("Error processing file: {}", e);
-                    None
+                    eprintln!("Error reading the index, extracting these files: {}", e);
+                    batch
                 }
-            })
-            .collect();
+            };
+        }
+        let _ = (options, reused);
+        batch
+    }
 
-        Ok(files)
+    // Extracts metadata for every path as `paths` yields it, returning the
+    // successfully processed files and the paths that failed with a
+    // transient error. `paths` is drawn on a thread of its own, at most
+    // WALK_QUEUE paths ahead of the extraction workers.
+    fn extract_all<P>(paths: P, progress: &Progress, options: &ScanOptions) -> (Vec<AudioFile>, Vec<PathBuf>)
+    where
+        P: IntoIterator<Item = PathBuf>,
+        P::IntoIter: Send,
+    {
+        let log = options.log.as_deref();
+
+        let results = pipeline::bounded_map(paths, parallel::current_threads(), WALK_QUEUE, |path| {
+            let extract = || match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.len() < PLACEHOLDER_BYTES => {
+                    Err(AudioError::Placeholder { path: path.clone(), size_bytes: metadata.len() })
+                }
+                _ => Self::extract_with(&path, &options.formats, options.accurate_duration),
+            };
+            let result = match &options.adaptive {
+                Some(adaptive) => adaptive.run(extract),
+                None => extract(),
+            };
+            
+            if let Ok(ref file) = result {
+                println!("Processed file: {} (Size: {} bytes, Duration: {:?}s, Bitrate: {:?}kbps)",
+                    file.file_name,
+                    file.size_bytes,
+                    file.duration_secs,
+                    file.bitrate
+                );
+            }
+
+            if let Some(log) = log {
+                match &result {
+                    Ok(file) => log.record(&LogEvent::Scanned { file }),
+                    Err(AudioError::Placeholder { size_bytes, .. }) => log.record(&LogEvent::Placeholder {
+                        path: &path,
+                        size_bytes: *size_bytes,
+                    }),
+                    Err(e) => log.record(&LogEvent::Failed {
+                        path: &path,
+                        error: e.to_string(),
+                        transient: e.is_transient(),
+                    }),
+                }
+            }
+
+            let processed = progress.advance(1);
+            let total_files = progress.total();
+            if progress.is_counting() {
+                if processed.is_multiple_of(100) {
+                    println!("Progress: {} files, {} found so far{}", processed, total_files, progress.rate(processed));
+                }
+            } else if processed.is_multiple_of(100) || processed == total_files {
+                println!("Progress: {}/{} files ({:.1}%){}", 
+                    processed,
+                    total_files,
+                    (processed as f64 / total_files as f64) * 100.0,
+                    progress.rate(processed)
+                );
+            }
+
+            (path, result)
+        });
+
+        let mut files = Vec::new();
+        let mut transient_failures = Vec::new();
+        for (path, result) in results {
+            match result {
+                Ok(file) => files.push(file),
+                Err(e) if e.is_transient() => {
+                    eprintln!("Transient error processing file (will retry): {}", e);
+                    transient_failures.push(path);
+                }
+                Err(e @ AudioError::Placeholder { .. }) => eprintln!("Skipping file: {}", e),
+                Err(e) => eprintln!("Error processing file: {}", e),
+            }
+        }
+
+        (files, transient_failures)
+    }
+}
+
+// Case-insensitive glob-style match supporting `*` (any run) and `?` (one char)
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
     }
-}
\ No newline at end of file
+    pattern[p..].iter().all(|&c| c == '*')
+}
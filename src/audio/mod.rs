@@ -1,2 +1,10 @@
+#[cfg(feature = "decode")]
+pub mod decode;
+#[cfg(feature = "decode")]
 pub mod metadata;
-pub mod formats;
\ No newline at end of file
+#[cfg(feature = "decode")]
+pub mod tags;
+pub mod formats;
+pub mod retag;
+pub mod source;
+pub mod stream;
\ No newline at end of file
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::AudioFile;
+use crate::exporters::keys::{KeyNotation, MusicalKey};
+use crate::utils::file_ops::FileOperation;
+#[cfg(feature = "tags")]
+use crate::{AudioError, Result};
+
+// Writes a track's key and BPM where players without custom columns can see
+// them: as a file name prefix ("8A - 124 - Artist - Title.mp3", which CDJs
+// show in their browser) or in the standard TKEY/TBPM frames (INITIALKEY/BPM
+// Vorbis comments for FLAC). Keys are re-spelled in the chosen notation.
+
+pub const DEFAULT_TEMPLATE: &str = "{key} - {bpm} - ";
+
+pub struct KeyConvention {
+    notation: KeyNotation,
+    // Prefix with {key} and {bpm} placeholders
+    template: String,
+    // Matches a prefix written with this template, so renaming twice doesn't
+    // stack prefixes
+    existing: Regex,
+}
+
+impl Default for KeyConvention {
+    fn default() -> Self {
+        Self::new(KeyNotation::Camelot)
+    }
+}
+
+impl KeyConvention {
+    pub fn new(notation: KeyNotation) -> Self {
+        Self::with_parts(notation, DEFAULT_TEMPLATE.to_string())
+    }
+
+    pub fn with_template(self, template: impl Into<String>) -> std::result::Result<Self, String> {
+        let template = template.into();
+        if !template.contains("{key}") && !template.contains("{bpm}") {
+            return Err(format!("Template needs {{key}} or {{bpm}}: {}", template));
+        }
+        Ok(Self::with_parts(self.notation, template))
+    }
+
+    fn with_parts(notation: KeyNotation, template: String) -> Self {
+        let pattern = regex::escape(&template)
+            .replace(r"\{key\}", r"[0-9A-Ga-g][0-9A-Za-z#♯♭]*")
+            .replace(r"\{bpm\}", r"\d{2,3}(?:\.\d+)?");
+        Self {
+            notation,
+            existing: Regex::new(&format!("^{}", pattern)).unwrap(),
+            template,
+        }
+    }
+
+    pub fn notation(&self) -> KeyNotation {
+        self.notation
+    }
+
+    pub fn format_key(&self, raw: &str) -> Option<String> {
+        MusicalKey::parse(raw).map(|key| key.format(self.notation))
+    }
+
+    // BPMs are rounded: players show whole numbers, and "124" sorts better
+    // than "123.98"
+    pub fn format_bpm(bpm: f64) -> String {
+        format!("{}", bpm.round() as u32)
+    }
+
+    // The prefix for a file, or None when a value the template uses is
+    // missing or not a key we understand
+    pub fn prefix(&self, file: &AudioFile) -> Option<String> {
+        let mut prefix = self.template.clone();
+        if prefix.contains("{key}") {
+            prefix = prefix.replace("{key}", &self.format_key(file.key.as_deref()?)?);
+        }
+        if prefix.contains("{bpm}") {
+            prefix = prefix.replace("{bpm}", &Self::format_bpm(file.bpm?));
+        }
+        Some(prefix)
+    }
+
+    // The file name with its prefix replaced (or added); None when there is
+    // no prefix to write
+    pub fn prefixed_name(&self, file: &AudioFile) -> Option<String> {
+        let prefix = self.prefix(file)?;
+        let bare = self.existing.replace(&file.file_name, "");
+        Some(format!("{}{}", prefix, bare))
+    }
+
+    // The key and BPM to write into a file's tags, each None when the tag
+    // already holds it; None overall when there's nothing to change
+    pub fn tag_update(&self, file: &AudioFile) -> Option<(Option<String>, Option<f64>)> {
+        let key = file.key.as_deref()
+            .and_then(|raw| self.format_key(raw).filter(|key| key != raw.trim()));
+        let bpm = file.bpm.filter(|bpm| bpm.fract() != 0.0);
+        (key.is_some() || bpm.is_some()).then_some((key, bpm))
+    }
+
+    // Renames for the files whose names don't carry their current prefix yet
+    pub fn rename_operations(&self, files: &[AudioFile]) -> Vec<FileOperation> {
+        files.iter()
+            .filter_map(|file| {
+                let name = self.prefixed_name(file)?;
+                let parent = file.path.parent()?;
+                (name != file.file_name).then(|| FileOperation::Move {
+                    from: file.path.clone(),
+                    to: parent.join(name),
+                })
+            })
+            .collect()
+    }
+}
+
+// Writes the key (in the convention's notation) and rounded BPM into the
+// standard fields. Missing values leave their field untouched. MP3 and FLAC
+// only.
+#[cfg(feature = "tags")]
+pub fn write_key_bpm_tags(path: &Path, key: Option<&str>, bpm: Option<f64>) -> Result<()> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+    let bpm = bpm.map(KeyConvention::format_bpm);
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(tag_error(e.to_string())),
+            };
+            if let Some(key) = key {
+                tag.set_text("TKEY", key);
+            }
+            if let Some(bpm) = bpm {
+                tag.set_text("TBPM", bpm);
+            }
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            if let Some(key) = key {
+                tag.set_vorbis("INITIALKEY", vec![key]);
+            }
+            if let Some(bpm) = bpm {
+                tag.set_vorbis("BPM", vec![bpm]);
+            }
+            tag.save().map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't write tags to {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
+
+pub fn is_taggable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "mp3" | "flac"))
+}
+
+// Track numbers recovered from file names, for compilations ripped or bought
+// with the number in the name ("03 - Artist - Title.mp3", "1-03 Title.flac"
+// for disc 1) but none in the tags. The prefix is read from the name as it
+// is, before title parsing strips it. A folder only counts when every file
+// in it has a prefix and no number repeats, so an artist like "808 State"
+// in a folder of mixed names isn't taken for track 808.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackNumbering {
+    pub path: PathBuf,
+    pub track: u32,
+    pub disc: Option<u32>,
+    // Tracks in the folder (or on the disc), when the numbers run 1..=total
+    pub total: Option<u32>,
+}
+
+// Disc and track number at the start of a file name
+pub fn track_number_prefix(file_name: &str) -> Option<(Option<u32>, u32)> {
+    static PREFIX: OnceLock<Regex> = OnceLock::new();
+    let prefix = PREFIX.get_or_init(|| {
+        Regex::new(r"^(?:(\d{1,2})[-.](\d{2,3})|(\d{1,3}))(?:\s*[-.)_]\s*|\s+)\S").unwrap()
+    });
+    let captures = prefix.captures(file_name)?;
+    let number = |index: usize| captures.get(index).and_then(|m| m.as_str().parse::<u32>().ok());
+    let (disc, track) = match number(3) {
+        Some(track) => (None, track),
+        None => (number(1), number(2)?),
+    };
+    (track > 0).then_some((disc, track))
+}
+
+// Numbering for every file in folders that qualify, and the folders that
+// don't (some file without a prefix, or a number used twice)
+pub fn infer_track_numbers(files: &[AudioFile]) -> (Vec<TrackNumbering>, Vec<PathBuf>) {
+    let mut folders: BTreeMap<&Path, Vec<&AudioFile>> = BTreeMap::new();
+    for file in files {
+        folders.entry(file.path.parent().unwrap_or(Path::new(""))).or_default().push(file);
+    }
+
+    let mut numbered = Vec::new();
+    let mut skipped = Vec::new();
+    for (folder, files) in folders {
+        let prefixes: Option<Vec<(Option<u32>, u32)>> = files.iter().map(|file| track_number_prefix(&file.file_name)).collect();
+        let Some(prefixes) = prefixes else {
+            skipped.push(folder.to_path_buf());
+            continue;
+        };
+        let unique: HashSet<&(Option<u32>, u32)> = prefixes.iter().collect();
+        if unique.len() != prefixes.len() {
+            skipped.push(folder.to_path_buf());
+            continue;
+        }
+
+        // Per disc: how many tracks and the highest number
+        let mut discs: BTreeMap<Option<u32>, (u32, u32)> = BTreeMap::new();
+        for (disc, track) in &prefixes {
+            let (count, highest) = discs.entry(*disc).or_default();
+            *count += 1;
+            *highest = (*highest).max(*track);
+        }
+        for (file, (disc, track)) in files.iter().zip(prefixes) {
+            let (count, highest) = discs[&disc];
+            numbered.push(TrackNumbering {
+                path: file.path.clone(),
+                track,
+                disc,
+                total: (count == highest).then_some(count),
+            });
+        }
+    }
+    (numbered, skipped)
+}
+
+// Track number as tagged ("3", "03" or "3/12")
+#[cfg(feature = "decode")]
+pub fn tagged_track_number(path: &Path) -> Option<u32> {
+    let tags = crate::audio::tags::read_tags(path).ok()?;
+    tags.get("TrackNumber")?.split('/').next()?.trim().parse().ok()
+}
+
+// Writes track number, total and disc into TRCK/TPOS (TRACKNUMBER,
+// TRACKTOTAL and DISCNUMBER for FLAC). MP3 and FLAC only.
+#[cfg(feature = "tags")]
+pub fn write_track_number(path: &Path, numbering: &TrackNumbering) -> Result<()> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(tag_error(e.to_string())),
+            };
+            tag.set_track(numbering.track);
+            match numbering.total {
+                Some(total) => tag.set_total_tracks(total),
+                None => tag.remove_total_tracks(),
+            }
+            if let Some(disc) = numbering.disc {
+                tag.set_disc(disc);
+            }
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            tag.set_vorbis("TRACKNUMBER", vec![numbering.track.to_string()]);
+            match numbering.total {
+                Some(total) => tag.set_vorbis("TRACKTOTAL", vec![total.to_string()]),
+                None => tag.remove_vorbis("TRACKTOTAL"),
+            }
+            if let Some(disc) = numbering.disc {
+                tag.set_vorbis("DISCNUMBER", vec![disc.to_string()]);
+            }
+            tag.save().map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't write tags to {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
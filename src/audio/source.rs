@@ -0,0 +1,11 @@
+use std::path::Path;
+use crate::{AudioFile, Result};
+
+// Where `AudioFile`s come from. The analysis code only ever sees `AudioFile`
+// values, so front-ends without filesystem or decoder access (e.g. a browser
+// build) can plug in their own source and still reuse the matching logic.
+pub trait MetadataSource: Send + Sync {
+    fn extract(&self, path: &Path) -> Result<AudioFile>;
+
+    fn scan(&self, root: &Path) -> Result<Vec<AudioFile>>;
+}
@@ -0,0 +1,220 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use crate::{AudioError, Result};
+use crate::utils::file_ops;
+
+// Size of the encoded audio alone, excluding tags, embedded artwork and
+// padding. Two copies of a track that only differ in their cover art have
+// the same audio size even though their file sizes differ.
+//
+// Returns None for formats this doesn't understand or unreadable headers.
+pub fn audio_stream_bytes(path: &Path) -> Option<u64> {
+    audio_stream_range(path).map(|(_, length)| length)
+}
+
+// Offset and length of the encoded audio within the file
+pub fn audio_stream_range(path: &Path) -> Option<(u64, u64)> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "mp3" => mp3_audio_range(&mut file, size),
+        "flac" => flac_audio_range(&mut file, size),
+        "wav" => wav_audio_range(&mut file),
+        "m4a" => mp4_audio_range(&mut file, size),
+        "aiff" | "aif" => aiff_audio_range(&mut file),
+        _ => None,
+    }
+}
+
+// Length of the tag block in front of the audio: the ID3v2 tag of an MP3,
+// or the FLAC marker plus every metadata block (tags, pictures, seek table).
+// Replacing these bytes swaps the tags without touching the audio.
+pub fn leading_tag_bytes(path: &Path) -> Option<u64> {
+    let mut file = File::open(path).ok()?;
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "mp3" => id3v2_length(&mut file),
+        "flac" => flac_metadata_length(&mut file),
+        _ => None,
+    }
+}
+
+// The leading tag block itself
+pub fn read_leading_tags(path: &Path) -> Result<Vec<u8>> {
+    let length = leading_tag_bytes(path).ok_or_else(|| unsupported_tag_block(path))?;
+    let mut tags = vec![0u8; length as usize];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut tags))
+        .map_err(|e| AudioError::io(path, e))?;
+    Ok(tags)
+}
+
+// Swaps the file's leading tag block for `tags`, keeping the audio. The new
+// file is written alongside and renamed over the old one, so a failure
+// never leaves a half-written track behind.
+pub fn replace_leading_tags(path: &Path, tags: &[u8]) -> Result<()> {
+    file_ops::ensure_writable(path)?;
+    let audio_start = leading_tag_bytes(path).ok_or_else(|| unsupported_tag_block(path))? as usize;
+    let current = std::fs::read(path).map_err(|e| AudioError::io(path, e))?;
+    let audio = current.get(audio_start..).unwrap_or_default();
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tags-tmp");
+    let temp = path.with_file_name(temp_name);
+    std::fs::write(&temp, [tags, audio].concat()).map_err(|e| AudioError::io(&temp, e))?;
+    std::fs::rename(&temp, path).map_err(|e| AudioError::io(path, e))
+}
+
+fn unsupported_tag_block(path: &Path) -> AudioError {
+    AudioError::UnsupportedFormat(format!(
+        "No tag block to save or replace in {} (only MP3 and FLAC)", path.display()
+    ))
+}
+
+fn id3v2_length(file: &mut File) -> Option<u64> {
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..3] != b"ID3" {
+        return Some(0);
+    }
+    let tag_size = synchsafe(&header[6..10]);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + tag_size + footer)
+}
+
+// ID3v2 at the start (optionally with a footer), ID3v1 and APEv2 at the end
+fn mp3_audio_range(file: &mut File, size: u64) -> Option<(u64, u64)> {
+    let start = id3v2_length(file)?;
+
+    let mut end = size;
+    if end >= 128 {
+        let mut v1 = [0u8; 3];
+        file.seek(SeekFrom::Start(end - 128)).ok()?;
+        file.read_exact(&mut v1).ok()?;
+        if &v1 == b"TAG" {
+            end -= 128;
+        }
+    }
+    if end >= 32 {
+        let mut ape = [0u8; 32];
+        file.seek(SeekFrom::Start(end - 32)).ok()?;
+        file.read_exact(&mut ape).ok()?;
+        if &ape[0..8] == b"APETAGEX" {
+            // The size excludes the 32-byte header, present if flagged
+            let tag_size = u32::from_le_bytes(ape[12..16].try_into().ok()?) as u64;
+            let flags = u32::from_le_bytes(ape[20..24].try_into().ok()?);
+            let has_header = flags & 0x8000_0000 != 0;
+            end = end.saturating_sub(tag_size + if has_header { 32 } else { 0 });
+        }
+    }
+
+    Some((start, end.checked_sub(start)?))
+}
+
+// Audio frames start right after the last metadata block
+fn flac_audio_range(file: &mut File, size: u64) -> Option<(u64, u64)> {
+    let start = flac_metadata_length(file)?;
+    Some((start, size.checked_sub(start)?))
+}
+
+fn flac_metadata_length(file: &mut File) -> Option<u64> {
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).ok()?;
+    if &magic != b"fLaC" {
+        return None;
+    }
+
+    let mut offset = 4u64;
+    loop {
+        let mut block = [0u8; 4];
+        file.read_exact(&mut block).ok()?;
+        let is_last = block[0] & 0x80 != 0;
+        let length = u32::from_be_bytes([0, block[1], block[2], block[3]]) as u64;
+        offset += 4 + length;
+        if is_last {
+            break;
+        }
+        file.seek(SeekFrom::Start(offset)).ok()?;
+    }
+    Some(offset)
+}
+
+// Size of the `data` chunk; LIST/id3 chunks are metadata
+fn wav_audio_range(file: &mut File) -> Option<(u64, u64)> {
+    let mut riff = [0u8; 12];
+    file.read_exact(&mut riff).ok()?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut offset = 12u64;
+    loop {
+        let mut chunk = [0u8; 8];
+        file.read_exact(&mut chunk).ok()?;
+        let length = u32::from_le_bytes(chunk[4..8].try_into().ok()?) as u64;
+        if &chunk[0..4] == b"data" {
+            return Some((offset + 8, length));
+        }
+        offset += 8 + length + (length & 1);
+        file.seek(SeekFrom::Start(offset)).ok()?;
+    }
+}
+
+// The `mdat` atom; the `moov` atom around it holds the tags and artwork
+fn mp4_audio_range(file: &mut File, size: u64) -> Option<(u64, u64)> {
+    let mut offset = 0u64;
+    while offset + 8 <= size {
+        let mut atom = [0u8; 8];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut atom).ok()?;
+        let (mut header, mut length) = (8, u32::from_be_bytes(atom[0..4].try_into().ok()?) as u64);
+        match length {
+            // 64-bit size follows the type
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large).ok()?;
+                header = 16;
+                length = u64::from_be_bytes(large);
+            }
+            // Runs to the end of the file
+            0 => length = size - offset,
+            _ => {}
+        }
+        if length < header {
+            return None;
+        }
+        if &atom[4..8] == b"mdat" {
+            return Some((offset + header, length - header));
+        }
+        offset += length;
+    }
+    None
+}
+
+// The `SSND` chunk of the big-endian IFF form; NAME, ID3 and other chunks
+// are metadata
+fn aiff_audio_range(file: &mut File) -> Option<(u64, u64)> {
+    let mut form = [0u8; 12];
+    file.read_exact(&mut form).ok()?;
+    if &form[0..4] != b"FORM" || !matches!(&form[8..12], b"AIFF" | b"AIFC") {
+        return None;
+    }
+
+    let mut offset = 12u64;
+    loop {
+        let mut chunk = [0u8; 8];
+        file.read_exact(&mut chunk).ok()?;
+        let length = u32::from_be_bytes(chunk[4..8].try_into().ok()?) as u64;
+        if &chunk[0..4] == b"SSND" {
+            return Some((offset + 8, length));
+        }
+        offset += 8 + length + (length & 1);
+        file.seek(SeekFrom::Start(offset)).ok()?;
+    }
+}
+
+fn synchsafe(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| (acc << 7) | (b & 0x7f) as u64)
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use symphonia::core::meta::{MetadataRevision, Value};
+use crate::Result;
+use crate::audio::decode;
+use crate::utils::hashing;
+
+// Every tag of a file as display strings, keyed by field name. Standard
+// fields use Symphonia's names (Artist, Rating, Comment, ...); anything else
+// keeps its raw frame/comment key, so DJ software data (Serato markers,
+// Traktor PRIV frames) shows up too. Binary values are summarized by length
+// and hash so two differing blobs can still be told apart.
+pub type TagMap = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagDifference {
+    pub field: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+pub fn read_tags(path: &Path) -> Result<TagMap> {
+    let mut probed = decode::probe(path)?;
+    let mut tags = TagMap::new();
+
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            collect(revision, &mut tags);
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        collect(revision, &mut tags);
+    }
+
+    // Symphonia skips ID3 GEOB frames, which is where Serato keeps cues,
+    // beat grids and overviews in MP3s
+    #[cfg(feature = "tags")]
+    if let Ok(id3_tag) = id3::Tag::read_from_path(path) {
+        for object in id3_tag.encapsulated_objects() {
+            insert(&mut tags, format!("GEOB:{}", object.description), describe_binary(&object.data));
+        }
+    }
+
+    Ok(tags)
+}
+
+// Fields whose values differ, including fields only one side has
+pub fn diff_tags(left: &TagMap, right: &TagMap) -> Vec<TagDifference> {
+    let mut fields: Vec<&String> = left.keys().chain(right.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    fields.into_iter()
+        .filter(|field| left.get(*field) != right.get(*field))
+        .map(|field| TagDifference {
+            field: field.clone(),
+            left: left.get(field).cloned(),
+            right: right.get(field).cloned(),
+        })
+        .collect()
+}
+
+fn collect(revision: &MetadataRevision, tags: &mut TagMap) {
+    for tag in revision.tags() {
+        let field = match tag.std_key {
+            Some(key) => format!("{:?}", key),
+            None => tag.key.clone(),
+        };
+        let value = match &tag.value {
+            Value::Binary(data) => describe_binary(data),
+            other => other.to_string(),
+        };
+        insert(tags, field, value);
+    }
+}
+
+// Repeated fields (several comments, several GEOB objects) are joined
+fn insert(tags: &mut TagMap, field: String, value: String) {
+    tags.entry(field)
+        .and_modify(|existing| {
+            existing.push_str(" | ");
+            existing.push_str(&value);
+        })
+        .or_insert(value);
+}
+
+fn describe_binary(data: &[u8]) -> String {
+    let hash = hashing::sha256_bytes(data);
+    format!("<{} bytes, sha256 {}>", data.len(), &hash[..12])
+}
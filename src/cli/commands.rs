@@ -1,5 +1,17 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use clap::error::ErrorKind;
 use std::path::PathBuf;
+use crate::analyzers::bpm;
+use crate::analyzers::drift;
+use crate::analyzers::duplicate::{MatchChain, MatchMode};
+use crate::audio::retag;
+use crate::cli::config::Config;
+use crate::cli::options::{self, CollectionOpts, parse_date, parse_fraction, parse_size, writable_dir, ReportOpts, ScanOpts};
+use crate::cli::routine::Routine;
+use crate::exporters::keys::KeyNotation;
+use crate::exporters::sync;
+use crate::utils::script::ScriptFormat;
 
 #[derive(Parser)]
 #[command(name = "dj-library-manager")]
@@ -9,33 +21,1041 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Settings file with defaults for input, output, formats, match mode, similarity, threads and report format (default: ~/.config/dj-library-manager/config.toml)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Never write, move or delete library files, whatever the command asks for (reports are still written)
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    /// Remove the library index lock left by a run that is no longer active, e.g. one that crashed on another machine
+    #[cfg(feature = "index")]
+    #[arg(long, global = true)]
+    pub force_unlock: bool,
+}
+
+impl Cli {
+    // Parses the command line over the defaults from the settings file
+    pub fn parse_with_config() -> Self {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let config = Config::load_for_args(&args)
+            .unwrap_or_else(|e| Self::command().error(ErrorKind::InvalidValue, e).exit());
+        let matches = config.apply(Self::command()).get_matches_from(args);
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut Self::command()).exit())
+    }
+
+    // Checks that involve several arguments, which value parsers can't
+    // make; errors read like clap's own
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        if let Commands::Duplicates { scan, output: Some(output), .. } = &self.command {
+            // An output below an input is left out of the scan, but an
+            // input itself can't be
+            if let Some(input) = scan.input.iter().find(|input| options::resolve(output) == options::resolve(input)) {
+                return Err(Self::command().error(ErrorKind::ArgumentConflict, format!(
+                    "the output directory {} is the input directory {}; duplicates must be moved out of the scanned folders",
+                    output.display(), input.display(),
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Find and manage duplicate audio files
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Duplicates {
-        /// Directory to scan for duplicates
-        #[arg(short = 'i', long = "input")]
-        input: PathBuf,
+        #[command(subcommand)]
+        command: Option<DuplicatesCommand>,
+
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Directory to move duplicates to (left out of the scan when inside an input directory)
+        #[arg(short = 'o', long = "output", value_parser = writable_dir, required = true)]
+        output: Option<PathBuf>,
+
+        /// Verify each moved file against its source before continuing
+        #[arg(long)]
+        verify: bool,
+
+        /// Memory budget in MB for duplicate candidates before spilling to a temporary store
+        #[arg(long = "memory-budget", default_value_t = 512)]
+        memory_budget: usize,
+
+        /// What makes files duplicates: name (artist and title) or fingerprint (the audio itself)
+        #[arg(long = "match-mode", default_value = "name")]
+        match_mode: MatchMode,
+
+        /// Where artist and title come from, in order of preference: isrc, tags, filename; add fuzzy to also pair tracks a typo apart
+        #[arg(long = "match-on", value_name = "SOURCES", default_value = "tags,filename")]
+        match_on: MatchChain,
+
+        /// Also pair tracks whose artist and title are at least this similar (0 to 1, e.g. 0.9), catching typos and punctuation
+        #[arg(long, value_parser = parse_fraction)]
+        similarity: Option<f64>,
+
+        /// Only compare files whose durations and sizes could belong to the same recording
+        #[arg(long)]
+        prefilter: bool,
+
+        /// Show a field-by-field tag diff (cues, rating, comments, ...) between each duplicate and the copy kept before acting
+        #[arg(long)]
+        explain: bool,
+
+        /// Approval list (JSON) naming the match classes to act on; matches of other classes are only reported
+        #[arg(long, value_name = "FILE")]
+        approval: Option<PathBuf>,
+
+        /// Maximum duration difference in seconds for --prefilter and fingerprint matching
+        #[arg(long = "duration-tolerance", default_value_t = 2.0)]
+        duration_tolerance: f64,
+
+        /// Library root in order of preference, highest first (repeatable); equal copies are kept on the preferred root
+        #[arg(long = "priority-root", value_name = "DIR")]
+        priority_root: Vec<PathBuf>,
+
+        /// Move each kept copy onto the preferred root when the copy it replaces lived there
+        #[arg(long = "relocate-best", requires = "priority_root")]
+        relocate_best: bool,
+
+        /// Write the planned moves to this script (PowerShell for .ps1, sh otherwise) instead of performing them
+        #[arg(long, value_name = "FILE")]
+        script: Option<PathBuf>,
+
+        /// Script format: sh or powershell (default: from the --script extension)
+        #[arg(long = "script-format", requires = "script")]
+        script_format: Option<ScriptFormat>,
+
+        /// In the --script, delete lower-quality copies instead of moving them to the output directory
+        #[arg(long = "script-delete", requires = "script")]
+        script_delete: bool,
+
+        /// Rename "Title - Artist" files to "Artist - Title" before matching
+        #[arg(long = "fix-swapped-names")]
+        fix_swapped_names: bool,
+
+        /// Keep leading numbers in file names when matching ("01 - " is normally dropped as a track number)
+        #[arg(long = "keep-track-numbers")]
+        keep_track_numbers: bool,
+
+        /// Also learn artists from this index to spot "Title - Artist" names of untagged files
+        #[cfg(feature = "index")]
+        #[arg(long = "artist-index", value_name = "INDEX")]
+        artist_index: Option<PathBuf>,
+
+        /// Only act on and report this many duplicate groups
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Skip this many duplicate groups first (with --limit or --top-by-waste, to page through them)
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Only act on and report the N groups whose lower-quality copies take the most space
+        #[arg(long = "top-by-waste", value_name = "N", conflicts_with = "limit")]
+        top_by_waste: Option<usize>,
+
+        /// Serato library folder (`_Serato_`); duplicates listed in its crates are flagged
+        #[arg(long, value_name = "DIR")]
+        serato: Option<PathBuf>,
+
+        /// Volume root the Serato paths are relative to (default: the drive holding `_Serato_`)
+        #[arg(long = "serato-root", requires = "serato")]
+        serato_root: Option<PathBuf>,
+
+        /// Point crate entries of moved duplicates at the copy kept (originals backed up under the output directory)
+        #[arg(long = "rewrite-crates", requires = "serato")]
+        rewrite_crates: bool,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Analyze audio files bitrates
+    Bitrate {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Folder to create each run's report folder in (a `latest` link points at the newest)
+        #[arg(short = 'o', long = "output", value_parser = writable_dir)]
+        output: PathBuf,
+
+        /// Measure how steady each track's tempo is and flag tracks a static beatgrid won't fit
+        #[arg(long = "tempo-stability")]
+        tempo_stability: bool,
+
+        /// Seconds of audio to analyze per file for --tempo-stability
+        #[arg(long = "analyze-secs", default_value_t = 600, requires = "tempo_stability")]
+        analyze_secs: u64,
+
+        /// Also report average quality per artist
+        #[arg(long = "by-artist")]
+        by_artist: bool,
+
+        /// Also report average quality per label, to spot promo pools sending low-quality files
+        #[arg(long = "by-label")]
+        by_label: bool,
+
+        /// Minimum quality per folder or genre (JSON); files below theirs are listed in `policy.csv`
+        #[arg(long)]
+        policy: Option<PathBuf>,
 
-        /// Directory to move duplicates to
+        /// Move placeholder-size files (failed downloads, listed in `placeholders.csv`) into this directory
+        #[arg(long = "quarantine-placeholders", value_parser = writable_dir)]
+        quarantine_placeholders: Option<PathBuf>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Scan once and run several steps over the result, e.g. a routine of quality check, duplicates and reports
+    Run {
+        /// Steps in order: scan, quality, duplicates, report, separated by commas or `->`
+        #[arg(value_name = "STEPS", default_value = "scan,quality,duplicates,report")]
+        routine: Routine,
+
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Folder for the run's report folder and, in `duplicates/`, the duplicates moved out
+        #[arg(short = 'o', long = "output", value_parser = writable_dir)]
+        output: PathBuf,
+
+        /// Minimum quality per folder or genre (JSON) for the quality step; files below theirs are listed in `policy.csv`
+        #[arg(long)]
+        policy: Option<PathBuf>,
+
+        /// Approval list (JSON) naming the match classes the duplicates step moves; the rest are only reported
+        #[arg(long, value_name = "FILE")]
+        approval: Option<PathBuf>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Audit embedded cover art size and resolution
+    #[cfg(feature = "artwork")]
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Artwork {
+        #[command(subcommand)]
+        command: Option<ArtworkCommand>,
+
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output", required = true)]
+        output: Option<PathBuf>,
+
+        /// Flag covers smaller than this many pixels on either side
+        #[arg(long = "min-size", default_value_t = 500)]
+        min_size: u32,
+
+        /// Flag covers larger than this many kilobytes
+        #[arg(long = "max-kb", default_value_t = 1024)]
+        max_kb: usize,
+
+        /// Shrink flagged oversized covers to fit within this many pixels and re-embed them (MP3/FLAC)
+        #[arg(long, value_name = "PX", num_args = 0..=1, default_missing_value = "1000")]
+        resize: Option<u32>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Flag lossless files whose bit depth or spectrum don't back up a hi-res claim
+    Authenticity {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
         #[arg(short = 'o', long = "output")]
         output: PathBuf,
 
-        /// Only detect duplicates without moving files
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 60)]
+        analyze_secs: u64,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Flag explicit tracks from their advisory tags, optionally transcribing untagged ones
+    Explicit {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Speech-to-text command printing the lyrics to stdout; `{}` is replaced by the file path
+        #[arg(long, value_name = "COMMAND")]
+        transcriber: Option<String>,
+
+        /// Words to flag in transcripts, one per line (`stem*` matches any ending)
+        #[arg(long, value_name = "FILE", requires = "transcriber")]
+        wordlist: Option<PathBuf>,
+
+        /// Write the tracks not flagged explicit to this M3U8 playlist
+        #[arg(long, value_name = "M3U")]
+        clean: Option<PathBuf>,
+
+        /// Leave tracks that couldn't be rated out of the clean playlist
+        #[arg(long, requires = "clean")]
+        strict: bool,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// List tracks that only exist in a dirty promo pool version
+    DirtyOnly {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Check a wish-list against the library and list the tracks still to buy
+    Wishlist {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Wish-list: CSV with artist and title columns, or Spotify's YourLibrary.json
+        #[arg(short = 'w', long = "wishlist")]
+        wishlist: PathBuf,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Detect vocals and classify tracks as vocal or instrumental
+    Vocals {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Write the classification into each file's VOCALS tag (MP3/FLAC)
+        #[arg(long)]
+        tag: bool,
+
+        /// Write the instrumental tracks to this M3U8 playlist
+        #[arg(long, value_name = "M3U")]
+        instrumental: Option<PathBuf>,
+
+        /// Write the vocal tracks to this M3U8 playlist
+        #[arg(long, value_name = "M3U")]
+        vocal: Option<PathBuf>,
+
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 600)]
+        analyze_secs: u64,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Detect each track's BPM, report it next to the tagged one and optionally write it into the tags
+    Bpm {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Write the detected BPM into files without a BPM tag (TBPM on MP3, BPM on FLAC)
+        #[arg(long)]
+        tag: bool,
+
+        /// With --tag, also replace BPM tags that differ from the detected tempo
+        #[arg(long, requires = "tag")]
+        overwrite: bool,
+
+        /// Lowest BPM to report; slower results are doubled
+        #[arg(long = "min-bpm", default_value_t = bpm::DEFAULT_MIN_BPM)]
+        min_bpm: f64,
+
+        /// Highest BPM to report; faster results are halved
+        #[arg(long = "max-bpm", default_value_t = bpm::DEFAULT_MAX_BPM)]
+        max_bpm: f64,
+
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 180)]
+        analyze_secs: u64,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Suggest four hot cues per track (intro, drop, breakdown, second drop or outro) from its structure
+    Cues {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Output JSON file with the suggested cues
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Also write a Rekordbox XML collection with the cues as hot cues
+        #[arg(long, value_name = "XML")]
+        rekordbox: Option<PathBuf>,
+
+        /// Also write a Traktor NML collection with the cues as hot cues and the BPM tags
+        #[arg(long, value_name = "NML")]
+        traktor: Option<PathBuf>,
+
+        /// Write the cues into each file's Serato markers (MP3/FLAC; files that already have Serato markers are left alone)
+        #[arg(long)]
+        serato: bool,
+
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 900)]
+        analyze_secs: u64,
+    },
+
+    /// Compare two duplicate analysis runs (default: the two most recent)
+    DiffRuns {
+        /// Older run: a run ID or path to a run record
+        old: Option<String>,
+
+        /// Newer run: a run ID or path to a run record
+        new: Option<String>,
+
+        /// Also write the comparison as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// List every move and delete recorded in the operation journals
+    Audit {
+        /// Journal directory to read (repeatable; default: this user's journal directory)
+        #[arg(long = "journal-dir", value_name = "DIR")]
+        journal_dir: Vec<PathBuf>,
+
+        /// Only operations on or after this day (YYYY-MM-DD, UTC)
+        #[arg(long, value_parser = parse_date)]
+        since: Option<u64>,
+
+        /// Only operations on or before this day (YYYY-MM-DD, UTC)
+        #[arg(long, value_parser = parse_date)]
+        until: Option<u64>,
+
+        /// Only operations whose source or destination is at or under this path
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Only operations run by this user
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Also list copies
+        #[arg(long = "include-copies")]
+        include_copies: bool,
+
+        /// Write the operations to this CSV file instead of listing them
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Move files back to where they were before a `duplicates` or `run` command, from its operation journal
+    Undo {
+        /// Journal to undo (default: the most recent `duplicates` or `run` journal with moves)
+        #[arg(long)]
+        journal: Option<PathBuf>,
+
+        /// Only list what would be moved back
         #[arg(short = 'd', long)]
         dry_run: bool,
     },
 
-    /// Analyze audio files bitrates
-    Bitrate {
-        /// Directory to scan for audio files
+    /// Export library data for use elsewhere
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
+
+    /// Mirror a folder onto a drive, copying new and changed files
+    Sync {
+        /// Folder to sync from (e.g. the library)
+        #[arg(long)]
+        source: PathBuf,
+
+        /// Folder or drive to sync to (e.g. a gig stick)
+        #[arg(long)]
+        target: PathBuf,
+
+        /// Delete files from the target that are no longer in the source, once past the retention period
+        #[arg(long = "allow-delete")]
+        allow_delete: bool,
+
+        /// Days a file removed from the source is kept on the target before it may be deleted
+        #[arg(long = "retention-days", default_value_t = sync::DEFAULT_RETENTION_DAYS)]
+        retention_days: u64,
+
+        /// Only show what would change
+        #[arg(short = 'd', long)]
+        dry_run: bool,
+    },
+
+    /// Work with exported library manifests
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
+
+    /// Work with M3U/M3U8 playlists
+    Playlist {
+        #[command(subcommand)]
+        command: PlaylistCommand,
+    },
+
+    /// Back up the library (or only its tags) to an incremental, checksummed store
+    Backup {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Backup directory (e.g. on another drive); created if missing
+        #[arg(long)]
+        target: PathBuf,
+
+        /// Only back up the tag block of each MP3/FLAC file, not the audio
+        #[arg(long = "tags-only")]
+        tags_only: bool,
+
+        /// Leave the library index database out of the backup
+        #[arg(long = "no-index")]
+        no_index: bool,
+    },
+
+    /// Restore files from a backup made with `backup`
+    Restore {
+        /// Backup directory
+        #[arg(long)]
+        target: PathBuf,
+
+        /// Snapshot ID to restore (default: the latest)
+        snapshot: Option<String>,
+
+        /// Restore below this directory instead of the original locations
+        #[arg(long)]
+        to: Option<PathBuf>,
+
+        /// Only list what would be restored
+        #[arg(short = 'd', long)]
+        dry_run: bool,
+    },
+
+    /// Save every file's tags to a vault file, or put them back from one
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommand,
+    },
+
+    /// Write each track's key and BPM into its file name ("8A - 124 - ...") and/or its TKEY/TBPM tags
+    #[command(group(clap::ArgGroup::new("target").args(["filename", "tags"]).required(true).multiple(true)))]
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Retag {
+        #[command(subcommand)]
+        command: Option<RetagCommand>,
+
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Prefix file names with key and BPM
+        #[arg(long)]
+        filename: bool,
+
+        /// Rewrite the key and BPM tags (TKEY/TBPM, INITIALKEY/BPM for FLAC) in the chosen notation
+        #[arg(long)]
+        tags: bool,
+
+        /// Key notation: camelot, classical or openkey
+        #[arg(long, default_value = "camelot")]
+        notation: KeyNotation,
+
+        /// File name prefix, with {key} and {bpm} placeholders
+        #[arg(long, default_value = retag::DEFAULT_TEMPLATE)]
+        template: String,
+    },
+
+    /// Forecast storage needs for the next year and estimate what converting WAV to FLAC would reclaim
+    Storage {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Also write the forecast as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Find multi-disc albums split into one folder per disc, and merge them
+    Discs {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Move each album's tracks into one folder, prefixing file names with the disc number
+        #[arg(long)]
+        merge: bool,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Find files that moved since DJ software last saw them
+    Relocate {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        #[command(flatten)]
+        collection: CollectionOpts,
+
+        /// Where to write the relocation mapping (JSON)
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Write a copy of the Rekordbox XML with the new locations filled in
+        #[arg(long = "write-xml")]
+        write_xml: Option<PathBuf>,
+
+        /// Write a copy of the Traktor NML with the new locations filled in, keeping play counts, cues and playlists
+        #[arg(long = "write-nml")]
+        write_nml: Option<PathBuf>,
+    },
+
+    /// Find tracks whose length changed since DJ software analyzed them
+    Drift {
+        #[command(flatten)]
+        collection: CollectionOpts,
+
+        /// Largest length difference in seconds still treated as a match
+        #[arg(long, default_value_t = drift::DEFAULT_TOLERANCE_SECS)]
+        tolerance: f64,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+
+    /// Watch folders and run new files through a tag, rename and move pipeline
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Watch {
+        #[cfg(feature = "library-watch")]
+        #[command(subcommand)]
+        command: Option<WatchCommand>,
+
+        /// Pipeline configuration (JSON)
+        #[arg(short = 'c', long = "config", required = true)]
+        config: Option<PathBuf>,
+
+        /// Process what is ready now and exit instead of watching
+        #[arg(long)]
+        once: bool,
+
+        /// Only show what would change; never tag, rename or move files
+        #[arg(short = 'd', long)]
+        dry_run: bool,
+    },
+
+    /// Check the environment and library folders for common problems
+    Doctor {
+        /// Library directory to check for read access (repeatable)
         #[arg(short = 'i', long = "input")]
-        input: PathBuf,
+        input: Vec<PathBuf>,
+
+        /// Output directory to check for write access (repeatable)
+        #[arg(short = 'o', long = "output")]
+        output: Vec<PathBuf>,
+    },
+
+    /// Manage the persistent library index
+    #[cfg(feature = "index")]
+    Index {
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index", global = true)]
+        path: Option<PathBuf>,
+
+        #[command(subcommand)]
+        command: IndexCommand,
+    },
+
+    /// Search the library index by artist, title, album or file name
+    #[cfg(feature = "index")]
+    Search {
+        /// Words to look for; every word must match
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index")]
+        index: Option<PathBuf>,
+
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Also write the matches as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Verify files against checksums stored in the index to catch silent corruption
+    #[cfg(feature = "index")]
+    Scrub {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index")]
+        index: Option<PathBuf>,
+
+        /// Skip files verified within this many days
+        #[arg(long = "recheck-days", default_value_t = 0)]
+        recheck_days: u64,
+
+        /// Also write every result as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Estimate 16/32-bar phrasing per track, store it in the index and list irregular tracks
+    #[cfg(feature = "index")]
+    Phrasing {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index")]
+        index: Option<PathBuf>,
+
+        /// Analyze files again even if the index has a result for them
+        #[arg(long)]
+        reanalyze: bool,
+
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 900)]
+        analyze_secs: u64,
+
+        /// Also write the phrasing of every scanned file as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+
+    /// Detect each track's musical key, store it in the index and report it in standard and Camelot notation
+    #[cfg(feature = "index")]
+    Key {
+        #[command(flatten)]
+        scan: ScanOpts,
 
         /// Output CSV file path
         #[arg(short = 'o', long = "output")]
         output: PathBuf,
+
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index")]
+        index: Option<PathBuf>,
+
+        /// Analyze files again even if the index has a result for them
+        #[arg(long)]
+        reanalyze: bool,
+
+        /// Seconds of audio to analyze per file
+        #[arg(long = "analyze-secs", default_value_t = 300)]
+        analyze_secs: u64,
+
+        #[command(flatten)]
+        report: ReportOpts,
     },
-}
\ No newline at end of file
+
+    /// Write the JSON schemas of the documents returned by the bindings
+    #[cfg(feature = "schema")]
+    Schema {
+        /// Directory to write the schema files to
+        #[arg(short, long, default_value = "schemas")]
+        output: PathBuf,
+    },
+
+    /// Check GitHub for a newer release and install it
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether an update is available
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExportCommand {
+    /// Write a share-safe manifest (hashes, artist/title, quality; no paths)
+    Manifest {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Manifest file to write (JSON)
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+
+    /// Copy the best copy of each track to a drive, fitting a size budget
+    Usb {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Drive or folder to export to
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Size budget, e.g. 64G, 500MB or 60GiB
+        #[arg(long, value_parser = parse_size)]
+        budget: u64,
+
+        /// Use a smaller copy of a track when the best one doesn't fit
+        #[arg(long)]
+        downgrade: bool,
+
+        /// Transcode to MP3 at this bitrate (kbps) with ffmpeg when a track doesn't fit otherwise
+        #[arg(long = "transcode", value_name = "KBPS")]
+        transcode: Option<u32>,
+
+        /// Where to write the export manifest (default: export-manifest.json on the target)
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+    },
+}
+
+// Import carries the scan options the other two don't need
+#[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
+pub enum PlaylistCommand {
+    /// Reorder a playlist by harmonic compatibility (Camelot key) and BPM progression
+    Sort {
+        /// Playlist to sort (M3U/M3U8)
+        playlist: PathBuf,
+
+        /// Sorted playlist to write (default: <name>-sorted.m3u8 next to the original)
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Also write the new order with keys, BPMs and transition scores as JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+
+    /// Score every possible transition between a playlist's tracks (key relation and BPM change)
+    Matrix {
+        /// Playlist to analyze (M3U/M3U8)
+        playlist: PathBuf,
+
+        /// Output file: an HTML heatmap for .html/.htm, CSV otherwise
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+
+    /// Match a Spotify or Apple Music playlist export against the library and report what is owned, in what quality
+    Import {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Exported playlist: Exportify CSV, Apple Music text export, or Spotify's YourLibrary.json
+        playlist: PathBuf,
+
+        /// Output CSV file path
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+
+        /// Also write an M3U8 playlist of the local copies of owned tracks
+        #[arg(long)]
+        m3u: Option<PathBuf>,
+
+        #[command(flatten)]
+        report: ReportOpts,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ManifestCommand {
+    /// List tracks each side is missing and quality upgrades available
+    Diff {
+        /// Your manifest
+        mine: PathBuf,
+
+        /// The other party's manifest
+        theirs: PathBuf,
+
+        /// Also write the comparison as JSON
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[cfg(feature = "index")]
+#[derive(Subcommand)]
+pub enum IndexCommand {
+    /// Scan directories and add or refresh their files in the index
+    Update {
+        #[command(flatten)]
+        scan: ScanOpts,
+    },
+
+    /// Remove records for missing files and rebuild derived tables
+    Repair {
+        /// Also compact the database file afterwards
+        #[arg(long)]
+        vacuum: bool,
+    },
+
+    /// Compact the database file
+    Vacuum,
+
+    /// Show schema version, record count and integrity
+    Status,
+
+    /// List the drives the index knows about and how many of their files are offline
+    Volumes,
+
+    /// Track a directory as a volume of its own (for shares or drives without a UUID)
+    MarkVolume {
+        /// Directory to mark as the volume root
+        dir: PathBuf,
+
+        /// Volume ID to record (default: generated)
+        #[arg(long)]
+        id: Option<String>,
+    },
+}
+
+#[cfg(feature = "artwork")]
+#[derive(Subcommand)]
+pub enum ArtworkCommand {
+    /// Write each folder's embedded cover to a picture file next to the tracks, where missing
+    Extract {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Name of the cover file to write in each folder (.jpg or .png)
+        #[arg(long = "as", value_name = "NAME", default_value = "folder.jpg", value_parser = options::parse_cover_name)]
+        file_name: String,
+
+        /// Replace cover files that already exist
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[cfg(feature = "library-watch")]
+#[derive(Subcommand)]
+pub enum WatchCommand {
+    /// Index files as they are added to the library and flag the ones that duplicate an indexed track
+    Library {
+        /// Library folders to watch (repeat for several)
+        #[arg(short = 'i', long = "input", required = true, value_parser = options::existing_dir)]
+        input: Vec<PathBuf>,
+
+        /// Index database path (default: in the per-user data directory)
+        #[arg(long = "index")]
+        index: Option<PathBuf>,
+
+        /// Seconds a file must go unchanged before it is read, so copies and downloads in progress are left alone
+        #[arg(long = "settle-secs", default_value_t = 5)]
+        settle_secs: u64,
+
+        /// Append every indexed file and flagged duplicate to this JSON-lines log
+        #[arg(long = "jsonl-log")]
+        jsonl_log: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DuplicatesCommand {
+    /// Pairs reviewed as different tracks, never reported as duplicates again
+    Ignore {
+        #[command(subcommand)]
+        command: IgnoreCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IgnoreCommand {
+    /// Stop reporting two files as duplicates of each other
+    Add {
+        file_a: PathBuf,
+        file_b: PathBuf,
+
+        /// Why they aren't duplicates, shown by `list`
+        #[arg(long)]
+        note: Option<String>,
+    },
+
+    /// Show the ignored pairs
+    List,
+
+    /// Report two files as duplicates again
+    Remove {
+        file_a: PathBuf,
+        file_b: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RetagCommand {
+    /// Take track numbers from file name prefixes ("03 - ...") and write them into the tags, for compilations without numbering
+    #[command(name = "tracknumbers", alias = "track-numbers")]
+    TrackNumbers {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Also replace tagged track numbers that differ from the file name
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommand {
+    /// Snapshot the tags, cues and artwork of every MP3/FLAC file into a vault
+    Save {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Vault file to write
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+    },
+
+    /// Re-apply saved tags to files whose audio matches, wherever they are now
+    Restore {
+        #[command(flatten)]
+        scan: ScanOpts,
+
+        /// Vault file to read
+        #[arg(long)]
+        vault: PathBuf,
+    },
+}
@@ -0,0 +1,125 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Command;
+use serde::Deserialize;
+use crate::{AudioError, Result};
+use crate::utils::paths;
+
+// Defaults for the command line, read from `config.toml` in the config
+// directory or the file given with --config. A value from the file stands in
+// for a flag left off the command line, in every command that has the flag;
+// a flag given on the command line wins. For example:
+//
+//   input = ["~/Music/Library"]
+//   output = "~/Music/Duplicates"
+//   formats = ["mp3", "flac"]
+//   match_mode = "name"
+//   similarity = 0.9
+//   threads = 4
+//   report_format = "html"
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // --input
+    pub input: Vec<PathBuf>,
+    // --output of the commands that write into a folder
+    pub output: Option<PathBuf>,
+    // --format
+    pub formats: Vec<String>,
+    // --match-mode
+    pub match_mode: Option<String>,
+    // --similarity
+    pub similarity: Option<f64>,
+    // --threads
+    pub threads: Option<usize>,
+    // --report-format
+    pub report_format: Option<String>,
+}
+
+// Commands whose --output is a folder rather than a report file
+const OUTPUT_DIR_COMMANDS: [&str; 4] = ["duplicates", "bitrate", "run", "doctor"];
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        paths::config_dir().join("config.toml")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        let mut config: Self = toml::from_str(&text)
+            .map_err(|e| AudioError::Metadata(format!("Invalid settings file {}: {}", path.display(), e)))?;
+        config.input = config.input.iter().map(|path| expand_home(path)).collect();
+        config.output = config.output.as_deref().map(expand_home);
+        Ok(config)
+    }
+
+    // The file named by --config in `args`, else the default one; no
+    // settings when there is no --config and no default file
+    pub fn load_for_args(args: &[OsString]) -> Result<Self> {
+        match config_arg(args) {
+            Some(path) => Self::load(path),
+            None => {
+                let path = Self::default_path();
+                if path.exists() { Self::load(path) } else { Ok(Self::default()) }
+            }
+        }
+    }
+
+    // Makes the settings the defaults of the matching arguments of `command`
+    // and its subcommands; arguments they fill in are no longer required
+    pub fn apply(&self, command: Command) -> Command {
+        let mut command = self.apply_args(command);
+        let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+        for name in names {
+            command = command.mut_subcommand(name, |sub| self.apply(sub));
+        }
+        command
+    }
+
+    fn apply_args(&self, mut command: Command) -> Command {
+        let output = self.output.as_ref().filter(|_| OUTPUT_DIR_COMMANDS.contains(&command.get_name()));
+        let defaults: [(&str, Vec<String>); 7] = [
+            ("input", self.input.iter().map(|path| path.to_string_lossy().into_owned()).collect()),
+            ("output", output.map(|path| path.to_string_lossy().into_owned()).into_iter().collect()),
+            ("format", self.formats.clone()),
+            ("match_mode", self.match_mode.clone().into_iter().collect()),
+            ("similarity", self.similarity.map(|similarity| similarity.to_string()).into_iter().collect()),
+            ("threads", self.threads.map(|threads| threads.to_string()).into_iter().collect()),
+            ("report_format", self.report_format.clone().into_iter().collect()),
+        ];
+        for (id, values) in defaults {
+            if values.is_empty() || !command.get_arguments().any(|arg| arg.get_id() == id) {
+                continue;
+            }
+            command = command.mut_arg(id, |arg| arg.default_values(values).required(false));
+        }
+        command
+    }
+}
+
+// `--config FILE` or `--config=FILE`, wherever it is on the command line
+fn config_arg(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
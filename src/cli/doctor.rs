@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::audio::metadata;
+use crate::utils::{file_ops, parallel, paths};
+
+// Environment and library sanity checks for the `doctor` command. Every
+// check produces a status plus, when something is off, a hint telling the
+// user what to do about it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self { name: name.into(), status: CheckStatus::Ok, detail: detail.into(), hint: None }
+    }
+
+    fn warning(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name: name.into(), status: CheckStatus::Warning, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    fn failed(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self { name: name.into(), status: CheckStatus::Failed, detail: detail.into(), hint: Some(hint.into()) }
+    }
+}
+
+// `library_dirs` are checked for read access, `output_dirs` for write access
+pub fn run_checks(library_dirs: &[PathBuf], output_dirs: &[PathBuf]) -> Vec<Check> {
+    let mut checks = vec![build_info()];
+
+    checks.push(writable_dir("Data directory", &paths::data_dir(), true));
+    checks.push(writable_dir("Journal directory", &paths::journal_dir(), true));
+    checks.push(writable_dir("Temporary directory", &std::env::temp_dir(), false));
+    checks.extend(journal_integrity());
+    #[cfg(feature = "index")]
+    checks.push(index_integrity());
+
+    for tool in ["ffmpeg", "ffprobe"] {
+        checks.push(optional_tool(tool));
+    }
+
+    for dir in library_dirs {
+        checks.push(readable_dir(dir));
+    }
+    for dir in output_dirs {
+        checks.push(writable_dir("Output directory", dir, true));
+    }
+
+    checks
+}
+
+fn build_info() -> Check {
+    let formats = metadata::decoder_extensions();
+
+    let detail = format!("version {}, formats: {}, threads: {}",
+        env!("CARGO_PKG_VERSION"),
+        if formats.is_empty() { "none".to_string() } else { formats.join(", ") },
+        parallel::available_threads()
+    );
+    if formats.is_empty() {
+        Check::failed("Build", detail, "Rebuild with at least one codecs-* feature enabled")
+    } else {
+        Check::ok("Build", detail)
+    }
+}
+
+// Creates the directory when `create` is set (like a real run would), then
+// probes it with a throwaway file
+fn writable_dir(name: &str, dir: &Path, create: bool) -> Check {
+    let name = format!("{} ({})", name, dir.display());
+    if create {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Check::failed(name, format!("cannot create: {}", e), "Check permissions of the parent directory");
+        }
+    } else if !dir.is_dir() {
+        return Check::failed(name, "does not exist", "Create the directory or point the command elsewhere");
+    }
+
+    let probe = dir.join(format!(".dj-library-manager-doctor-{}", std::process::id()));
+    match fs::write(&probe, b"probe") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Check::ok(name, "writable")
+        }
+        Err(e) => Check::failed(name, format!("not writable: {}", e), "Fix permissions or choose a different directory"),
+    }
+}
+
+fn readable_dir(dir: &Path) -> Check {
+    let name = format!("Library directory ({})", dir.display());
+    match fs::read_dir(dir) {
+        Ok(entries) => Check::ok(name, format!("readable, {} top-level entries", entries.count())),
+        Err(e) => Check::failed(name, format!("cannot read: {}", e), "Check the path, and that the drive is mounted"),
+    }
+}
+
+// Every journal must parse, otherwise undo/audit tooling will choke on it
+fn journal_integrity() -> Vec<Check> {
+    let dir = paths::journal_dir();
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return vec![Check::ok("Journals", "none yet")];
+    };
+
+    let journals: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+
+    let mut checks = Vec::new();
+    let mut operations = 0;
+    for journal in &journals {
+        match file_ops::read_journal(journal) {
+            Ok(entries) => operations += entries.len(),
+            Err(e) => checks.push(Check::warning(
+                "Journal",
+                e.to_string(),
+                format!("The file may be truncated from an interrupted run; move {} aside if it can't be repaired", journal.display()),
+            )),
+        }
+    }
+
+    if checks.is_empty() {
+        checks.push(Check::ok("Journals", format!("{} journals, {} recorded operations", journals.len(), operations)));
+    }
+    checks
+}
+
+#[cfg(feature = "index")]
+fn index_integrity() -> Check {
+    use crate::index::{lock::IndexLock, LibraryIndex};
+
+    let path = LibraryIndex::default_path();
+    let name = format!("Index ({})", path.display());
+    if !path.exists() {
+        return Check::ok(name, "none yet");
+    }
+
+    match LibraryIndex::inspect(&path) {
+        Ok(status) if !status.integrity_errors.is_empty() => Check::failed(
+            name,
+            status.integrity_errors.join("; "),
+            "Run `dj-library-manager index repair`, or delete the file and rebuild with `index update`",
+        ),
+        Ok(status) if status.schema_version > status.latest_version => Check::failed(
+            name,
+            format!("schema version {} is newer than this build supports ({})", status.schema_version, status.latest_version),
+            "Update dj-library-manager",
+        ),
+        Ok(status) if status.schema_version < status.latest_version => Check::warning(
+            name,
+            format!("schema version {}, {} files", status.schema_version, status.file_count),
+            "It will be migrated automatically the next time it's opened",
+        ),
+        Ok(_) if IndexLock::is_locked(&path) => Check::warning(
+            name,
+            format!("locked by {}", IndexLock::owner(&path).map_or("an unknown run".to_string(), |owner| owner.describe())),
+            "If that run is no longer active, pass --force-unlock to the next index command",
+        ),
+        Ok(status) => Check::ok(name, format!("schema version {}, {} files", status.schema_version, status.file_count)),
+        Err(e) => Check::failed(name, e.to_string(), "Delete the file and rebuild with `index update`"),
+    }
+}
+
+fn optional_tool(tool: &str) -> Check {
+    match find_in_path(tool) {
+        Some(path) => Check::ok(tool, format!("found at {}", path.display())),
+        None => Check::warning(tool, "not found on PATH", format!("Optional: install ffmpeg (provides {}) for features that need external decoding", tool)),
+    }
+}
+
+fn find_in_path(tool: &str) -> Option<PathBuf> {
+    let path_var = std::env::var_os("PATH")?;
+    let names: Vec<String> = if cfg!(windows) {
+        vec![format!("{}.exe", tool), tool.to_string()]
+    } else {
+        vec![tool.to_string()]
+    };
+
+    std::env::split_paths(&path_var)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
@@ -1 +1,7 @@
-pub mod commands;
\ No newline at end of file
+pub mod commands;
+pub mod config;
+pub mod doctor;
+pub mod options;
+pub mod routine;
+#[cfg(feature = "self-update")]
+pub mod update;
@@ -0,0 +1,276 @@
+use clap::Args;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::ScanOptions;
+use crate::utils::audit;
+use crate::utils::parallel::{self, AdaptiveThreads};
+use crate::utils::report_sink::{self, ReportSink};
+use crate::utils::scan_cache::ScanCache;
+#[cfg(feature = "index")]
+use crate::index::incremental::IncrementalScan;
+use crate::utils::reporting::{ReportFormat, ReportOptions};
+
+// Flags shared by every command that scans a library. Keep new scan-related
+// flags here so names and behavior stay the same across subcommands.
+#[derive(Args, Debug, Clone)]
+pub struct ScanOpts {
+    /// Directories to scan (repeat for several roots)
+    #[arg(short = 'i', long = "input", required = true, value_parser = existing_dir)]
+    pub input: Vec<PathBuf>,
+
+    /// Only scan the top level of each input directory
+    #[arg(long = "no-recursive")]
+    pub no_recursive: bool,
+
+    /// Skip paths matching this pattern (`*` and `?` wildcards; repeatable)
+    #[arg(long = "exclude", value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Worker threads (default: all CPU cores)
+    #[arg(short = 't', long = "threads")]
+    pub threads: Option<usize>,
+
+    /// Files decoded at once by the commands that read the audio (BPM, key, fingerprints, ...); each holds its samples in memory (default: --threads)
+    #[arg(long = "decode-threads")]
+    pub decode_threads: Option<usize>,
+
+    /// Use fewer workers while throughput stays collapsed, as on a laptop that throttles when hot, and more again once it recovers
+    #[arg(long = "adaptive-threads")]
+    pub adaptive_threads: bool,
+
+    /// Only scan these formats (mp3, wav, flac, m4a, aac, ogg, opus, aiff, wma, dsf, dff, mod, xm, s3m, it; repeatable)
+    #[arg(long = "format", value_name = "EXT")]
+    pub format: Vec<String>,
+
+    /// Only show what would change; never move, copy or delete library files
+    #[arg(short = 'd', long)]
+    pub dry_run: bool,
+
+    /// Extra passes over files that failed with transient I/O errors
+    #[arg(long = "retries", default_value_t = 1)]
+    pub retries: u32,
+
+    /// Seconds to wait before each retry pass
+    #[arg(long = "retry-delay", default_value_t = 2.0, value_parser = parse_seconds)]
+    pub retry_delay: f64,
+
+    /// Append every processed file and result to this JSON-lines log as the run progresses
+    #[arg(long = "jsonl-log")]
+    pub jsonl_log: Option<PathBuf>,
+
+    /// Also scan folders DJ software keeps its own data in (_Serato_, PIONEER, rekordbox, ...)
+    #[arg(long = "scan-software-folders")]
+    pub scan_software_folders: bool,
+
+    /// Measure duration and bitrate by reading through MP3s and files without a frame count in their header, instead of estimating; slower
+    #[arg(long = "accurate-duration")]
+    pub accurate_duration: bool,
+
+    /// Reuse the previous scan of an input folder when no file under it changed (names, sizes, modification times)
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Only extract new or changed files, taking the rest from the library index (default index, or the one given)
+    #[cfg(feature = "index")]
+    #[arg(long, value_name = "INDEX", num_args = 0..=1)]
+    pub incremental: Option<Option<PathBuf>>,
+}
+
+impl ScanOpts {
+    // The scan log is opened by the caller so it can report errors itself
+    pub fn to_scan_options(&self) -> ScanOptions {
+        let defaults = ScanOptions::default();
+        ScanOptions {
+            retry_count: self.retries,
+            retry_delay: Duration::try_from_secs_f64(self.retry_delay).unwrap_or_default(),
+            recursive: !self.no_recursive,
+            excludes: self.exclude.clone(),
+            exclude_dirs: Vec::new(),
+            extensions: if self.format.is_empty() {
+                defaults.extensions
+            } else {
+                self.format.iter().map(|f| f.trim_start_matches('.').to_lowercase()).collect()
+            },
+            formats: defaults.formats,
+            log: None,
+            skip_software_folders: !self.scan_software_folders,
+            cache: self.cache.then(ScanCache::default),
+            accurate_duration: self.accurate_duration,
+            phases: None,
+            adaptive: self.adaptive_threads.then(|| Arc::new(AdaptiveThreads::new(parallel::current_threads()))),
+            #[cfg(feature = "index")]
+            index: self.incremental.as_ref().map(|path| match path {
+                Some(path) => IncrementalScan::new(path),
+                None => IncrementalScan::default(),
+            }),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReportOpts {
+    /// Report file format: csv, json or html (a page with sortable tables)
+    #[arg(long = "report-format", value_name = "FORMAT", default_value = "csv")]
+    pub report_format: ReportFormat,
+
+    /// Gzip report files (report.csv.gz)
+    #[arg(long)]
+    pub gzip: bool,
+
+    /// Split report tables into numbered parts of at most this many rows
+    #[arg(long = "split-rows")]
+    pub split_rows: Option<usize>,
+
+    /// Also deliver every report to TARGET: `-` for stdout, a folder, or a WebDAV URL (report-upload feature)
+    #[arg(long = "report-to", value_name = "TARGET", value_parser = report_sink::sink_for)]
+    pub report_to: Option<Arc<dyn ReportSink>>,
+
+    /// Start CSV reports with a UTF-8 byte order mark so Excel shows accented names correctly
+    #[arg(long)]
+    pub bom: bool,
+}
+
+impl From<&ReportOpts> for ReportOptions {
+    fn from(opts: &ReportOpts) -> Self {
+        Self {
+            format: opts.report_format,
+            gzip: opts.gzip,
+            max_rows_per_file: opts.split_rows,
+            sink: opts.report_to.clone(),
+            bom: opts.bom,
+        }
+    }
+}
+
+// The DJ software collection a command reads: exactly one of them
+#[derive(Args, Debug, Clone)]
+pub struct CollectionOpts {
+    /// Rekordbox XML collection export
+    #[arg(long, conflicts_with_all = ["serato", "traktor"], required_unless_present_any = ["serato", "traktor"])]
+    pub rekordbox: Option<PathBuf>,
+
+    /// Serato `database V2` file
+    #[arg(long, conflicts_with = "traktor")]
+    pub serato: Option<PathBuf>,
+
+    /// Volume root the Serato paths are relative to (default: the drive holding `_Serato_`)
+    #[arg(long = "serato-root")]
+    pub serato_root: Option<PathBuf>,
+
+    /// Traktor NML collection (collection.nml)
+    #[arg(long, value_name = "NML")]
+    pub traktor: Option<PathBuf>,
+}
+
+// Directory that exists, so a typo or an unmounted drive fails before the
+// scan rather than as an empty library
+pub fn existing_dir(text: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(text);
+    match fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => Ok(path),
+        Ok(_) => Err(format!("Not a directory: {}", text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(format!("Directory does not exist: {} (is the drive mounted?)", text))
+        }
+        Err(e) => Err(format!("Cannot read {}: {}", text, e)),
+    }
+}
+
+// Directory results can be written to. It needn't exist yet, as long as
+// it can be created: the nearest existing parent must take a new file.
+pub fn writable_dir(text: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(text);
+    if path.exists() && !path.is_dir() {
+        return Err(format!("Not a directory: {}", text));
+    }
+    let existing = path.ancestors()
+        .map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| format!("No existing parent directory for {}", text))?;
+    let probe = existing.join(format!(".dj-library-manager-probe-{}", std::process::id()));
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Ok(path)
+        }
+        Err(e) => Err(format!("Cannot write to {}: {}", existing.display(), e)),
+    }
+}
+
+// Whether `path` is `dir` or lies below it, comparing absolute paths with
+// symlinks resolved as far as they exist
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    resolve(path).starts_with(resolve(dir))
+}
+
+// Canonical form of the nearest existing ancestor, with the rest appended
+pub fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    for ancestor in absolute.ancestors() {
+        if let Ok(canonical) = fs::canonicalize(ancestor) {
+            let rest = absolute.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            // Joining an empty rest would add a trailing separator
+            return if rest.as_os_str().is_empty() { canonical } else { canonical.join(rest) };
+        }
+    }
+    absolute
+}
+
+// `YYYY-MM-DD` day as the Unix second it starts (UTC)
+pub fn parse_date(text: &str) -> Result<u64, String> {
+    audit::parse_date(text).ok_or_else(|| format!("Invalid date (expected YYYY-MM-DD): {}", text))
+}
+
+// Fraction from 0.0 to 1.0, such as a similarity threshold
+pub fn parse_fraction(text: &str) -> Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("Expected a number from 0 to 1: {}", text)),
+    }
+}
+
+// Picture file name written into each album folder: one plain name (no
+// folders, so every album gets its own) ending in .jpg or .png
+pub fn parse_cover_name(text: &str) -> Result<String, String> {
+    if Path::new(text).file_name() != Some(OsStr::new(text)) {
+        return Err(format!("Expected a file name without folders: {}", text));
+    }
+    let extension = Path::new(text).extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    if !matches!(extension.as_deref(), Some("jpg" | "jpeg" | "png")) {
+        return Err(format!("Cover file name must end in .jpg or .png: {}", text));
+    }
+    Ok(text.to_string())
+}
+
+// Seconds to wait, such as a retry delay: finite and not negative
+pub fn parse_seconds(text: &str) -> Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!("Expected a number of seconds, 0 or more: {}", text)),
+    }
+}
+
+// Byte count such as `64G`, `500MB` or `1.5TiB`. Plain units are decimal,
+// like drive capacities; `KiB`/`MiB`/... are binary.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("Invalid size: {}", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "T" | "TB" => 1_000_000_000_000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        "TIB" => 1 << 40,
+        _ => return Err(format!("Unknown size unit in {} (use K, M, G, T or KiB, MiB, GiB, TiB)", text)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::str::FromStr;
+
+// One step of `run`. Every step works on the files of the single scan the
+// run starts with, as left by the steps before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineStep {
+    // Always runs first; listing it only documents the order
+    Scan,
+    // Bitrate statistics and, with a policy, the files below it
+    Quality,
+    // Duplicate groups, with every copy but the best moved out
+    Duplicates,
+    // Report files for the steps before it
+    Report,
+}
+
+impl RoutineStep {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Scan => "scan",
+            Self::Quality => "quality",
+            Self::Duplicates => "duplicates",
+            Self::Report => "report",
+        }
+    }
+}
+
+// Steps separated by commas or arrows, e.g. `scan -> quality -> duplicates -> report`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routine {
+    pub steps: Vec<RoutineStep>,
+}
+
+impl Routine {
+    pub fn has(&self, step: RoutineStep) -> bool {
+        self.steps.contains(&step)
+    }
+}
+
+impl FromStr for Routine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for part in s.split([',', '→']).flat_map(|part| part.split("->")).map(|part| part.trim().to_lowercase()) {
+            let step = match part.as_str() {
+                "scan" => RoutineStep::Scan,
+                "quality" | "bitrate" => RoutineStep::Quality,
+                "duplicates" | "dupes" => RoutineStep::Duplicates,
+                "report" | "reports" => RoutineStep::Report,
+                other => return Err(format!("Unknown step: {} (use scan, quality, duplicates or report)", other)),
+            };
+            if steps.contains(&step) {
+                return Err(format!("{} is listed twice", step.as_str()));
+            }
+            steps.push(step);
+        }
+        if steps.iter().skip(1).any(|step| *step == RoutineStep::Scan) {
+            return Err("scan always runs first; list it first or leave it out".to_string());
+        }
+        if steps.iter().rev().skip(1).any(|step| *step == RoutineStep::Report) {
+            return Err("report must come last".to_string());
+        }
+        if !steps.iter().any(|step| matches!(step, RoutineStep::Quality | RoutineStep::Duplicates)) {
+            return Err("List quality, duplicates or both".to_string());
+        }
+        Ok(Self { steps })
+    }
+}
+
+impl fmt::Display for Routine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<&str> = self.steps.iter().map(RoutineStep::as_str).collect();
+        write!(f, "{}", steps.join(" -> "))
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+// Checks GitHub releases for a newer build and optionally replaces the
+// running binary. Only ever runs when the user invokes `self-update`.
+
+const USER_AGENT: &str = concat!("dj-library-manager/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateError {
+    #[error("Request to {url} failed: {reason}")]
+    Http { url: String, reason: String },
+    #[error("Unexpected release data: {0}")]
+    Release(String),
+    #[error("No release asset for this platform ({0}); download it manually from {1}")]
+    NoAsset(String, String),
+    #[error("IO error on {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+impl Release {
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    // Prefers a bare executable named after the platform, e.g.
+    // `dj-library-manager-linux-x86_64` or `...-windows-x86_64.exe`
+    fn asset_for_platform(&self) -> Option<&ReleaseAsset> {
+        let os = std::env::consts::OS;
+        let arch = std::env::consts::ARCH;
+        self.assets.iter().find(|asset| {
+            let name = asset.name.to_lowercase();
+            let is_archive = [".zip", ".tar.gz", ".tgz", ".tar.xz"].iter().any(|ext| name.ends_with(ext));
+            name.contains(os) && name.contains(arch) && !is_archive
+        })
+    }
+}
+
+pub fn latest_release() -> Result<Release, UpdateError> {
+    let url = format!("{}/releases/latest", api_base());
+    let response = ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .map_err(|e| UpdateError::Http { url: url.clone(), reason: e.to_string() })?;
+    serde_json::from_reader(response.into_reader())
+        .map_err(|e| UpdateError::Release(e.to_string()))
+}
+
+// True when `candidate` is a strictly newer dotted version than `current`.
+// Pre-release suffixes ("1.2.0-beta") are ignored.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parts(version: &str) -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    }
+
+    let (candidate, current) = (parts(candidate), parts(current));
+    let len = candidate.len().max(current.len());
+    let pad = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    for i in 0..len {
+        match pad(&candidate, i).cmp(&pad(&current, i)) {
+            std::cmp::Ordering::Greater => return true,
+            std::cmp::Ordering::Less => return false,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    false
+}
+
+// Downloads the release binary and swaps it in for the running executable.
+// The previous binary is kept next to it with an `.old` extension.
+pub fn install(release: &Release) -> Result<PathBuf, UpdateError> {
+    let platform = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    let asset = release.asset_for_platform()
+        .ok_or_else(|| UpdateError::NoAsset(platform, release.html_url.clone()))?;
+
+    let current = std::env::current_exe()
+        .map_err(|e| UpdateError::Io { path: PathBuf::from("<current executable>"), source: e })?;
+    let download_path = current.with_extension("download");
+    download(&asset.browser_download_url, &download_path)?;
+    make_executable(&download_path)?;
+
+    // Renaming a running binary works on every platform; overwriting it
+    // doesn't on Windows
+    let backup_path = current.with_extension("old");
+    let _ = fs::remove_file(&backup_path);
+    fs::rename(&current, &backup_path).map_err(|e| UpdateError::Io { path: current.clone(), source: e })?;
+    if let Err(e) = fs::rename(&download_path, &current) {
+        // Put the old binary back so the user isn't left without one
+        let _ = fs::rename(&backup_path, &current);
+        return Err(UpdateError::Io { path: current, source: e });
+    }
+    Ok(current)
+}
+
+fn download(url: &str, destination: &Path) -> Result<(), UpdateError> {
+    let response = ureq::get(url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|e| UpdateError::Http { url: url.to_string(), reason: e.to_string() })?;
+
+    let io_error = |e| UpdateError::Io { path: destination.to_path_buf(), source: e };
+    let mut file = File::create(destination).map_err(io_error)?;
+    let mut reader = response.into_reader().take(512 * 1024 * 1024);
+    io::copy(&mut reader, &mut file).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), UpdateError> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .map_err(|e| UpdateError::Io { path: path.to_path_buf(), source: e })
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), UpdateError> {
+    Ok(())
+}
+
+// https://github.com/owner/repo -> https://api.github.com/repos/owner/repo
+fn api_base() -> String {
+    let repository = env!("CARGO_PKG_REPOSITORY").trim_end_matches('/');
+    let slug = repository.trim_start_matches("https://github.com/");
+    format!("https://api.github.com/repos/{}", slug)
+}
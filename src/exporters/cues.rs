@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+use serde::Serialize;
+
+// Hot cues suggested for a track, in the form the Rekordbox, Serato and
+// Traktor writers take them. Slots follow the usual prep convention: A on the
+// intro, B on the drop, C on the breakdown, D on the second drop or outro.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CueKind {
+    Intro,
+    Drop,
+    Breakdown,
+    SecondDrop,
+    Outro,
+}
+
+impl CueKind {
+    // Hot cue slot, 0 = A
+    pub fn slot(&self) -> u8 {
+        match self {
+            Self::Intro => 0,
+            Self::Drop => 1,
+            Self::Breakdown => 2,
+            Self::SecondDrop | Self::Outro => 3,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Intro => "Intro",
+            Self::Drop => "Drop",
+            Self::Breakdown => "Breakdown",
+            Self::SecondDrop => "Drop 2",
+            Self::Outro => "Outro",
+        }
+    }
+
+    // From Serato's default cue palette
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Self::Intro => [0x00, 0xCC, 0x00],
+            Self::Drop => [0xCC, 0x00, 0x00],
+            Self::Breakdown => [0x00, 0x00, 0xCC],
+            Self::SecondDrop => [0xCC, 0x88, 0x00],
+            Self::Outro => [0xCC, 0xCC, 0x00],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CuePoint {
+    pub kind: CueKind,
+    pub position_secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackCues {
+    pub path: PathBuf,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub duration_secs: f64,
+    // From the file's BPM tag
+    pub bpm: Option<f64>,
+    pub cues: Vec<CuePoint>,
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Musical key handling for exports. DJ software only recognizes keys written
+// in its own notation (Rekordbox expects classical names like "Am", Serato
+// users mostly work in Camelot "8A", Traktor writes Open Key "1m"), so raw tag
+// strings are parsed into a `MusicalKey` and re-rendered per target.
+
+const MAJOR_NAMES: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+const MINOR_NAMES: [&str; 12] = ["Cm", "C#m", "Dm", "Ebm", "Em", "Fm", "F#m", "Gm", "G#m", "Am", "Bbm", "Bm"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MusicalKey {
+    // Pitch class of the tonic, C = 0 .. B = 11
+    pub tonic: u8,
+    pub minor: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyNotation {
+    // "C", "F#m", "Bbm"
+    Classical,
+    // "8B", "8A"
+    Camelot,
+    // "1d", "1m"
+    OpenKey,
+}
+
+// How mixing from one key into another sounds, judged on the Camelot wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRelation {
+    // 8A -> 8A
+    Same,
+    // 8A -> 8B, the relative major/minor
+    Relative,
+    // 8A -> 7A or 9A
+    Adjacent,
+    // 8A -> 9B or 8B -> 7A, a change of mood
+    Diagonal,
+    // 8A -> 10A (a tone up) or 8A -> 3A (a semitone up)
+    EnergyBoost,
+    Clash,
+}
+
+impl KeyRelation {
+    // How smooth the transition is, 0..=1
+    pub fn score(&self) -> f64 {
+        match self {
+            Self::Same => 1.0,
+            Self::Relative | Self::Adjacent => 0.9,
+            Self::Diagonal => 0.6,
+            Self::EnergyBoost => 0.5,
+            Self::Clash => 0.0,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Same => "same",
+            Self::Relative => "relative",
+            Self::Adjacent => "adjacent",
+            Self::Diagonal => "diagonal",
+            Self::EnergyBoost => "energy boost",
+            Self::Clash => "clash",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTarget {
+    Rekordbox,
+    Serato,
+    Traktor,
+}
+
+impl ExportTarget {
+    pub fn default_notation(&self) -> KeyNotation {
+        match self {
+            Self::Rekordbox => KeyNotation::Classical,
+            Self::Serato => KeyNotation::Camelot,
+            Self::Traktor => KeyNotation::OpenKey,
+        }
+    }
+}
+
+impl MusicalKey {
+    pub fn new(tonic: u8, minor: bool) -> Self {
+        Self { tonic: tonic % 12, minor }
+    }
+
+    // Accepts classical ("A minor", "F#m", "Db"), Camelot ("8A") and Open Key
+    // ("1m") spellings, case-insensitively
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        Self::parse_wheel(text).or_else(|| Self::parse_classical(text))
+    }
+
+    // Position on the Camelot wheel, 1..=12
+    pub fn camelot_number(&self) -> u8 {
+        // Minor keys share the number of their relative major
+        let major_tonic = if self.minor { (self.tonic + 3) % 12 } else { self.tonic };
+        let fifths = (major_tonic * 7) % 12;
+        (fifths + 7) % 12 + 1
+    }
+
+    // Relation of mixing from this key into `next`
+    pub fn relation_to(&self, next: &MusicalKey) -> KeyRelation {
+        let from = self.camelot_number() as i8;
+        let to = next.camelot_number() as i8;
+        let steps = (to - from).rem_euclid(12);
+        if self.minor == next.minor {
+            return match steps {
+                0 => KeyRelation::Same,
+                1 | 11 => KeyRelation::Adjacent,
+                2 | 7 => KeyRelation::EnergyBoost,
+                _ => KeyRelation::Clash,
+            };
+        }
+        let (minor, major) = if self.minor { (from, to) } else { (to, from) };
+        match (steps, (major - minor).rem_euclid(12)) {
+            (0, _) => KeyRelation::Relative,
+            (_, 1) => KeyRelation::Diagonal,
+            _ => KeyRelation::Clash,
+        }
+    }
+
+    pub fn format(&self, notation: KeyNotation) -> String {
+        match notation {
+            KeyNotation::Classical => {
+                let names = if self.minor { &MINOR_NAMES } else { &MAJOR_NAMES };
+                names[self.tonic as usize].to_string()
+            }
+            KeyNotation::Camelot => {
+                format!("{}{}", self.camelot_number(), if self.minor { 'A' } else { 'B' })
+            }
+            KeyNotation::OpenKey => {
+                let number = (self.camelot_number() + 12 - 8) % 12 + 1;
+                format!("{}{}", number, if self.minor { 'm' } else { 'd' })
+            }
+        }
+    }
+
+    fn parse_wheel(text: &str) -> Option<Self> {
+        let split = text.find(|c: char| !c.is_ascii_digit())?;
+        let (number, suffix) = text.split_at(split);
+        let number: u8 = number.parse().ok().filter(|n| (1..=12).contains(n))?;
+
+        let (camelot, minor) = match suffix.to_ascii_lowercase().as_str() {
+            "a" => (number, true),
+            "b" => (number, false),
+            // Open Key 1d/1m sits at Camelot 8B/8A
+            "m" => ((number + 6) % 12 + 1, true),
+            "d" => ((number + 6) % 12 + 1, false),
+            _ => return None,
+        };
+
+        let fifths = (camelot + 12 - 8) % 12;
+        let major_tonic = (fifths * 7) % 12;
+        let tonic = if minor { (major_tonic + 9) % 12 } else { major_tonic };
+        Some(Self::new(tonic, minor))
+    }
+
+    fn parse_classical(text: &str) -> Option<Self> {
+        let mut chars = text.chars();
+        let base = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+
+        let rest = chars.as_str();
+        let (accidental, rest) = match rest.chars().next() {
+            Some('#') | Some('♯') => (1, &rest[rest.chars().next().map_or(0, char::len_utf8)..]),
+            Some('b') | Some('♭') => (11, &rest[rest.chars().next().map_or(0, char::len_utf8)..]),
+            _ => (0, rest),
+        };
+
+        let minor = match rest.trim().to_lowercase().as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+
+        Some(Self::new((base + accidental) % 12, minor))
+    }
+}
+
+impl FromStr for KeyNotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "classical" | "standard" | "musical" => Ok(Self::Classical),
+            "camelot" => Ok(Self::Camelot),
+            "openkey" | "open-key" | "open_key" => Ok(Self::OpenKey),
+            other => Err(format!("Unknown key notation: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for MusicalKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(KeyNotation::Classical))
+    }
+}
+
+// How keys are written for one export target. Overrides replace the rendered
+// value for individual keys, e.g. to write "Abm" instead of "G#m".
+#[derive(Debug, Clone)]
+pub struct KeyMapping {
+    notation: KeyNotation,
+    overrides: HashMap<MusicalKey, String>,
+}
+
+impl KeyMapping {
+    pub fn new(notation: KeyNotation) -> Self {
+        Self {
+            notation,
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn for_target(target: ExportTarget) -> Self {
+        Self::new(target.default_notation())
+    }
+
+    pub fn notation(&self) -> KeyNotation {
+        self.notation
+    }
+
+    // `key` may be written in any notation `MusicalKey::parse` accepts
+    pub fn with_override(mut self, key: &str, value: impl Into<String>) -> Result<Self, String> {
+        let parsed = MusicalKey::parse(key).ok_or_else(|| format!("Unrecognized key: {}", key))?;
+        self.overrides.insert(parsed, value.into());
+        Ok(self)
+    }
+
+    // Renders a raw tag value for the target, or None when it isn't a key we
+    // understand. Exporters should leave the field empty rather than write a
+    // string the DJ software would ignore.
+    pub fn map(&self, raw: &str) -> Option<String> {
+        let key = MusicalKey::parse(raw)?;
+        Some(self.overrides.get(&key).cloned().unwrap_or_else(|| key.format(self.notation)))
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::title::TitleParser;
+use crate::utils::hashing;
+use crate::utils::parallel::ParallelProcessor;
+use crate::utils::unix_timestamp_millis;
+
+// Privacy-preserving library manifest for trading tracks with friends. It
+// lists what you have (normalized artist/title, quality, content hash) but
+// never where: no paths or file names are included.
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub generated_at: u64,
+    pub tracks: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    // SHA-256 of the file contents; identical files hash identically
+    pub sha256: String,
+    // Normalized the same way the duplicate matcher does it
+    pub artist: String,
+    pub title: String,
+    pub version: Option<String>,
+    pub format: String,
+    pub bitrate: Option<u32>,
+    pub duration_secs: Option<f64>,
+    pub size_bytes: u64,
+}
+
+struct ManifestBuilder;
+
+impl ParallelProcessor for ManifestBuilder {}
+
+impl Manifest {
+    // Hashes every file; files that can't be read are reported and left out
+    pub fn build(files: &[AudioFile]) -> Self {
+        let parser = TitleParser::new();
+        let entries = ManifestBuilder::parallel_map(files, |file| {
+            match hashing::sha256_file(&file.path) {
+                Ok(sha256) => Some(ManifestEntry::new(&parser, file, sha256)),
+                Err(e) => {
+                    eprintln!("Skipping file in manifest: {}", e);
+                    None
+                }
+            }
+        });
+
+        let mut tracks: Vec<ManifestEntry> = entries.into_iter().flatten().collect();
+        // Stable order so manifests of the same library diff cleanly
+        tracks.sort_by(|a, b| (&a.artist, &a.title, &a.sha256).cmp(&(&b.artist, &b.title, &b.sha256)));
+
+        Self {
+            version: MANIFEST_VERSION,
+            generated_at: unix_timestamp_millis(),
+            tracks,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        fs::write(path, json).map_err(|e| AudioError::io(path, e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        let manifest: Self = serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid manifest {}: {}", path.display(), e)))?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(AudioError::Metadata(format!(
+                "Manifest {} has version {}, this build only understands up to {}",
+                path.display(), manifest.version, MANIFEST_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+}
+
+impl ManifestEntry {
+    fn new(parser: &TitleParser, file: &AudioFile, sha256: String) -> Self {
+        let parsed = parser.parse(&file.file_name);
+        Self {
+            sha256,
+            artist: parsed.artist,
+            title: parsed.title,
+            version: parsed.version,
+            format: file.path.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            bitrate: file.bitrate,
+            duration_secs: file.duration_secs,
+            size_bytes: file.size_bytes,
+        }
+    }
+}
+
+// What each side of a trade could get from the other
+#[derive(Debug, Default, Serialize)]
+pub struct ManifestDiff {
+    pub missing_from_mine: Vec<ManifestEntry>,
+    pub missing_from_theirs: Vec<ManifestEntry>,
+    pub upgrades_for_mine: Vec<Upgrade>,
+    pub upgrades_for_theirs: Vec<Upgrade>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Upgrade {
+    pub current: ManifestEntry,
+    pub available: ManifestEntry,
+}
+
+impl ManifestDiff {
+    // Tracks are matched like duplicates: same artist and title with
+    // compatible versions. Identical files (same hash) are never reported.
+    pub fn compare(mine: &Manifest, theirs: &Manifest) -> Self {
+        let (missing_from_theirs, upgrades_for_theirs) = one_side(&mine.tracks, &theirs.tracks);
+        let (missing_from_mine, upgrades_for_mine) = one_side(&theirs.tracks, &mine.tracks);
+        Self {
+            missing_from_mine,
+            missing_from_theirs,
+            upgrades_for_mine,
+            upgrades_for_theirs,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing_from_mine.is_empty()
+            && self.missing_from_theirs.is_empty()
+            && self.upgrades_for_mine.is_empty()
+            && self.upgrades_for_theirs.is_empty()
+    }
+}
+
+// Entries of `offered` the other side lacks, and upgrades they'd get from it
+fn one_side(offered: &[ManifestEntry], other: &[ManifestEntry]) -> (Vec<ManifestEntry>, Vec<Upgrade>) {
+    let hashes: HashSet<&str> = other.iter().map(|entry| entry.sha256.as_str()).collect();
+    let mut by_title: HashMap<(&str, &str), Vec<&ManifestEntry>> = HashMap::new();
+    for entry in other {
+        by_title.entry((&entry.artist, &entry.title)).or_default().push(entry);
+    }
+
+    let mut missing = Vec::new();
+    let mut upgrades = Vec::new();
+    for entry in offered {
+        if hashes.contains(entry.sha256.as_str()) {
+            continue;
+        }
+        let same_track: Vec<&ManifestEntry> = by_title.get(&(entry.artist.as_str(), entry.title.as_str()))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|other| !TitleParser::are_different_versions(entry.version.as_deref(), other.version.as_deref()))
+            .collect();
+
+        // Compare against their best copy; an upgrade must beat all of them
+        match same_track.into_iter().reduce(|best, other| if other.is_better_than(best) { other } else { best }) {
+            None => missing.push(entry.clone()),
+            Some(current) if entry.is_better_than(current) => upgrades.push(Upgrade {
+                current: current.clone(),
+                available: entry.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    (missing, upgrades)
+}
+
+impl ManifestEntry {
+    // Same ordering as BitrateAnalyzer::compare_quality: FLAC over lossy,
+    // then bitrate, then size
+    pub fn is_better_than(&self, other: &ManifestEntry) -> bool {
+        match (self.bitrate, other.bitrate) {
+            (Some(b1), Some(b2)) if b1 != b2 => match (self.format == "flac", other.format == "flac") {
+                (true, false) => true,
+                (false, true) => false,
+                _ => b1 > b2,
+            },
+            _ => self.size_bytes > other.size_bytes,
+        }
+    }
+}
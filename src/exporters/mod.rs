@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+pub mod cues;
+pub mod keys;
+pub mod manifest;
+pub mod rekordbox;
+pub mod serato;
+#[cfg(feature = "decode")]
+pub mod sync;
+pub mod traktor;
+pub mod usb;
+
+// A track entry as recorded by DJ software, reduced to what we need for
+// matching it against files on disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionTrack {
+    pub location: PathBuf,
+    pub size_bytes: Option<u64>,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub duration_secs: Option<f64>,
+}
+
+impl CollectionTrack {
+    pub fn new(location: impl Into<PathBuf>) -> Self {
+        Self {
+            location: location.into(),
+            size_bytes: None,
+            artist: None,
+            title: None,
+            duration_secs: None,
+        }
+    }
+
+    pub fn is_missing(&self) -> bool {
+        !self.location.exists()
+    }
+}
+
+// Decodes %XX escapes; invalid escapes are kept verbatim
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(high), Some(low)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Escapes everything except unreserved characters and path separators
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
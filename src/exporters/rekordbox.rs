@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use crate::{AudioError, Result};
+use crate::exporters::{percent_decode, percent_encode, CollectionTrack};
+use crate::exporters::cues::TrackCues;
+
+// Rekordbox XML collections (File > Export Collection in xml format). Tracks
+// live in DJ_PLAYLISTS/COLLECTION/TRACK with a `file://localhost/` URL in
+// their Location attribute.
+
+const LOCATION_PREFIX: &str = "file://localhost";
+
+pub fn read_collection(path: impl AsRef<Path>) -> Result<Vec<CollectionTrack>> {
+    let path = path.as_ref();
+    let mut reader = Reader::from_file(path).map_err(|e| xml_error(path, e))?;
+    let mut buf = Vec::new();
+    let mut tracks = Vec::new();
+    let mut in_collection = false;
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(|e| xml_error(path, e))? {
+            Event::Start(e) if e.name().as_ref() == b"COLLECTION" => in_collection = true,
+            Event::End(e) if e.name().as_ref() == b"COLLECTION" => in_collection = false,
+            Event::Start(e) | Event::Empty(e) if in_collection && e.name().as_ref() == b"TRACK" => {
+                if let Some(track) = parse_track(&e) {
+                    tracks.push(track);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(tracks)
+}
+
+// Copies the collection to `output`, pointing every TRACK whose location is
+// a key of `relocations` at its new path. Everything else is written through
+// untouched. Returns the number of rewritten tracks.
+pub fn rewrite_locations(input: impl AsRef<Path>, output: impl AsRef<Path>, relocations: &HashMap<PathBuf, PathBuf>) -> Result<usize> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let mut reader = Reader::from_reader(BufReader::new(File::open(input).map_err(|e| AudioError::io(input, e))?));
+    let mut writer = Writer::new(BufWriter::new(File::create(output).map_err(|e| AudioError::io(output, e))?));
+    let mut buf = Vec::new();
+    let mut rewritten = 0;
+
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|e| xml_error(input, e))?;
+        let event = match event {
+            Event::Start(e) if e.name().as_ref() == b"TRACK" => match relocate_track(&e, relocations) {
+                Some(updated) => {
+                    rewritten += 1;
+                    Event::Start(updated)
+                }
+                None => Event::Start(e),
+            },
+            Event::Empty(e) if e.name().as_ref() == b"TRACK" => match relocate_track(&e, relocations) {
+                Some(updated) => {
+                    rewritten += 1;
+                    Event::Empty(updated)
+                }
+                None => Event::Empty(e),
+            },
+            Event::Eof => break,
+            other => other,
+        };
+        writer.write_event(event).map_err(|e| xml_error(output, e))?;
+        buf.clear();
+    }
+
+    writer.into_inner().flush().map_err(|e| AudioError::io(output, e))?;
+    Ok(rewritten)
+}
+
+// Writes a collection holding only `tracks`, each with its cues as hot cues
+// (POSITION_MARK with Num 0..3). Rekordbox picks the cues up when the tracks
+// are imported from this file through its "Imported Library" view.
+pub fn write_cue_collection(output: impl AsRef<Path>, tracks: &[TrackCues]) -> Result<()> {
+    let output = output.as_ref();
+    let file = File::create(output).map_err(|e| AudioError::io(output, e))?;
+    let mut writer = Writer::new_with_indent(BufWriter::new(file), b' ', 2);
+    let mut write = |event: Event<'_>| writer.write_event(event).map_err(|e| xml_error(output, e));
+
+    write(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    write(Event::Start(BytesStart::new("DJ_PLAYLISTS").with_attributes([("Version", "1.0.0")])))?;
+    write(Event::Empty(BytesStart::new("PRODUCT").with_attributes([
+        ("Name", "dj-library-manager"),
+        ("Version", env!("CARGO_PKG_VERSION")),
+        ("Company", ""),
+    ])))?;
+    let entries = tracks.len().to_string();
+    write(Event::Start(BytesStart::new("COLLECTION").with_attributes([("Entries", entries.as_str())])))?;
+
+    for (index, track) in tracks.iter().enumerate() {
+        let id = (index + 1).to_string();
+        let location = path_to_location(&track.path);
+        let total_time = (track.duration_secs.round() as u64).to_string();
+        let name = track.title.clone().unwrap_or_else(|| {
+            track.path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        });
+        let mut element = BytesStart::new("TRACK");
+        element.push_attribute(("TrackID", id.as_str()));
+        element.push_attribute(("Name", name.as_str()));
+        element.push_attribute(("Artist", track.artist.as_deref().unwrap_or_default()));
+        element.push_attribute(("TotalTime", total_time.as_str()));
+        element.push_attribute(("Location", location.as_str()));
+        write(Event::Start(element))?;
+
+        for cue in &track.cues {
+            let start = format!("{:.3}", cue.position_secs);
+            let num = cue.kind.slot().to_string();
+            let [red, green, blue] = cue.kind.color().map(|c| c.to_string());
+            write(Event::Empty(BytesStart::new("POSITION_MARK").with_attributes([
+                ("Name", cue.kind.label()),
+                ("Type", "0"),
+                ("Start", start.as_str()),
+                ("Num", num.as_str()),
+                ("Red", red.as_str()),
+                ("Green", green.as_str()),
+                ("Blue", blue.as_str()),
+            ])))?;
+        }
+        write(Event::End(BytesEnd::new("TRACK")))?;
+    }
+
+    write(Event::End(BytesEnd::new("COLLECTION")))?;
+    write(Event::End(BytesEnd::new("DJ_PLAYLISTS")))?;
+    writer.into_inner().flush().map_err(|e| AudioError::io(output, e))
+}
+
+// file://localhost/C:/Music/a%20b.mp3 -> C:/Music/a b.mp3
+// file://localhost/Users/me/a.mp3     -> /Users/me/a.mp3
+pub fn location_to_path(location: &str) -> Option<PathBuf> {
+    let rest = location.strip_prefix(LOCATION_PREFIX)
+        .or_else(|| location.strip_prefix("file://"))?;
+    let decoded = percent_decode(rest);
+
+    // Windows drive paths are written as /C:/...
+    let bytes = decoded.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(PathBuf::from(&decoded[1..]));
+    }
+    Some(PathBuf::from(decoded))
+}
+
+pub fn path_to_location(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("{}{}", LOCATION_PREFIX, percent_encode(&path))
+    } else {
+        format!("{}/{}", LOCATION_PREFIX, percent_encode(&path))
+    }
+}
+
+fn parse_track(element: &BytesStart<'_>) -> Option<CollectionTrack> {
+    let mut track: Option<CollectionTrack> = None;
+    let mut size = None;
+    let mut artist = None;
+    let mut title = None;
+    let mut duration = None;
+
+    for attr in element.attributes().flatten() {
+        let Ok(value) = attr.unescape_value() else { continue };
+        match attr.key.as_ref() {
+            b"Location" => track = location_to_path(&value).map(CollectionTrack::new),
+            b"Size" => size = value.parse().ok(),
+            b"Artist" if !value.is_empty() => artist = Some(value.into_owned()),
+            b"Name" if !value.is_empty() => title = Some(value.into_owned()),
+            b"TotalTime" => duration = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    track.map(|mut track| {
+        track.size_bytes = size;
+        track.artist = artist;
+        track.title = title;
+        track.duration_secs = duration;
+        track
+    })
+}
+
+fn relocate_track(element: &BytesStart<'_>, relocations: &HashMap<PathBuf, PathBuf>) -> Option<BytesStart<'static>> {
+    let location = element.attributes().flatten()
+        .find(|attr| attr.key.as_ref() == b"Location")?
+        .unescape_value().ok()?
+        .into_owned();
+    let new_path = relocations.get(&location_to_path(&location)?)?;
+    let new_location = path_to_location(new_path);
+
+    let mut updated = BytesStart::new("TRACK");
+    for attr in element.attributes().flatten() {
+        if attr.key.as_ref() == b"Location" {
+            updated.push_attribute(("Location", new_location.as_str()));
+        } else {
+            // Other attributes are copied still escaped
+            updated.push_attribute(Attribute::from((attr.key.as_ref(), attr.value.as_ref())));
+        }
+    }
+    Some(updated)
+}
+
+fn xml_error(path: &Path, error: impl std::fmt::Display) -> AudioError {
+    AudioError::Metadata(format!("Invalid XML in {}: {}", path.display(), error))
+}
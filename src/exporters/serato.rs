@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{AudioError, Result};
+use crate::exporters::CollectionTrack;
+use crate::exporters::cues::CuePoint;
+use crate::utils::report_dir;
+
+// Serato's `_Serato_/database V2`: a flat sequence of records, each a 4-byte
+// ASCII tag, a big-endian u32 length and the payload. `otrk` records hold a
+// track and contain nested fields of the same shape; strings are UTF-16BE.
+// Track paths (`pfil`) are relative to the root of the volume the database
+// lives on.
+
+pub fn read_database(path: impl AsRef<Path>, volume_root: impl AsRef<Path>) -> Result<Vec<CollectionTrack>> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| AudioError::io(path, e))?;
+    let records = parse_records(&data)
+        .ok_or_else(|| AudioError::Metadata(format!("Malformed Serato database: {}", path.display())))?;
+
+    let mut tracks = Vec::new();
+    for (tag, payload) in records {
+        if tag != *b"otrk" {
+            continue;
+        }
+        let Some(fields) = parse_records(payload) else { continue };
+
+        let mut location = None;
+        let mut artist = None;
+        let mut title = None;
+        let mut length = None;
+        for (field, value) in fields {
+            match &field {
+                b"pfil" => location = Some(decode_utf16(value)),
+                b"tart" => artist = Some(decode_utf16(value)),
+                b"tsng" => title = Some(decode_utf16(value)),
+                b"tlen" => length = parse_length(&decode_utf16(value)),
+                _ => {}
+            }
+        }
+
+        if let Some(location) = location {
+            let mut track = CollectionTrack::new(resolve(volume_root.as_ref(), &location));
+            track.artist = artist.filter(|a| !a.is_empty());
+            track.title = title.filter(|t| !t.is_empty());
+            track.duration_secs = length;
+            tracks.push(track);
+        }
+    }
+
+    Ok(tracks)
+}
+
+// A crate: `_Serato_/Subcrates/<name>.crate`, records laid out as in the
+// database, with each track an `otrk` record holding its path in `ptrk`
+// (relative to the volume root, like `pfil`). Subcrates are named
+// "Parent%%Child".
+#[derive(Debug, Clone)]
+pub struct SeratoCrate {
+    // As Serato shows it, "Parent / Child" for subcrates
+    pub name: String,
+    pub path: PathBuf,
+    pub tracks: Vec<PathBuf>,
+}
+
+pub fn read_crate(path: impl AsRef<Path>, volume_root: impl AsRef<Path>) -> Result<SeratoCrate> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|e| AudioError::io(path, e))?;
+    let records = parse_records(&data)
+        .ok_or_else(|| AudioError::Metadata(format!("Malformed Serato crate: {}", path.display())))?;
+
+    let tracks = records.into_iter()
+        .filter(|(tag, _)| tag == b"otrk")
+        .filter_map(|(_, payload)| parse_records(payload))
+        .filter_map(|fields| fields.into_iter().find(|(field, _)| field == b"ptrk"))
+        .map(|(_, value)| resolve(volume_root.as_ref(), &decode_utf16(value)))
+        .collect();
+    Ok(SeratoCrate { name: crate_name(path), path: path.to_path_buf(), tracks })
+}
+
+// Every crate under `serato_dir` (the `_Serato_` folder), by name
+pub fn read_crates(serato_dir: impl AsRef<Path>, volume_root: impl AsRef<Path>) -> Result<Vec<SeratoCrate>> {
+    let dir = serato_dir.as_ref().join("Subcrates");
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir).map_err(|e| AudioError::io(&dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("crate")))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| read_crate(path, volume_root.as_ref())).collect()
+}
+
+fn crate_name(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().replace("%%", " / ")
+}
+
+// Rewrites the crate in place, pointing every track that is a key of
+// `relocations` at its new path; a track whose new path the crate already
+// lists is dropped instead of listed twice. Tracks moving off the volume
+// can't be listed and stay as they are. Everything else is written through
+// untouched. Returns the number of changed tracks.
+pub fn rewrite_crate(path: impl AsRef<Path>, volume_root: impl AsRef<Path>, relocations: &HashMap<PathBuf, PathBuf>) -> Result<usize> {
+    let (path, volume_root) = (path.as_ref(), volume_root.as_ref());
+    let data = fs::read(path).map_err(|e| AudioError::io(path, e))?;
+    let records = parse_records(&data)
+        .ok_or_else(|| AudioError::Metadata(format!("Malformed Serato crate: {}", path.display())))?;
+    let mut listed: Vec<PathBuf> = read_crate(path, volume_root)?.tracks;
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut changed = 0;
+    for (tag, payload) in records {
+        let fields = (tag == *b"otrk").then(|| parse_records(payload)).flatten();
+        let Some(fields) = fields else {
+            push_record(&mut output, &tag, payload);
+            continue;
+        };
+        let location = fields.iter()
+            .find(|(field, _)| field == b"ptrk")
+            .map(|(_, value)| resolve(volume_root, &decode_utf16(value)));
+        let Some((new_path, relative)) = location.as_ref()
+            .and_then(|location| relocations.get(location))
+            .and_then(|new_path| Some((new_path, relative_location(volume_root, new_path)?))) else {
+            push_record(&mut output, &tag, payload);
+            continue;
+        };
+
+        changed += 1;
+        if listed.contains(new_path) {
+            continue;
+        }
+        listed.push(new_path.clone());
+        let mut track = Vec::with_capacity(payload.len());
+        for (field, value) in fields {
+            if field == *b"ptrk" {
+                push_record(&mut track, &field, &encode_utf16(&relative));
+            } else {
+                push_record(&mut track, &field, value);
+            }
+        }
+        push_record(&mut output, &tag, &track);
+    }
+
+    if changed > 0 {
+        report_dir::write_atomic(path, output)?;
+    }
+    Ok(changed)
+}
+
+// `path` as a crate or database stores it, None when it's not on the volume
+fn relative_location(volume_root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(volume_root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn push_record(output: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    output.extend_from_slice(tag);
+    output.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    output.extend_from_slice(payload);
+}
+
+// Volume root for a database: the drive the `_Serato_` folder is on. The
+// library in the home folder stores paths relative to the filesystem root.
+pub fn default_volume_root(database: &Path) -> PathBuf {
+    let serato_dir = database.parent().unwrap_or(database);
+    let in_home = dirs::home_dir().is_some_and(|home| serato_dir.starts_with(home));
+    if in_home {
+        return serato_dir.ancestors().last().map(Path::to_path_buf).unwrap_or_default();
+    }
+    serato_dir.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+// Cues, loops and track color live in a "Serato Markers2" blob: a 0x01 0x01
+// version, then base64 of another version header followed by entries (a
+// NUL-terminated type name, a u32 length, the data) and a closing NUL.
+// Serato reads the blob from a GEOB frame in MP3s and from the
+// SERATO_MARKERS_V2 comment, wrapped in a GEOB-like header, in FLAC.
+#[cfg(feature = "tags")]
+const MARKERS2: &str = "Serato Markers2";
+#[cfg(feature = "tags")]
+const FLAC_MARKERS2: &str = "SERATO_MARKERS_V2";
+// Serato pads the blob to at least this size
+const MARKERS2_MIN_LEN: usize = 470;
+
+// Markers2 blob holding `cues` as hot cues
+pub fn markers2_data(cues: &[CuePoint]) -> Vec<u8> {
+    let mut payload = vec![0x01, 0x01];
+    for cue in cues {
+        let mut entry = vec![0x00, cue.kind.slot()];
+        entry.extend_from_slice(&((cue.position_secs * 1000.0).round() as u32).to_be_bytes());
+        entry.push(0x00);
+        entry.extend_from_slice(&cue.kind.color());
+        entry.extend_from_slice(&[0x00, 0x00]);
+        entry.extend_from_slice(cue.kind.label().as_bytes());
+        entry.push(0x00);
+
+        payload.extend_from_slice(b"CUE\0");
+        payload.extend_from_slice(&(entry.len() as u32).to_be_bytes());
+        payload.extend_from_slice(&entry);
+    }
+    payload.push(0x00);
+
+    // Serato writes base64 without padding, in lines of 72 characters
+    let encoded = base64(&payload);
+    let mut data = vec![0x01, 0x01];
+    for (index, line) in encoded.as_bytes().chunks(72).enumerate() {
+        if index > 0 {
+            data.push(b'\n');
+        }
+        data.extend_from_slice(line);
+    }
+    data.push(0x00);
+    if data.len() < MARKERS2_MIN_LEN {
+        data.resize(MARKERS2_MIN_LEN, 0x00);
+    }
+    data
+}
+
+// Stores `cues` in the file's Serato markers. Files that already carry
+// Serato markers keep them and return Ok(false): those hold the DJ's own
+// cues, loops and colors, which a rewrite would throw away.
+#[cfg(feature = "tags")]
+pub fn write_cue_markers(path: &Path, cues: &[CuePoint]) -> Result<bool> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+    let data = markers2_data(cues);
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(tag_error(e.to_string())),
+            };
+            if tag.encapsulated_objects().any(|object| object.description == MARKERS2) {
+                return Ok(false);
+            }
+            let object = id3::frame::EncapsulatedObject {
+                mime_type: "application/octet-stream".to_string(),
+                filename: String::new(),
+                description: MARKERS2.to_string(),
+                data,
+            };
+            // Serato looks for the description in Latin-1
+            tag.add_frame(id3::Frame::with_content("GEOB", id3::Content::EncapsulatedObject(object))
+                .set_encoding(Some(id3::Encoding::Latin1)));
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))?;
+            Ok(true)
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            if tag.get_vorbis(FLAC_MARKERS2).is_some_and(|mut values| values.next().is_some()) {
+                return Ok(false);
+            }
+            let mut wrapped = b"application/octet-stream\0\0".to_vec();
+            wrapped.extend_from_slice(MARKERS2.as_bytes());
+            wrapped.push(0x00);
+            wrapped.extend_from_slice(&data);
+            let encoded = base64(&wrapped);
+            let lines: Vec<&str> = encoded.as_bytes()
+                .chunks(72)
+                .map(|line| std::str::from_utf8(line).unwrap_or_default())
+                .collect();
+            tag.set_vorbis(FLAC_MARKERS2, vec![lines.join("\n")]);
+            tag.save().map_err(|e| tag_error(e.to_string()))?;
+            Ok(true)
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't write Serato markers to {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
+
+// Standard alphabet, no padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..=chunk.len() {
+            encoded.push(ALPHABET[(value >> (18 - 6 * index) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn resolve(volume_root: &Path, location: &str) -> PathBuf {
+    let relative = location.trim_start_matches('/');
+    volume_root.join(relative)
+}
+
+fn parse_records(mut data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let mut records = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 8 {
+            return None;
+        }
+        let tag: [u8; 4] = data[..4].try_into().ok()?;
+        let len = u32::from_be_bytes(data[4..8].try_into().ok()?) as usize;
+        let payload = data.get(8..8 + len)?;
+        records.push((tag, payload));
+        data = &data[8 + len..];
+    }
+    Some(records)
+}
+
+// Track length as Serato displays it: "mm:ss.xx", or "h:mm:ss.xx"
+fn parse_length(text: &str) -> Option<f64> {
+    text.trim().trim_end_matches('\0').split(':')
+        .try_fold(0.0, |total, part| Some(total * 60.0 + part.trim().parse::<f64>().ok()?))
+        .filter(|&secs| secs > 0.0)
+}
+
+fn encode_utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
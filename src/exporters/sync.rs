@@ -0,0 +1,270 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::audio::metadata;
+use crate::utils::file_ops::{FileOperation, FileOperationExecutor, OperationOutcome};
+use crate::utils::unix_timestamp_millis;
+
+// One-way sync of a folder onto a drive (the library onto a gig stick):
+// new and changed files are copied, files gone from the source are deleted
+// from the target only when deletion is allowed and only once they've been
+// missing for the retention period. A source folder that was emptied or
+// half-moved by mistake then can't wipe the stick the night before a gig.
+// When each file was first found missing is kept in a state file on the
+// target. Folders DJ software keeps its own data in (`PIONEER`, `_Serato_`,
+// ...) are left alone on both sides.
+
+pub const STATE_FILE: &str = ".djlm-sync.json";
+pub const DEFAULT_RETENTION_DAYS: u64 = 30;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncState {
+    // Target file (relative) -> when it was first found missing from the
+    // source, unix milliseconds
+    removed: BTreeMap<PathBuf, u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "action")]
+pub enum SyncAction {
+    // Not on the target yet
+    Copy,
+    // Different size, or newer on the source
+    Update,
+    // Missing from the source for longer than the retention period
+    Delete,
+    // Missing from the source, still within the retention period; deletable
+    // from this time on (unix milliseconds)
+    Retain { delete_after: u64 },
+    // Missing from the source, and deletions weren't allowed
+    Protected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncEntry {
+    // Relative to the source and target folders
+    pub path: PathBuf,
+    pub action: SyncAction,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    // Files already identical on both sides
+    pub unchanged: usize,
+    pub entries: Vec<SyncEntry>,
+    #[serde(skip)]
+    state: SyncState,
+}
+
+impl SyncPlan {
+    pub fn count(&self, action: SyncAction) -> usize {
+        self.entries.iter().filter(|entry| entry.action == action).count()
+    }
+
+    pub fn retained(&self) -> usize {
+        self.entries.iter().filter(|entry| matches!(entry.action, SyncAction::Retain { .. })).count()
+    }
+
+    pub fn bytes_to_copy(&self) -> u64 {
+        self.entries.iter()
+            .filter(|entry| matches!(entry.action, SyncAction::Copy | SyncAction::Update))
+            .map(|entry| entry.size_bytes)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncResult {
+    pub path: PathBuf,
+    pub action: SyncAction,
+    pub outcome: std::result::Result<OperationOutcome, String>,
+}
+
+pub struct Syncer {
+    allow_delete: bool,
+    retention_days: u64,
+}
+
+impl Default for Syncer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Syncer {
+    pub fn new() -> Self {
+        Self {
+            allow_delete: false,
+            retention_days: DEFAULT_RETENTION_DAYS,
+        }
+    }
+
+    pub fn with_allow_delete(mut self, allow: bool) -> Self {
+        self.allow_delete = allow;
+        self
+    }
+
+    pub fn with_retention_days(mut self, days: u64) -> Self {
+        self.retention_days = days;
+        self
+    }
+
+    pub fn plan(&self, source: &Path, target: &Path) -> Result<SyncPlan> {
+        if !source.is_dir() {
+            return Err(AudioError::io(source, std::io::Error::new(
+                std::io::ErrorKind::NotFound, "source folder not found (is the drive mounted?)",
+            )));
+        }
+        let source_files = list_files(source)?;
+        let target_files = if target.exists() { list_files(target)? } else { HashMap::new() };
+        if source_files.is_empty() && !target_files.is_empty() {
+            return Err(AudioError::Metadata(format!(
+                "{} is empty; refusing to sync it over {} files on the target", source.display(), target_files.len()
+            )));
+        }
+
+        let now = unix_timestamp_millis();
+        let previous = load_state(target);
+        let mut state = SyncState::default();
+        let mut entries = Vec::new();
+        let mut unchanged = 0;
+
+        let mut source_paths: Vec<&PathBuf> = source_files.keys().collect();
+        source_paths.sort();
+        for path in source_paths {
+            let (size, modified) = source_files[path];
+            let action = match target_files.get(path) {
+                None => SyncAction::Copy,
+                Some(&(target_size, target_modified)) => {
+                    let newer = match (modified, target_modified) {
+                        (Some(source_time), Some(target_time)) => source_time > target_time,
+                        _ => false,
+                    };
+                    if size != target_size || newer {
+                        SyncAction::Update
+                    } else {
+                        unchanged += 1;
+                        continue;
+                    }
+                }
+            };
+            entries.push(SyncEntry { path: path.clone(), action, size_bytes: size });
+        }
+
+        let mut removed: Vec<&PathBuf> = target_files.keys().filter(|path| !source_files.contains_key(*path)).collect();
+        removed.sort();
+        for path in removed {
+            let missing_since = previous.removed.get(path).copied().unwrap_or(now);
+            state.removed.insert(path.clone(), missing_since);
+            // Saturating: a retention too long to represent never comes due
+            let delete_after = missing_since.saturating_add(self.retention_days.saturating_mul(DAY_MS));
+            let action = if !self.allow_delete {
+                SyncAction::Protected
+            } else if now >= delete_after {
+                SyncAction::Delete
+            } else {
+                SyncAction::Retain { delete_after }
+            };
+            entries.push(SyncEntry { path: path.clone(), action, size_bytes: target_files[path].0 });
+        }
+
+        Ok(SyncPlan {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            unchanged,
+            entries,
+            state,
+        })
+    }
+
+    // Copies and deletes as planned, then records which files are missing
+    // from the source (not on a dry run)
+    pub fn execute(&self, plan: &SyncPlan, executor: &FileOperationExecutor) -> Result<Vec<SyncResult>> {
+        let mut results = Vec::new();
+        let mut state = plan.state.clone();
+
+        for entry in &plan.entries {
+            let to = plan.target.join(&entry.path);
+            let operation = match entry.action {
+                SyncAction::Copy | SyncAction::Update => FileOperation::Copy { from: plan.source.join(&entry.path), to },
+                SyncAction::Delete => FileOperation::Delete { path: to },
+                SyncAction::Retain { .. } | SyncAction::Protected => continue,
+            };
+            let outcome = executor.execute(&operation).map_err(|e| e.to_string());
+            if entry.action == SyncAction::Delete && matches!(outcome, Ok(OperationOutcome::Performed(_))) {
+                state.removed.remove(&entry.path);
+                remove_empty_parents(&plan.target, &plan.target.join(&entry.path));
+            }
+            results.push(SyncResult { path: entry.path.clone(), action: entry.action, outcome });
+        }
+
+        if !executor.is_dry_run() {
+            save_state(&plan.target, &state)?;
+        }
+        Ok(results)
+    }
+}
+
+type FileState = (u64, Option<SystemTime>);
+
+// Every file under `root` by relative path, without DJ software data,
+// macOS resource files and our own state file
+fn list_files(root: &Path) -> Result<HashMap<PathBuf, FileState>> {
+    let mut files = HashMap::new();
+    let walker = walkdir::WalkDir::new(root).into_iter().filter_entry(|entry| {
+        let name = entry.file_name().to_string_lossy();
+        if entry.file_type().is_dir() {
+            entry.depth() == 0 || !metadata::is_software_folder(&name)
+        } else {
+            !metadata::is_resource_file(&name) && name != STATE_FILE
+        }
+    });
+    for entry in walker {
+        let entry = entry.map_err(|e| {
+            let path = e.path().unwrap_or(root).to_path_buf();
+            AudioError::io(&path, e.into())
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|e| AudioError::io(entry.path(), e.into()))?;
+        let Ok(relative) = entry.path().strip_prefix(root) else { continue };
+        files.insert(relative.to_path_buf(), (metadata.len(), metadata.modified().ok()));
+    }
+    Ok(files)
+}
+
+// A missing or unreadable state file starts the retention clock over, which
+// only ever delays deletions
+fn load_state(target: &Path) -> SyncState {
+    fs::read_to_string(target.join(STATE_FILE)).ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(target: &Path, state: &SyncState) -> Result<()> {
+    fs::create_dir_all(target).map_err(|e| AudioError::io(target, e))?;
+    let path = target.join(STATE_FILE);
+    let json = serde_json::to_string_pretty(state).map_err(|e| AudioError::Metadata(e.to_string()))?;
+    fs::write(&path, json).map_err(|e| AudioError::io(&path, e))
+}
+
+// Removes the folders a deletion left empty, up to (not including) the
+// target itself
+fn remove_empty_parents(target: &Path, deleted: &Path) {
+    let mut dir = deleted.parent();
+    while let Some(current) = dir {
+        if current == target || !current.starts_with(target) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
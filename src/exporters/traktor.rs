@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use crate::{AudioError, Result};
+use crate::exporters::CollectionTrack;
+use crate::exporters::cues::TrackCues;
+
+// Traktor NML collections (collection.nml, or File > Export Collection).
+// Tracks live in NML/COLLECTION/ENTRY; each has a LOCATION split into a
+// VOLUME, a DIR written as "/:Users/:me/:Music/:" and a FILE, an INFO with
+// the play count and length, a TEMPO with the BPM and one CUE_V2 per cue,
+// grid marker and loop. Playlists refer to tracks by the same location
+// joined into one key, "Macintosh HD/:Users/:me/:Music/:a.mp3".
+
+// What Traktor calls the system drive when /Volumes doesn't say
+const DEFAULT_BOOT_VOLUME: &str = "Macintosh HD";
+const DIR_SEPARATOR: &str = "/:";
+
+// A collection entry with the Traktor data worth keeping across a move
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraktorEntry {
+    // FILESIZE is in kilobytes, too coarse to match files on, so the
+    // track's size_bytes is left empty
+    pub track: CollectionTrack,
+    pub bpm: Option<f64>,
+    pub play_count: Option<u32>,
+    pub cues: Vec<TraktorCue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CueType {
+    Cue,
+    FadeIn,
+    FadeOut,
+    Load,
+    Grid,
+    Loop,
+}
+
+impl CueType {
+    // Unknown types are read as plain cues
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => Self::FadeIn,
+            2 => Self::FadeOut,
+            3 => Self::Load,
+            4 => Self::Grid,
+            5 => Self::Loop,
+            _ => Self::Cue,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Cue => 0,
+            Self::FadeIn => 1,
+            Self::FadeOut => 2,
+            Self::Load => 3,
+            Self::Grid => 4,
+            Self::Loop => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraktorCue {
+    pub name: String,
+    pub cue_type: CueType,
+    pub start_secs: f64,
+    pub length_secs: f64,
+    // Hot cue slot, 0 = 1; None when the cue isn't on a hot cue
+    pub hotcue: Option<u8>,
+}
+
+// Where an NML entry says its file is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NmlLocation {
+    pub volume: String,
+    pub dir: String,
+    pub file: String,
+}
+
+impl NmlLocation {
+    // The form playlists use in PRIMARYKEY
+    pub fn key(&self) -> String {
+        format!("{}{}{}", self.volume, self.dir, self.file)
+    }
+}
+
+pub fn read_collection(path: impl AsRef<Path>) -> Result<Vec<CollectionTrack>> {
+    Ok(read_entries(path)?.into_iter().map(|entry| entry.track).collect())
+}
+
+pub fn read_entries(path: impl AsRef<Path>) -> Result<Vec<TraktorEntry>> {
+    let path = path.as_ref();
+    let mut reader = Reader::from_file(path).map_err(|e| xml_error(path, e))?;
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+    let mut in_collection = false;
+    // The entry being read, with its title and artist until LOCATION comes
+    let mut current: Option<(Option<String>, Option<String>, Option<TraktorEntry>)> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf).map_err(|e| xml_error(path, e))? {
+            Event::Start(e) if e.name().as_ref() == b"COLLECTION" => in_collection = true,
+            Event::End(e) if e.name().as_ref() == b"COLLECTION" => in_collection = false,
+            Event::Start(e) if in_collection && e.name().as_ref() == b"ENTRY" => {
+                current = Some((attribute(&e, b"TITLE"), attribute(&e, b"ARTIST"), None));
+            }
+            Event::End(e) if e.name().as_ref() == b"ENTRY" => {
+                if let Some((_, _, Some(entry))) = current.take() {
+                    entries.push(entry);
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if in_collection => if let Some((title, artist, entry)) = current.as_mut() {
+                match e.name().as_ref() {
+                    b"LOCATION" => {
+                        if let Some(location) = parse_location(&e) {
+                            let mut track = CollectionTrack::new(location_to_path(&location));
+                            track.title = title.take();
+                            track.artist = artist.take();
+                            *entry = Some(TraktorEntry { track, bpm: None, play_count: None, cues: Vec::new() });
+                        }
+                    }
+                    b"INFO" => if let Some(entry) = entry {
+                        entry.play_count = attribute(&e, b"PLAYCOUNT").and_then(|v| v.parse().ok());
+                        entry.track.duration_secs = attribute(&e, b"PLAYTIME_FLOAT")
+                            .or_else(|| attribute(&e, b"PLAYTIME"))
+                            .and_then(|v| v.parse().ok());
+                    },
+                    b"TEMPO" => if let Some(entry) = entry {
+                        entry.bpm = attribute(&e, b"BPM").and_then(|v| v.parse().ok());
+                    },
+                    b"CUE_V2" => if let Some(entry) = entry {
+                        entry.cues.push(parse_cue(&e));
+                    },
+                    _ => {}
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(entries)
+}
+
+// Copies the collection to `output`, pointing every entry whose location is
+// a key of `relocations` at its new path, along with the playlist entries
+// that refer to it. Play counts, cues, grids and everything else are written
+// through untouched. Returns the number of rewritten entries.
+pub fn rewrite_locations(input: impl AsRef<Path>, output: impl AsRef<Path>, relocations: &HashMap<PathBuf, PathBuf>) -> Result<usize> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let mut reader = Reader::from_reader(BufReader::new(File::open(input).map_err(|e| AudioError::io(input, e))?));
+    let mut writer = Writer::new(BufWriter::new(File::create(output).map_err(|e| AudioError::io(output, e))?));
+    let mut buf = Vec::new();
+    let mut in_collection = false;
+    let mut rewritten = 0;
+    // Old playlist key -> new, filled in as the collection is read; playlists
+    // come after it
+    let mut keys: HashMap<String, String> = HashMap::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|e| xml_error(input, e))?;
+        let event = match event {
+            Event::Start(e) if e.name().as_ref() == b"COLLECTION" => {
+                in_collection = true;
+                Event::Start(e)
+            }
+            Event::End(e) if e.name().as_ref() == b"COLLECTION" => {
+                in_collection = false;
+                Event::End(e)
+            }
+            Event::Start(e) if in_collection && e.name().as_ref() == b"LOCATION" => match relocate_location(&e, relocations, &mut keys) {
+                Some(updated) => {
+                    rewritten += 1;
+                    Event::Start(updated)
+                }
+                None => Event::Start(e),
+            },
+            Event::Empty(e) if in_collection && e.name().as_ref() == b"LOCATION" => match relocate_location(&e, relocations, &mut keys) {
+                Some(updated) => {
+                    rewritten += 1;
+                    Event::Empty(updated)
+                }
+                None => Event::Empty(e),
+            },
+            Event::Start(e) if e.name().as_ref() == b"PRIMARYKEY" => Event::Start(rekey(&e, &keys).unwrap_or(e)),
+            Event::Empty(e) if e.name().as_ref() == b"PRIMARYKEY" => Event::Empty(rekey(&e, &keys).unwrap_or(e)),
+            Event::Eof => break,
+            other => other,
+        };
+        writer.write_event(event).map_err(|e| xml_error(output, e))?;
+        buf.clear();
+    }
+
+    writer.into_inner().flush().map_err(|e| AudioError::io(output, e))?;
+    Ok(rewritten)
+}
+
+// Writes a collection holding only `tracks`, each with its cues as hot cues
+// 1-4 and its BPM when the file has one. Traktor merges it into its own
+// collection through File > Import Another Collection.
+pub fn write_cue_collection(output: impl AsRef<Path>, tracks: &[TrackCues]) -> Result<()> {
+    let output = output.as_ref();
+    let file = File::create(output).map_err(|e| AudioError::io(output, e))?;
+    let mut writer = Writer::new_with_indent(BufWriter::new(file), b' ', 2);
+    let mut write = |event: Event<'_>| writer.write_event(event).map_err(|e| xml_error(output, e));
+
+    write(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), Some("no"))))?;
+    write(Event::Start(BytesStart::new("NML").with_attributes([("VERSION", "19")])))?;
+    // Traktor only imports files that look like its own
+    write(Event::Empty(BytesStart::new("HEAD").with_attributes([
+        ("COMPANY", "www.native-instruments.com"),
+        ("PROGRAM", "Traktor"),
+    ])))?;
+    let entries = tracks.len().to_string();
+    write(Event::Start(BytesStart::new("COLLECTION").with_attributes([("ENTRIES", entries.as_str())])))?;
+
+    for track in tracks {
+        let title = track.title.clone().unwrap_or_else(|| {
+            track.path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        });
+        let mut element = BytesStart::new("ENTRY");
+        element.push_attribute(("TITLE", title.as_str()));
+        element.push_attribute(("ARTIST", track.artist.as_deref().unwrap_or_default()));
+        write(Event::Start(element))?;
+
+        let location = path_to_location(&track.path);
+        write(Event::Empty(BytesStart::new("LOCATION").with_attributes([
+            ("DIR", location.dir.as_str()),
+            ("FILE", location.file.as_str()),
+            ("VOLUME", location.volume.as_str()),
+        ])))?;
+        let playtime = (track.duration_secs.round() as u64).to_string();
+        let playtime_float = format!("{:.6}", track.duration_secs);
+        write(Event::Empty(BytesStart::new("INFO").with_attributes([
+            ("PLAYTIME", playtime.as_str()),
+            ("PLAYTIME_FLOAT", playtime_float.as_str()),
+        ])))?;
+        if let Some(bpm) = track.bpm {
+            let bpm = format!("{:.6}", bpm);
+            write(Event::Empty(BytesStart::new("TEMPO").with_attributes([("BPM", bpm.as_str())])))?;
+        }
+
+        for (order, cue) in track.cues.iter().enumerate() {
+            let order = order.to_string();
+            let start = format!("{:.6}", cue.position_secs * 1000.0);
+            let hotcue = cue.kind.slot().to_string();
+            let cue_type = CueType::Cue.code().to_string();
+            write(Event::Empty(BytesStart::new("CUE_V2").with_attributes([
+                ("NAME", cue.kind.label()),
+                ("DISPL_ORDER", order.as_str()),
+                ("TYPE", cue_type.as_str()),
+                ("START", start.as_str()),
+                ("LEN", "0.000000"),
+                ("REPEATS", "-1"),
+                ("HOTCUE", hotcue.as_str()),
+            ])))?;
+        }
+        write(Event::End(BytesEnd::new("ENTRY")))?;
+    }
+
+    write(Event::End(BytesEnd::new("COLLECTION")))?;
+    write(Event::End(BytesEnd::new("NML")))?;
+    writer.into_inner().flush().map_err(|e| AudioError::io(output, e))
+}
+
+// C:   /:Music/:      a.mp3 -> C:/Music/a.mp3
+// USB  /:Music/:      a.mp3 -> /Volumes/USB/Music/a.mp3
+// (system drive) /:Users/:me/: a.mp3 -> /Users/me/a.mp3
+pub fn location_to_path(location: &NmlLocation) -> PathBuf {
+    let mut path = String::new();
+    let volume = location.volume.as_str();
+    if is_drive(volume) {
+        path.push_str(volume);
+    } else if !volume.is_empty() && volume != boot_volume() {
+        path.push_str("/Volumes/");
+        path.push_str(volume);
+    }
+    for part in location.dir.split(DIR_SEPARATOR).filter(|part| !part.is_empty()) {
+        path.push('/');
+        path.push_str(part);
+    }
+    path.push('/');
+    path.push_str(&location.file);
+    PathBuf::from(path)
+}
+
+pub fn path_to_location(path: &Path) -> NmlLocation {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let (volume, rest) = if let Some(drive) = path.get(..2).filter(|start| is_drive(start)) {
+        (drive.to_string(), &path[2..])
+    } else if let Some(rest) = path.strip_prefix("/Volumes/") {
+        let (volume, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        (volume.to_string(), rest)
+    } else {
+        (boot_volume().to_string(), path.as_str())
+    };
+    let mut parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let file = parts.pop().unwrap_or_default().to_string();
+    let mut dir = DIR_SEPARATOR.to_string();
+    for part in parts {
+        dir.push_str(part);
+        dir.push_str(DIR_SEPARATOR);
+    }
+    NmlLocation { volume, dir, file }
+}
+
+// On macOS the system drive is also listed in /Volumes, as a link back to /
+fn boot_volume() -> &'static str {
+    static BOOT_VOLUME: OnceLock<String> = OnceLock::new();
+    BOOT_VOLUME.get_or_init(|| {
+        std::fs::read_dir("/Volumes").into_iter()
+            .flatten()
+            .flatten()
+            .find(|entry| entry.path().canonicalize().is_ok_and(|target| target == Path::new("/")))
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .unwrap_or_else(|| DEFAULT_BOOT_VOLUME.to_string())
+    })
+}
+
+fn is_drive(volume: &str) -> bool {
+    let bytes = volume.as_bytes();
+    bytes.len() == 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+fn attribute(element: &BytesStart<'_>, key: &[u8]) -> Option<String> {
+    element.attributes().flatten()
+        .find(|attr| attr.key.as_ref() == key)
+        .and_then(|attr| attr.unescape_value().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.into_owned())
+}
+
+fn parse_location(element: &BytesStart<'_>) -> Option<NmlLocation> {
+    Some(NmlLocation {
+        volume: attribute(element, b"VOLUME").unwrap_or_default(),
+        dir: attribute(element, b"DIR").unwrap_or_else(|| DIR_SEPARATOR.to_string()),
+        file: attribute(element, b"FILE")?,
+    })
+}
+
+// START and LEN are in milliseconds
+fn parse_cue(element: &BytesStart<'_>) -> TraktorCue {
+    let millis = |key| attribute(element, key).and_then(|v| v.parse::<f64>().ok()).unwrap_or_default();
+    TraktorCue {
+        name: attribute(element, b"NAME").unwrap_or_default(),
+        cue_type: CueType::from_code(attribute(element, b"TYPE").and_then(|v| v.parse().ok()).unwrap_or_default()),
+        start_secs: millis(b"START") / 1000.0,
+        length_secs: millis(b"LEN") / 1000.0,
+        hotcue: attribute(element, b"HOTCUE").and_then(|v| v.parse().ok()),
+    }
+}
+
+fn relocate_location(
+    element: &BytesStart<'_>,
+    relocations: &HashMap<PathBuf, PathBuf>,
+    keys: &mut HashMap<String, String>,
+) -> Option<BytesStart<'static>> {
+    let old = parse_location(element)?;
+    let new_path = relocations.get(&location_to_path(&old))?;
+    let new = path_to_location(new_path);
+
+    let mut updated = BytesStart::new("LOCATION");
+    for attr in element.attributes().flatten() {
+        match attr.key.as_ref() {
+            b"DIR" => updated.push_attribute(("DIR", new.dir.as_str())),
+            b"FILE" => updated.push_attribute(("FILE", new.file.as_str())),
+            b"VOLUME" => updated.push_attribute(("VOLUME", new.volume.as_str())),
+            // The ID belongs to the old volume; Traktor looks the new one up
+            // by name
+            b"VOLUMEID" if new.volume != old.volume => {}
+            // Other attributes are copied still escaped
+            _ => updated.push_attribute(Attribute::from((attr.key.as_ref(), attr.value.as_ref()))),
+        }
+    }
+    keys.insert(old.key(), new.key());
+    Some(updated)
+}
+
+fn rekey(element: &BytesStart<'_>, keys: &HashMap<String, String>) -> Option<BytesStart<'static>> {
+    let new_key = keys.get(&attribute(element, b"KEY")?)?;
+    let mut updated = BytesStart::new("PRIMARYKEY");
+    for attr in element.attributes().flatten() {
+        if attr.key.as_ref() == b"KEY" {
+            updated.push_attribute(("KEY", new_key.as_str()));
+        } else {
+            updated.push_attribute(Attribute::from((attr.key.as_ref(), attr.value.as_ref())));
+        }
+    }
+    Some(updated)
+}
+
+fn xml_error(path: &Path, error: impl std::fmt::Display) -> AudioError {
+    AudioError::Metadata(format!("Invalid NML in {}: {}", path.display(), error))
+}
@@ -0,0 +1,364 @@
+use std::collections::{BinaryHeap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::Serialize;
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::bitrate::BitrateAnalyzer;
+use crate::analyzers::title::{ParsedTitle, TitleParser};
+use crate::utils::file_ops::{FileOperation, FileOperationExecutor, OperationOutcome};
+use crate::utils::unix_timestamp_millis;
+
+// Export onto a drive of fixed size ("the best of the library on 64 GB").
+// Every track starts out as its best copy. While the selection is over
+// budget, the track whose next cheaper option saves the most space steps
+// down: to a smaller copy of the same track when downgrading is allowed, or
+// to an MP3 transcode of the best copy when a bitrate is given. Whatever
+// still doesn't fit is left out, preferring to drop one track that covers
+// the excess over several smaller ones.
+
+pub const MANIFEST_FILE: &str = "export-manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportAction {
+    // Best copy, as is
+    Copy,
+    // A smaller copy of the same track
+    Downgrade,
+    // The best copy transcoded with ffmpeg
+    Transcode,
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportEntry {
+    pub artist: String,
+    pub title: String,
+    pub version: Option<String>,
+    pub action: ExportAction,
+    pub source: PathBuf,
+    // Relative to the export target; None for skipped tracks
+    pub destination: Option<PathBuf>,
+    // Estimated for transcodes until they are written
+    pub size_bytes: u64,
+    pub bitrate: Option<u32>,
+    // Best copy in the library, when something else was exported
+    pub best_copy: Option<PathBuf>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportManifest {
+    pub generated_at: u64,
+    pub budget_bytes: u64,
+    pub total_bytes: u64,
+    pub entries: Vec<ExportEntry>,
+}
+
+impl ExportManifest {
+    pub fn count(&self, action: ExportAction) -> usize {
+        self.entries.iter().filter(|entry| entry.action == action).count()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        fs::write(path, json).map_err(|e| AudioError::io(path, e))
+    }
+
+    fn update_total(&mut self) {
+        self.total_bytes = self.entries.iter()
+            .filter(|entry| entry.action != ExportAction::Skip)
+            .map(|entry| entry.size_bytes)
+            .sum();
+    }
+}
+
+// One way of putting a track on the drive
+struct Candidate {
+    source: usize,
+    action: ExportAction,
+    size_bytes: u64,
+    bitrate: Option<u32>,
+}
+
+struct Track {
+    parsed: ParsedTitle,
+    copies: Vec<AudioFile>,
+    // Strictly shrinking in size, best first
+    candidates: Vec<Candidate>,
+    chosen: Option<usize>,
+}
+
+impl Track {
+    fn size(&self) -> u64 {
+        self.chosen.map_or(0, |chosen| self.candidates[chosen].size_bytes)
+    }
+
+    // Space saved by stepping down to the next candidate
+    fn next_saving(&self) -> Option<u64> {
+        let chosen = self.chosen?;
+        let next = self.candidates.get(chosen + 1)?;
+        Some(self.candidates[chosen].size_bytes - next.size_bytes)
+    }
+}
+
+pub struct UsbExporter {
+    budget_bytes: u64,
+    allow_downgrade: bool,
+    transcode_bitrate: Option<u32>,
+}
+
+impl UsbExporter {
+    pub fn new(budget_bytes: u64) -> Self {
+        Self {
+            budget_bytes,
+            allow_downgrade: false,
+            transcode_bitrate: None,
+        }
+    }
+
+    // Export a smaller copy of a track when the best one doesn't fit
+    pub fn with_downgrade(mut self, allow: bool) -> Self {
+        self.allow_downgrade = allow;
+        self
+    }
+
+    // Transcode to MP3 at this bitrate (kbps) when a track doesn't fit
+    // otherwise. Needs ffmpeg on PATH when the export is written.
+    pub fn with_transcode(mut self, bitrate: Option<u32>) -> Self {
+        self.transcode_bitrate = bitrate;
+        self
+    }
+
+    // Decides what goes on the drive; nothing is written
+    pub fn plan(&self, files: &[AudioFile]) -> ExportManifest {
+        let mut tracks = self.group_tracks(files);
+
+        let mut total: u64 = tracks.iter().map(Track::size).sum();
+        let mut steps: BinaryHeap<(u64, usize)> = tracks.iter()
+            .enumerate()
+            .filter_map(|(index, track)| Some((track.next_saving()?, index)))
+            .collect();
+        while total > self.budget_bytes {
+            let Some((saving, index)) = steps.pop() else { break };
+            let track = &mut tracks[index];
+            track.chosen = track.chosen.map(|chosen| chosen + 1);
+            total -= saving;
+            if let Some(saving) = track.next_saving() {
+                steps.push((saving, index));
+            }
+        }
+
+        while total > self.budget_bytes {
+            let excess = total - self.budget_bytes;
+            let included = tracks.iter_mut().filter(|track| track.chosen.is_some());
+            let (covering, smaller): (Vec<&mut Track>, Vec<&mut Track>) = included.partition(|track| track.size() >= excess);
+            let dropped = match covering.into_iter().min_by_key(|track| track.size()) {
+                Some(track) => Some(track),
+                None => smaller.into_iter().max_by_key(|track| track.size()),
+            };
+            let Some(track) = dropped else { break };
+            total -= track.size();
+            track.chosen = None;
+        }
+
+        let mut manifest = ExportManifest {
+            generated_at: unix_timestamp_millis(),
+            budget_bytes: self.budget_bytes,
+            total_bytes: 0,
+            entries: tracks.into_iter().map(|track| self.entry(track)).collect(),
+        };
+        manifest.update_total();
+        manifest
+    }
+
+    // Writes every included track to `target`. Tracks that fail are marked
+    // skipped with the reason; files already on the target are kept.
+    pub fn export(&self, manifest: &mut ExportManifest, target: &Path, executor: &FileOperationExecutor) -> Result<()> {
+        executor.ensure_directory(target)?;
+        let total = manifest.entries.iter().filter(|entry| entry.action != ExportAction::Skip).count();
+        let mut done = 0;
+
+        for entry in &mut manifest.entries {
+            let Some(relative) = entry.destination.clone() else { continue };
+            let destination = target.join(&relative);
+            let result = match entry.action {
+                ExportAction::Transcode => self.write_transcode(entry, &destination, executor.is_dry_run()),
+                _ => copy_entry(entry, destination, target, executor),
+            };
+            if let Err(e) = result {
+                eprintln!("Error exporting {}: {}", entry.source.display(), e);
+                entry.action = ExportAction::Skip;
+                entry.destination = None;
+                entry.note = Some(e.to_string());
+            }
+
+            done += 1;
+            if done % 100 == 0 || done == total {
+                println!("Progress: {}/{} tracks", done, total);
+            }
+        }
+
+        manifest.update_total();
+        Ok(())
+    }
+
+    fn group_tracks(&self, files: &[AudioFile]) -> Vec<Track> {
+        let parser = TitleParser::new();
+        let mut tracks: Vec<Track> = Vec::new();
+        let mut by_title: HashMap<(String, String), Vec<usize>> = HashMap::new();
+
+        for file in files {
+            let parsed = parser.parse(&file.file_name);
+            let key = (parsed.artist.clone(), parsed.title.clone());
+            let same_title = by_title.entry(key).or_default();
+            let existing = same_title.iter().copied().find(|&index| {
+                !TitleParser::are_different_versions(parsed.version.as_deref(), tracks[index].parsed.version.as_deref())
+            });
+            match existing {
+                Some(index) => tracks[index].copies.push(file.clone()),
+                None => {
+                    same_title.push(tracks.len());
+                    tracks.push(Track { parsed, copies: vec![file.clone()], candidates: Vec::new(), chosen: Some(0) });
+                }
+            }
+        }
+
+        for track in &mut tracks {
+            track.candidates = self.candidates(&mut track.copies);
+        }
+        tracks
+    }
+
+    // Moves the best copy to the front and lists the ways of exporting the
+    // track, each smaller than the one before
+    fn candidates(&self, copies: &mut [AudioFile]) -> Vec<Candidate> {
+        let best = (1..copies.len()).fold(0, |best, index| {
+            if BitrateAnalyzer::compare_quality(&copies[index], &copies[best]).0 { index } else { best }
+        });
+        copies.swap(0, best);
+        let best = &copies[0];
+
+        let mut options = Vec::new();
+        if self.allow_downgrade {
+            options.extend(copies.iter().enumerate().skip(1).map(|(index, copy)| Candidate {
+                source: index,
+                action: ExportAction::Downgrade,
+                size_bytes: copy.size_bytes,
+                bitrate: copy.bitrate,
+            }));
+        }
+        if let Some(bitrate) = self.transcode_bitrate {
+            let worth_it = best.bitrate.is_none_or(|b| b > bitrate);
+            if let (true, Some(duration)) = (worth_it, best.duration_secs) {
+                options.push(Candidate {
+                    source: 0,
+                    action: ExportAction::Transcode,
+                    size_bytes: (duration * bitrate as f64 * 125.0) as u64,
+                    bitrate: Some(bitrate),
+                });
+            }
+        }
+        options.sort_by_key(|option| std::cmp::Reverse(option.size_bytes));
+
+        let mut candidates = vec![Candidate {
+            source: 0,
+            action: ExportAction::Copy,
+            size_bytes: best.size_bytes,
+            bitrate: best.bitrate,
+        }];
+        for option in options {
+            if option.size_bytes < candidates[candidates.len() - 1].size_bytes {
+                candidates.push(option);
+            }
+        }
+        candidates
+    }
+
+    fn entry(&self, track: Track) -> ExportEntry {
+        let best = &track.copies[0];
+        let mut entry = ExportEntry {
+            artist: track.parsed.artist,
+            title: track.parsed.title,
+            version: track.parsed.version,
+            action: ExportAction::Skip,
+            source: best.path.clone(),
+            destination: None,
+            size_bytes: best.size_bytes,
+            bitrate: best.bitrate,
+            best_copy: None,
+            note: None,
+        };
+        let Some(chosen) = track.chosen else {
+            entry.note = Some("Does not fit the budget".to_string());
+            return entry;
+        };
+
+        let candidate = &track.candidates[chosen];
+        let source = &track.copies[candidate.source];
+        let file_name = match candidate.action {
+            ExportAction::Transcode => Path::new(&source.file_name).with_extension("mp3"),
+            _ => PathBuf::from(&source.file_name),
+        };
+        entry.action = candidate.action;
+        entry.source = source.path.clone();
+        entry.destination = Some(file_name);
+        entry.size_bytes = candidate.size_bytes;
+        entry.bitrate = candidate.bitrate;
+        if candidate.action != ExportAction::Copy {
+            entry.best_copy = Some(best.path.clone());
+        }
+        entry
+    }
+
+    fn write_transcode(&self, entry: &mut ExportEntry, destination: &Path, dry_run: bool) -> Result<()> {
+        if dry_run {
+            return Ok(());
+        }
+        if destination.exists() {
+            entry.note = Some("Already on the target".to_string());
+        } else {
+            transcode(&entry.source, destination, entry.bitrate.unwrap_or(320))?;
+        }
+        entry.size_bytes = fs::metadata(destination).map_err(|e| AudioError::io(destination, e))?.len();
+        Ok(())
+    }
+}
+
+fn copy_entry(entry: &mut ExportEntry, destination: PathBuf, target: &Path, executor: &FileOperationExecutor) -> Result<()> {
+    let operation = FileOperation::Copy { from: entry.source.clone(), to: destination };
+    match executor.execute(&operation)? {
+        OperationOutcome::Performed(op) | OperationOutcome::Planned(op) => {
+            entry.destination = op.destination()
+                .and_then(|to| to.strip_prefix(target).ok())
+                .map(Path::to_path_buf);
+        }
+        OperationOutcome::Skipped { .. } => entry.note = Some("Already on the target".to_string()),
+    }
+    Ok(())
+}
+
+fn transcode(from: &Path, to: &Path, bitrate: u32) -> Result<()> {
+    let output = Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-n", "-i"])
+        .arg(from)
+        .args(["-map", "0:a", "-map", "0:v?", "-c:v", "copy", "-map_metadata", "0"])
+        .args(["-codec:a", "libmp3lame", "-b:a"])
+        .arg(format!("{}k", bitrate))
+        .args(["-id3v2_version", "3"])
+        .arg(to)
+        .output()
+        .map_err(|e| AudioError::Metadata(format!("Could not run ffmpeg (needed for --transcode): {}", e)))?;
+    if !output.status.success() {
+        // Don't leave a partial file behind
+        let _ = fs::remove_file(to);
+        return Err(AudioError::Metadata(format!(
+            "ffmpeg failed on {}: {}",
+            from.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::{AudioError, MetadataExtractor, Result};
+use crate::analyzers::bitrate::BitrateAnalyzer;
+use crate::analyzers::duplicate::DuplicateAnalyzer;
+
+// C ABI for non-Rust tools. Every function returning `*mut c_char` hands out a
+// JSON document owned by the caller, which must be released with
+// `djlm_free_string`. On failure NULL is returned and the message can be
+// fetched with `djlm_last_error`. Panics are caught at the boundary and
+// reported the same way rather than unwinding into the caller.
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+unsafe fn path_from_ptr(path: *const c_char) -> Result<PathBuf> {
+    if path.is_null() {
+        return Err(AudioError::Metadata("Path pointer is null".into()));
+    }
+    CStr::from_ptr(path)
+        .to_str()
+        .map(PathBuf::from)
+        .map_err(|_| AudioError::Metadata("Path is not valid UTF-8".into()))
+}
+
+fn into_json_ptr<T: Serialize>(result: Result<T>) -> *mut c_char {
+    let json = result.and_then(|value| {
+        serde_json::to_string(&value).map_err(|e| AudioError::Metadata(e.to_string()))
+    });
+
+    match json.map(CString::new) {
+        Ok(Ok(json)) => json.into_raw(),
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            std::ptr::null_mut()
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// Runs an export's body; a panic, in it or re-raised from a worker thread,
+// becomes the last error and NULL
+fn catch_panic(f: impl FnOnce() -> *mut c_char) -> *mut c_char {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        set_last_error(format!("Internal error: {}", message));
+        std::ptr::null_mut()
+    })
+}
+
+/// Scans a directory and returns the extracted metadata as a JSON array.
+///
+/// # Safety
+/// `path` must be a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn djlm_scan_directory(path: *const c_char) -> *mut c_char {
+    catch_panic(|| into_json_ptr(path_from_ptr(path).and_then(MetadataExtractor::process_directory)))
+}
+
+/// Scans a directory and returns the duplicate analysis results as JSON.
+///
+/// # Safety
+/// `path` must be a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn djlm_find_duplicates(path: *const c_char) -> *mut c_char {
+    catch_panic(|| into_json_ptr(path_from_ptr(path).and_then(|dir| {
+        let files = MetadataExtractor::process_directory(dir)?;
+        Ok(DuplicateAnalyzer::new(1.0).find_duplicates(files))
+    })))
+}
+
+/// Scans a directory and returns the bitrate statistics as JSON.
+///
+/// # Safety
+/// `path` must be a valid, NUL-terminated C string.
+#[no_mangle]
+pub unsafe extern "C" fn djlm_analyze_bitrate(path: *const c_char) -> *mut c_char {
+    catch_panic(|| into_json_ptr(path_from_ptr(path).and_then(|dir| {
+        let files = MetadataExtractor::process_directory(dir)?;
+        Ok(BitrateAnalyzer::new().analyze(&files))
+    })))
+}
+
+/// Returns the version of the JSON schema the documents above follow
+/// (see `schemas/` in the repository).
+#[no_mangle]
+pub extern "C" fn djlm_schema_version() -> u32 {
+    crate::schema::SCHEMA_VERSION
+}
+
+/// Returns the last error raised on the calling thread, or NULL if there is none.
+/// The returned string must be released with `djlm_free_string`.
+#[no_mangle]
+pub extern "C" fn djlm_last_error() -> *mut c_char {
+    LAST_ERROR.with(|e| {
+        e.borrow_mut()
+            .take()
+            .and_then(|message| CString::new(message).ok())
+            .map_or(std::ptr::null_mut(), CString::into_raw)
+    })
+}
+
+/// Releases a string previously returned by this library.
+///
+/// # Safety
+/// `ptr` must be NULL or a pointer obtained from one of the `djlm_*` functions,
+/// and must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn djlm_free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(CString::from_raw(ptr));
+    }
+}
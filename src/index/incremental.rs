@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+use crate::{AudioFile, Result};
+use super::LibraryIndex;
+
+// Incremental scanning on top of the index: a scan takes the metadata of
+// files whose size and modification time still match their index record
+// from the index instead of opening them, and records what it extracted
+// for the next run. Unlike the folder-level scan cache, one changed file
+// only costs that file.
+#[derive(Debug, Clone)]
+pub struct IncrementalScan {
+    index_path: PathBuf,
+}
+
+impl Default for IncrementalScan {
+    fn default() -> Self {
+        Self::new(LibraryIndex::default_path())
+    }
+}
+
+impl IncrementalScan {
+    pub fn new(index_path: impl Into<PathBuf>) -> Self {
+        Self { index_path: index_path.into() }
+    }
+
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    // Indexed records still current, and the paths left to extract. The
+    // index is only held open for the lookup, so other runs can use it
+    // while files are being extracted.
+    pub fn split(&self, paths: &[PathBuf]) -> Result<(Vec<AudioFile>, Vec<PathBuf>)> {
+        LibraryIndex::open(&self.index_path)?.split_current(paths)
+    }
+
+    pub fn record(&self, files: &[AudioFile]) -> Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        LibraryIndex::open(&self.index_path)?.upsert_all(files).map(|_| ())
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::utils::audit;
+use crate::utils::unix_timestamp_millis;
+
+// Advisory lock next to the index (`index.sqlite.lock`), so two machines
+// sharing an index on a NAS don't run at the same time. SQLite's own locking
+// can't be trusted over SMB/NFS; creating a file exclusively can. The file
+// names who holds the lock. A lock left behind by a crashed run on this
+// machine is taken over; one from another machine has to be cleared with
+// --force-unlock.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub user: Option<String>,
+    pub host: Option<String>,
+    pub pid: u32,
+    // Unix seconds
+    pub since: u64,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        Self {
+            user: ["USER", "USERNAME"].iter().find_map(|var| std::env::var(var).ok()),
+            host: hostname(),
+            pid: std::process::id(),
+            since: unix_timestamp_millis() / 1000,
+        }
+    }
+
+    // "alice@studio-pc (pid 4242) since 2024-05-01 21:03:00 UTC"
+    pub fn describe(&self) -> String {
+        format!("{}@{} (pid {}) since {} UTC",
+            self.user.as_deref().unwrap_or("unknown"),
+            self.host.as_deref().unwrap_or("unknown host"),
+            self.pid,
+            audit::format_timestamp(self.since))
+    }
+
+    // Held by a process on this machine that no longer exists
+    fn is_stale(&self) -> bool {
+        self.host.is_some() && self.host == hostname() && !process_running(self.pid)
+    }
+}
+
+// Held while an index is open; the lock file is removed on drop
+#[derive(Debug)]
+pub struct IndexLock {
+    path: PathBuf,
+}
+
+impl IndexLock {
+    pub fn lock_path(index_path: &Path) -> PathBuf {
+        let mut name = index_path.file_name().unwrap_or_default().to_os_string();
+        name.push(".lock");
+        index_path.with_file_name(name)
+    }
+
+    pub fn acquire(index_path: &Path) -> Result<Self> {
+        let path = Self::lock_path(index_path);
+        let owner = LockOwner::current();
+        let contents = serde_json::to_string(&owner).map_err(|e| AudioError::Metadata(e.to_string()))?;
+
+        // Two attempts: the second after clearing a stale lock
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(contents.as_bytes()).map_err(|e| AudioError::io(&path, e))?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    match Self::owner(index_path) {
+                        Some(holder) if holder.is_stale() => {
+                            println!("Removing stale index lock left by {}", holder.describe());
+                            Self::force_unlock(index_path)?;
+                        }
+                        holder => {
+                            return Err(AudioError::IndexLocked {
+                                path: index_path.to_path_buf(),
+                                owner: holder.map_or("another run".to_string(), |holder| holder.describe()),
+                            });
+                        }
+                    }
+                }
+                Err(e) => return Err(AudioError::io(&path, e)),
+            }
+        }
+        Err(AudioError::IndexLocked { path: index_path.to_path_buf(), owner: "another run".to_string() })
+    }
+
+    // Who holds the lock on an index, if anyone; None also when the lock
+    // file is unreadable
+    pub fn owner(index_path: &Path) -> Option<LockOwner> {
+        let contents = fs::read_to_string(Self::lock_path(index_path)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn is_locked(index_path: &Path) -> bool {
+        Self::lock_path(index_path).exists()
+    }
+
+    // Removes the lock whoever holds it; returns whether there was one
+    pub fn force_unlock(index_path: &Path) -> Result<bool> {
+        let path = Self::lock_path(index_path);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(AudioError::io(&path, e)),
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"].iter()
+        .find_map(|var| std::env::var(var).ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+// Without a cheap portable check, processes elsewhere count as running
+fn process_running(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
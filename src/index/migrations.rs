@@ -0,0 +1,94 @@
+// Schema migrations, applied in order. The index records how many have run in
+// SQLite's `user_version`; never edit a released migration, append a new one.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: extracted metadata per file. `data` holds the serialized AudioFile so
+    // new fields don't need a schema change.
+    "CREATE TABLE files (
+        path TEXT PRIMARY KEY,
+        size_bytes INTEGER NOT NULL,
+        modified INTEGER,
+        indexed_at INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );",
+    // 2: duplicate-candidate buckets (derived from files)
+    "CREATE TABLE buckets (
+        match_key TEXT NOT NULL,
+        path TEXT NOT NULL REFERENCES files(path) ON DELETE CASCADE,
+        PRIMARY KEY (match_key, path)
+    );
+    CREATE INDEX buckets_path ON buckets(path);",
+    // 3: audio fingerprints (derived from files)
+    "CREATE TABLE fingerprints (
+        path TEXT PRIMARY KEY REFERENCES files(path) ON DELETE CASCADE,
+        algorithm TEXT NOT NULL,
+        fingerprint BLOB NOT NULL,
+        computed_at INTEGER NOT NULL
+    );",
+    // 4: content checksums for scrubbing. Not derived from files: a record
+    // must outlive its file row so corruption can still be proven later.
+    "CREATE TABLE checksums (
+        path TEXT PRIMARY KEY,
+        sha256 TEXT NOT NULL,
+        size_bytes INTEGER NOT NULL,
+        modified INTEGER,
+        recorded_at INTEGER NOT NULL,
+        verified_at INTEGER NOT NULL
+    );",
+    // 5: volume identity, so files on unplugged drives are kept (offline)
+    // and found again when the drive mounts somewhere else
+    "CREATE TABLE volumes (
+        id TEXT PRIMARY KEY,
+        label TEXT,
+        mount_point TEXT NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    ALTER TABLE files ADD COLUMN volume_id TEXT;
+    ALTER TABLE files ADD COLUMN volume_path TEXT;
+    ALTER TABLE files ADD COLUMN offline INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX files_volume ON files(volume_id);",
+    // 6: phrase structure per file (derived from the audio). `modified` is
+    // the file's mtime when analyzed, so edited files get analyzed again.
+    "CREATE TABLE phrasing (
+        path TEXT PRIMARY KEY REFERENCES files(path) ON DELETE CASCADE,
+        modified INTEGER,
+        bpm REAL,
+        phrase_bars INTEGER,
+        regularity REAL,
+        regular INTEGER,
+        analyzed_at INTEGER NOT NULL
+    );",
+    // 7: trigram full-text search over artist, title, album and file name,
+    // keyed by the files rowid and kept in step with it by triggers
+    "CREATE VIRTUAL TABLE search USING fts5(
+        artist, title, album, file_name,
+        tokenize = 'trigram remove_diacritics 1'
+    );
+    INSERT INTO search (rowid, artist, title, album, file_name)
+        SELECT rowid, data ->> '$.artist', data ->> '$.title', data ->> '$.album', data ->> '$.file_name' FROM files;
+    CREATE TRIGGER files_search_insert AFTER INSERT ON files BEGIN
+        INSERT INTO search (rowid, artist, title, album, file_name)
+        VALUES (new.rowid, new.data ->> '$.artist', new.data ->> '$.title', new.data ->> '$.album', new.data ->> '$.file_name');
+    END;
+    CREATE TRIGGER files_search_update AFTER UPDATE OF data ON files BEGIN
+        DELETE FROM search WHERE rowid = old.rowid;
+        INSERT INTO search (rowid, artist, title, album, file_name)
+        VALUES (new.rowid, new.data ->> '$.artist', new.data ->> '$.title', new.data ->> '$.album', new.data ->> '$.file_name');
+    END;
+    CREATE TRIGGER files_search_delete AFTER DELETE ON files BEGIN
+        DELETE FROM search WHERE rowid = old.rowid;
+    END;",
+    // 8: musical key per file (derived from the audio), as pitch class of
+    // the tonic and mode; `modified` as for phrasing
+    "CREATE TABLE keys (
+        path TEXT PRIMARY KEY REFERENCES files(path) ON DELETE CASCADE,
+        modified INTEGER,
+        tonic INTEGER,
+        minor INTEGER,
+        confidence REAL,
+        analyzed_at INTEGER NOT NULL
+    );",
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
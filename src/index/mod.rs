@@ -0,0 +1,838 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::title::TitleParser;
+use crate::exporters::keys::MusicalKey;
+use crate::utils::{paths, unix_timestamp_millis};
+use crate::utils::volumes::{self, Volume, VolumeResolver};
+
+pub mod incremental;
+pub mod lock;
+pub mod migrations;
+#[cfg(feature = "decode")]
+pub mod monitor;
+pub mod scrub;
+
+use lock::IndexLock;
+
+// Persistent SQLite index of extracted metadata. Opening an index brings its
+// schema up to date; derived tables (buckets, fingerprints, search) can
+// always be rebuilt from `files` with `repair`. Each file also records the
+// volume it lives on, so records for unplugged drives are kept as offline
+// and follow the drive when it mounts somewhere else. An open index holds
+// the advisory lock in `lock`, so runs sharing one index take turns.
+pub struct LibraryIndex {
+    conn: Connection,
+    path: PathBuf,
+    // Declared last so the connection is closed before the lock is released
+    _lock: IndexLock,
+}
+
+// Tables caching analysis of a file's audio. Their rows are dropped when the
+// file changes (size or modification time on update, content on scrub), so
+// the next analysis run computes them again.
+const ANALYSIS_TABLES: &[&str] = &["fingerprints", "phrasing", "keys"];
+
+#[derive(Debug, Clone, Default)]
+pub struct UpdateReport {
+    pub indexed: usize,
+    // Already indexed files whose size or modification time changed; any
+    // cached analysis of theirs was dropped
+    pub invalidated: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    pub missing_removed: usize,
+    // Missing because their drive isn't connected; kept
+    pub marked_offline: usize,
+    // Moved to where their drive is mounted now
+    pub relocated: usize,
+    pub orphans_removed: usize,
+    pub buckets_rebuilt: usize,
+}
+
+// Known-good content hash of a file, as last recorded by a scrub
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChecksumRecord {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub modified_secs: Option<i64>,
+    // Unix seconds
+    pub recorded_at: u64,
+    pub verified_at: u64,
+}
+
+// Phrase structure of a file as last analyzed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhrasingRecord {
+    pub path: PathBuf,
+    pub modified_secs: Option<i64>,
+    pub bpm: Option<f64>,
+    pub phrase_bars: Option<u32>,
+    // Percent of section changes on a 16-bar line
+    pub regularity: Option<f64>,
+    // Follows 16/32-bar phrasing; None when it couldn't be judged
+    pub regular: Option<bool>,
+    // Unix seconds
+    pub analyzed_at: u64,
+}
+
+impl PhrasingRecord {
+    // Stamped with the file's current modification time and the time now
+    pub fn new(path: impl Into<PathBuf>, bpm: Option<f64>, phrase_bars: Option<u32>, regularity: Option<f64>, regular: Option<bool>) -> Self {
+        let path = path.into();
+        Self {
+            modified_secs: modified_secs(&path),
+            path,
+            bpm,
+            phrase_bars,
+            regularity,
+            regular,
+            analyzed_at: unix_timestamp_millis() / 1000,
+        }
+    }
+}
+
+// Musical key of a file as last analyzed
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRecord {
+    pub path: PathBuf,
+    pub modified_secs: Option<i64>,
+    // None when the track has no clear key
+    pub key: Option<MusicalKey>,
+    // Percent correlation with the key's profile
+    pub confidence: Option<f64>,
+    // Unix seconds
+    pub analyzed_at: u64,
+}
+
+impl KeyRecord {
+    // Stamped with the file's current modification time and the time now
+    pub fn new(path: impl Into<PathBuf>, key: Option<MusicalKey>, confidence: Option<f64>) -> Self {
+        let path = path.into();
+        Self {
+            modified_secs: modified_secs(&path),
+            path,
+            key,
+            confidence,
+            analyzed_at: unix_timestamp_millis() / 1000,
+        }
+    }
+}
+
+// Indexed file matching a search
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub file: AudioFile,
+    // On a drive that isn't connected
+    pub offline: bool,
+}
+
+// Indexed file whose tags or name almost match a search
+#[derive(Debug, Clone, Serialize)]
+pub struct NearMiss {
+    #[serde(flatten)]
+    pub hit: SearchHit,
+    // The normalized artist/title text that came closest
+    pub matched: String,
+    // Edits between it and the normalized query
+    pub distance: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeRecord {
+    pub id: String,
+    pub label: Option<String>,
+    // Where the volume was last seen mounted
+    pub mount_point: PathBuf,
+    // Unix seconds
+    pub last_seen: u64,
+    pub file_count: usize,
+    pub offline_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexStatus {
+    pub schema_version: u32,
+    pub latest_version: u32,
+    pub file_count: usize,
+    pub offline_count: usize,
+    // Problems reported by SQLite's integrity check; empty when healthy
+    pub integrity_errors: Vec<String>,
+}
+
+impl LibraryIndex {
+    pub fn default_path() -> PathBuf {
+        paths::data_dir().join("index.sqlite")
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Self::open(Self::default_path())
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        let lock = IndexLock::acquire(&path)?;
+        let conn = Connection::open(&path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        let mut index = Self { conn, path, _lock: lock };
+        index.migrate()?;
+        Ok(index)
+    }
+
+    // Read-only look at an existing index without migrating it
+    pub fn inspect(path: impl AsRef<Path>) -> Result<IndexStatus> {
+        let conn = Connection::open_with_flags(path.as_ref(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let schema_version = Self::user_version(&conn)?;
+        let file_count = if schema_version >= 1 {
+            conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get::<_, i64>(0))? as usize
+        } else {
+            0
+        };
+        let offline_count = if schema_version >= 5 {
+            conn.query_row("SELECT COUNT(*) FROM files WHERE offline = 1", [], |row| row.get::<_, i64>(0))? as usize
+        } else {
+            0
+        };
+
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let integrity_errors = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|line| line != "ok")
+            .collect();
+
+        Ok(IndexStatus {
+            schema_version,
+            latest_version: migrations::latest_version(),
+            file_count,
+            offline_count,
+            integrity_errors,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
+        Self::user_version(&self.conn)
+    }
+
+    pub fn upsert(&mut self, file: &AudioFile) -> Result<()> {
+        self.upsert_all(std::slice::from_ref(file)).map(|_| ())
+    }
+
+    // Single transaction for the whole batch; much faster than one per file
+    pub fn upsert_all(&mut self, files: &[AudioFile]) -> Result<UpdateReport> {
+        let parser = TitleParser::new();
+        let mut resolver = VolumeResolver::new();
+        let mut seen: HashMap<String, Volume> = HashMap::new();
+        let mut report = UpdateReport { indexed: files.len(), ..UpdateReport::default() };
+        let tx = self.conn.transaction()?;
+        for file in files {
+            let volume = resolver.resolve(&file.path);
+            if Self::upsert_in(&tx, &parser, file, volume.as_ref())? {
+                report.invalidated.push(file.path.clone());
+            }
+            if let Some(volume) = volume {
+                seen.entry(volume.id.clone()).or_insert(volume);
+            }
+        }
+        // A drive seen at a new mount point takes its other records along
+        for volume in seen.values() {
+            Self::remount_in(&tx, &parser, volume)?;
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    // Drops the cached analysis of files whose content changed; returns how
+    // many had any
+    pub fn invalidate(&mut self, paths: &[PathBuf]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut invalidated = 0;
+        for path in paths {
+            if Self::invalidate_in(&tx, &path_key(path))? {
+                invalidated += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(invalidated)
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Result<Option<AudioFile>> {
+        let data: Option<String> = self.conn
+            .query_row(
+                "SELECT data FROM files WHERE path = ?1",
+                params![path_key(path.as_ref())],
+                |row| row.get(0),
+            )
+            .optional()?;
+        data.map(|data| decode(&data)).transpose()
+    }
+
+    // Splits paths into the records of files unchanged since they were
+    // indexed (same size and modification time) and the paths of new or
+    // changed files, which need extracting again
+    pub fn split_current(&self, paths: &[PathBuf]) -> Result<(Vec<AudioFile>, Vec<PathBuf>)> {
+        let mut stmt = self.conn.prepare("SELECT size_bytes, modified, data FROM files WHERE path = ?1")?;
+        let mut current = Vec::new();
+        let mut stale = Vec::new();
+        for path in paths {
+            let record: Option<(i64, Option<i64>, String)> = stmt
+                .query_row(params![path_key(path)], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .optional()?;
+            let size = fs::metadata(path).map(|metadata| metadata.len() as i64).ok();
+            match record {
+                Some((size_bytes, modified, data)) if Some(size_bytes) == size && modified.is_some() && modified == modified_secs(path) => {
+                    match decode(&data) {
+                        Ok(file) => current.push(file),
+                        Err(_) => stale.push(path.clone()),
+                    }
+                }
+                _ => stale.push(path.clone()),
+            }
+        }
+        Ok((current, stale))
+    }
+
+    pub fn files(&self) -> Result<Vec<AudioFile>> {
+        let mut stmt = self.conn.prepare("SELECT data FROM files ORDER BY path")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|data| decode(&data?)).collect()
+    }
+
+    // Every distinct tagged artist, for telling artist and title apart in
+    // file names of untagged tracks
+    pub fn artists(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT data ->> '$.artist' FROM files WHERE data ->> '$.artist' IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<std::result::Result<_, _>>()?)
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    // Volumes the index knows about, with how many of their files are offline
+    pub fn volumes(&self) -> Result<Vec<VolumeRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT v.id, v.label, v.mount_point, v.last_seen, COUNT(f.path), COALESCE(SUM(f.offline), 0)
+             FROM volumes v LEFT JOIN files f ON f.volume_id = v.id
+             GROUP BY v.id ORDER BY v.label, v.id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(VolumeRecord {
+                id: row.get(0)?,
+                label: row.get(1)?,
+                mount_point: PathBuf::from(row.get::<_, String>(2)?),
+                last_seen: row.get::<_, i64>(3)? as u64,
+                file_count: row.get::<_, i64>(4)? as usize,
+                offline_count: row.get::<_, i64>(5)? as usize,
+            })
+        })?;
+        Ok(rows.collect::<std::result::Result<_, _>>()?)
+    }
+
+    // Files whose artist, title, album or file name contain every word of
+    // the query, ignoring case and accents, best matches first. Words of
+    // three or more characters use the trigram index; shorter ones are
+    // checked against the candidates.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let (long, short): (Vec<&str>, Vec<&str>) = query.split_whitespace().partition(|word| word.chars().count() >= 3);
+        if long.is_empty() && short.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut sql = String::from("SELECT f.data, f.offline FROM search JOIN files f ON f.rowid = search.rowid WHERE 1");
+        let mut values: Vec<String> = Vec::new();
+        if !long.is_empty() {
+            sql.push_str(" AND search MATCH ?");
+            // Each word as a quoted phrase so FTS5 syntax in it is literal
+            values.push(long.iter().map(|word| format!("\"{}\"", word.replace('"', "\"\""))).collect::<Vec<_>>().join(" "));
+        }
+        for word in &short {
+            sql.push_str(" AND (COALESCE(search.artist, '') || ' ' || COALESCE(search.title, '') || ' ' || COALESCE(search.album, '') || ' ' || search.file_name) LIKE ? ESCAPE '\\'");
+            let escaped = word.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            values.push(format!("%{}%", escaped));
+        }
+        sql.push_str(if long.is_empty() { " ORDER BY f.path" } else { " ORDER BY search.rank, f.path" });
+        sql.push_str(&format!(" LIMIT {}", limit));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?))
+        })?;
+        rows.map(|row| {
+            let (data, offline) = row?;
+            Ok(SearchHit { file: decode(&data)?, offline })
+        }).collect()
+    }
+
+    // Files whose title, artist, "artist title" or parsed file name are
+    // within a few edits of the query (about one per three characters),
+    // closest first. Meant for when `search` finds nothing: it reads every
+    // record, but catches misspellings on either side.
+    pub fn near_misses(&self, query: &str, limit: usize) -> Result<Vec<NearMiss>> {
+        let query = TitleParser::normalize_text(query);
+        let query_len = query.chars().count();
+        if query_len == 0 {
+            return Ok(Vec::new());
+        }
+        let max_distance = (query_len / 3).max(1);
+        let parser = TitleParser::new();
+
+        let mut candidates: Vec<(usize, String, i64)> = Vec::new();
+        {
+            let mut stmt = self.conn.prepare("SELECT rowid, artist, title, file_name FROM search")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let rowid: i64 = row.get(0)?;
+                let artist: Option<String> = row.get(1)?;
+                let title: Option<String> = row.get(2)?;
+                let file_name: String = row.get(3)?;
+                let parsed = parser.parse(&file_name);
+
+                let mut texts = vec![parsed.title.clone(), format!("{} {}", parsed.artist, parsed.title)];
+                texts.extend(title.clone());
+                texts.extend(artist.clone());
+                if let (Some(artist), Some(title)) = (&artist, &title) {
+                    texts.push(format!("{} {}", artist, title));
+                }
+                let closest = texts.iter()
+                    .map(|text| TitleParser::normalize_text(text))
+                    .filter(|text| text.chars().count().abs_diff(query_len) <= max_distance)
+                    .map(|text| (TitleParser::edit_distance(&query, &text), text))
+                    .filter(|(distance, _)| *distance <= max_distance)
+                    .min();
+                if let Some((distance, text)) = closest {
+                    candidates.push((distance, text, rowid));
+                }
+            }
+        }
+        candidates.sort();
+        candidates.truncate(limit);
+
+        let mut stmt = self.conn.prepare("SELECT data, offline FROM files WHERE rowid = ?1")?;
+        candidates.into_iter()
+            .map(|(distance, matched, rowid)| {
+                let (data, offline) = stmt.query_row(params![rowid], |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?)))?;
+                Ok(NearMiss { hit: SearchHit { file: decode(&data)?, offline }, matched, distance })
+            })
+            .collect()
+    }
+
+    pub fn is_offline(&self, path: impl AsRef<Path>) -> Result<bool> {
+        let offline: Option<bool> = self.conn
+            .query_row("SELECT offline FROM files WHERE path = ?1", params![path_key(path.as_ref())], |row| row.get(0))
+            .optional()?;
+        Ok(offline.unwrap_or(false))
+    }
+
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Result<bool> {
+        let removed = self.conn.execute("DELETE FROM files WHERE path = ?1", params![path_key(path.as_ref())])?;
+        Ok(removed > 0)
+    }
+
+    pub fn checksums(&self) -> Result<Vec<ChecksumRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, sha256, size_bytes, modified, recorded_at, verified_at FROM checksums ORDER BY path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ChecksumRecord {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                sha256: row.get(1)?,
+                size_bytes: row.get::<_, i64>(2)? as u64,
+                modified_secs: row.get(3)?,
+                recorded_at: row.get::<_, i64>(4)? as u64,
+                verified_at: row.get::<_, i64>(5)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<std::result::Result<_, _>>()?)
+    }
+
+    // Inserts or replaces the records in one transaction
+    pub fn store_checksums(&mut self, records: &[ChecksumRecord]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO checksums (path, sha256, size_bytes, modified, recorded_at, verified_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for record in records {
+                stmt.execute(params![
+                    path_key(&record.path),
+                    record.sha256,
+                    record.size_bytes as i64,
+                    record.modified_secs,
+                    record.recorded_at as i64,
+                    record.verified_at as i64,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn remove_checksum(&mut self, path: impl AsRef<Path>) -> Result<bool> {
+        let removed = self.conn.execute("DELETE FROM checksums WHERE path = ?1", params![path_key(path.as_ref())])?;
+        Ok(removed > 0)
+    }
+
+    pub fn phrasing(&self) -> Result<Vec<PhrasingRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, modified, bpm, phrase_bars, regularity, regular, analyzed_at FROM phrasing ORDER BY path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PhrasingRecord {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                modified_secs: row.get(1)?,
+                bpm: row.get(2)?,
+                phrase_bars: row.get(3)?,
+                regularity: row.get(4)?,
+                regular: row.get(5)?,
+                analyzed_at: row.get::<_, i64>(6)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<std::result::Result<_, _>>()?)
+    }
+
+    // Files with no phrasing record, or one from before they were last modified
+    pub fn needs_phrasing<'a>(&self, files: &'a [AudioFile]) -> Result<Vec<&'a AudioFile>> {
+        let current: HashMap<PathBuf, Option<i64>> = self.phrasing()?
+            .into_iter()
+            .map(|record| (record.path, record.modified_secs))
+            .collect();
+        Ok(files.iter()
+            .filter(|file| current.get(&file.path).is_none_or(|&modified| modified != modified_secs(&file.path)))
+            .collect())
+    }
+
+    // Inserts or replaces the records in one transaction; the files must
+    // already be indexed
+    pub fn store_phrasing(&mut self, records: &[PhrasingRecord]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO phrasing (path, modified, bpm, phrase_bars, regularity, regular, analyzed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for record in records {
+                stmt.execute(params![
+                    path_key(&record.path),
+                    record.modified_secs,
+                    record.bpm,
+                    record.phrase_bars,
+                    record.regularity,
+                    record.regular,
+                    record.analyzed_at as i64,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn keys(&self) -> Result<Vec<KeyRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, modified, tonic, minor, confidence, analyzed_at FROM keys ORDER BY path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let tonic: Option<u8> = row.get(2)?;
+            let minor: Option<bool> = row.get(3)?;
+            Ok(KeyRecord {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                modified_secs: row.get(1)?,
+                key: tonic.zip(minor).map(|(tonic, minor)| MusicalKey::new(tonic, minor)),
+                confidence: row.get(4)?,
+                analyzed_at: row.get::<_, i64>(5)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<std::result::Result<_, _>>()?)
+    }
+
+    // Files with no key record, or one from before they were last modified
+    pub fn needs_key<'a>(&self, files: &'a [AudioFile]) -> Result<Vec<&'a AudioFile>> {
+        let current: HashMap<PathBuf, Option<i64>> = self.keys()?
+            .into_iter()
+            .map(|record| (record.path, record.modified_secs))
+            .collect();
+        Ok(files.iter()
+            .filter(|file| current.get(&file.path).is_none_or(|&modified| modified != modified_secs(&file.path)))
+            .collect())
+    }
+
+    // Inserts or replaces the records in one transaction; the files must
+    // already be indexed
+    pub fn store_keys(&mut self, records: &[KeyRecord]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO keys (path, modified, tonic, minor, confidence, analyzed_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for record in records {
+                stmt.execute(params![
+                    path_key(&record.path),
+                    record.modified_secs,
+                    record.key.map(|key| key.tonic),
+                    record.key.map(|key| key.minor),
+                    record.confidence,
+                    record.analyzed_at as i64,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Follows drives that mounted somewhere else, marks records on
+    // unplugged drives offline, drops records for files that are really gone,
+    // clears rows in derived tables that lost their file, and rebuilds the
+    // duplicate buckets
+    pub fn repair(&mut self) -> Result<RepairReport> {
+        let mut report = RepairReport::default();
+        let parser = TitleParser::new();
+        let known: Vec<(String, Option<String>, String)> = {
+            let mut stmt = self.conn.prepare("SELECT id, label, mount_point FROM volumes")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<std::result::Result<_, _>>()?
+        };
+        let mounted = if known.is_empty() { Vec::new() } else { volumes::mounted_volumes() };
+        // Where each known volume is mounted now, if anywhere
+        let online: HashMap<String, Volume> = known.into_iter()
+            .filter_map(|(id, label, mount_point)| {
+                let volume = mounted.iter()
+                    .find(|v| v.id == id)
+                    .cloned()
+                    .or_else(|| volumes::volume_of(&Path::new(&mount_point).join(volumes::VOLUME_MARKER))
+                        .filter(|v| v.id == id))
+                    .map(|v| Volume { label: v.label.or(label), ..v })?;
+                Some((id, volume))
+            })
+            .collect();
+
+        let tx = self.conn.transaction()?;
+        for volume in online.values() {
+            report.relocated += Self::remount_in(&tx, &parser, volume)?;
+        }
+
+        let rows: Vec<(String, Option<String>, bool)> = {
+            let mut stmt = tx.prepare("SELECT path, volume_id, offline FROM files")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<std::result::Result<_, _>>()?
+        };
+        for (path, volume_id, offline) in rows {
+            if Path::new(&path).exists() {
+                if offline {
+                    tx.execute("UPDATE files SET offline = 0 WHERE path = ?1", params![path])?;
+                }
+                continue;
+            }
+            let drive_connected = volume_id.as_ref().is_none_or(|id| online.contains_key(id));
+            if drive_connected {
+                tx.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+                report.missing_removed += 1;
+            } else if !offline {
+                tx.execute("UPDATE files SET offline = 1 WHERE path = ?1", params![path])?;
+                report.marked_offline += 1;
+            }
+        }
+
+        // Foreign keys cascade, but rows written with enforcement off (older
+        // builds, external tools) may still dangle
+        report.orphans_removed += tx.execute("DELETE FROM buckets WHERE path NOT IN (SELECT path FROM files)", [])?;
+        report.orphans_removed += tx.execute("DELETE FROM fingerprints WHERE path NOT IN (SELECT path FROM files)", [])?;
+        report.orphans_removed += tx.execute("DELETE FROM phrasing WHERE path NOT IN (SELECT path FROM files)", [])?;
+        report.orphans_removed += tx.execute("DELETE FROM keys WHERE path NOT IN (SELECT path FROM files)", [])?;
+
+        tx.execute("DELETE FROM search", [])?;
+        tx.execute(
+            "INSERT INTO search (rowid, artist, title, album, file_name)
+             SELECT rowid, data ->> '$.artist', data ->> '$.title', data ->> '$.album', data ->> '$.file_name' FROM files",
+            [],
+        )?;
+
+        tx.execute("DELETE FROM buckets", [])?;
+        {
+            let mut select = tx.prepare("SELECT path, data FROM files")?;
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO buckets (match_key, path) VALUES (?1, ?2)")?;
+            let rows = select.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            for row in rows {
+                let (path, data) = row?;
+                let file = decode(&data)?;
+                insert.execute(params![parser.match_key_file(&file), path])?;
+                report.buckets_rebuilt += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    // Reclaims space left behind by deleted rows
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    fn migrate(&mut self) -> Result<()> {
+        let current = self.schema_version()?;
+        let latest = migrations::latest_version();
+        if current > latest {
+            return Err(AudioError::Metadata(format!(
+                "Index {} has schema version {}, this build only knows up to {}; update dj-library-manager",
+                self.path.display(), current, latest
+            )));
+        }
+
+        for (version, migration) in migrations::MIGRATIONS.iter().enumerate().skip(current as usize) {
+            let version = version as u32 + 1;
+            println!("Migrating index to schema version {}", version);
+            let tx = self.conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", version)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    // Returns whether the file was already indexed with a different size or
+    // modification time, in which case its cached analysis is dropped
+    fn upsert_in(conn: &Connection, parser: &TitleParser, file: &AudioFile, volume: Option<&Volume>) -> Result<bool> {
+        let key = path_key(&file.path);
+        let modified = modified_secs(&file.path);
+        let previous: Option<(i64, Option<i64>)> = conn
+            .query_row("SELECT size_bytes, modified FROM files WHERE path = ?1", params![key], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        let changed = previous.is_some_and(|previous| previous != (file.size_bytes as i64, modified));
+        if changed {
+            Self::invalidate_in(conn, &key)?;
+        }
+
+        let data = serde_json::to_string(file).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        let volume_path = volume.and_then(|v| v.relative_path(&file.path)).map(path_key);
+        conn.execute(
+            "INSERT INTO files (path, size_bytes, modified, indexed_at, data, volume_id, volume_path, offline)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)
+             ON CONFLICT(path) DO UPDATE SET
+                size_bytes = excluded.size_bytes,
+                modified = excluded.modified,
+                indexed_at = excluded.indexed_at,
+                data = excluded.data,
+                volume_id = excluded.volume_id,
+                volume_path = excluded.volume_path,
+                offline = 0",
+            params![
+                key,
+                file.size_bytes as i64,
+                modified,
+                unix_timestamp_millis() as i64,
+                data,
+                volume_path.as_ref().and(volume.map(|v| v.id.as_str())),
+                volume_path,
+            ],
+        )?;
+        conn.execute("DELETE FROM buckets WHERE path = ?1", params![key])?;
+        conn.execute(
+            "INSERT INTO buckets (match_key, path) VALUES (?1, ?2)",
+            params![parser.match_key_file(file), key],
+        )?;
+        Ok(changed)
+    }
+
+    fn invalidate_in(conn: &Connection, key: &str) -> Result<bool> {
+        let mut removed = 0;
+        for table in ANALYSIS_TABLES {
+            removed += conn.execute(&format!("DELETE FROM {} WHERE path = ?1", table), params![key])?;
+        }
+        Ok(removed > 0)
+    }
+
+    // Records the volume as seen at its current mount point and moves the
+    // records of its files there if it used to be mounted elsewhere.
+    // Returns how many records moved.
+    fn remount_in(conn: &Connection, parser: &TitleParser, volume: &Volume) -> Result<usize> {
+        let mount_point = path_key(&volume.mount_point);
+        let previous: Option<String> = conn
+            .query_row("SELECT mount_point FROM volumes WHERE id = ?1", params![volume.id], |row| row.get(0))
+            .optional()?;
+        conn.execute(
+            "INSERT INTO volumes (id, label, mount_point, last_seen) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET
+                label = excluded.label,
+                mount_point = excluded.mount_point,
+                last_seen = excluded.last_seen",
+            params![volume.id, volume.label, mount_point, (unix_timestamp_millis() / 1000) as i64],
+        )?;
+        if previous.is_none_or(|previous| previous == mount_point) {
+            return Ok(0);
+        }
+
+        let rows: Vec<(String, String, String)> = {
+            let mut stmt = conn.prepare("SELECT path, volume_path, data FROM files WHERE volume_id = ?1")?;
+            let rows = stmt.query_map(params![volume.id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect::<std::result::Result<_, _>>()?
+        };
+        let mut moved = 0;
+        for (old_path, volume_path, data) in rows {
+            let new_path = volume.mount_point.join(&volume_path);
+            let new_key = path_key(&new_path);
+            if new_key == old_path {
+                continue;
+            }
+            // The scan may already have indexed the file at its new path
+            let already_indexed = conn
+                .query_row("SELECT 1 FROM files WHERE path = ?1", params![new_key], |_| Ok(()))
+                .optional()?
+                .is_some();
+            if !already_indexed {
+                let mut file = decode(&data)?;
+                file.path = new_path;
+                Self::upsert_in(conn, parser, &file, Some(volume))?;
+            }
+            conn.execute("UPDATE OR IGNORE checksums SET path = ?2 WHERE path = ?1", params![old_path, new_key])?;
+            conn.execute("UPDATE OR IGNORE phrasing SET path = ?2 WHERE path = ?1", params![old_path, new_key])?;
+            conn.execute("UPDATE OR IGNORE keys SET path = ?2 WHERE path = ?1", params![old_path, new_key])?;
+            conn.execute("DELETE FROM checksums WHERE path = ?1", params![old_path])?;
+            conn.execute("DELETE FROM files WHERE path = ?1", params![old_path])?;
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    fn user_version(conn: &Connection) -> Result<u32> {
+        Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+}
+
+fn path_key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+pub(crate) fn modified_secs(path: &Path) -> Option<i64> {
+    fs::metadata(path).ok()?
+        .modified().ok()?
+        .duration_since(UNIX_EPOCH).ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+fn decode(data: &str) -> Result<AudioFile> {
+    serde_json::from_str(data).map_err(|e| AudioError::Metadata(format!("Corrupt index record: {}", e)))
+}
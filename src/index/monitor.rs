@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::{AudioError, AudioFile, Result};
+use crate::analyzers::duplicate::{DuplicateAnalyzer, DuplicateMatch};
+use crate::analyzers::ignore::IgnoreList;
+use crate::analyzers::title::KnownArtists;
+use crate::audio::metadata::{MetadataExtractor, PLACEHOLDER_BYTES};
+use super::LibraryIndex;
+
+// Keeps the index current while files come and go in a watched library, and
+// flags each new file that duplicates one already indexed. Matching is by
+// artist and title as in `duplicates` (tags first, then the file name); the
+// indexed files are kept grouped by that identity, so a new file is only
+// compared with its namesakes. The index is opened for each change rather
+// than held, so other runs can use it in between.
+pub struct LibraryMonitor {
+    index_path: PathBuf,
+    analyzer: DuplicateAnalyzer,
+    // Indexed files by "artist\0title"
+    tracks: HashMap<String, Vec<Arc<AudioFile>>>,
+    // Identity each indexed file is filed under
+    identities: HashMap<PathBuf, String>,
+}
+
+// A file that appeared or changed, as extracted and indexed
+pub struct Arrival {
+    pub file: Arc<AudioFile>,
+    // One match per indexed copy of the same track
+    pub duplicates: Vec<DuplicateMatch>,
+}
+
+impl LibraryMonitor {
+    pub fn new(index_path: impl Into<PathBuf>) -> Result<Self> {
+        let index_path = index_path.into();
+        let files = LibraryIndex::open(&index_path)?.files()?;
+        let mut monitor = Self {
+            index_path,
+            analyzer: DuplicateAnalyzer::new(1.0).with_known_artists(KnownArtists::from_files(&files)),
+            tracks: HashMap::new(),
+            identities: HashMap::new(),
+        };
+        for file in files {
+            monitor.remember(Arc::new(file));
+        }
+        Ok(monitor)
+    }
+
+    // Pairs reviewed as different tracks are never flagged
+    pub fn with_ignored_pairs(mut self, ignored: IgnoreList) -> Self {
+        self.analyzer = self.analyzer.with_ignored_pairs(ignored);
+        self
+    }
+
+    // Indexed files with an artist and title to match on
+    pub fn tracked(&self) -> usize {
+        self.identities.len()
+    }
+
+    fn identity(&self, file: &AudioFile) -> Option<String> {
+        let (parsed, _) = self.analyzer.identify(file)?;
+        Some(format!("{}\u{0}{}", parsed.artist, parsed.title))
+    }
+
+    fn remember(&mut self, file: Arc<AudioFile>) {
+        if let Some(identity) = self.identity(&file) {
+            self.identities.insert(file.path.clone(), identity.clone());
+            self.tracks.entry(identity).or_default().push(file);
+        }
+    }
+
+    fn forget(&mut self, path: &Path) {
+        let Some(identity) = self.identities.remove(path) else { return };
+        if let Some(files) = self.tracks.get_mut(&identity) {
+            files.retain(|file| file.path != path);
+            if files.is_empty() {
+                self.tracks.remove(&identity);
+            }
+        }
+    }
+
+    // Extracts a new or changed file, indexes it and matches it against the
+    // other indexed files
+    pub fn arrived(&mut self, path: &Path) -> Result<Arrival> {
+        let size_bytes = std::fs::metadata(path).map_err(|e| AudioError::io(path, e))?.len();
+        if size_bytes < PLACEHOLDER_BYTES {
+            return Err(AudioError::Placeholder { path: path.to_path_buf(), size_bytes });
+        }
+        let file = MetadataExtractor::extract_metadata(path)?;
+        LibraryIndex::open(&self.index_path)?.upsert(&file)?;
+        self.forget(path);
+
+        let file = Arc::new(file);
+        let duplicates = self.identity(&file)
+            .and_then(|identity| self.tracks.get(&identity))
+            .map(|copies| copies.iter().filter_map(|copy| self.analyzer.match_pair(&file, copy)).collect())
+            .unwrap_or_default();
+        self.remember(Arc::clone(&file));
+        Ok(Arrival { file, duplicates })
+    }
+
+    // Drops a file deleted or moved out of the library; true if it was indexed
+    pub fn removed(&mut self, path: &Path) -> Result<bool> {
+        self.forget(path);
+        LibraryIndex::open(&self.index_path)?.remove(path)
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Serialize;
+use crate::Result;
+use crate::index::{modified_secs, ChecksumRecord, LibraryIndex};
+use crate::utils::hashing;
+use crate::utils::parallel::{self, ParallelProcessor};
+
+// Bit-rot scrubbing. The first run records a SHA-256 per file in the index;
+// later runs hash the files again. A file whose content changed while its
+// size and modification time didn't was not edited by anything, so it is
+// reported as corrupt. Its recorded checksum is kept, so it stays reported
+// until the file is restored from a good copy.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ScrubStatus {
+    // First time this file was seen; checksum recorded
+    New,
+    // Content matches the recorded checksum
+    Verified,
+    // Verified recently enough to skip this run
+    Skipped,
+    // Size or modification time changed, so the file was edited; the new
+    // checksum replaces the old one and cached analysis is dropped
+    Modified,
+    Corrupt { expected: String, actual: String },
+    // Recorded under a scanned directory but gone from disk
+    Missing,
+    Unreadable { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrubReport {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub status: ScrubStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScrubResults {
+    pub reports: Vec<ScrubReport>,
+}
+
+impl ScrubResults {
+    pub fn count(&self, matches: impl Fn(&ScrubStatus) -> bool) -> usize {
+        self.reports.iter().filter(|r| matches(&r.status)).count()
+    }
+
+    pub fn corrupt(&self) -> impl Iterator<Item = &ScrubReport> {
+        self.reports.iter().filter(|r| matches!(r.status, ScrubStatus::Corrupt { .. }))
+    }
+}
+
+pub struct Scrubber {
+    // Files verified more recently than this are skipped
+    recheck_after: Duration,
+    dry_run: bool,
+}
+
+impl ParallelProcessor for Scrubber {}
+
+impl Default for Scrubber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scrubber {
+    pub fn new() -> Self {
+        Self {
+            recheck_after: Duration::ZERO,
+            dry_run: false,
+        }
+    }
+
+    // Spreads the work of a large library over several runs
+    pub fn with_recheck_after(mut self, recheck_after: Duration) -> Self {
+        self.recheck_after = recheck_after;
+        self
+    }
+
+    // Verify as usual but don't record anything in the index
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Scrubs `paths` (everything found under `roots`) against the index
+    pub fn scrub(&self, index: &mut LibraryIndex, paths: &[PathBuf], roots: &[PathBuf]) -> Result<ScrubResults> {
+        let recorded: HashMap<PathBuf, ChecksumRecord> = index.checksums()?
+            .into_iter()
+            .map(|record| (record.path.clone(), record))
+            .collect();
+        let now = crate::utils::unix_timestamp_millis() / 1000;
+
+        println!("Scrubbing {} files using {} threads", paths.len(), parallel::current_threads());
+        let total = paths.len();
+        let progress = Self::get_progress_counter(total);
+        let checked = Self::parallel_map(paths, |path| {
+            let result = self.check(path, recorded.get(path), now);
+            let processed = progress.advance(1);
+            if processed % 100 == 0 || processed == total {
+                println!("Progress: {}/{} files{}", processed, total, progress.rate(processed));
+            }
+            result
+        });
+
+        let mut results = ScrubResults::default();
+        let mut updates = Vec::new();
+        for (report, update) in checked {
+            results.reports.push(report);
+            updates.extend(update);
+        }
+
+        // Recorded files under the scanned roots that the scan didn't find
+        let scanned: HashSet<&PathBuf> = paths.iter().collect();
+        let roots: Vec<PathBuf> = roots.iter()
+            .map(|root| std::fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+            .collect();
+        let mut missing: Vec<&PathBuf> = recorded.keys()
+            .filter(|path| !scanned.contains(path) && roots.iter().any(|root| path.starts_with(root)))
+            .filter(|path| !path.exists())
+            .collect();
+        missing.sort();
+        results.reports.extend(missing.into_iter().map(|path| ScrubReport {
+            path: path.clone(),
+            status: ScrubStatus::Missing,
+        }));
+
+        if !self.dry_run {
+            index.store_checksums(&updates)?;
+            // Edited files need analyzing again
+            let modified: Vec<PathBuf> = results.reports.iter()
+                .filter(|r| r.status == ScrubStatus::Modified)
+                .map(|r| r.path.clone())
+                .collect();
+            index.invalidate(&modified)?;
+        }
+        Ok(results)
+    }
+
+    // The report for one file, plus the record to store if anything changed
+    fn check(&self, path: &Path, recorded: Option<&ChecksumRecord>, now: u64) -> (ScrubReport, Option<ChecksumRecord>) {
+        let report = |status| ScrubReport { path: path.to_path_buf(), status };
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => return (report(ScrubStatus::Unreadable { error: e.to_string() }), None),
+        };
+        let size_bytes = metadata.len();
+        let modified = modified_secs(path);
+
+        let unchanged = recorded.filter(|r| r.size_bytes == size_bytes && r.modified_secs == modified);
+        if let Some(record) = unchanged {
+            if now.saturating_sub(record.verified_at) < self.recheck_after.as_secs() {
+                return (report(ScrubStatus::Skipped), None);
+            }
+        }
+
+        let sha256 = match hashing::sha256_file(path) {
+            Ok(sha256) => sha256,
+            Err(e) => return (report(ScrubStatus::Unreadable { error: e.to_string() }), None),
+        };
+        let fresh = |recorded_at| ChecksumRecord {
+            path: path.to_path_buf(),
+            sha256: sha256.clone(),
+            size_bytes,
+            modified_secs: modified,
+            recorded_at,
+            verified_at: now,
+        };
+
+        match (recorded, unchanged) {
+            (None, _) => (report(ScrubStatus::New), Some(fresh(now))),
+            (Some(_), None) => (report(ScrubStatus::Modified), Some(fresh(now))),
+            (Some(_), Some(record)) if record.sha256 == sha256 => {
+                (report(ScrubStatus::Verified), Some(fresh(record.recorded_at)))
+            }
+            (Some(_), Some(record)) => {
+                let status = ScrubStatus::Corrupt { expected: record.sha256.clone(), actual: sha256.clone() };
+                // Keep the good checksum, and never count it as recently
+                // verified so --recheck-days doesn't hide it
+                let record = ChecksumRecord { verified_at: 0, ..record.clone() };
+                (report(status), Some(record))
+            }
+        }
+    }
+}
@@ -1,38 +1,226 @@
-use std::path::PathBuf;
-use serde::Serialize;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
 
 pub mod analyzers;
 pub mod audio;
 pub mod utils;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod library;
+pub mod exporters;
+pub mod playlist;
+pub mod schema;
+#[cfg(feature = "index")]
+pub mod index;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AudioFile {
     pub path: PathBuf,
     pub file_name: String,
     pub size_bytes: u64,
+    // Space allocated on disk, when the platform can report it
+    pub allocated_bytes: Option<u64>,
+    // Bytes of encoded audio, without tags, artwork or padding
+    pub audio_bytes: Option<u64>,
     pub duration_secs: Option<f64>,
+    // Duration from the exact frame count, rounded to the millisecond
+    pub duration_ms: Option<u64>,
     pub bitrate: Option<u32>,
+    // Why `bitrate` is missing, when it is
+    pub bitrate_unknown: Option<UnknownBitrate>,
     pub artist: Option<String>,
     pub title: Option<String>,
     pub album: Option<String>,
+    // Record label / publisher as tagged
+    pub label: Option<String>,
+    // International Standard Recording Code as tagged, without hyphens
+    pub isrc: Option<String>,
+    // Musical key as tagged, in whatever notation the tagger used
+    pub key: Option<String>,
+    // Tempo as tagged
+    pub bpm: Option<f64>,
+    // Percent of the track a static beatgrid stays on the beat, when measured
+    pub tempo_stability: Option<f64>,
+}
+
+// Why a file's bitrate couldn't be worked out, to tell broken files from
+// gaps in the extractor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum UnknownBitrate {
+    // The container holds no audio track the decoder can read
+    NoAudioTrack,
+    // The track header gives no frame count, so there is no duration
+    NoFrameCount,
+    // Frames, but neither a sample rate nor a time base to time them by
+    NoTimeBase,
+    ZeroDuration,
+    // The format has no stream bitrate to report: tracker modules are
+    // rendered from patterns and samples as they play
+    NoStreamBitrate,
+    // The file couldn't be opened or probed at all; it is a scan error
+    ProbeFailed,
+}
+
+impl UnknownBitrate {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NoAudioTrack => "no audio track",
+            Self::NoFrameCount => "no frame count",
+            Self::NoTimeBase => "no sample rate",
+            Self::ZeroDuration => "zero duration",
+            Self::NoStreamBitrate => "no stream bitrate",
+            Self::ProbeFailed => "unreadable",
+        }
+    }
+}
+
+impl AudioFile {
+    // Bare record with no extracted metadata yet
+    pub fn new(path: impl Into<PathBuf>, size_bytes: u64) -> Self {
+        let path = path.into();
+        let file_name = path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            path,
+            file_name,
+            size_bytes,
+            allocated_bytes: None,
+            audio_bytes: None,
+            duration_secs: None,
+            duration_ms: None,
+            bitrate: None,
+            bitrate_unknown: None,
+            artist: None,
+            title: None,
+            album: None,
+            label: None,
+            isrc: None,
+            key: None,
+            bpm: None,
+            tempo_stability: None,
+        }
+    }
+
+    // Below this a static beatgrid drifts audibly somewhere in the track
+    pub const STEADY_TEMPO_PERCENT: f64 = 90.0;
+
+    // Whether the track needs manual gridding, when tempo stability was measured
+    pub fn needs_manual_grid(&self) -> Option<bool> {
+        self.tempo_stability.map(|stability| stability < Self::STEADY_TEMPO_PERCENT)
+    }
+
+    // Bytes the OS would free by deleting the file, falling back to the logical size
+    pub fn size_on_disk(&self) -> u64 {
+        self.allocated_bytes.unwrap_or(self.size_bytes)
+    }
+
+    // Size that reflects audio quality: the audio stream alone when known,
+    // so differing artwork or tag padding doesn't count
+    pub fn audio_size(&self) -> u64 {
+        self.audio_bytes.unwrap_or(self.size_bytes)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum AudioError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("IO error on {path}: {source}")]
+    FileIo {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("Metadata extraction error: {0}")]
     Metadata(String),
     #[error("Unsupported file format: {0}")]
     UnsupportedFormat(String),
+    #[error("File name is not valid UTF-8: {path}")]
+    InvalidFileName { path: PathBuf },
+    #[error("Unrecognized audio container: {path}")]
+    Probe { path: PathBuf },
+    #[error("Placeholder-size file ({size_bytes} bytes), likely a failed download: {path}")]
+    Placeholder { path: PathBuf, size_bytes: u64 },
+    #[cfg(feature = "decode")]
+    #[error("Failed to decode {path}: {source}")]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: symphonia::core::errors::Error,
+    },
+    #[error("Failed to read tags from {path}: {reason}")]
+    TagRead { path: PathBuf, reason: String },
+    #[error("Read-only mode: refusing to modify {path}")]
+    ReadOnly { path: PathBuf },
+    #[cfg(feature = "report-upload")]
+    #[error("Failed to upload {path} to {url}: {reason}")]
+    Upload { path: PathBuf, url: String, reason: String },
+    #[cfg(feature = "reports-csv")]
     #[error("CSV error: {0}")]
     Csv(#[from] csv::Error),
+    #[cfg(feature = "index")]
+    #[error("Index database error: {0}")]
+    Index(#[from] rusqlite::Error),
+    #[cfg(feature = "index")]
+    #[error("Index {path} is in use by {owner}; wait for that run to finish, or rerun with --force-unlock if it is no longer running")]
+    IndexLocked { path: PathBuf, owner: String },
+}
+
+impl AudioError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::FileIo { path: path.into(), source }
+    }
+
+    // Errors worth retrying later, e.g. an external drive still spinning up
+    pub fn is_transient(&self) -> bool {
+        use std::io::ErrorKind;
+        let io_error = match self {
+            Self::Io(source) | Self::FileIo { source, .. } => source,
+            _ => return false,
+        };
+        matches!(io_error.kind(),
+            ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::ResourceBusy
+        )
+    }
+
+    // The file an error relates to, if any, so reports and retries can target it
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::FileIo { path, .. }
+            | Self::InvalidFileName { path }
+            | Self::Probe { path }
+            | Self::Placeholder { path, .. }
+            | Self::TagRead { path, .. }
+            | Self::ReadOnly { path } => Some(path),
+            #[cfg(feature = "report-upload")]
+            Self::Upload { path, .. } => Some(path),
+            #[cfg(feature = "decode")]
+            Self::Decode { path, .. } => Some(path),
+            _ => None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, AudioError>;
 
 // Re-exports for convenience
-pub use audio::metadata::MetadataExtractor;
-pub use analyzers::duplicate::{DuplicateAnalyzer, DuplicateMatch, DuplicateResults};
-pub use analyzers::bitrate::{BitrateAnalyzer, BitrateStats};
\ No newline at end of file
+#[cfg(feature = "decode")]
+pub use audio::metadata::{MetadataExtractor, ScanOptions};
+pub use audio::source::MetadataSource;
+pub use analyzers::duplicate::{DuplicateAnalyzer, DuplicateGroup, DuplicateMatch, DuplicateResults};
+pub use analyzers::bitrate::{BitrateAnalyzer, BitrateStats};
+pub use analyzers::title::{ParsedTitle, TitleParser};
+pub use library::{Library, LibraryEvent, LibraryFilter};
+#[cfg(feature = "index")]
+pub use index::LibraryIndex;
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use crate::{AudioFile, Result};
+#[cfg(feature = "decode")]
+use crate::audio::metadata::MetadataExtractor;
+use crate::audio::source::MetadataSource;
+use crate::analyzers::duplicate::{DuplicateAnalyzer, DuplicateResults};
+
+#[cfg(feature = "decode")]
+pub mod watch;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LibraryEvent {
+    Added(PathBuf),
+    Updated(PathBuf),
+    Removed(PathBuf),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LibraryFilter {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub extension: Option<String>,
+    pub min_bitrate: Option<u32>,
+    pub max_bitrate: Option<u32>,
+    pub path_prefix: Option<PathBuf>,
+}
+
+impl LibraryFilter {
+    pub fn matches(&self, file: &AudioFile) -> bool {
+        // Text filters are case-insensitive substring matches against tags,
+        // falling back to the file name when a tag is missing
+        let text_matches = |needle: &Option<String>, value: &Option<String>| {
+            needle.as_ref().is_none_or(|needle| {
+                let haystack = value.as_deref().unwrap_or(&file.file_name);
+                haystack.to_lowercase().contains(&needle.to_lowercase())
+            })
+        };
+
+        let extension_matches = self.extension.as_ref().is_none_or(|ext| {
+            file.path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case(ext.trim_start_matches('.')))
+        });
+
+        let bitrate_matches = match (self.min_bitrate, self.max_bitrate, file.bitrate) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (min, max, Some(bitrate)) => {
+                min.is_none_or(|m| bitrate >= m) && max.is_none_or(|m| bitrate <= m)
+            }
+        };
+
+        let prefix_matches = self.path_prefix.as_ref()
+            .is_none_or(|prefix| file.path.starts_with(prefix));
+
+        text_matches(&self.artist, &file.artist)
+            && text_matches(&self.title, &file.title)
+            && text_matches(&self.album, &file.album)
+            && extension_matches
+            && bitrate_matches
+            && prefix_matches
+    }
+}
+
+// In-memory view of a music library for embedding applications (GUIs etc).
+// Changes are broadcast to every subscriber so front-ends can stay in sync
+// without re-scanning the whole library.
+pub struct Library {
+    files: HashMap<PathBuf, AudioFile>,
+    listeners: Vec<Sender<LibraryEvent>>,
+    source: Box<dyn MetadataSource>,
+}
+
+#[cfg(feature = "decode")]
+impl Default for Library {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Library {
+    #[cfg(feature = "decode")]
+    pub fn new() -> Self {
+        Self::with_source(MetadataExtractor)
+    }
+
+    pub fn with_source(source: impl MetadataSource + 'static) -> Self {
+        Self {
+            files: HashMap::new(),
+            listeners: Vec::new(),
+            source: Box::new(source),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<LibraryEvent> {
+        let (sender, receiver) = channel();
+        self.listeners.push(sender);
+        receiver
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&AudioFile> {
+        self.files.get(path.as_ref())
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &AudioFile> {
+        self.files.values()
+    }
+
+    // Adds files and directories to the library. Directories are scanned
+    // recursively; files already present are only reported as updated when
+    // their metadata actually changed.
+    pub fn add_paths(&mut self, paths: &[impl AsRef<Path>]) -> Result<Vec<LibraryEvent>> {
+        let mut scanned = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            if path.is_dir() {
+                scanned.extend(self.source.scan(path)?);
+            } else {
+                scanned.push(self.source.extract(path)?);
+            }
+        }
+
+        let mut events = Vec::new();
+        for file in scanned {
+            let path = file.path.clone();
+            match self.files.insert(path.clone(), file) {
+                None => events.push(LibraryEvent::Added(path)),
+                Some(previous) if self.files.get(&path) != Some(&previous) => {
+                    events.push(LibraryEvent::Updated(path))
+                }
+                Some(_) => {}
+            }
+        }
+
+        self.emit(&events);
+        Ok(events)
+    }
+
+    // Drops every file that no longer exists on disk
+    pub fn remove_missing(&mut self) -> Vec<LibraryEvent> {
+        let missing: Vec<PathBuf> = self.files.keys()
+            .filter(|path| !path.exists())
+            .cloned()
+            .collect();
+
+        let events: Vec<LibraryEvent> = missing.into_iter()
+            .map(|path| {
+                self.files.remove(&path);
+                LibraryEvent::Removed(path)
+            })
+            .collect();
+
+        self.emit(&events);
+        events
+    }
+
+    pub fn query(&self, filter: &LibraryFilter) -> Vec<&AudioFile> {
+        let mut results: Vec<&AudioFile> = self.files.values()
+            .filter(|file| filter.matches(file))
+            .collect();
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+        results
+    }
+
+    pub fn find_duplicates(&self) -> DuplicateResults {
+        let analyzer = DuplicateAnalyzer::new(1.0);
+        analyzer.find_duplicates(self.files.values().cloned().collect())
+    }
+
+    fn emit(&mut self, events: &[LibraryEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        // Drop listeners whose receiving end has gone away
+        self.listeners.retain(|listener| {
+            events.iter().all(|event| listener.send(event.clone()).is_ok())
+        });
+    }
+}
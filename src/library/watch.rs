@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result, ScanOptions};
+use crate::analyzers::title::TitleParser;
+use crate::audio::metadata::{self, MetadataExtractor};
+use crate::audio::tags;
+use crate::utils::file_ops::{FileOperation, FileOperationExecutor, OperationOutcome};
+
+// Hands-off ingest: folders polled for new audio files, each new file run
+// through that folder's pipeline (fill in tags, rename, move into the
+// library). A file is only picked up once it stopped changing, so downloads
+// still in progress are left alone.
+//
+// The pipeline comes from a JSON config:
+//
+//   {
+//     "folders": [{
+//       "path": "/Users/me/Downloads",
+//       "steps": [
+//         { "step": "enrich" },
+//         { "step": "rename", "template": "{artist} - {title}" },
+//         { "step": "move", "destination": "/Music", "folder": "{genre}" }
+//       ]
+//     }]
+//   }
+
+pub const DEFAULT_INTERVAL_SECS: u64 = 10;
+// Files modified more recently than this may still be downloading
+pub const DEFAULT_SETTLE_SECS: u64 = 30;
+pub const DEFAULT_FALLBACK_FOLDER: &str = "Unsorted";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_settle")]
+    pub settle_secs: u64,
+    pub folders: Vec<WatchFolder>,
+}
+
+fn default_interval() -> u64 {
+    DEFAULT_INTERVAL_SECS
+}
+
+fn default_settle() -> u64 {
+    DEFAULT_SETTLE_SECS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_fallback() -> String {
+    DEFAULT_FALLBACK_FOLDER.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchFolder {
+    pub path: PathBuf,
+    #[serde(default = "default_true")]
+    pub recursive: bool,
+    pub steps: Vec<PipelineStep>,
+}
+
+// Templates use `{artist}`, `{title}`, `{album}`, `{genre}`, `{label}`,
+// `{key}` and `{bpm}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PipelineStep {
+    // Fills missing artist and title tags from an "Artist - Title" file name
+    Enrich,
+    // Renames the file (extension kept); skipped when a field is missing
+    Rename { template: String },
+    // Moves the file into `destination/<folder>`; `fallback` stands in for
+    // a folder name with missing fields
+    Move {
+        destination: PathBuf,
+        #[serde(default)]
+        folder: Option<String>,
+        #[serde(default = "default_fallback")]
+        fallback: String,
+    },
+}
+
+impl WatchConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+        let config: Self = serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid watch config {}: {}", path.display(), e)))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.folders.is_empty() {
+            return Err(AudioError::Metadata("Watch config has no folders".to_string()));
+        }
+        for folder in &self.folders {
+            if folder.steps.is_empty() {
+                return Err(AudioError::Metadata(format!("No steps for watched folder {}", folder.path.display())));
+            }
+            for step in &folder.steps {
+                let template = match step {
+                    PipelineStep::Enrich => continue,
+                    PipelineStep::Rename { template } => template.as_str(),
+                    PipelineStep::Move { folder: Some(template), .. } => template.as_str(),
+                    PipelineStep::Move { folder: None, .. } => continue,
+                };
+                if let Some(field) = unknown_field(template) {
+                    return Err(AudioError::Metadata(format!("Unknown template field {{{}}} in \"{}\"", field, template)));
+                }
+            }
+            // A file moved away from a watched folder into another one would
+            // be ingested twice
+            let nested = self.folders.iter().any(|other| {
+                folder.steps.iter().any(|step| matches!(step,
+                    PipelineStep::Move { destination, .. } if destination.starts_with(&other.path)))
+            });
+            if nested {
+                return Err(AudioError::Metadata(format!(
+                    "Files from {} would be moved into a watched folder", folder.path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    pub fn settle(&self) -> Duration {
+        Duration::from_secs(self.settle_secs)
+    }
+}
+
+const FIELDS: [&str; 7] = ["artist", "title", "album", "genre", "label", "key", "bpm"];
+
+fn field_regex() -> Regex {
+    Regex::new(r"\{([a-z_]+)\}").unwrap()
+}
+
+fn unknown_field(template: &str) -> Option<String> {
+    field_regex().captures_iter(template)
+        .map(|captures| captures[1].to_string())
+        .find(|field| !FIELDS.contains(&field.as_str()))
+}
+
+// What a track knows about itself while it moves through the pipeline.
+// Values filled in by `enrich` are kept here too, so a dry run plans the
+// later steps as a real run would.
+#[derive(Debug, Clone)]
+pub struct IngestTrack {
+    pub file: AudioFile,
+    pub genre: Option<String>,
+}
+
+impl IngestTrack {
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = MetadataExtractor::extract_metadata(path)?;
+        let genre = tags::read_tags(path).ok()
+            .and_then(|tags| tags.get("Genre").cloned())
+            .filter(|genre| !genre.trim().is_empty());
+        Ok(Self { file, genre })
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "artist" => self.file.artist.clone(),
+            "title" => self.file.title.clone(),
+            "album" => self.file.album.clone(),
+            "genre" => self.genre.clone(),
+            "label" => self.file.label.clone(),
+            "key" => self.file.key.clone(),
+            "bpm" => self.file.bpm.map(|bpm| format!("{}", bpm.round() as u32)),
+            _ => None,
+        };
+        value.map(|value| safe_component(&value)).filter(|value| !value.is_empty())
+    }
+
+    // None when a field the template uses is missing
+    pub fn render(&self, template: &str) -> Option<String> {
+        let mut missing = false;
+        let rendered = field_regex().replace_all(template, |captures: &regex::Captures| {
+            self.field(&captures[1]).unwrap_or_else(|| {
+                missing = true;
+                String::new()
+            })
+        });
+        let rendered = rendered.trim().to_string();
+        (!missing && !rendered.is_empty()).then_some(rendered)
+    }
+}
+
+// Characters that can't appear in a file name on one of the platforms a
+// library travels between, replaced; trailing dots and spaces (invalid on
+// Windows) trimmed
+pub fn safe_component(value: &str) -> String {
+    let replaced: String = value.chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() { '_' } else { c })
+        .collect();
+    replaced.trim().trim_end_matches(['.', ' ']).to_string()
+}
+
+// Artist and title from an "Artist - Title" file name, leading track or pool
+// numbers and copy markers removed
+pub fn names_from_file_name(file_name: &str) -> Option<(String, String)> {
+    let stem = Path::new(file_name).file_stem()?.to_string_lossy().replace('_', " ");
+    let stem = TitleParser::strip_copy_markers(stem.trim());
+    let stem = &stem[TitleParser::new().track_number_prefix(stem).len()..];
+    let (artist, title) = stem.split_once(" - ")?;
+    let (artist, title) = (artist.trim(), title.trim());
+    (!artist.is_empty() && !title.is_empty()).then(|| (artist.to_string(), title.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    // Tag fields filled in (planned, on a dry run)
+    Enriched(Vec<String>),
+    Renamed(PathBuf),
+    Moved(PathBuf),
+    Unchanged { step: &'static str, reason: String },
+}
+
+pub struct Pipeline<'a> {
+    steps: &'a [PipelineStep],
+    executor: &'a FileOperationExecutor,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(steps: &'a [PipelineStep], executor: &'a FileOperationExecutor) -> Self {
+        Self { steps, executor }
+    }
+
+    // Runs every step on one file; returns where the file ended up (where it
+    // would, on a dry run) and what each step did. Stops at the first error.
+    pub fn run(&self, path: &Path) -> Result<(PathBuf, Vec<StepOutcome>)> {
+        let mut track = IngestTrack::read(path)?;
+        let mut outcomes = Vec::new();
+
+        for step in self.steps {
+            let outcome = match step {
+                PipelineStep::Enrich => self.enrich(&mut track)?,
+                PipelineStep::Rename { template } => {
+                    let extension = track.file.path.extension()
+                        .map(|ext| format!(".{}", ext.to_string_lossy()))
+                        .unwrap_or_default();
+                    match track.render(template) {
+                        Some(name) => {
+                            let name = format!("{}{}", name, extension);
+                            let to = track.file.path.with_file_name(&name);
+                            self.relocate(&mut track, to, "rename", StepOutcome::Renamed)?
+                        }
+                        None => StepOutcome::Unchanged { step: "rename", reason: "missing template fields".to_string() },
+                    }
+                }
+                PipelineStep::Move { destination, folder, fallback } => {
+                    let mut to = destination.clone();
+                    if let Some(folder) = folder {
+                        to.push(track.render(folder).unwrap_or_else(|| fallback.clone()));
+                    }
+                    to.push(&track.file.file_name);
+                    self.relocate(&mut track, to, "move", StepOutcome::Moved)?
+                }
+            };
+            outcomes.push(outcome);
+        }
+
+        Ok((track.file.path, outcomes))
+    }
+
+    fn enrich(&self, track: &mut IngestTrack) -> Result<StepOutcome> {
+        let Some((artist, title)) = names_from_file_name(&track.file.file_name) else {
+            return Ok(StepOutcome::Unchanged { step: "enrich", reason: "no \"Artist - Title\" in the file name".to_string() });
+        };
+        let artist = track.file.artist.is_none().then_some(artist);
+        let title = track.file.title.is_none().then_some(title);
+        if artist.is_none() && title.is_none() {
+            return Ok(StepOutcome::Unchanged { step: "enrich", reason: "tags complete".to_string() });
+        }
+
+        if !self.executor.is_dry_run() {
+            write_artist_title_tags(&track.file.path, artist.as_deref(), title.as_deref())?;
+        }
+        let mut filled = Vec::new();
+        if let Some(artist) = artist {
+            filled.push(format!("artist \"{}\"", artist));
+            track.file.artist = Some(artist);
+        }
+        if let Some(title) = title {
+            filled.push(format!("title \"{}\"", title));
+            track.file.title = Some(title);
+        }
+        Ok(StepOutcome::Enriched(filled))
+    }
+
+    fn relocate(
+        &self,
+        track: &mut IngestTrack,
+        to: PathBuf,
+        step: &'static str,
+        done: fn(PathBuf) -> StepOutcome,
+    ) -> Result<StepOutcome> {
+        if to == track.file.path {
+            return Ok(StepOutcome::Unchanged { step, reason: "already in place".to_string() });
+        }
+        let operation = FileOperation::Move { from: track.file.path.clone(), to };
+        match self.executor.execute(&operation)? {
+            OperationOutcome::Performed(op) | OperationOutcome::Planned(op) => {
+                let new_path = op.destination().unwrap_or(&track.file.path).to_path_buf();
+                track.file.file_name = new_path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                track.file.path = new_path.clone();
+                Ok(done(new_path))
+            }
+            OperationOutcome::Skipped { reason, .. } => Ok(StepOutcome::Unchanged { step, reason }),
+        }
+    }
+}
+
+// Writes the given artist and title; missing values leave their field
+// untouched. MP3 and FLAC only.
+#[cfg(feature = "tags")]
+pub fn write_artist_title_tags(path: &Path, artist: Option<&str>, title: Option<&str>) -> Result<()> {
+    crate::utils::file_ops::ensure_writable(path)?;
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let tag_error = |reason: String| AudioError::TagRead { path: path.to_path_buf(), reason };
+
+    match extension.as_str() {
+        "mp3" => {
+            use id3::TagLike;
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(tag_error(e.to_string())),
+            };
+            if let Some(artist) = artist {
+                tag.set_artist(artist);
+            }
+            if let Some(title) = title {
+                tag.set_title(title);
+            }
+            let version = tag.version();
+            tag.write_to_path(path, version).map_err(|e| tag_error(e.to_string()))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| tag_error(e.to_string()))?;
+            if let Some(artist) = artist {
+                tag.set_vorbis("ARTIST", vec![artist]);
+            }
+            if let Some(title) = title {
+                tag.set_vorbis("TITLE", vec![title]);
+            }
+            tag.save().map_err(|e| tag_error(e.to_string()))
+        }
+        _ => Err(AudioError::UnsupportedFormat(format!(
+            "Can't write tags to {} (only MP3 and FLAC)", path.display()
+        ))),
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+pub fn write_artist_title_tags(path: &Path, _artist: Option<&str>, _title: Option<&str>) -> Result<()> {
+    Err(AudioError::UnsupportedFormat(format!(
+        "Can't write tags to {} (needs a build with the `tags` feature)", path.display()
+    )))
+}
+
+type FileState = (u64, Option<SystemTime>);
+
+fn file_state(path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+// Tracks one watched folder between polls
+pub struct FolderWatcher {
+    folder: WatchFolder,
+    extensions: Vec<String>,
+    settle: Duration,
+    // Size and modification time of each file at the previous poll
+    seen: HashMap<PathBuf, FileState>,
+    // Files the pipeline already ran on that are still in the folder, as
+    // they were afterwards; they're only picked up again once they change
+    handled: HashMap<PathBuf, FileState>,
+}
+
+impl FolderWatcher {
+    pub fn new(folder: WatchFolder, settle: Duration) -> Self {
+        Self {
+            folder,
+            extensions: ScanOptions::default().extensions,
+            settle,
+            seen: HashMap::new(),
+            handled: HashMap::new(),
+        }
+    }
+
+    pub fn folder(&self) -> &WatchFolder {
+        &self.folder
+    }
+
+    // Files ready for the pipeline: unchanged since the previous poll, or
+    // seen for the first time and not modified within the settle time
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let now = SystemTime::now();
+        let mut current = HashMap::new();
+        let mut ready = Vec::new();
+
+        let depth = if self.folder.recursive { usize::MAX } else { 1 };
+        let walker = walkdir::WalkDir::new(&self.folder.path).max_depth(depth).into_iter()
+            .filter_entry(|entry| {
+                let name = entry.file_name().to_string_lossy();
+                if entry.file_type().is_dir() {
+                    entry.depth() == 0 || !metadata::is_software_folder(&name)
+                } else {
+                    !metadata::is_resource_file(&name)
+                }
+            });
+        for entry in walker.filter_map(|entry| entry.ok()) {
+            if !entry.file_type().is_file() || !self.is_audio(entry.path()) {
+                continue;
+            }
+            let path = entry.into_path();
+            let Some(state) = file_state(&path) else { continue };
+            if self.handled.get(&path) == Some(&state) {
+                current.insert(path, state);
+                continue;
+            }
+            let settled = match self.seen.get(&path) {
+                Some(previous) => *previous == state,
+                None => state.1.is_some_and(|modified| now.duration_since(modified).is_ok_and(|age| age >= self.settle)),
+            };
+            if settled {
+                ready.push(path.clone());
+            }
+            current.insert(path, state);
+        }
+
+        self.handled.retain(|path, _| current.contains_key(path));
+        self.seen = current;
+        ready.sort();
+        ready
+    }
+
+    // Records where a file ended up so an unchanged file isn't processed
+    // again while it stays in the folder
+    pub fn mark_handled(&mut self, original: &Path, final_path: &Path) {
+        for path in [original, final_path] {
+            if let Some(state) = file_state(path) {
+                self.handled.insert(path.to_path_buf(), state);
+            }
+        }
+    }
+
+    fn is_audio(&self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| self.extensions.contains(&ext))
+    }
+}
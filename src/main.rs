@@ -1,40 +1,205 @@
-use clap::Parser;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use dj_library_manager::{
+    AudioFile,
     MetadataExtractor,
+    UnknownBitrate,
+    ScanOptions,
+    audio::metadata::PLACEHOLDER_BYTES,
     analyzers::{
-        bitrate::BitrateAnalyzer,
-        duplicate::DuplicateAnalyzer,
+        bitrate::{BitrateAnalyzer, Grouping, LOW_QUALITY_KBPS},
+        discs::DiscAnalyzer,
+        drift::DriftChecker,
+        duplicate::{DuplicateAnalyzer, DuplicateMatch, DuplicateResults, MatchClass, MatchMode},
+        policy::QualityPolicy,
+        pool,
+        title::{KnownArtists, TitleParser},
+        wishlist::{self, WishStatus, WishlistMatcher},
     },
     utils::{
-        file_ops::FileManager,
-        reporting::Reporter,
+        audit::{self, AuditFilter},
+        file_ops::{self, CollisionPolicy, FileManager, FileOperation, FileOperationExecutor, OperationOutcome},
+        parallel,
+        paths,
+        report_dir::{self, ReportRun, RunIndex},
+        reporting::{ReportOptions, Reporter},
+        scan_log::{LogEvent, ScanLog},
+        script::{OperationScript, ScriptFormat},
     },
 };
-use dj_library_manager::cli::commands::{Cli, Commands};
+use dj_library_manager::analyzers::relocate::{Relocation, Relocator};
+use dj_library_manager::audio::{retag::{self, KeyConvention}, tags};
+use dj_library_manager::analyzers::authenticity::AuthenticityAnalyzer;
+use dj_library_manager::analyzers::bpm::{BpmAnalyzer, BpmReport, BpmStatus};
+use dj_library_manager::analyzers::structure::StructureAnalyzer;
+use dj_library_manager::analyzers::tempo::{TempoAnalyzer, TempoReport};
+use dj_library_manager::analyzers::explicit::{ExplicitAnalyzer, ExplicitStatus, Transcriber, WordList};
+use dj_library_manager::analyzers::vocals::{VocalAnalyzer, VocalClass, VocalReport};
+use dj_library_manager::analyzers::approval::ApprovalList;
+use dj_library_manager::analyzers::ignore::IgnoreList;
+use dj_library_manager::analyzers::prefilter::Prefilter;
+use dj_library_manager::analyzers::roots::RootPriority;
+use dj_library_manager::analyzers::runs::DuplicateRun;
+use dj_library_manager::analyzers::storage::{GrowthSource, StoragePlanner};
+use dj_library_manager::cli::commands::{
+    Cli, Commands, DuplicatesCommand, ExportCommand, IgnoreCommand, ManifestCommand, PlaylistCommand, RetagCommand, VaultCommand,
+};
+use dj_library_manager::cli::options::{self, CollectionOpts, ReportOpts, ScanOpts};
+use dj_library_manager::cli::routine::{Routine, RoutineStep};
+use dj_library_manager::utils::backup::{BackupMode, BackupStore};
+use dj_library_manager::utils::progress::{self, PhaseTimer};
+use dj_library_manager::utils::vault::MetadataVault;
+#[cfg(feature = "index")]
+use dj_library_manager::utils::volumes;
+use dj_library_manager::exporters::{CollectionTrack, cues::TrackCues, keys::KeyNotation};
+use dj_library_manager::exporters::{manifest::{Manifest, ManifestDiff, ManifestEntry}, rekordbox, serato::{self, SeratoCrate}, sync::{SyncAction, Syncer}, traktor, usb::{self, ExportAction, UsbExporter}};
+use dj_library_manager::library::watch::{FolderWatcher, Pipeline, PipelineStep, StepOutcome, WatchConfig};
+use dj_library_manager::playlist::{Playlist, harmonic::{HarmonicTrack, SortedPlaylist}, matrix::CompatibilityMatrix};
+#[cfg(feature = "index")]
+use dj_library_manager::{cli::commands::IndexCommand, LibraryIndex};
+#[cfg(feature = "index")]
+use dj_library_manager::index::{lock::IndexLock, scrub::{ScrubStatus, Scrubber}};
+#[cfg(feature = "index")]
+use dj_library_manager::{analyzers::phrasing::PhraseAnalyzer, index::PhrasingRecord};
+#[cfg(feature = "index")]
+use dj_library_manager::{analyzers::key::{KeyAnalyzer, KeyReport, KeyStatus}, exporters::keys::MusicalKey, index::KeyRecord};
+#[cfg(feature = "tags")]
+use dj_library_manager::analyzers::vocals;
+#[cfg(feature = "artwork")]
+use dj_library_manager::analyzers::artwork::{self, ArtworkAnalyzer, ArtworkFlag, CoverExtractor, FolderCoverStatus};
+#[cfg(feature = "artwork")]
+use dj_library_manager::cli::commands::ArtworkCommand;
+#[cfg(feature = "library-watch")]
+use dj_library_manager::{cli::commands::WatchCommand, index::monitor::LibraryMonitor};
+#[cfg(feature = "schema")]
+use dj_library_manager::schema;
 
 fn main() {
     env_logger::init();
     
+    let cli = Cli::parse_with_config();
+    if let Err(e) = cli.validate() {
+        e.exit();
+    }
+
     // Configure thread pool
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(num_cpus::get()) // Use all available CPU cores
-        .build_global()
-        .unwrap();
+    let scan = match &cli.command {
+        Commands::Duplicates { scan, .. }
+        | Commands::Bitrate { scan, .. }
+        | Commands::Run { scan, .. }
+        | Commands::Authenticity { scan, .. }
+        | Commands::Explicit { scan, .. }
+        | Commands::DirtyOnly { scan, .. }
+        | Commands::Wishlist { scan, .. }
+        | Commands::Playlist { command: PlaylistCommand::Import { scan, .. } }
+        | Commands::Vocals { scan, .. }
+        | Commands::Bpm { scan, .. }
+        | Commands::Cues { scan, .. }
+        | Commands::Discs { scan, .. }
+        | Commands::Retag { command: Some(RetagCommand::TrackNumbers { scan, .. }), .. }
+        | Commands::Retag { command: None, scan, .. }
+        | Commands::Storage { scan, .. }
+        | Commands::Relocate { scan, .. }
+        | Commands::Backup { scan, .. }
+        | Commands::Vault { command: VaultCommand::Save { scan, .. } | VaultCommand::Restore { scan, .. } }
+        | Commands::Export { command: ExportCommand::Manifest { scan, .. } }
+        | Commands::Export { command: ExportCommand::Usb { scan, .. } } => Some(scan),
+        #[cfg(feature = "index")]
+        Commands::Index { command: IndexCommand::Update { scan }, .. } => Some(scan),
+        #[cfg(feature = "artwork")]
+        Commands::Artwork { command: Some(ArtworkCommand::Extract { scan, .. }), .. } => Some(scan),
+        #[cfg(feature = "artwork")]
+        Commands::Artwork { scan, .. } => Some(scan),
+        #[cfg(feature = "index")]
+        Commands::Scrub { scan, .. } | Commands::Phrasing { scan, .. } | Commands::Key { scan, .. } => Some(scan),
+        _ => None,
+    };
+    parallel::init_global_pool(scan.and_then(|scan| scan.threads).unwrap_or_else(parallel::available_threads));
+    if let Some(threads) = scan.and_then(|scan| scan.decode_threads) {
+        parallel::set_decode_threads(threads);
+    }
 
-    println!("Initialized with {} threads", rayon::current_num_threads());
-    
-    let cli = Cli::parse();
+    println!("Initialized with {} threads", parallel::current_threads());
+
+    if cli.read_only {
+        file_ops::set_read_only(true);
+        println!("Read-only mode: library files will not be written, moved or deleted");
+    }
+
+    #[cfg(feature = "index")]
+    let force_unlock = cli.force_unlock;
 
     match cli.command {
-        Commands::Duplicates { input, output, dry_run } => {
+        Commands::Duplicates { command: Some(DuplicatesCommand::Ignore { command }), .. } => duplicates_ignore(command),
+
+        Commands::Duplicates {
+            command: None, scan, output: Some(output), verify, memory_budget, match_mode, match_on, similarity, prefilter, explain, approval, duration_tolerance, priority_root, relocate_best,
+            script, script_format, script_delete, fix_swapped_names, keep_track_numbers, #[cfg(feature = "index")] artist_index,
+            limit, offset, top_by_waste, serato: serato_dir, serato_root, rewrite_crates, report,
+        } => {
+            // A script only plans: the user runs it, not us
+            let dry_run = scan.dry_run || script.is_some();
             println!("=== Starting Duplicate Analysis ===");
-            println!("Input directory: {}", input.display());
+            let mut run = DuplicateRun::start(&scan.input);
+            let mut index = RunIndex::new("duplicates", &scan.input);
+            println!("Run ID: {}", run.run_id);
+            for input in &scan.input {
+                println!("Input directory: {}", input.display());
+            }
             println!("Output directory: {}", output.display());
             println!("Dry run mode: {}", dry_run);
+            if let Some(path) = &script {
+                println!("Operation script: {}", path.display());
+            }
+            let mut operation_script = script.as_ref()
+                .map(|path| OperationScript::new(script_format.unwrap_or_else(|| ScriptFormat::for_path(path))));
+            let Some(approval) = load_approval_list(approval.as_deref()) else { return };
+            if let Some(list) = &approval {
+                let classes: Vec<&str> = list.act_on.iter().map(MatchClass::as_str).collect();
+                println!("Acting on: {} matches", if classes.is_empty() { "no".to_string() } else { classes.join(", ") });
+            }
+            let approved = |dup_match: &DuplicateMatch| approval.as_ref().is_none_or(|list| list.approves(dup_match));
+            let serato_root = serato_dir.as_ref()
+                .map(|dir| serato_root.unwrap_or_else(|| serato::default_volume_root(&dir.join("database V2"))));
+            let crates = match (&serato_dir, &serato_root) {
+                (Some(dir), Some(root)) => match serato::read_crates(dir, root) {
+                    Ok(crates) => {
+                        println!("Serato crates: {}", crates.len());
+                        crates
+                    }
+                    Err(e) => {
+                        eprintln!("Error reading Serato crates: {}", e);
+                        return;
+                    }
+                },
+                _ => Vec::new(),
+            };
+            let mut crates_by_track: HashMap<&Path, Vec<&str>> = HashMap::new();
+            for serato_crate in &crates {
+                for track in &serato_crate.tracks {
+                    crates_by_track.entry(track.as_path()).or_default().push(&serato_crate.name);
+                }
+            }
             
             // Extract metadata from all audio files
             println!("\nScanning for audio files...");
-            let files = match MetadataExtractor::process_directory(&input) {
+            let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+            let phases = Arc::new(PhaseTimer::new());
+            let mut scan_options = ScanOptions {
+                log: log.clone(),
+                phases: Some(Arc::clone(&phases)),
+                ..scan.to_scan_options()
+            };
+            // Quarantined files inside an input would be found and matched
+            // again on the next run
+            if let Some(input) = scan.input.iter().find(|input| options::is_within(&output, input)) {
+                println!("Warning: output directory {} is inside input directory {}; leaving it out of the scan",
+                    output.display(), input.display());
+                scan_options.exclude_dirs.push(options::resolve(&output));
+            }
+            let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
                 Ok(files) => files,
                 Err(e) => {
                     eprintln!("Error processing directory: {}", e);
@@ -49,108 +214,3457 @@ fn main() {
                 return;
             }
 
+            let executor = FileOperationExecutor::new()
+                .with_dry_run(dry_run)
+                .with_verification(verify);
+            let executor = match executor.with_command_journal("duplicates") {
+                Ok(executor) => executor,
+                Err(e) => {
+                    eprintln!("Error creating operation journal: {}", e);
+                    return;
+                }
+            };
+
+            // Tagged artists tell "Title - Artist" names of untagged files apart
+            #[allow(unused_mut)]
+            let mut known_artists = KnownArtists::from_files(&files);
+            #[cfg(feature = "index")]
+            if let Some(path) = &artist_index {
+                let Some(library_index) = open_index(path, force_unlock) else { return };
+                match library_index.artists() {
+                    Ok(artists) => artists.iter().for_each(|artist| known_artists.insert(artist)),
+                    Err(e) => eprintln!("Error reading artists from index: {}", e),
+                }
+            }
+            let mut files = files;
+            // Where files stand once planned renames have run, for the script
+            let mut renamed_paths = HashMap::new();
+            if fix_swapped_names {
+                let parser = TitleParser::new()
+                    .with_track_number_stripping(!keep_track_numbers)
+                    .with_known_artists(known_artists.clone());
+                let renames = rename_swapped_files(&mut files, &parser, &executor);
+                if let Some(script) = operation_script.as_mut().filter(|_| !renames.is_empty()) {
+                    script.comment("Swapped \"Title - Artist\" file names");
+                    for operation in &renames {
+                        script.push(operation);
+                    }
+                    script.blank_line();
+                }
+                renamed_paths.extend(renames.iter()
+                    .filter_map(|op| Some((op.source().to_path_buf(), op.destination()?.to_path_buf()))));
+            }
+            let current_path = |path: &Path| renamed_paths.get(path).cloned().unwrap_or_else(|| path.to_path_buf());
+
             // Find duplicates
             println!("\nAnalyzing for duplicates...");
-            let analyzer = DuplicateAnalyzer::new(0.0);
-            let results = analyzer.find_duplicates(files);
+            let mut analyzer = DuplicateAnalyzer::new(similarity.unwrap_or(1.0))
+                .with_memory_budget(memory_budget.saturating_mul(1024 * 1024))
+                .with_known_artists(known_artists)
+                .with_track_number_stripping(!keep_track_numbers)
+                .with_match_chain(match_on)
+                .with_match_mode(match_mode);
+            if prefilter || match_mode == MatchMode::Fingerprint {
+                analyzer = analyzer.with_prefilter(Prefilter::new().with_duration_tolerance(duration_tolerance));
+            }
+            let priority = RootPriority::new(&priority_root);
+            if !priority.is_empty() {
+                analyzer = analyzer.with_root_priority(priority.clone());
+            }
+            let Some(ignored) = load_ignored_pairs() else { return };
+            if !ignored.is_empty() {
+                println!("Skipping {} pairs reviewed as different tracks", ignored.len());
+            }
+            let analyzer = analyzer.with_ignored_pairs(ignored);
+            let mut results = phases.time(progress::MATCHING, || analyzer.find_duplicates(files));
+
+            if let Some(log) = &log {
+                for dup_match in results.matches() {
+                    log.record(&LogEvent::Duplicate {
+                        higher_quality: &dup_match.higher_quality.path,
+                        lower_quality: &dup_match.lower_quality.path,
+                        match_reason: &dup_match.match_reason,
+                        quality_difference: &dup_match.quality_difference,
+                    });
+                }
+            }
 
-            println!("\nFound {} duplicate matches in {} scanned files", 
-                results.matches.len(), 
+            run.record(&results);
+            match run.save() {
+                Ok(path) => {
+                    println!("Run record saved to: {}", path.display());
+                    index.add_artifacts("Run record", &[path]);
+                }
+                Err(e) => eprintln!("Error saving run record: {}", e),
+            }
+            index.add_metric("run_id", "Run ID", run.run_id.clone());
+            index.add_metric("files_scanned", "Files scanned", results.total_files_scanned);
+            index.add_metric("duplicate_groups", "Duplicate groups", results.groups.len());
+            index.add_metric("duplicate_files", "Duplicate copies", results.duplicate_count());
+
+            println!("\nFound {} duplicate groups ({} copies to remove) in {} scanned files",
+                results.groups.len(),
+                results.duplicate_count(),
                 results.total_files_scanned
             );
 
-            if results.matches.is_empty() {
+            if results.groups.is_empty() {
                 println!("No duplicates found.");
                 return;
             }
 
+            let (reclaimable, reclaimable_on_disk) = results.reclaimable_bytes();
+            println!("Space reclaimable: {:.2} MB ({:.2} MB on disk)",
+                reclaimable as f64 / 1_048_576.0,
+                reclaimable_on_disk as f64 / 1_048_576.0);
+            index.add_metric("reclaimable_mb", "Space reclaimable (MB)", (reclaimable as f64 / 1_048_576.0 * 100.0).round() / 100.0);
+
+            // The run record and log above cover every group; moves and the
+            // report only the selected page
+            let total_groups = results.groups.len();
+            if top_by_waste.is_some() {
+                results.sort_by_waste();
+            }
+            if offset > 0 || limit.is_some() || top_by_waste.is_some() {
+                results.paginate(offset, top_by_waste.or(limit));
+                if results.groups.is_empty() {
+                    println!("No duplicate groups past offset {} (of {})", offset, total_groups);
+                    return;
+                }
+                let (selected, selected_on_disk) = results.reclaimable_bytes();
+                println!("Selected groups {}-{} of {}{}: {:.2} MB ({:.2} MB on disk)",
+                    offset + 1,
+                    offset + results.groups.len(),
+                    total_groups,
+                    if top_by_waste.is_some() { " by space reclaimable" } else { "" },
+                    selected as f64 / 1_048_576.0,
+                    selected_on_disk as f64 / 1_048_576.0);
+            }
+
+            let file_manager = FileManager::with_executor(&output, executor);
+
             if dry_run {
                 println!("\nDry run - no files will be moved");
                 println!("The following actions would be taken:");
-                for dup_match in &results.matches {
-                    println!("\nDuplicate pair found:");
-                    println!("  Will keep: {} ({} kbps)", 
-                        dup_match.higher_quality.file_name,
-                        dup_match.higher_quality.bitrate.unwrap_or(0));
-                    println!("  Would move: {} ({} kbps)", 
-                        dup_match.lower_quality.file_name,
-                        dup_match.lower_quality.bitrate.unwrap_or(0));
-                    println!("  Reason: {}", dup_match.match_reason);
-                    println!("  Quality difference: {}", dup_match.quality_difference);
-                }
             } else {
                 // Create output directory if it doesn't exist
                 println!("\nPreparing output directory...");
-                let file_manager = FileManager::new(&output);
-                file_manager.ensure_directory(&output)
-                    .expect("Failed to create output directory");
-
-                // Move duplicates
+                if let Err(e) = file_manager.ensure_directory(&output) {
+                    eprintln!("Error creating output directory: {}", e);
+                    return;
+                }
                 println!("\nMoving duplicate files...");
-                for dup_match in &results.matches {
-                    println!("\nProcessing duplicate pair:");
-                    println!("  Keeping: {} ({} kbps)", 
-                        dup_match.higher_quality.file_name,
-                        dup_match.higher_quality.bitrate.unwrap_or(0));
-                    
-                    match file_manager.move_duplicate(&dup_match.lower_quality.path) {
-                        Ok(new_path) => println!("  Moved: {} ({} kbps) -> {}", 
-                            dup_match.lower_quality.file_name,
-                            dup_match.lower_quality.bitrate.unwrap_or(0),
-                            new_path.file_name().unwrap_or_default().to_string_lossy()),
-                        Err(e) => eprintln!("  Error moving file {}: {}", 
-                            dup_match.lower_quality.file_name, e),
+            }
+
+            if explain {
+                for dup_match in results.matches() {
+                    explain_match(dup_match);
+                }
+            }
+
+            // Moves run grouped per source drive; report each group afterwards
+            let lower_paths: Vec<&std::path::Path> = results.matches()
+                .filter(|m| approved(m))
+                .map(|m| m.lower_quality.path.as_path())
+                .collect();
+            let move_results = phases.time(progress::MOVING, || file_manager.move_duplicates(&lower_paths));
+            let mut removed = HashSet::new();
+            let mut failed_moves = 0;
+            let mut report_only = 0;
+            let mut in_crates = 0;
+
+            let mut move_results = move_results.into_iter();
+            for group in &results.groups {
+                if dry_run {
+                    println!("\nDuplicate group found:");
+                    println!("  Will keep: {} ({} kbps)",
+                        group.original.file_name,
+                        group.original.bitrate.unwrap_or(0));
+                } else {
+                    println!("\nProcessing duplicate group:");
+                    println!("  Keeping: {} ({} kbps)",
+                        group.original.file_name,
+                        group.original.bitrate.unwrap_or(0));
+                }
+
+                for dup_match in &group.duplicates {
+                    let move_result = if approved(dup_match) { move_results.next() } else { None };
+                    match move_result {
+                        Some(Ok(new_path)) => {
+                            if let Some(script) = &mut operation_script {
+                                script.comment(&format!("Keep: {} ({} kbps)\nReason: {}\nQuality difference: {}",
+                                    current_path(&dup_match.higher_quality.path).display(),
+                                    dup_match.higher_quality.bitrate.unwrap_or(0),
+                                    dup_match.match_reason,
+                                    dup_match.quality_difference));
+                                let from = current_path(&dup_match.lower_quality.path);
+                                script.push(&if script_delete {
+                                    FileOperation::Delete { path: from }
+                                } else {
+                                    FileOperation::Move { from, to: new_path.clone() }
+                                });
+                                script.blank_line();
+                            }
+                            removed.insert(dup_match.lower_quality.path.as_path());
+                            println!("  {}: {} ({} kbps) -> {}", 
+                                if dry_run { "Would move" } else { "Moved" },
+                                dup_match.lower_quality.file_name,
+                                dup_match.lower_quality.bitrate.unwrap_or(0),
+                                new_path.file_name().unwrap_or_default().to_string_lossy());
+                            if let Some(log) = &log {
+                                log.record(&LogEvent::Moved {
+                                    from: &dup_match.lower_quality.path,
+                                    to: &new_path,
+                                    dry_run,
+                                });
+                            }
+                        }
+                        Some(Err(e)) => {
+                            failed_moves += 1;
+                            eprintln!("  Error moving file {}: {}", dup_match.lower_quality.file_name, e);
+                        }
+                        None => {
+                            report_only += 1;
+                            println!("  Report only ({} match): {} ({} kbps)",
+                                dup_match.match_class.as_str(),
+                                dup_match.lower_quality.file_name,
+                                dup_match.lower_quality.bitrate.unwrap_or(0));
+                        }
+                    }
+
+                    if let Some(names) = crates_by_track.get(dup_match.lower_quality.path.as_path()) {
+                        in_crates += 1;
+                        println!("    Warning: listed in Serato crates: {}", names.join(", "));
+                    }
+                    if dry_run {
+                        println!("    Reason: {}", dup_match.match_reason);
+                        println!("    Quality difference: {}", dup_match.quality_difference);
+                    }
+                }
+            }
+
+            let relocations = if relocate_best {
+                relocate_best_copies(&results, &priority, file_manager.executor(), &removed)
+            } else {
+                Vec::new()
+            };
+            if let Some(script) = operation_script.as_mut().filter(|_| !relocations.is_empty()) {
+                script.comment("Kept copies moved onto their preferred root");
+                for operation in &relocations {
+                    if let FileOperation::Move { from, to } = operation {
+                        script.push(&FileOperation::Move { from: current_path(from), to: to.clone() });
+                    }
+                }
+                script.blank_line();
+            }
+
+            if serato_dir.is_some() {
+                println!("\n{} duplicates are listed in Serato crates", in_crates);
+                index.add_metric("in_serato_crates", "Duplicates in Serato crates", in_crates);
+            }
+            if let (true, Some(root)) = (rewrite_crates, &serato_root) {
+                // Crate entries follow each moved copy to where its keeper
+                // ends up, after any relocation
+                let kept_at: HashMap<&Path, &Path> = relocations.iter()
+                    .filter_map(|operation| Some((operation.source(), operation.destination()?)))
+                    .collect();
+                let mapping: HashMap<PathBuf, PathBuf> = results.matches()
+                    .filter(|m| removed.contains(m.lower_quality.path.as_path()))
+                    .map(|m| {
+                        let kept = m.higher_quality.path.as_path();
+                        (m.lower_quality.path.clone(), kept_at.get(kept).copied().unwrap_or(kept).to_path_buf())
+                    })
+                    .collect();
+                let updated = rewrite_serato_crates(&crates, root, &mapping, &output.join("serato_crates"), dry_run);
+                index.add_metric("crate_entries_updated", "Serato crate entries updated", updated);
+            }
+
+            if let (Some(path), Some(operation_script)) = (&script, &operation_script) {
+                match operation_script.write(path) {
+                    Ok(()) => {
+                        println!("\nWrote {} operations to {} script: {}", operation_script.len(), operation_script.format(), path.display());
+                        index.add_artifacts("Operation script", std::slice::from_ref(path));
                     }
+                    Err(e) => eprintln!("Error writing operation script: {}", e),
                 }
             }
 
+            index.add_metric(if dry_run { "would_move" } else { "moved" },
+                if dry_run { "Files that would be moved" } else { "Files moved" }, removed.len());
+            index.add_metric("failed_moves", "Failed moves", failed_moves);
+            if approval.is_some() {
+                println!("\n{} duplicates not approved for action, listed in the report only", report_only);
+                index.add_metric("report_only", "Duplicates only reported", report_only);
+            }
+            if let Some(journal) = file_manager.executor().journal_path() {
+                println!("\nOperation journal: {}", journal.display());
+                index.add_artifacts("Operation journal", &[journal.to_path_buf()]);
+            }
+            if let Some(log) = &log {
+                index.add_artifacts("Scan log", &[log.path().to_path_buf()]);
+            }
+            add_timing_metrics(&mut index, &phases, results.total_files_scanned);
+
             // Generate report
             println!("\nGenerating report...");
-            let reporter = Reporter::new();
+            // Nothing was moved there on a dry run, so it may not exist yet
+            if let Err(e) = std::fs::create_dir_all(&output) {
+                eprintln!("Error creating output directory {}: {}", output.display(), e);
+                return;
+            }
+            let reporter = Reporter::new().with_options(ReportOptions::from(&report));
             let report_path = output.join("duplicate_report.csv");
             match reporter.generate_duplicate_report(&results, &report_path) {
-                Ok(_) => println!("Report saved to: {}", report_path.display()),
+                Ok(paths) => {
+                    for path in &paths {
+                        println!("Report saved to: {}", path.display());
+                    }
+                    index.add_artifacts("Duplicate report", &paths);
+                    match index.write(&output) {
+                        Ok(paths) => {
+                            for path in paths {
+                                println!("Run summary written: {}", path.display());
+                            }
+                        }
+                        Err(e) => eprintln!("Error writing run summary: {}", e),
+                    }
+                }
                 Err(e) => eprintln!("Error generating report: {}", e),
             }
 
-            println!("\n=== Duplicate Analysis Complete ===");
+            println!("\n=== Duplicate Analysis Complete: {} ===", phases.summary(results.total_files_scanned));
         }
+        Commands::Duplicates { .. } => unreachable!("duplicates without --output"),
 
-        Commands::Bitrate { input, output } => {
-            // Bitrate command implementation remains unchanged
-            println!("=== Starting Bitrate Analysis ===");
-            println!("Analyzing bitrates in directory: {}", input.display());
-            
-            let dirs = vec![input];
-            
-            println!("\nScanning for audio files...");
-            let files = match MetadataExtractor::process_directories(&dirs) {
+        Commands::Bitrate {
+            scan, output, tempo_stability, analyze_secs, by_artist, by_label, policy, quarantine_placeholders, report,
+        } => {
+            let groupings: Vec<Grouping> = [(Grouping::Artist, by_artist), (Grouping::Label, by_label)].into_iter()
+                .filter_map(|(grouping, wanted)| wanted.then_some(grouping))
+                .collect();
+            bitrate(&scan, &output, tempo_stability.then_some(analyze_secs), &groupings, policy.as_deref(),
+                quarantine_placeholders.as_deref(), &report)
+        }
+
+        Commands::Run { routine, scan, output, policy, approval, report } => {
+            run_routine(&routine, &scan, &output, policy.as_deref(), approval.as_deref(), &report)
+        }
+
+        #[cfg(feature = "artwork")]
+        Commands::Artwork { command: Some(ArtworkCommand::Extract { scan, file_name, overwrite }), .. } => {
+            artwork_extract(&scan, &file_name, overwrite)
+        }
+
+        #[cfg(feature = "artwork")]
+        Commands::Artwork { command: None, scan, output: Some(output), min_size, max_kb, resize, report } => {
+            artwork(&scan, &output, min_size, max_kb.saturating_mul(1024), resize, &report)
+        }
+
+        // clap requires --output when no subcommand is given
+        #[cfg(feature = "artwork")]
+        Commands::Artwork { .. } => unreachable!("artwork audit without --output"),
+
+        Commands::Authenticity { scan, output, analyze_secs, report } => {
+            authenticity(&scan, &output, analyze_secs, &report)
+        }
+
+        Commands::DirtyOnly { scan, output, report } => dirty_only(&scan, &output, &report),
+        Commands::Wishlist { scan, wishlist: list, output, report } => wishlist(&scan, &list, &output, &report),
+
+        Commands::Explicit { scan, output, transcriber, wordlist, clean, strict, report } => {
+            explicit(&scan, &output, transcriber.as_deref(), wordlist.as_deref(), clean.as_deref(), strict, &report)
+        }
+
+        Commands::Vocals { scan, output, tag, instrumental, vocal, analyze_secs, report } => {
+            vocals(&scan, &output, tag, instrumental.as_deref(), vocal.as_deref(), analyze_secs, &report)
+        }
+
+        Commands::Bpm { scan, output, tag, overwrite, min_bpm, max_bpm, analyze_secs, report } => {
+            bpm(&scan, &output, tag, overwrite, (min_bpm, max_bpm), analyze_secs, &report)
+        }
+
+        Commands::Cues { scan, output, rekordbox, traktor, serato, analyze_secs } => {
+            cues(&scan, &output, rekordbox.as_deref(), traktor.as_deref(), serato, analyze_secs)
+        }
+
+        Commands::DiffRuns { old, new, output } => diff_runs(old, new, output.as_deref()),
+
+        Commands::Undo { journal, dry_run } => undo(journal, dry_run),
+
+        Commands::Audit { journal_dir, since, until, path, user, include_copies, output, report } => {
+            let filter = AuditFilter {
+                since,
+                until: until.map(audit::end_of_day),
+                path,
+                user,
+                include_copies,
+            };
+            audit_operations(&journal_dir, &filter, output.as_deref(), &report)
+        }
+
+        Commands::Export { command: ExportCommand::Manifest { scan, output } } => export_manifest(&scan, &output),
+
+        Commands::Export { command: ExportCommand::Usb { scan, output, budget, downgrade, transcode, manifest } } => {
+            export_usb(&scan, &output, budget, downgrade, transcode, manifest.as_deref())
+        }
+
+        Commands::Manifest { command: ManifestCommand::Diff { mine, theirs, output } } => {
+            manifest_diff(&mine, &theirs, output.as_deref())
+        }
+
+        Commands::Playlist { command: PlaylistCommand::Sort { playlist, output, report } } => {
+            playlist_sort(&playlist, output.as_deref(), report.as_deref())
+        }
+
+        Commands::Playlist { command: PlaylistCommand::Matrix { playlist, output } } => playlist_matrix(&playlist, &output),
+        Commands::Playlist { command: PlaylistCommand::Import { scan, playlist, output, m3u, report } } => {
+            playlist_import(&scan, &playlist, &output, m3u.as_deref(), &report)
+        }
+
+        Commands::Backup { scan, target, tags_only, no_index } => backup(&scan, &target, tags_only, no_index),
+
+        Commands::Restore { target, snapshot, to, dry_run } => restore(&target, snapshot.as_deref(), to.as_deref(), dry_run),
+
+        Commands::Vault { command: VaultCommand::Save { scan, output } } => vault_save(&scan, &output),
+
+        Commands::Vault { command: VaultCommand::Restore { scan, vault } } => vault_restore(&scan, &vault),
+
+        Commands::Discs { scan, merge, output, report } => discs(&scan, merge, output.as_deref(), &report),
+        Commands::Retag { command: Some(RetagCommand::TrackNumbers { scan, overwrite }), .. } => retag_track_numbers(&scan, overwrite),
+        Commands::Retag { command: None, scan, filename, tags, notation, template } => retag(&scan, filename, tags, notation, &template),
+        Commands::Storage { scan, output } => storage(&scan, output.as_deref()),
+
+        Commands::Relocate { scan, collection, output, write_xml, write_nml } => {
+            relocate(&scan, &collection, &output, write_xml, write_nml)
+        }
+
+        Commands::Drift { collection, tolerance, output, report } => {
+            drift(&collection, tolerance, &output, &report)
+        }
+
+        #[cfg(feature = "library-watch")]
+        Commands::Watch { command: Some(WatchCommand::Library { input, index, settle_secs, jsonl_log }), .. } => {
+            watch_library(&input, index, settle_secs, jsonl_log, force_unlock)
+        }
+
+        Commands::Watch { config: Some(config), once, dry_run, .. } => watch(&config, once, dry_run),
+
+        // clap requires --config when no subcommand is given
+        Commands::Watch { .. } => unreachable!("watch without --config"),
+
+        Commands::Sync { source, target, allow_delete, retention_days, dry_run } => {
+            sync(&source, &target, allow_delete, retention_days, dry_run)
+        }
+
+        Commands::Doctor { input, output } => doctor(&input, &output),
+
+        #[cfg(feature = "index")]
+        Commands::Index { path, command } => index(path, command, force_unlock),
+
+        #[cfg(feature = "index")]
+        Commands::Search { query, index, limit, output } => search(&query.join(" "), index, limit, output.as_deref(), force_unlock),
+
+        #[cfg(feature = "index")]
+        Commands::Scrub { scan, index, recheck_days, output } => scrub(&scan, index, recheck_days, output.as_deref(), force_unlock),
+
+        #[cfg(feature = "index")]
+        Commands::Phrasing { scan, index, reanalyze, analyze_secs, output } => {
+            phrasing(&scan, index, reanalyze, analyze_secs, output.as_deref(), force_unlock)
+        }
+
+        #[cfg(feature = "index")]
+        Commands::Key { scan, output, index, reanalyze, analyze_secs, report } => {
+            key(&scan, &output, index, reanalyze, analyze_secs, &report, force_unlock)
+        }
+
+        #[cfg(feature = "schema")]
+        Commands::Schema { output } => write_schemas(&output),
+
+        #[cfg(feature = "self-update")]
+        Commands::SelfUpdate { check } => self_update(check),
+    }
+}
+
+// Opens the --jsonl-log file if one was requested. An unwritable log aborts
+// the run since the user explicitly asked for it.
+fn open_scan_log(path: Option<PathBuf>) -> Result<Option<Arc<ScanLog>>, ()> {
+    let Some(path) = path else { return Ok(None) };
+    match ScanLog::open(&path) {
+        Ok(log) => {
+            println!("Appending scan log to: {}", log.path().display());
+            Ok(Some(Arc::new(log)))
+        }
+        Err(e) => {
+            eprintln!("Error opening scan log: {}", e);
+            Err(())
+        }
+    }
+}
+
+// Opens the index, first clearing its lock when --force-unlock was given
+#[cfg(feature = "index")]
+fn open_index(path: &Path, force_unlock: bool) -> Option<LibraryIndex> {
+    if force_unlock {
+        let owner = IndexLock::owner(path);
+        match IndexLock::force_unlock(path) {
+            Ok(true) => match owner {
+                Some(owner) => println!("Removed index lock held by {}", owner.describe()),
+                None => println!("Removed index lock on {}", path.display()),
+            },
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("Error removing index lock: {}", e);
+                return None;
+            }
+        }
+    }
+    match LibraryIndex::open(path) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            eprintln!("Error opening index: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "index")]
+fn index(path: Option<PathBuf>, command: IndexCommand, force_unlock: bool) {
+    let path = path.unwrap_or_else(LibraryIndex::default_path);
+
+    if let IndexCommand::Status = command {
+        if !path.exists() {
+            println!("No index at {}", path.display());
+            return;
+        }
+        match LibraryIndex::inspect(&path) {
+            Ok(status) => {
+                println!("Index: {}", path.display());
+                println!("Schema version: {} (latest {})", status.schema_version, status.latest_version);
+                println!("Files: {}", status.file_count);
+                if status.offline_count > 0 {
+                    println!("Offline (drive not connected): {}", status.offline_count);
+                }
+                if let Some(owner) = IndexLock::owner(&path) {
+                    println!("Locked by: {}", owner.describe());
+                }
+                if status.integrity_errors.is_empty() {
+                    println!("Integrity: ok");
+                } else {
+                    println!("Integrity problems:");
+                    for error in &status.integrity_errors {
+                        println!("  {}", error);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Error reading index: {}", e),
+        }
+        return;
+    }
+
+    let Some(mut index) = open_index(&path, force_unlock) else { return };
+    println!("Using index: {}", index.path().display());
+
+    match command {
+        IndexCommand::Update { scan } => {
+            let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+            let scan_options = ScanOptions {
+                log,
+                // Every file is extracted again; that's what an update is for
+                index: None,
+                ..scan.to_scan_options()
+            };
+            let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
                 Ok(files) => files,
                 Err(e) => {
                     eprintln!("Error processing directory: {}", e);
                     return;
                 }
             };
+            match index.upsert_all(&files) {
+                Ok(report) => {
+                    println!("Indexed {} files ({} total in index)", report.indexed, index.len().unwrap_or(0));
+                    print_invalidated(&report.invalidated);
+                }
+                Err(e) => eprintln!("Error updating index: {}", e),
+            }
+        }
+        IndexCommand::Repair { vacuum } => {
+            match index.repair() {
+                Ok(report) => {
+                    println!("Removed {} records for missing files", report.missing_removed);
+                    println!("Marked {} records offline (drive not connected)", report.marked_offline);
+                    println!("Relocated {} records to their drive's new mount point", report.relocated);
+                    println!("Removed {} orphaned derived rows", report.orphans_removed);
+                    println!("Rebuilt {} duplicate buckets", report.buckets_rebuilt);
+                }
+                Err(e) => {
+                    eprintln!("Error repairing index: {}", e);
+                    return;
+                }
+            }
+            if vacuum {
+                if let Err(e) = index.vacuum() {
+                    eprintln!("Error compacting index: {}", e);
+                }
+            }
+        }
+        IndexCommand::Vacuum => match index.vacuum() {
+            Ok(()) => println!("Index compacted"),
+            Err(e) => eprintln!("Error compacting index: {}", e),
+        },
+        IndexCommand::Volumes => match index.volumes() {
+            Ok(volumes) if volumes.is_empty() => println!("No volumes recorded yet; run `index update` first"),
+            Ok(volumes) => {
+                for volume in volumes {
+                    let label = volume.label.as_deref().unwrap_or("(no label)");
+                    println!("{} [{}]", label, volume.id);
+                    println!("  Last mounted at: {}", volume.mount_point.display());
+                    println!("  Files: {} ({} offline)", volume.file_count, volume.offline_count);
+                }
+            }
+            Err(e) => eprintln!("Error reading volumes: {}", e),
+        },
+        IndexCommand::MarkVolume { dir, id } => {
+            match volumes::mark_volume(&dir, id.as_deref()) {
+                Ok(id) => println!("Marked {} as volume {}", dir.display(), id),
+                Err(e) => eprintln!("Error writing volume marker in {}: {}", dir.display(), e),
+            }
+        }
+        IndexCommand::Status => unreachable!("handled above"),
+    }
+}
 
-            println!("\nFound {} audio files", files.len());
+#[cfg(feature = "index")]
+fn search(query: &str, index_path: Option<PathBuf>, limit: usize, output: Option<&Path>, force_unlock: bool) {
+    let path = index_path.unwrap_or_else(LibraryIndex::default_path);
+    if !path.exists() {
+        eprintln!("No index at {}; run `index update` first", path.display());
+        return;
+    }
+    let Some(index) = open_index(&path, force_unlock) else { return };
 
-            if files.is_empty() {
-                println!("No audio files found to analyze.");
+    let started = std::time::Instant::now();
+    let hits = match index.search(query, limit) {
+        Ok(hits) => hits,
+        Err(e) => {
+            eprintln!("Error searching index: {}", e);
+            return;
+        }
+    };
+    let elapsed = started.elapsed();
+
+    for hit in &hits {
+        let file = &hit.file;
+        let name = match (&file.artist, &file.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            _ => file.file_name.clone(),
+        };
+        let album = file.album.as_deref().map_or(String::new(), |album| format!(" [{}]", album));
+        println!("{}{}", name, album);
+        println!("  {}{}", file.path.display(), if hit.offline { " (offline)" } else { "" });
+    }
+    println!("{} matches in {:.1} ms{}", hits.len(), elapsed.as_secs_f64() * 1000.0,
+        if hits.len() == limit { " (limit reached, use -n for more)" } else { "" });
+
+    if let Some(output) = output {
+        match serde_json::to_string_pretty(&hits) {
+            Ok(json) => match report_dir::write_atomic(output, json) {
+                Ok(()) => println!("Matches written to: {}", output.display()),
+                Err(e) => eprintln!("Error writing matches: {}", e),
+            },
+            Err(e) => eprintln!("Error serializing matches: {}", e),
+        }
+    }
+
+    if hits.is_empty() {
+        match index.near_misses(query, limit) {
+            Ok(near) if near.is_empty() => {}
+            Ok(near) => {
+                println!("Did you mean:");
+                for miss in near {
+                    let file = &miss.hit.file;
+                    println!("  {} ({} {})", miss.matched, miss.distance, if miss.distance == 1 { "edit" } else { "edits" });
+                    println!("    {}{}", file.path.display(), if miss.hit.offline { " (offline)" } else { "" });
+                }
+            }
+            Err(e) => eprintln!("Error looking for near misses: {}", e),
+        }
+    }
+}
+
+// Files that changed since they were indexed and will be analyzed again
+#[cfg(feature = "index")]
+fn print_invalidated(paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    println!("{} files changed since they were indexed; their cached analysis will be recomputed:", paths.len());
+    for path in paths {
+        println!("  {}", path.display());
+    }
+}
+
+#[cfg(feature = "index")]
+fn scrub(scan: &ScanOpts, index_path: Option<PathBuf>, recheck_days: u64, output: Option<&Path>, force_unlock: bool) {
+    println!("=== Scrubbing Library ===");
+    let path = index_path.unwrap_or_else(LibraryIndex::default_path);
+    let Some(mut index) = open_index(&path, force_unlock) else { return };
+    println!("Using index: {}", index.path().display());
+
+    let paths = MetadataExtractor::list_audio_files(&scan.input, &scan.to_scan_options());
+    let scrubber = Scrubber::new()
+        .with_recheck_after(Duration::from_secs(recheck_days * 24 * 60 * 60))
+        .with_dry_run(scan.dry_run);
+    let results = match scrubber.scrub(&mut index, &paths, &scan.input) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Error scrubbing: {}", e);
+            return;
+        }
+    };
+
+    for report in &results.reports {
+        match &report.status {
+            ScrubStatus::Corrupt { expected, actual } => {
+                println!("CORRUPT: {}", report.path.display());
+                println!("  expected {}", expected);
+                println!("  found    {}", actual);
+            }
+            ScrubStatus::Missing => println!("MISSING: {}", report.path.display()),
+            ScrubStatus::Unreadable { error } => println!("UNREADABLE: {} ({})", report.path.display(), error),
+            _ => {}
+        }
+    }
+
+    let corrupt = results.corrupt().count();
+    println!("\n=== Scrub Summary ===");
+    println!("Verified: {}", results.count(|s| *s == ScrubStatus::Verified));
+    println!("New checksums: {}", results.count(|s| *s == ScrubStatus::New));
+    println!("Modified since last scrub: {}", results.count(|s| *s == ScrubStatus::Modified));
+    println!("Skipped (verified recently): {}", results.count(|s| *s == ScrubStatus::Skipped));
+    println!("Missing: {}", results.count(|s| *s == ScrubStatus::Missing));
+    println!("Unreadable: {}", results.count(|s| matches!(s, ScrubStatus::Unreadable { .. })));
+    println!("Corrupt: {}", corrupt);
+    if scan.dry_run {
+        println!("(dry run: checksums not recorded)");
+    }
+
+    if let Some(output) = output {
+        let json = match serde_json::to_string_pretty(&results) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Error serializing scrub results: {}", e);
+                return;
+            }
+        };
+        match report_dir::write_atomic(output, json) {
+            Ok(()) => println!("\nScrub results saved to: {}", output.display()),
+            Err(e) => eprintln!("Error writing scrub results: {}", e),
+        }
+    }
+
+    if corrupt > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "index")]
+fn phrasing(scan: &ScanOpts, index_path: Option<PathBuf>, reanalyze: bool, analyze_secs: u64, output: Option<&Path>, force_unlock: bool) {
+    println!("=== Estimating Phrasing ===");
+    let path = index_path.unwrap_or_else(LibraryIndex::default_path);
+    let Some(mut index) = open_index(&path, force_unlock) else { return };
+    println!("Using index: {}", index.path().display());
+
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        // This run already holds the index open
+        index: None,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+    // Phrasing records hang off the files table
+    match index.upsert_all(&files) {
+        Ok(report) => print_invalidated(&report.invalidated),
+        Err(e) => {
+            eprintln!("Error updating index: {}", e);
+            return;
+        }
+    }
+
+    let pending = if reanalyze {
+        files.iter().collect()
+    } else {
+        match index.needs_phrasing(&files) {
+            Ok(pending) => pending,
+            Err(e) => {
+                eprintln!("Error reading index: {}", e);
                 return;
             }
+        }
+    };
+    println!("\n{} of {} files need analyzing", pending.len(), files.len());
 
-            println!("\nAnalyzing bitrates...");
-            let analyzer = BitrateAnalyzer::new();
-            let stats = analyzer.analyze(&files);
+    let analyzer = PhraseAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let records: Vec<PhrasingRecord> = analyzer.analyze(&pending)
+        .into_iter()
+        .map(|analysis| {
+            let regular = analysis.is_regular();
+            PhrasingRecord::new(analysis.path, analysis.bpm, analysis.phrase_bars, analysis.regularity, regular)
+        })
+        .collect();
+    if let Err(e) = index.store_phrasing(&records) {
+        eprintln!("Error storing phrasing: {}", e);
+        return;
+    }
+
+    let scanned: HashSet<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+    let results: Vec<PhrasingRecord> = match index.phrasing() {
+        Ok(all) => all.into_iter().filter(|record| scanned.contains(record.path.as_path())).collect(),
+        Err(e) => {
+            eprintln!("Error reading index: {}", e);
+            return;
+        }
+    };
+
+    let irregular: Vec<&PhrasingRecord> = results.iter().filter(|record| record.regular == Some(false)).collect();
+    println!("\n{} of {} tracks don't follow 16/32-bar phrasing", irregular.len(), results.len());
+    for record in &irregular {
+        println!("  {} ({}% on 16-bar lines{})",
+            record.path.display(),
+            record.regularity.unwrap_or_default(),
+            record.phrase_bars.map_or(String::new(), |bars| format!(", {}-bar phrases", bars)));
+    }
+    let unknown = results.iter().filter(|record| record.regular.is_none()).count();
+    if unknown > 0 {
+        println!("{} tracks have too little structure or no tempo to judge", unknown);
+    }
+
+    if let Some(output) = output {
+        match serde_json::to_string_pretty(&results) {
+            Ok(json) => match report_dir::write_atomic(output, json) {
+                Ok(()) => println!("\nPhrasing saved to: {}", output.display()),
+                Err(e) => eprintln!("Error writing phrasing: {}", e),
+            },
+            Err(e) => eprintln!("Error serializing phrasing: {}", e),
+        }
+    }
+
+    println!("\n=== Phrasing Estimate Complete ===");
+}
+
+#[cfg(feature = "index")]
+fn key(scan: &ScanOpts, output: &Path, index_path: Option<PathBuf>, reanalyze: bool, analyze_secs: u64, report: &ReportOpts, force_unlock: bool) {
+    println!("=== Detecting Keys ===");
+    let path = index_path.unwrap_or_else(LibraryIndex::default_path);
+    let Some(mut index) = open_index(&path, force_unlock) else { return };
+    println!("Using index: {}", index.path().display());
+
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        // This run already holds the index open
+        index: None,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+    // Key records hang off the files table
+    match index.upsert_all(&files) {
+        Ok(report) => print_invalidated(&report.invalidated),
+        Err(e) => {
+            eprintln!("Error updating index: {}", e);
+            return;
+        }
+    }
 
-            println!("\nGenerating reports...");
-            let reporter = Reporter::new();
-            match reporter.generate_bitrate_report(&stats, &files, &output) {
-                Ok(_) => println!("Reports generated successfully."),
-                Err(e) => eprintln!("Error generating reports: {}", e),
+    let pending = if reanalyze {
+        files.iter().collect()
+    } else {
+        match index.needs_key(&files) {
+            Ok(pending) => pending,
+            Err(e) => {
+                eprintln!("Error reading index: {}", e);
+                return;
             }
+        }
+    };
+    println!("\n{} of {} files need analyzing", pending.len(), files.len());
+
+    let analyzer = KeyAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let records: Vec<KeyRecord> = analyzer.analyze(&pending)
+        .into_iter()
+        .map(|report| KeyRecord::new(report.path, report.key, report.confidence))
+        .collect();
+    if let Err(e) = index.store_keys(&records) {
+        eprintln!("Error storing keys: {}", e);
+        return;
+    }
+
+    let mut stored: HashMap<PathBuf, KeyRecord> = match index.keys() {
+        Ok(all) => all.into_iter().map(|record| (record.path.clone(), record)).collect(),
+        Err(e) => {
+            eprintln!("Error reading index: {}", e);
+            return;
+        }
+    };
+    let reports: Vec<KeyReport> = files.iter()
+        .filter_map(|file| {
+            let record = stored.remove(&file.path)?;
+            Some(KeyReport {
+                path: record.path,
+                key: record.key,
+                confidence: record.confidence,
+                tag_key: file.key.as_deref().and_then(MusicalKey::parse),
+            })
+        })
+        .collect();
+
+    let count = |status: KeyStatus| reports.iter().filter(|r| r.status() == status).count();
+    println!("\n{} detected without a tag, {} matching their tag, {} relative to it, {} differing from it, {} without a clear key",
+        count(KeyStatus::Detected), count(KeyStatus::MatchesTag), count(KeyStatus::RelativeOfTag),
+        count(KeyStatus::DiffersFromTag), count(KeyStatus::NoKey));
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_key_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
 
-            println!("\n=== Bitrate Analysis Complete ===");
+    println!("\n=== Key Detection Complete ===");
+}
+
+#[cfg(feature = "artwork")]
+fn artwork(scan: &ScanOpts, output: &Path, min_size: u32, max_bytes: usize, resize: Option<u32>, report: &ReportOpts) {
+    println!("=== Starting Artwork Audit ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nReading embedded artwork...");
+    let analyzer = ArtworkAnalyzer::new()
+        .with_min_dimension(min_size)
+        .with_max_bytes(max_bytes);
+    let reports = analyzer.analyze(&files);
+
+    let count = |matches: fn(&ArtworkFlag) -> bool| reports.iter().filter(|r| r.flags.iter().any(matches)).count();
+    println!("\nChecked {} files:", reports.len());
+    println!("  Without cover: {}", count(|f| matches!(f, ArtworkFlag::Missing)));
+    println!("  Cover below {}x{}: {}", min_size, min_size, count(|f| matches!(f, ArtworkFlag::TooSmall { .. })));
+    println!("  Cover above {} KB: {}", max_bytes / 1024, count(|f| matches!(f, ArtworkFlag::Oversized { .. })));
+
+    if let Some(max_dimension) = resize {
+        println!("\n{} oversized covers to fit within {}x{}...",
+            if scan.dry_run { "Would shrink" } else { "Shrinking" }, max_dimension, max_dimension);
+        let oversized = reports.iter()
+            .filter(|r| r.flags.iter().any(|f| matches!(f, ArtworkFlag::Oversized { .. })));
+        for report in oversized {
+            let shrunk = artwork::read_cover(&report.path).and_then(|cover| match cover {
+                Some(cover) => artwork::shrink_cover(&cover, max_dimension, max_bytes),
+                None => Ok(None),
+            });
+            match shrunk {
+                Ok(Some(data)) if scan.dry_run => {
+                    println!("  Would re-embed {} KB cover: {}", data.len() / 1024, report.path.display());
+                }
+                Ok(Some(data)) => match artwork::embed_cover(&report.path, &data, "image/jpeg") {
+                    Ok(()) => println!("  Re-embedded {} KB cover: {}", data.len() / 1024, report.path.display()),
+                    Err(e) => eprintln!("  Error re-embedding cover: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => eprintln!("  Error shrinking cover of {}: {}", report.path.display(), e),
+            }
         }
     }
-}
\ No newline at end of file
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_artwork_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    println!("\n=== Artwork Audit Complete ===");
+}
+
+#[cfg(feature = "artwork")]
+fn artwork_extract(scan: &ScanOpts, file_name: &str, overwrite: bool) {
+    println!("=== Extracting Folder Covers ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!();
+    let extractor = CoverExtractor::new(file_name)
+        .with_overwrite(overwrite)
+        .with_dry_run(scan.dry_run);
+    let covers: Vec<_> = extractor.extract(&files).into_iter()
+        .filter_map(|result| result.map_err(|e| eprintln!("  Error extracting cover: {}", e)).ok())
+        .collect();
+    for cover in &covers {
+        match cover.status {
+            FolderCoverStatus::Written => println!("  Written: {}", cover.destination.display()),
+            FolderCoverStatus::Planned => println!("  Would write: {}", cover.destination.display()),
+            FolderCoverStatus::Exists | FolderCoverStatus::NoArtwork => {}
+        }
+    }
+
+    let count = |status: FolderCoverStatus| covers.iter().filter(|c| c.status == status).count();
+    if scan.dry_run {
+        println!("\nWould write {} covers", count(FolderCoverStatus::Planned));
+    } else {
+        println!("\nWrote {} covers", count(FolderCoverStatus::Written));
+    }
+    println!("  Folders that already had {}: {}", file_name, count(FolderCoverStatus::Exists));
+    println!("  Folders without embedded artwork: {}", count(FolderCoverStatus::NoArtwork));
+    println!("\n=== Folder Cover Extraction Complete ===");
+}
+
+fn authenticity(scan: &ScanOpts, output: &Path, analyze_secs: u64, report: &ReportOpts) {
+    println!("=== Starting Hi-Res Authenticity Check ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nAnalyzing audio content...");
+    let analyzer = AuthenticityAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let reports = analyzer.analyze(&files);
+
+    let suspicious: Vec<_> = reports.iter().filter(|r| r.is_suspicious()).collect();
+    println!("\n{} of {} checked files look like upscaled or padded masters", suspicious.len(), reports.len());
+    for report in &suspicious {
+        println!("  {}", report.path.display());
+        for flag in &report.flags {
+            println!("    {}", flag);
+        }
+    }
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_authenticity_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    println!("\n=== Authenticity Check Complete ===");
+}
+
+// Fills in each file's tempo stability and lists the tracks that need
+// manual gridding
+fn measure_tempo_stability(files: &mut [AudioFile], analyze_secs: u64) {
+    let analyzer = TempoAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let reports: HashMap<PathBuf, TempoReport> = analyzer.analyze(files)
+        .into_iter()
+        .map(|report| (report.path.clone(), report))
+        .collect();
+    for file in files.iter_mut() {
+        file.tempo_stability = reports.get(&file.path).and_then(|report| report.stability);
+    }
+
+    let mut variable: Vec<&TempoReport> = reports.values().filter(|report| report.needs_manual_grid()).collect();
+    variable.sort_by(|a, b| a.path.cmp(&b.path));
+    println!("\n{} of {} tracks have a tempo that drifts off a static beatgrid", variable.len(), reports.len());
+    for report in variable {
+        println!("  {} ({:.1}% on grid, drifts up to {} ms)",
+            report.path.display(),
+            report.stability.unwrap_or_default(),
+            report.max_drift_ms.unwrap_or_default()
+        );
+    }
+}
+
+// Moves each kept copy into the place of the copy it replaced when that one
+// sat on a more preferred root. Files that are themselves being removed as
+// a lower-quality copy elsewhere stay put.
+// Renames "Title - Artist" files to "Artist - Title" where they are, and
+// points the scanned records at the new names. Returns the renames made
+// (planned, on a dry run).
+fn rename_swapped_files(files: &mut [AudioFile], parser: &TitleParser, executor: &FileOperationExecutor) -> Vec<FileOperation> {
+    println!("\nFixing swapped artist/title file names...");
+    let mut renamed = Vec::new();
+    for file in files.iter_mut() {
+        let Some(name) = parser.normalized_file_name(file) else { continue };
+        let operation = FileOperation::Move { from: file.path.clone(), to: file.path.with_file_name(&name) };
+        match executor.execute(&operation) {
+            Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                let to = op.destination().unwrap_or(&file.path).to_path_buf();
+                println!("  {}: {} -> {}",
+                    if executor.is_dry_run() { "Would rename" } else { "Renamed" },
+                    file.file_name,
+                    to.file_name().unwrap_or_default().to_string_lossy());
+                if !executor.is_dry_run() {
+                    file.file_name = to.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    file.path = to;
+                }
+                renamed.push(op);
+            }
+            Ok(OperationOutcome::Skipped { reason, .. }) => println!("  Skipped {}: {}", file.file_name, reason),
+            Err(e) => eprintln!("  Error renaming {}: {}", file.file_name, e),
+        }
+    }
+    println!("{} swapped file names {}", renamed.len(), if executor.is_dry_run() { "would be fixed" } else { "fixed" });
+    renamed
+}
+
+fn relocate_best_copies(
+    results: &DuplicateResults,
+    priority: &RootPriority,
+    executor: &FileOperationExecutor,
+    removed: &HashSet<&Path>,
+) -> Vec<FileOperation> {
+    println!("\nRelocating kept copies to preferred roots...");
+    let mut operations = Vec::new();
+    let mut relocated = HashSet::new();
+
+    for dup_match in results.matches() {
+        let Some(target) = priority.relocation_target(dup_match) else { continue };
+        let keep = dup_match.higher_quality.path.as_path();
+        if !removed.contains(dup_match.lower_quality.path.as_path()) || !relocated.insert(keep) {
+            continue;
+        }
+
+        let operation = FileOperation::Move { from: keep.to_path_buf(), to: target };
+        match executor.execute(&operation) {
+            Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                println!("  {}: {} -> {}",
+                    if executor.is_dry_run() { "Would relocate" } else { "Relocated" },
+                    keep.display(),
+                    op.destination().unwrap_or(keep).display());
+                operations.push(op);
+            }
+            Ok(OperationOutcome::Skipped { reason, .. }) => println!("  Skipped {}: {}", keep.display(), reason),
+            Err(e) => eprintln!("  Error relocating {}: {}", keep.display(), e),
+        }
+    }
+    operations
+}
+
+fn cues(scan: &ScanOpts, output: &Path, rekordbox_xml: Option<&Path>, traktor_nml: Option<&Path>, serato: bool, analyze_secs: u64) {
+    println!("=== Suggesting Hot Cues ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nAnalyzing track structure...");
+    let analyzer = StructureAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let suggestions = analyzer.analyze(&files);
+
+    for track in &suggestions {
+        let cues: Vec<String> = track.cues.iter()
+            .map(|cue| format!("{} {}:{:04.1}", cue.kind.label(), (cue.position_secs / 60.0) as u64, cue.position_secs % 60.0))
+            .collect();
+        println!("  {}: {}", track.path.display(), if cues.is_empty() { "no structure found".to_string() } else { cues.join(", ") });
+    }
+
+    match serde_json::to_string_pretty(&suggestions) {
+        Ok(json) => match report_dir::write_atomic(output, json) {
+            Ok(()) => println!("\nSuggestions saved to: {}", output.display()),
+            Err(e) => eprintln!("Error writing suggestions: {}", e),
+        },
+        Err(e) => eprintln!("Error serializing suggestions: {}", e),
+    }
+
+    if let Some(xml) = rekordbox_xml {
+        match rekordbox::write_cue_collection(xml, &suggestions) {
+            Ok(()) => println!("Rekordbox collection saved to: {}", xml.display()),
+            Err(e) => eprintln!("Error writing Rekordbox collection: {}", e),
+        }
+    }
+
+    if let Some(nml) = traktor_nml {
+        match traktor::write_cue_collection(nml, &suggestions) {
+            Ok(()) => println!("Traktor collection saved to: {}", nml.display()),
+            Err(e) => eprintln!("Error writing Traktor collection: {}", e),
+        }
+    }
+
+    if serato {
+        write_serato_cues(&suggestions, scan.dry_run);
+    }
+
+    println!("\n=== Cue Suggestion Complete ===");
+}
+
+#[cfg(feature = "tags")]
+fn write_serato_cues(suggestions: &[TrackCues], dry_run: bool) {
+    println!("\n{} Serato markers...", if dry_run { "Checking" } else { "Writing" });
+    let (mut written, mut kept) = (0, 0);
+    let (writable, unsupported): (Vec<&TrackCues>, Vec<&TrackCues>) = suggestions.iter()
+        .filter(|track| !track.cues.is_empty())
+        .partition(|track| {
+            track.path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "mp3" | "flac"))
+        });
+    for track in writable {
+        if dry_run {
+            println!("  Would write {} cues to {}", track.cues.len(), track.path.display());
+            continue;
+        }
+        match serato::write_cue_markers(&track.path, &track.cues) {
+            Ok(true) => written += 1,
+            Ok(false) => kept += 1,
+            Err(e) => eprintln!("  Error writing Serato markers: {}", e),
+        }
+    }
+    if !dry_run {
+        println!("Serato markers written to {} files; {} files already had markers and were left alone", written, kept);
+    }
+    if !unsupported.is_empty() {
+        println!("{} files are not MP3 or FLAC and were skipped", unsupported.len());
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+fn write_serato_cues(_suggestions: &[TrackCues], _dry_run: bool) {
+    eprintln!("Writing Serato markers needs a build with the `tags` feature");
+}
+
+fn explicit(scan: &ScanOpts, output: &Path, transcriber: Option<&str>, wordlist: Option<&Path>, clean: Option<&Path>, strict: bool, report: &ReportOpts) {
+    println!("=== Checking Explicit Content ===");
+    let mut analyzer = ExplicitAnalyzer::new();
+    if let Some(command) = transcriber {
+        match Transcriber::parse(command) {
+            Ok(transcriber) => analyzer = analyzer.with_transcriber(transcriber),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return;
+            }
+        }
+    }
+    if let Some(wordlist) = wordlist {
+        match WordList::from_file(wordlist) {
+            Ok(words) => {
+                println!("Using {} words from {}", words.len(), wordlist.display());
+                analyzer = analyzer.with_words(words);
+            }
+            Err(e) => {
+                eprintln!("Error reading word list: {}", e);
+                return;
+            }
+        }
+    }
+
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nReading advisory tags{}...", if transcriber.is_some() { " and transcribing untagged tracks" } else { "" });
+    let reports = analyzer.analyze(&files);
+    let count = |status: ExplicitStatus| reports.iter().filter(|r| r.status == status).count();
+    println!("\n{} explicit, {} clean, {} unknown",
+        count(ExplicitStatus::Explicit), count(ExplicitStatus::Clean), count(ExplicitStatus::Unknown));
+    for report in reports.iter().filter(|r| r.status == ExplicitStatus::Explicit) {
+        if report.matched_words.is_empty() {
+            println!("  {}", report.path.display());
+        } else {
+            println!("  {} ({})", report.path.display(), report.matched_words.join(", "));
+        }
+    }
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_explicit_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    if let Some(playlist_path) = clean {
+        // Files whose check failed have no report and are left out too
+        let statuses: HashMap<&Path, ExplicitStatus> = reports.iter().map(|r| (r.path.as_path(), r.status)).collect();
+        let playlist = Playlist::from_files(files.iter().filter(|file| match statuses.get(file.path.as_path()) {
+            Some(ExplicitStatus::Clean) => true,
+            Some(ExplicitStatus::Unknown) => !strict,
+            _ => false,
+        }));
+        match playlist.save(playlist_path) {
+            Ok(()) => println!("Clean playlist ({} tracks) saved to: {}", playlist.len(), playlist_path.display()),
+            Err(e) => eprintln!("Error writing playlist: {}", e),
+        }
+    }
+
+    println!("\n=== Explicit Content Check Complete ===");
+}
+
+fn bitrate(
+    scan: &ScanOpts,
+    output: &Path,
+    // Seconds of audio to measure tempo stability over, when asked to
+    tempo_stability: Option<u64>,
+    groupings: &[Grouping],
+    policy_path: Option<&Path>,
+    quarantine: Option<&Path>,
+    report: &ReportOpts,
+) {
+    println!("=== Starting Bitrate Analysis ===");
+    let policy = match policy_path.map(QualityPolicy::load).transpose() {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    for input in &scan.input {
+        println!("Analyzing bitrates in directory: {}", input.display());
+    }
+
+    let mut index = RunIndex::new("bitrate", &scan.input);
+    let round2 = |value: f64| (value * 100.0).round() / 100.0;
+    let run = match ReportRun::create(output, "bitrate") {
+        Ok(run) => run,
+        Err(e) => {
+            eprintln!("Error creating report folder: {}", e);
+            return;
+        }
+    };
+    println!("Report folder: {}", run.dir().display());
+
+    println!("\nScanning for audio files...");
+    let log_path = scan.jsonl_log.clone().unwrap_or_else(|| run.path("scan.jsonl"));
+    let Ok(log) = open_scan_log(Some(log_path.clone())) else { return };
+    let scan_options = ScanOptions {
+        log: log.clone(),
+        ..scan.to_scan_options()
+    };
+    let mut files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nFound {} audio files", files.len());
+    index.add_metric("files_scanned", "Files scanned", files.len());
+
+    // Files recovered on a retry pass aren't errors; for the others the last
+    // attempt's error is the one that counts
+    let mut reported: HashSet<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+    let mut failures: Vec<(PathBuf, String)> = log.as_ref().map(|log| log.failures()).unwrap_or_default()
+        .into_iter()
+        .rev()
+        .filter(|(path, _)| reported.insert(path.clone()))
+        .collect();
+    failures.reverse();
+
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if files.is_empty() {
+        println!("No audio files found to analyze.");
+    } else {
+        println!("\nAnalyzing bitrates...");
+        let analyzer = BitrateAnalyzer::new();
+        let mut stats = analyzer.analyze(&files);
+        // Files the scan couldn't read have no bitrate either
+        if !failures.is_empty() {
+            stats.unknown_causes.insert(UnknownBitrate::ProbeFailed, failures.len());
+        }
+
+        if let Some(analyze_secs) = tempo_stability {
+            println!("\nMeasuring tempo stability...");
+            measure_tempo_stability(&mut files, analyze_secs);
+        }
+
+        index.add_metric("total_size_mb", "Total size (MB)", round2(stats.total_size_bytes as f64 / 1_048_576.0));
+        index.add_metric("average_bitrate_kbps", "Average bitrate (kbps)", round2(stats.average_bitrate));
+        index.add_metric("min_bitrate_kbps", "Min bitrate (kbps)", stats.min_bitrate);
+        index.add_metric("max_bitrate_kbps", "Max bitrate (kbps)", stats.max_bitrate);
+        let violations = policy.as_ref().unwrap_or(&QualityPolicy::default()).evaluate(&files, |file| {
+            tags::read_tags(&file.path).ok().and_then(|tags| tags.get("Genre").cloned())
+        });
+        let label = match policy {
+            Some(_) => "Files below their quality policy".to_string(),
+            None => format!("Files below {} kbps", LOW_QUALITY_KBPS),
+        };
+        index.add_metric("low_quality_files", &label, violations.len());
+        if policy.is_some() {
+            println!("\n{} files are below the quality their folder or genre requires", violations.len());
+        }
+
+        println!("\nGenerating reports...");
+        let result = reporter.generate_bitrate_report(&stats, &files, run.dir()).and_then(|paths| {
+            index.add_artifacts("Bitrate report", &paths);
+            for grouping in groupings {
+                let paths = reporter.generate_group_report(&files, *grouping, run.dir())?;
+                index.add_artifacts(&format!("Quality by {}", grouping.as_str().to_lowercase()), &paths);
+            }
+            if policy.is_some() {
+                let paths = reporter.generate_policy_report(&violations, run.path("policy.csv"))?;
+                index.add_artifacts("Below quality policy", &paths);
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => println!("Reports generated successfully."),
+            Err(e) => eprintln!("Error generating reports: {}", e),
+        }
+    }
+
+    match reporter.generate_scan_error_report(&failures, run.path("errors.csv")) {
+        Ok(paths) => index.add_artifacts("Scan errors", &paths),
+        Err(e) => eprintln!("Error generating scan error report: {}", e),
+    }
+
+    let placeholders = log.as_ref().map(|log| log.placeholders()).unwrap_or_default();
+    if !placeholders.is_empty() {
+        println!("\n{} placeholder-size files (under {} bytes) were left out of the analysis", placeholders.len(), PLACEHOLDER_BYTES);
+        let placeholders = match quarantine {
+            Some(dir) => quarantine_placeholders(&placeholders, dir, scan.dry_run),
+            None => placeholders.iter().map(|(path, size)| (path.clone(), *size, None)).collect(),
+        };
+        match reporter.generate_placeholder_report(&placeholders, run.path("placeholders.csv")) {
+            Ok(paths) => index.add_artifacts("Placeholder files", &paths),
+            Err(e) => eprintln!("Error generating placeholder file report: {}", e),
+        }
+    }
+    index.add_artifacts("Scan log", &[log_path]);
+
+    index.add_metric("scan_errors", "Files that couldn't be read", failures.len());
+    index.add_metric("placeholder_files", "Placeholder-size files", placeholders.len());
+    match index.write(run.dir()) {
+        Ok(paths) => {
+            for path in paths {
+                println!("Run summary written: {}", path.display());
+            }
+        }
+        Err(e) => eprintln!("Error writing run summary: {}", e),
+    }
+
+    match run.mark_latest() {
+        Ok(latest) => println!("Latest report: {}", latest.display()),
+        Err(e) => eprintln!("Error updating latest report link: {}", e),
+    }
+
+    println!("\n=== Bitrate Analysis Complete ===");
+}
+
+// Scans once and hands the files from step to step; duplicates moved out by
+// one step are gone for the steps after it, on a dry run too
+fn run_routine(routine: &Routine, scan: &ScanOpts, output: &Path, policy_path: Option<&Path>, approval_path: Option<&Path>, report: &ReportOpts) {
+    println!("=== Starting Run: {} ===", routine);
+    let policy = match policy_path.map(QualityPolicy::load).transpose() {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let Some(approval) = load_approval_list(approval_path) else { return };
+    for input in &scan.input {
+        println!("Input directory: {}", input.display());
+    }
+    println!("Dry run mode: {}", scan.dry_run);
+
+    let mut index = RunIndex::new("run", &scan.input);
+    let run = match routine.has(RoutineStep::Report).then(|| ReportRun::create(output, "run")).transpose() {
+        Ok(run) => run,
+        Err(e) => {
+            eprintln!("Error creating report folder: {}", e);
+            return;
+        }
+    };
+    if let Some(run) = &run {
+        println!("Report folder: {}", run.dir().display());
+    }
+
+    println!("\nScanning for audio files...");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let phases = Arc::new(PhaseTimer::new());
+    let mut scan_options = ScanOptions {
+        log: log.clone(),
+        phases: Some(Arc::clone(&phases)),
+        ..scan.to_scan_options()
+    };
+    if let Some(input) = scan.input.iter().find(|input| options::is_within(output, input)) {
+        println!("Warning: output directory {} is inside input directory {}; leaving it out of the scan",
+            output.display(), input.display());
+        scan_options.exclude_dirs.push(options::resolve(output));
+    }
+    let mut files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+    let scanned = files.len();
+    println!("\nFound {} audio files", scanned);
+    index.add_metric("files_scanned", "Files scanned", scanned);
+    if files.is_empty() {
+        println!("No audio files found to analyze.");
+        return;
+    }
+
+    // What the steps found, for the report step
+    let mut quality = None;
+    let mut duplicates = None;
+    for step in &routine.steps {
+        match step {
+            RoutineStep::Scan => {}
+            RoutineStep::Quality => {
+                println!("\n--- Quality ---");
+                let (stats, violations) = phases.time(progress::ANALYZING, || {
+                    let stats = BitrateAnalyzer::new().analyze(&files);
+                    let violations = policy.as_ref().unwrap_or(&QualityPolicy::default()).evaluate(&files, |file| {
+                        tags::read_tags(&file.path).ok().and_then(|tags| tags.get("Genre").cloned())
+                    });
+                    (stats, violations)
+                });
+                let label = match policy {
+                    Some(_) => "Files below their quality policy".to_string(),
+                    None => format!("Files below {} kbps", LOW_QUALITY_KBPS),
+                };
+                println!("\n{}: {}", label, violations.len());
+                index.add_metric("average_bitrate_kbps", "Average bitrate (kbps)", (stats.average_bitrate * 100.0).round() / 100.0);
+                index.add_metric("low_quality_files", &label, violations.len());
+                quality = Some((stats, violations, files.clone()));
+            }
+            RoutineStep::Duplicates => {
+                println!("\n--- Duplicates ---");
+                let Some(ignored) = load_ignored_pairs() else { return };
+                let analyzer = DuplicateAnalyzer::new(1.0)
+                    .with_known_artists(KnownArtists::from_files(&files))
+                    .with_ignored_pairs(ignored);
+                let results = phases.time(progress::MATCHING, || analyzer.find_duplicates(files.clone()));
+                println!("{} copies to remove", results.duplicate_count());
+                index.add_metric("duplicate_groups", "Duplicate groups", results.groups.len());
+                index.add_metric("duplicate_files", "Duplicate copies", results.duplicate_count());
+                let removed = phases.time(progress::MOVING,
+                    || move_routine_duplicates(&results, approval.as_ref(), &output.join("duplicates"), scan.dry_run));
+                index.add_metric(if scan.dry_run { "would_move" } else { "moved" },
+                    if scan.dry_run { "Files that would be moved" } else { "Files moved" }, removed.len());
+                files.retain(|file| !removed.contains(&file.path));
+                duplicates = Some(results);
+            }
+            RoutineStep::Report => {
+                let Some(run) = &run else { continue };
+                println!("\n--- Report ---");
+                let reporter = Reporter::new().with_options(ReportOptions::from(report));
+                if let Some((stats, violations, files)) = &quality {
+                    match reporter.generate_bitrate_report(stats, files, run.dir()) {
+                        Ok(paths) => index.add_artifacts("Bitrate report", &paths),
+                        Err(e) => eprintln!("Error generating bitrate report: {}", e),
+                    }
+                    if policy.is_some() {
+                        match reporter.generate_policy_report(violations, run.path("policy.csv")) {
+                            Ok(paths) => index.add_artifacts("Below quality policy", &paths),
+                            Err(e) => eprintln!("Error generating policy report: {}", e),
+                        }
+                    }
+                }
+                if let Some(results) = &duplicates {
+                    match reporter.generate_duplicate_report(results, run.path("duplicate_report.csv")) {
+                        Ok(paths) => index.add_artifacts("Duplicate report", &paths),
+                        Err(e) => eprintln!("Error generating duplicate report: {}", e),
+                    }
+                }
+                if let Some(log) = &log {
+                    index.add_artifacts("Scan log", &[log.path().to_path_buf()]);
+                }
+                add_timing_metrics(&mut index, &phases, scanned);
+                match index.write(run.dir()) {
+                    Ok(paths) => {
+                        for path in paths {
+                            println!("Run summary written: {}", path.display());
+                        }
+                    }
+                    Err(e) => eprintln!("Error writing run summary: {}", e),
+                }
+                match run.mark_latest() {
+                    Ok(latest) => println!("Latest report: {}", latest.display()),
+                    Err(e) => eprintln!("Error updating latest report link: {}", e),
+                }
+            }
+        }
+    }
+
+    println!("\n=== Run Complete: {} ===", phases.summary(scanned));
+}
+
+// Moves every copy but the kept one of each group into `dir`. Returns the
+// library paths moved, or that would be on a dry run.
+fn move_routine_duplicates(results: &DuplicateResults, approval: Option<&ApprovalList>, dir: &Path, dry_run: bool) -> HashSet<PathBuf> {
+    let executor = match FileOperationExecutor::new().with_dry_run(dry_run).with_command_journal("run") {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return HashSet::new();
+        }
+    };
+    let file_manager = FileManager::with_executor(dir, executor);
+    if !dry_run {
+        if let Err(e) = file_manager.ensure_directory(dir) {
+            eprintln!("Error creating duplicates directory: {}", e);
+            return HashSet::new();
+        }
+    }
+
+    let (approved, held): (Vec<&DuplicateMatch>, Vec<&DuplicateMatch>) = results.matches()
+        .partition(|m| approval.is_none_or(|list| list.approves(m)));
+    for dup_match in &held {
+        println!("  Report only ({} match): {}", dup_match.match_class.as_str(), dup_match.lower_quality.path.display());
+    }
+    let lower_paths: Vec<&Path> = approved.iter().map(|m| m.lower_quality.path.as_path()).collect();
+    let mut removed = HashSet::new();
+    for (path, result) in lower_paths.iter().zip(file_manager.move_duplicates(&lower_paths)) {
+        match result {
+            Ok(new_path) => {
+                println!("  {}: {} -> {}", if dry_run { "Would move" } else { "Moved" }, path.display(), new_path.display());
+                removed.insert(path.to_path_buf());
+            }
+            Err(e) => eprintln!("  Error moving file {}: {}", path.display(), e),
+        }
+    }
+    if let Some(journal) = file_manager.executor().journal_path() {
+        println!("Operation journal: {}", journal.display());
+    }
+    removed
+}
+
+// Moves placeholder-size files into `dir`, journaled like any other move.
+// Returns every file with its new location, or None where it stayed.
+fn quarantine_placeholders(placeholders: &[(PathBuf, u64)], dir: &Path, dry_run: bool) -> Vec<(PathBuf, u64, Option<PathBuf>)> {
+    println!("\nQuarantining placeholder files to {}...", dir.display());
+    let unmoved = || placeholders.iter().map(|(path, size)| (path.clone(), *size, None)).collect();
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(dry_run)
+        .with_collision_policy(CollisionPolicy::Rename)
+        .with_rename_label("placeholder");
+    let executor = match executor.with_command_journal("placeholders") {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return unmoved();
+        }
+    };
+    if let Err(e) = executor.ensure_directory(dir) {
+        eprintln!("Error creating quarantine directory: {}", e);
+        return unmoved();
+    }
+
+    let quarantined = placeholders.iter()
+        .map(|(path, size)| {
+            let operation = FileOperation::Move { from: path.clone(), to: dir.join(path.file_name().unwrap_or_default()) };
+            let moved_to = match executor.execute(&operation) {
+                Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                    let to = op.destination().map(Path::to_path_buf);
+                    println!("  {}: {} -> {}",
+                        if dry_run { "Would move" } else { "Moved" },
+                        path.display(),
+                        to.as_deref().unwrap_or(dir).display());
+                    to
+                }
+                Ok(OperationOutcome::Skipped { reason, .. }) => {
+                    println!("  Skipped {}: {}", path.display(), reason);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("  Error moving {}: {}", path.display(), e);
+                    None
+                }
+            };
+            (path.clone(), *size, moved_to)
+        })
+        .collect();
+    if let Some(journal) = executor.journal_path() {
+        println!("Operation journal: {}", journal.display());
+    }
+    quarantined
+}
+
+fn dirty_only(scan: &ScanOpts, output: &Path, report: &ReportOpts) {
+    println!("=== Finding Dirty-Only Tracks ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let tracks = pool::find_dirty_only(&files, &TitleParser::new());
+    println!("\n{} tracks only have a dirty version:", tracks.len());
+    for track in &tracks {
+        println!("  {} - {}", track.artist, track.title);
+        for version in &track.versions {
+            println!("    {}", version.path.display());
+        }
+    }
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_dirty_only_report(&tracks, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    println!("\n=== Dirty-Only Check Complete ===");
+}
+
+fn wishlist(scan: &ScanOpts, wishlist_path: &Path, output: &Path, report: &ReportOpts) {
+    println!("=== Checking Wish-List Against the Library ===");
+    let entries = match wishlist::read_wishlist(wishlist_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading wish-list: {}", e);
+            return;
+        }
+    };
+    println!("Wish-list has {} tracks", entries.len());
+
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let matches = WishlistMatcher::new(&files).check_all(&entries);
+    let count = |status| matches.iter().filter(|m| m.status == status).count();
+    println!("\n{} already in the library, {} only in another version, {} to buy",
+        count(WishStatus::Owned), count(WishStatus::OtherVersion), count(WishStatus::Missing));
+    for wish in matches.iter().filter(|m| m.status == WishStatus::OtherVersion) {
+        if let Some(path) = &wish.library_path() {
+            println!("  {} - {}: have {}", wish.entry.artist, wish.entry.title, path.display());
+        }
+    }
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_wishlist_report(&matches, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    println!("\n=== Wish-List Check Complete ===");
+}
+
+fn vocals(scan: &ScanOpts, output: &Path, tag: bool, instrumental: Option<&Path>, vocal: Option<&Path>, analyze_secs: u64, report: &ReportOpts) {
+    println!("=== Detecting Vocals ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nAnalyzing audio content...");
+    let analyzer = VocalAnalyzer::new().with_max_duration(Duration::from_secs(analyze_secs));
+    let reports = analyzer.analyze(&files);
+    let vocal_count = reports.iter().filter(|r| r.class == VocalClass::Vocal).count();
+    println!("\n{} vocal and {} instrumental tracks", vocal_count, reports.len() - vocal_count);
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_vocal_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    let classes: HashMap<&Path, VocalClass> = reports.iter().map(|r| (r.path.as_path(), r.class)).collect();
+    for (class, playlist_path) in [(VocalClass::Instrumental, instrumental), (VocalClass::Vocal, vocal)] {
+        let Some(playlist_path) = playlist_path else { continue };
+        let playlist = Playlist::from_files(files.iter().filter(|file| classes.get(file.path.as_path()) == Some(&class)));
+        match playlist.save(playlist_path) {
+            Ok(()) => println!("{} playlist ({} tracks) saved to: {}", class.label(), playlist.len(), playlist_path.display()),
+            Err(e) => eprintln!("Error writing playlist: {}", e),
+        }
+    }
+
+    if tag {
+        write_vocal_tags(&reports, scan.dry_run);
+    }
+
+    println!("\n=== Vocal Detection Complete ===");
+}
+
+#[cfg(feature = "tags")]
+fn write_vocal_tags(reports: &[VocalReport], dry_run: bool) {
+    println!("\n{} {} tags...", if dry_run { "Checking" } else { "Writing" }, vocals::VOCALS_TAG);
+    let (writable, unsupported): (Vec<&VocalReport>, Vec<&VocalReport>) = reports.iter()
+        .partition(|report| {
+            report.path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "mp3" | "flac"))
+        });
+    let mut written = 0;
+    for report in writable {
+        if dry_run {
+            println!("  Would tag {} as {}", report.path.display(), report.class.label());
+            continue;
+        }
+        match vocals::write_vocal_tag(&report.path, report.class) {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!("  Error writing tag: {}", e),
+        }
+    }
+    if !dry_run {
+        println!("Tagged {} files", written);
+    }
+    if !unsupported.is_empty() {
+        println!("{} files are not MP3 or FLAC and were skipped", unsupported.len());
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+fn write_vocal_tags(_reports: &[VocalReport], _dry_run: bool) {
+    eprintln!("Writing tags needs a build with the `tags` feature");
+}
+
+fn bpm(scan: &ScanOpts, output: &Path, tag: bool, overwrite: bool, range: (f64, f64), analyze_secs: u64, report: &ReportOpts) {
+    println!("=== Detecting BPM ===");
+    let (min_bpm, max_bpm) = range;
+    if !(min_bpm > 0.0 && max_bpm >= min_bpm * 2.0) {
+        eprintln!("--max-bpm must be at least twice --min-bpm so every tempo fits the range");
+        return;
+    }
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nAnalyzing tempo...");
+    let analyzer = BpmAnalyzer::new()
+        .with_max_duration(Duration::from_secs(analyze_secs))
+        .with_range(min_bpm, max_bpm);
+    let reports = analyzer.analyze(&files);
+    let count = |status: BpmStatus| reports.iter().filter(|r| r.status() == status).count();
+    println!("\n{} detected without a tag, {} matching their tag, {} differing from it, {} without a beat",
+        count(BpmStatus::Detected), count(BpmStatus::MatchesTag), count(BpmStatus::DiffersFromTag), count(BpmStatus::NoBeat));
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_bpm_report(&reports, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    if tag {
+        write_bpm_tags(&reports, overwrite, scan.dry_run);
+    }
+
+    println!("\n=== BPM Detection Complete ===");
+}
+
+#[cfg(feature = "tags")]
+fn write_bpm_tags(reports: &[BpmReport], overwrite: bool, dry_run: bool) {
+    println!("\n{} BPM tags...", if dry_run { "Checking" } else { "Writing" });
+    let (writable, unsupported): (Vec<&BpmReport>, Vec<&BpmReport>) = reports.iter()
+        .filter(|report| match report.status() {
+            BpmStatus::Detected => true,
+            BpmStatus::DiffersFromTag => overwrite,
+            BpmStatus::MatchesTag | BpmStatus::NoBeat => false,
+        })
+        .partition(|report| retag::is_taggable(&report.path));
+    let mut written = 0;
+    for report in writable {
+        let Some(bpm) = report.bpm else { continue };
+        if dry_run {
+            println!("  Would tag {} with BPM {}", report.path.display(), KeyConvention::format_bpm(bpm));
+            continue;
+        }
+        match retag::write_key_bpm_tags(&report.path, None, Some(bpm)) {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!("  Error writing tag: {}", e),
+        }
+    }
+    if !dry_run {
+        println!("Tagged {} files", written);
+    }
+    if !unsupported.is_empty() {
+        println!("{} files are not MP3 or FLAC and were skipped", unsupported.len());
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+fn write_bpm_tags(_reports: &[BpmReport], _overwrite: bool, _dry_run: bool) {
+    eprintln!("Writing tags needs a build with the `tags` feature");
+}
+
+// Tag differences between the two files of a match, to help decide which to keep
+fn explain_match(dup_match: &DuplicateMatch) {
+    let (keep, other) = (&dup_match.higher_quality.path, &dup_match.lower_quality.path);
+    println!("\nExplaining: {}", dup_match.match_reason);
+    println!("  Keep:  {}", keep.display());
+    println!("  Other: {}", other.display());
+    println!("  {}", dup_match.quality_difference);
+
+    let tags = tags::read_tags(keep).and_then(|keep_tags| Ok((keep_tags, tags::read_tags(other)?)));
+    let (keep_tags, other_tags) = match tags {
+        Ok(tags) => tags,
+        Err(e) => {
+            eprintln!("  Error reading tags: {}", e);
+            return;
+        }
+    };
+
+    let differences = tags::diff_tags(&keep_tags, &other_tags);
+    if differences.is_empty() {
+        println!("  Tags are identical");
+        return;
+    }
+    println!("  Tag differences:");
+    for difference in differences {
+        println!("    {}:", difference.field);
+        println!("      keep:  {}", difference.left.as_deref().unwrap_or("(missing)"));
+        println!("      other: {}", difference.right.as_deref().unwrap_or("(missing)"));
+    }
+}
+
+// Backs each crate up into `backup_dir` before changing it. Returns the
+// number of entries changed (that would be, on a dry run).
+fn rewrite_serato_crates(crates: &[SeratoCrate], volume_root: &Path, mapping: &HashMap<PathBuf, PathBuf>, backup_dir: &Path, dry_run: bool) -> usize {
+    println!("\nUpdating Serato crates...");
+    let mut updated = 0;
+    for serato_crate in crates {
+        let entries = serato_crate.tracks.iter().filter(|track| mapping.contains_key(*track)).count();
+        if entries == 0 {
+            continue;
+        }
+        if dry_run {
+            println!("  Would update {} entries in crate {}", entries, serato_crate.name);
+            updated += entries;
+            continue;
+        }
+        let backup = backup_dir.join(serato_crate.path.file_name().unwrap_or_default());
+        if let Err(e) = std::fs::create_dir_all(backup_dir).and_then(|_| std::fs::copy(&serato_crate.path, &backup)) {
+            eprintln!("  Error backing up crate {} (left unchanged): {}", serato_crate.name, e);
+            continue;
+        }
+        match serato::rewrite_crate(&serato_crate.path, volume_root, mapping) {
+            Ok(count) => {
+                println!("  Updated {} entries in crate {} (backup: {})", count, serato_crate.name, backup.display());
+                updated += count;
+            }
+            Err(e) => eprintln!("  Error updating crate {}: {}", serato_crate.name, e),
+        }
+    }
+    updated
+}
+
+// Run time, throughput and each phase's share, for the run summary
+fn add_timing_metrics(index: &mut RunIndex, phases: &PhaseTimer, files: usize) {
+    let elapsed = phases.elapsed();
+    index.add_metric("took", "Took", progress::format_duration(elapsed));
+    index.add_metric("throughput", "Throughput", progress::format_rate(files, elapsed));
+    for (phase, time) in phases.phases() {
+        index.add_metric(&format!("{}_secs", phase), &format!("Time {} (s)", phase), (time.as_secs_f64() * 10.0).round() / 10.0);
+    }
+}
+
+// None on error; Some(None) when no list was given, so everything is acted on
+fn load_approval_list(path: Option<&Path>) -> Option<Option<ApprovalList>> {
+    match path.map(ApprovalList::load).transpose() {
+        Ok(list) => Some(list),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            None
+        }
+    }
+}
+
+fn load_ignored_pairs() -> Option<IgnoreList> {
+    match IgnoreList::load(IgnoreList::default_path()) {
+        Ok(list) => Some(list),
+        Err(e) => {
+            eprintln!("Error reading ignored pairs: {}", e);
+            None
+        }
+    }
+}
+
+fn duplicates_ignore(command: IgnoreCommand) {
+    println!("=== Ignored Duplicate Pairs ===");
+    let path = IgnoreList::default_path();
+    let Some(mut list) = load_ignored_pairs() else { return };
+    let changed = match command {
+        IgnoreCommand::Add { file_a, file_b, note } => {
+            let (a, b) = (options::resolve(&file_a), options::resolve(&file_b));
+            if a == b {
+                eprintln!("Error: both paths name the same file");
+                return;
+            }
+            let added = list.add(&a, &b, note);
+            if added {
+                println!("Ignoring {} and {}", a.display(), b.display());
+            } else {
+                println!("Already ignored: {} and {}", a.display(), b.display());
+            }
+            added
+        }
+        IgnoreCommand::Remove { file_a, file_b } => {
+            let (a, b) = (options::resolve(&file_a), options::resolve(&file_b));
+            let removed = list.remove(&a, &b);
+            if removed {
+                println!("No longer ignoring {} and {}", a.display(), b.display());
+            } else {
+                println!("Not ignored: {} and {}", a.display(), b.display());
+            }
+            removed
+        }
+        IgnoreCommand::List => {
+            for pair in &list.pairs {
+                println!("{}  {}\n    {}", audit::format_timestamp(pair.added_at / 1000), pair.first.display(), pair.second.display());
+                if let Some(note) = &pair.note {
+                    println!("    Note: {}", note);
+                }
+            }
+            println!("{} ignored pairs ({})", list.len(), path.display());
+            false
+        }
+    };
+    if changed {
+        if let Err(e) = list.save(&path) {
+            eprintln!("Error saving ignored pairs: {}", e);
+        }
+    }
+}
+
+fn audit_operations(journal_dirs: &[PathBuf], filter: &AuditFilter, output: Option<&Path>, report: &ReportOpts) {
+    println!("=== Operation Audit ===");
+    let log = match audit::read_audit(journal_dirs, filter) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("Error reading journals: {}", e);
+            return;
+        }
+    };
+    if log.skipped_lines > 0 {
+        eprintln!("Skipped {} unreadable journal lines (interrupted runs?)", log.skipped_lines);
+    }
+    println!("{} operations in {} journals", log.entries.len(), log.journals_read);
+
+    if let Some(output) = output {
+        let reporter = Reporter::new().with_options(ReportOptions::from(report));
+        if let Err(e) = reporter.generate_audit_report(&log.entries, output) {
+            eprintln!("Error generating report: {}", e);
+        }
+        return;
+    }
+    for entry in &log.entries {
+        let user = entry.user.as_deref().unwrap_or("unknown user");
+        match &entry.operation {
+            FileOperation::Move { from, to } => println!("{}  {}  moved {} -> {}", audit::format_timestamp(entry.timestamp), user, from.display(), to.display()),
+            FileOperation::Copy { from, to } => println!("{}  {}  copied {} -> {}", audit::format_timestamp(entry.timestamp), user, from.display(), to.display()),
+            FileOperation::Delete { path } => println!("{}  {}  deleted {}", audit::format_timestamp(entry.timestamp), user, path.display()),
+        }
+    }
+}
+
+fn diff_runs(old: Option<String>, new: Option<String>, output: Option<&Path>) {
+    println!("=== Comparing Duplicate Runs ===");
+    // Missing runs default to the most recent stored ones
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (old, _) => {
+            let mut runs = match DuplicateRun::list() {
+                Ok(runs) => runs,
+                Err(e) => {
+                    eprintln!("Error listing runs: {}", e);
+                    return;
+                }
+            };
+            let latest = runs.pop();
+            match (old.or_else(|| runs.pop()), latest) {
+                (Some(old), Some(latest)) => (old, latest),
+                _ => {
+                    eprintln!("Not enough stored runs to compare");
+                    return;
+                }
+            }
+        }
+    };
+
+    let runs = DuplicateRun::load(&old).and_then(|old| Ok((old, DuplicateRun::load(&new)?)));
+    let (old, new) = match runs {
+        Ok(runs) => runs,
+        Err(e) => {
+            eprintln!("Error loading run: {}", e);
+            return;
+        }
+    };
+    println!("Old run: {} ({} duplicates)", old.run_id, old.matches.len());
+    println!("New run: {} ({} duplicates)", new.run_id, new.matches.len());
+
+    let diff = old.diff(&new);
+    println!("\nNew duplicates: {}", diff.new_matches.len());
+    for m in &diff.new_matches {
+        println!("  {} (keep {})", m.lower_quality.display(), m.higher_quality.display());
+    }
+    println!("\nResolved duplicates: {}", diff.resolved.len());
+    for m in &diff.resolved {
+        println!("  {} (kept {})", m.lower_quality.display(), m.higher_quality.display());
+    }
+    println!("\nUnchanged duplicates: {}", diff.unchanged);
+
+    if let Some(output) = output {
+        let json = match serde_json::to_string_pretty(&diff) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Error serializing comparison: {}", e);
+                return;
+            }
+        };
+        match report_dir::write_atomic(output, json) {
+            Ok(()) => println!("\nComparison saved to: {}", output.display()),
+            Err(e) => eprintln!("Error writing comparison: {}", e),
+        }
+    }
+}
+
+fn export_manifest(scan: &ScanOpts, output: &Path) {
+    println!("=== Exporting Library Manifest ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    println!("\nHashing {} files...", files.len());
+    let manifest = Manifest::build(&files);
+    match manifest.save(output) {
+        Ok(()) => println!("Manifest with {} tracks saved to: {}", manifest.tracks.len(), output.display()),
+        Err(e) => eprintln!("Error writing manifest: {}", e),
+    }
+}
+
+fn export_usb(scan: &ScanOpts, output: &Path, budget: u64, downgrade: bool, transcode: Option<u32>, manifest_path: Option<&Path>) {
+    println!("=== Exporting to Drive ===");
+    println!("Target: {}", output.display());
+    println!("Budget: {:.2} GB", budget as f64 / 1e9);
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let exporter = UsbExporter::new(budget)
+        .with_downgrade(downgrade)
+        .with_transcode(transcode);
+    let mut manifest = exporter.plan(&files);
+    println!("\nSelected {} of {} tracks ({:.2} GB): {} best copies, {} downgraded, {} transcoded, {} skipped",
+        manifest.entries.len() - manifest.count(ExportAction::Skip),
+        manifest.entries.len(),
+        manifest.total_bytes as f64 / 1e9,
+        manifest.count(ExportAction::Copy),
+        manifest.count(ExportAction::Downgrade),
+        manifest.count(ExportAction::Transcode),
+        manifest.count(ExportAction::Skip));
+
+    // Re-running an export tops up the drive instead of duplicating files
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(scan.dry_run)
+        .with_collision_policy(CollisionPolicy::Skip);
+    println!("\n{} tracks...", if scan.dry_run { "Planning" } else { "Writing" });
+    if let Err(e) = exporter.export(&mut manifest, output, &executor) {
+        eprintln!("Error exporting: {}", e);
+        return;
+    }
+
+    let manifest_path = match manifest_path {
+        Some(path) => path.to_path_buf(),
+        None if scan.dry_run => {
+            println!("\nDry run: nothing written ({:.2} GB would be exported)", manifest.total_bytes as f64 / 1e9);
+            return;
+        }
+        None => output.join(usb::MANIFEST_FILE),
+    };
+    match manifest.save(&manifest_path) {
+        Ok(()) => println!("\nExported {:.2} GB; manifest saved to: {}", manifest.total_bytes as f64 / 1e9, manifest_path.display()),
+        Err(e) => eprintln!("Error writing manifest: {}", e),
+    }
+}
+
+// Key and BPM of every playlist entry; entries that can't be read stay in
+// the playlist with nothing known about them
+fn harmonic_tracks(playlist: &Playlist) -> Vec<HarmonicTrack> {
+    playlist.entries.iter().map(|entry| {
+        match MetadataExtractor::extract_metadata(&entry.path) {
+            Ok(file) => HarmonicTrack::from_file(&file),
+            Err(e) => {
+                eprintln!("Warning: {}: {}", entry.path.display(), e);
+                HarmonicTrack::unknown(&entry.path)
+            }
+        }
+    }).collect()
+}
+
+fn playlist_sort(playlist_path: &Path, output: Option<&Path>, report: Option<&Path>) {
+    println!("=== Sorting Playlist ===");
+    let playlist = match Playlist::load(playlist_path) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Error loading playlist: {}", e);
+            return;
+        }
+    };
+    println!("Reading {} tracks from {}", playlist.len(), playlist_path.display());
+
+    let tracks = harmonic_tracks(&playlist);
+    let partial = tracks.iter().filter(|track| track.key.is_none() != track.bpm.is_none()).count();
+    let unknown = tracks.iter().filter(|track| track.key.is_none() && track.bpm.is_none()).count();
+    if partial > 0 {
+        println!("{} tracks lack a key or BPM tag and are placed on the other", partial);
+    }
+    if unknown > 0 {
+        println!("{} tracks have neither and are moved to the end", unknown);
+    }
+
+    let sorted = SortedPlaylist::sort(&tracks);
+    println!("\nSorted order:");
+    for (position, track) in sorted.tracks.iter().enumerate() {
+        let name = track.path.file_name().map_or_else(|| track.path.to_string_lossy(), |n| n.to_string_lossy());
+        println!("{:>3}. [{:>3} {:>6}] {}{}",
+            position + 1,
+            track.key.as_deref().unwrap_or("?"),
+            track.bpm.map_or("?".to_string(), |bpm| format!("{:.1}", bpm)),
+            name,
+            track.transition_score.map_or(String::new(), |score| format!("  ({:.0})", score)));
+    }
+    println!("\nCompatibility score: {:.0}/100 (was {:.0}/100)", sorted.score_after, sorted.score_before);
+
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| {
+        let stem = playlist_path.file_stem().map_or_else(|| "playlist".into(), |s| s.to_string_lossy());
+        playlist_path.with_file_name(format!("{}-sorted.m3u8", stem))
+    });
+    match playlist.reordered(&sorted.order).save(&output) {
+        Ok(()) => println!("Sorted playlist saved to: {}", output.display()),
+        Err(e) => eprintln!("Error writing playlist: {}", e),
+    }
+
+    if let Some(report) = report {
+        match serde_json::to_string_pretty(&sorted) {
+            Ok(json) => match report_dir::write_atomic(report, json) {
+                Ok(()) => println!("Report saved to: {}", report.display()),
+                Err(e) => eprintln!("Error writing report: {}", e),
+            },
+            Err(e) => eprintln!("Error serializing report: {}", e),
+        }
+    }
+}
+
+fn playlist_import(scan: &ScanOpts, playlist: &Path, output: &Path, m3u: Option<&Path>, report: &ReportOpts) {
+    println!("=== Importing Streaming Playlist ===");
+    let entries = match wishlist::read_wishlist(playlist) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading playlist: {}", e);
+            return;
+        }
+    };
+    println!("Playlist has {} tracks", entries.len());
+
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let matches = WishlistMatcher::new(&files).check_all(&entries);
+    let owned: Vec<&AudioFile> = matches.iter()
+        .filter(|m| m.status == WishStatus::Owned)
+        .filter_map(|m| m.library_copy.as_ref())
+        .collect();
+    let low_quality = owned.iter().filter(|file| file.bitrate.is_some_and(|bitrate| bitrate < LOW_QUALITY_KBPS)).count();
+    let count = |status| matches.iter().filter(|m| m.status == status).count();
+    println!("\n{} of {} tracks are in the library ({} below {} kbps), {} only in another version, {} missing",
+        owned.len(), matches.len(), low_quality, LOW_QUALITY_KBPS, count(WishStatus::OtherVersion), count(WishStatus::Missing));
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_playlist_gap_report(&matches, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    if let Some(m3u) = m3u {
+        match Playlist::from_files(owned.iter().copied()).save(m3u) {
+            Ok(()) => println!("Playlist of the {} local copies saved to: {}", owned.len(), m3u.display()),
+            Err(e) => eprintln!("Error writing playlist: {}", e),
+        }
+    }
+
+    println!("\n=== Playlist Import Complete ===");
+}
+
+fn playlist_matrix(playlist_path: &Path, output: &Path) {
+    println!("=== Building Compatibility Matrix ===");
+    let playlist = match Playlist::load(playlist_path) {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Error loading playlist: {}", e);
+            return;
+        }
+    };
+    println!("Reading {} tracks from {}", playlist.len(), playlist_path.display());
+
+    let matrix = CompatibilityMatrix::build(&harmonic_tracks(&playlist));
+    println!("\nBest next track:");
+    for (from, row) in matrix.cells.iter().enumerate() {
+        let best = row.iter().enumerate()
+            .filter_map(|(to, cell)| Some((to, cell.as_ref()?)))
+            .max_by(|a, b| a.1.score.total_cmp(&b.1.score));
+        if let Some((to, cell)) = best {
+            println!("  {} -> {}  ({:.0}: {})", matrix.label(from), matrix.label(to), cell.score, cell.summary());
+        }
+    }
+
+    let html = output.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "html" | "htm"));
+    let result = if html {
+        let title = playlist_path.file_stem().map_or_else(|| "Playlist".into(), |s| s.to_string_lossy());
+        matrix.write_html(output, &format!("{} - transition compatibility", title))
+    } else {
+        matrix.write_csv(output)
+    };
+    match result {
+        Ok(()) => println!("\nMatrix saved to: {}", output.display()),
+        Err(e) => eprintln!("Error writing matrix: {}", e),
+    }
+}
+
+fn manifest_diff(mine: &Path, theirs: &Path, output: Option<&Path>) {
+    println!("=== Comparing Manifests ===");
+    let manifests = Manifest::load(mine).and_then(|mine| Ok((mine, Manifest::load(theirs)?)));
+    let (mine, theirs) = match manifests {
+        Ok(manifests) => manifests,
+        Err(e) => {
+            eprintln!("Error loading manifest: {}", e);
+            return;
+        }
+    };
+    println!("Mine: {} tracks, theirs: {} tracks", mine.tracks.len(), theirs.tracks.len());
+
+    let diff = ManifestDiff::compare(&mine, &theirs);
+    let describe = |entry: &ManifestEntry| {
+        let version = entry.version.as_ref().map_or(String::new(), |v| format!(" ({})", v));
+        format!("{} - {}{} [{}, {} kbps]", entry.artist, entry.title, version, entry.format, entry.bitrate.unwrap_or(0))
+    };
+
+    println!("\nYou are missing {} tracks:", diff.missing_from_mine.len());
+    for entry in &diff.missing_from_mine {
+        println!("  {}", describe(entry));
+    }
+    println!("\nThey are missing {} tracks:", diff.missing_from_theirs.len());
+    for entry in &diff.missing_from_theirs {
+        println!("  {}", describe(entry));
+    }
+    println!("\nUpgrades available to you: {}", diff.upgrades_for_mine.len());
+    for upgrade in &diff.upgrades_for_mine {
+        println!("  {} -> {}", describe(&upgrade.current), describe(&upgrade.available));
+    }
+    println!("\nUpgrades available to them: {}", diff.upgrades_for_theirs.len());
+    for upgrade in &diff.upgrades_for_theirs {
+        println!("  {} -> {}", describe(&upgrade.current), describe(&upgrade.available));
+    }
+
+    if let Some(output) = output {
+        let json = match serde_json::to_string_pretty(&diff) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Error serializing comparison: {}", e);
+                return;
+            }
+        };
+        match report_dir::write_atomic(output, json) {
+            Ok(()) => println!("\nComparison saved to: {}", output.display()),
+            Err(e) => eprintln!("Error writing comparison: {}", e),
+        }
+    }
+}
+
+fn backup(scan: &ScanOpts, target: &Path, tags_only: bool, no_index: bool) {
+    println!("=== Backing Up Library ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let mode = if tags_only { BackupMode::TagsOnly } else { BackupMode::Full };
+    let index_path = library_index_path().filter(|path| !no_index && path.exists());
+    let store = BackupStore::open(target).with_dry_run(scan.dry_run);
+    println!("\nBacking up {} files to {}", files.len(), target.display());
+
+    match store.backup(&files, &scan.input, mode, index_path.as_deref()) {
+        Ok((snapshot, summary)) => {
+            println!("\n=== Backup Summary ===");
+            println!("Snapshot: {}{}", snapshot.id, if scan.dry_run { " (dry run, not saved)" } else { "" });
+            println!("New objects: {} ({:.2} MB)", summary.stored, summary.bytes_written as f64 / 1_048_576.0);
+            println!("Already in backup: {}", summary.deduplicated);
+            println!("Unchanged since last backup: {}", summary.unchanged);
+            if summary.skipped > 0 {
+                println!("Skipped (no tag block to save): {}", summary.skipped);
+            }
+            if snapshot.index.is_some() {
+                println!("Library index included");
+            }
+            if summary.failed > 0 {
+                println!("Failed: {}", summary.failed);
+            }
+        }
+        Err(e) => eprintln!("Error writing backup: {}", e),
+    }
+}
+
+fn restore(target: &Path, snapshot: Option<&str>, to: Option<&Path>, dry_run: bool) {
+    println!("=== Restoring Backup ===");
+    let store = BackupStore::open(target).with_dry_run(dry_run);
+    let snapshot = match store.load_snapshot(snapshot) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Error loading backup: {}", e);
+            return;
+        }
+    };
+    println!("Snapshot {} with {} files", snapshot.id, snapshot.entries.len());
+
+    // Next to the restored files when relocating, otherwise back in place
+    let index_to = match to {
+        Some(to) => snapshot.index.as_ref().map(|entry| to.join(&entry.relative)),
+        None => library_index_path(),
+    };
+    match store.restore(&snapshot, to, index_to.as_deref()) {
+        Ok(summary) => {
+            println!("\n=== Restore Summary ===");
+            println!("{}: {}", if dry_run { "Would restore" } else { "Restored" }, summary.restored);
+            println!("Already up to date: {}", summary.unchanged);
+            if summary.failed > 0 {
+                println!("Failed: {}", summary.failed);
+            }
+        }
+        Err(e) => eprintln!("Error restoring backup: {}", e),
+    }
+}
+
+fn vault_save(scan: &ScanOpts, output: &Path) {
+    println!("=== Saving Metadata Vault ===");
+    let Some(files) = scan_for_vault(scan) else { return };
+    let vault = MetadataVault::build(&files);
+    match vault.save(output) {
+        Ok(()) => println!("Tags of {} files saved to: {}", vault.entries.len(), output.display()),
+        Err(e) => eprintln!("Error writing vault: {}", e),
+    }
+}
+
+fn vault_restore(scan: &ScanOpts, vault_path: &Path) {
+    println!("=== Restoring Tags from Vault ===");
+    let vault = match MetadataVault::load(vault_path) {
+        Ok(vault) => vault,
+        Err(e) => {
+            eprintln!("Error loading vault: {}", e);
+            return;
+        }
+    };
+    let Some(files) = scan_for_vault(scan) else { return };
+
+    let summary = vault.apply(&files, scan.dry_run);
+    println!("\n=== Vault Restore Summary ===");
+    println!("{}: {}", if scan.dry_run { "Would restore" } else { "Restored" }, summary.restored);
+    println!("Already up to date: {}", summary.unchanged);
+    println!("Not in vault: {}", summary.unmatched);
+    if summary.failed > 0 {
+        println!("Failed: {}", summary.failed);
+    }
+}
+
+fn scan_for_vault(scan: &ScanOpts) -> Option<Vec<AudioFile>> {
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return None };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => Some(files),
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            None
+        }
+    }
+}
+
+fn library_index_path() -> Option<PathBuf> {
+    #[cfg(feature = "index")]
+    return Some(LibraryIndex::default_path());
+    #[cfg(not(feature = "index"))]
+    None
+}
+
+fn discs(scan: &ScanOpts, merge: bool, output: Option<&Path>, report: &ReportOpts) {
+    println!("=== Finding Split Multi-Disc Albums ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let albums = DiscAnalyzer::find_split_albums(&files);
+    println!("\nFound {} albums split across disc folders", albums.len());
+    for album in &albums {
+        println!("  {} ({} discs, {} tracks)", album.target.display(), album.discs.len(), album.track_count());
+        for disc in &album.discs {
+            println!("    Disc {}: {}", disc.disc, disc.path.display());
+        }
+    }
+
+    if let Some(output) = output {
+        println!("\nGenerating report...");
+        let reporter = Reporter::new().with_options(ReportOptions::from(report));
+        if let Err(e) = reporter.generate_split_album_report(&albums, output) {
+            eprintln!("Error generating report: {}", e);
+        }
+    }
+
+    if !merge {
+        if !albums.is_empty() {
+            println!("\nRun again with --merge to merge them (add --dry-run to preview)");
+        }
+        println!("\n=== Split Album Search Complete ===");
+        return;
+    }
+
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(scan.dry_run)
+        .with_collision_policy(CollisionPolicy::Skip);
+    let executor = match executor.with_default_journal() {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return;
+        }
+    };
+
+    println!("\n{} albums...", if scan.dry_run { "Would merge" } else { "Merging" });
+    let (mut moved, mut skipped, mut failed) = (0, 0, 0);
+    for album in &albums {
+        for result in executor.execute_all(&album.merge_operations()) {
+            match result {
+                Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                    moved += 1;
+                    if scan.dry_run {
+                        println!("  Would move {} -> {}", op.source().display(), op.destination().unwrap_or(op.source()).display());
+                    }
+                }
+                Ok(OperationOutcome::Skipped { operation, reason }) => {
+                    skipped += 1;
+                    println!("  Skipped {}: {}", operation.source().display(), reason);
+                }
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("  Error moving track: {}", e);
+                }
+            }
+        }
+        if !scan.dry_run {
+            // Only succeeds once nothing (covers, cue sheets, skipped tracks) is left
+            for disc in &album.discs {
+                if file_ops::ensure_writable(&disc.path).is_ok() && std::fs::remove_dir(&disc.path).is_ok() {
+                    println!("  Removed empty folder: {}", disc.path.display());
+                }
+            }
+        }
+    }
+
+    println!("\n{} {} tracks ({} skipped, {} failed)",
+        if scan.dry_run { "Would move" } else { "Moved" }, moved, skipped, failed);
+    if let Some(journal) = executor.journal_path() {
+        println!("Operation journal: {}", journal.display());
+    }
+    println!("\n=== Split Album Merge Complete ===");
+}
+
+fn retag(scan: &ScanOpts, filename: bool, tags: bool, notation: KeyNotation, template: &str) {
+    println!("=== Writing Keys and BPMs ===");
+    let convention = match KeyConvention::new(notation).with_template(template) {
+        Ok(convention) => convention,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+    let missing = files.iter().filter(|file| convention.prefix(file).is_none()).count();
+    println!("\n{} of {} files have the key and BPM the template needs", files.len() - missing, files.len());
+
+    // Tags first: renaming changes the paths
+    if tags {
+        write_key_bpm_tags(&files, &convention, scan.dry_run);
+    }
+    if filename {
+        rename_with_prefix(&files, &convention, scan.dry_run);
+    }
+
+    println!("\n=== Retag Complete ===");
+}
+
+fn retag_track_numbers(scan: &ScanOpts, overwrite: bool) {
+    println!("=== Writing Track Numbers ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let (numbered, skipped) = retag::infer_track_numbers(&files);
+    println!("\n{} of {} files have a track number in their name", numbered.len(), files.len());
+    if !skipped.is_empty() {
+        println!("{} folders skipped: not every file has a number prefix, or a number repeats", skipped.len());
+        for folder in &skipped {
+            println!("  {}", folder.display());
+        }
+    }
+
+    // Only files whose tag lacks the number, or disagrees with --overwrite
+    let pending: Vec<&retag::TrackNumbering> = numbered.iter()
+        .filter(|numbering| match retag::tagged_track_number(&numbering.path) {
+            None => true,
+            Some(tagged) => overwrite && tagged != numbering.track,
+        })
+        .collect();
+    println!("{} files need their track number written", pending.len());
+    write_track_numbers(&pending, scan.dry_run);
+
+    println!("\n=== Retag Complete ===");
+}
+
+#[cfg(feature = "tags")]
+fn write_track_numbers(numberings: &[&retag::TrackNumbering], dry_run: bool) {
+    println!("\n{} track numbers...", if dry_run { "Checking" } else { "Writing" });
+    let (writable, unsupported): (Vec<&retag::TrackNumbering>, Vec<&retag::TrackNumbering>) = numberings.iter()
+        .partition(|numbering| retag::is_taggable(&numbering.path));
+    let mut written = 0;
+    for numbering in writable {
+        if dry_run {
+            println!("  Would tag {} as track {}{}", numbering.path.display(), numbering.track,
+                numbering.total.map_or(String::new(), |total| format!(" of {}", total)));
+            continue;
+        }
+        match retag::write_track_number(&numbering.path, numbering) {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!("  Error writing tags: {}", e),
+        }
+    }
+    if !dry_run {
+        println!("Tagged {} files", written);
+    }
+    if !unsupported.is_empty() {
+        println!("{} files are not MP3 or FLAC and were skipped", unsupported.len());
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+fn write_track_numbers(_numberings: &[&retag::TrackNumbering], _dry_run: bool) {
+    eprintln!("Writing tags needs a build with the `tags` feature");
+}
+
+#[cfg(feature = "tags")]
+fn write_key_bpm_tags(files: &[AudioFile], convention: &KeyConvention, dry_run: bool) {
+    println!("\n{} key and BPM tags...", if dry_run { "Checking" } else { "Writing" });
+    let (writable, unsupported): (Vec<&AudioFile>, Vec<&AudioFile>) = files.iter()
+        .partition(|file| retag::is_taggable(&file.path));
+    let mut written = 0;
+    for file in writable {
+        let Some((key, bpm)) = convention.tag_update(file) else { continue };
+        if dry_run {
+            println!("  Would tag {} (key {}, BPM {})", file.path.display(),
+                key.as_deref().unwrap_or("unchanged"),
+                bpm.map_or_else(|| "unchanged".to_string(), KeyConvention::format_bpm));
+            continue;
+        }
+        match retag::write_key_bpm_tags(&file.path, key.as_deref(), bpm) {
+            Ok(()) => written += 1,
+            Err(e) => eprintln!("  Error writing tags: {}", e),
+        }
+    }
+    if !dry_run {
+        println!("Tagged {} files", written);
+    }
+    if !unsupported.is_empty() {
+        println!("{} files are not MP3 or FLAC and were skipped", unsupported.len());
+    }
+}
+
+#[cfg(not(feature = "tags"))]
+fn write_key_bpm_tags(_files: &[AudioFile], _convention: &KeyConvention, _dry_run: bool) {
+    eprintln!("Writing tags needs a build with the `tags` feature");
+}
+
+fn undo(journal: Option<PathBuf>, dry_run: bool) {
+    println!("=== Undoing File Operations ===");
+    let Some(journal) = journal.or_else(|| file_ops::latest_command_journal(&["duplicates", "run"])) else {
+        println!("No `duplicates` or `run` journal found in {}", paths::journal_dir().display());
+        return;
+    };
+    println!("Journal: {}", journal.display());
+    let entries = match file_ops::read_journal(&journal) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading journal: {}", e);
+            return;
+        }
+    };
+    let (moves, kept) = file_ops::undo_operations(&entries);
+    println!("{} recorded operations, {} moves to undo", entries.len(), moves.len());
+
+    // Never overwrite: a file now at the original path stays, and so does the moved copy
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(dry_run)
+        .with_collision_policy(CollisionPolicy::Skip);
+    let executor = match executor.with_command_journal("undo") {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return;
+        }
+    };
+
+    let (mut restored, mut already, mut skipped, mut failed) = (0, 0, 0, 0);
+    for operation in &moves {
+        // Undone by an earlier run
+        if !operation.source().exists() && operation.destination().is_some_and(Path::exists) {
+            already += 1;
+            continue;
+        }
+        match executor.execute(operation) {
+            Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                restored += 1;
+                if dry_run {
+                    println!("  Would move {} back to {}", op.source().display(), op.destination().unwrap_or(op.source()).display());
+                }
+            }
+            Ok(OperationOutcome::Skipped { operation, reason }) => {
+                skipped += 1;
+                println!("  Skipped {}: {}", operation.source().display(), reason);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("  Error moving file back: {}", e);
+            }
+        }
+    }
+
+    println!("\n{} {} files ({} already back, {} skipped, {} failed)",
+        if dry_run { "Would move back" } else { "Moved back" }, restored, already, skipped, failed);
+    if !kept.is_empty() {
+        println!("{} copies and deletes in the journal can't be undone and were left alone", kept.len());
+    }
+    if let Some(journal) = executor.journal_path() {
+        println!("Operation journal: {}", journal.display());
+    }
+}
+
+fn rename_with_prefix(files: &[AudioFile], convention: &KeyConvention, dry_run: bool) {
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(dry_run)
+        .with_collision_policy(CollisionPolicy::Skip);
+    let executor = match executor.with_default_journal() {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return;
+        }
+    };
+
+    println!("\n{} files...", if dry_run { "Would rename" } else { "Renaming" });
+    let (mut renamed, mut skipped, mut failed) = (0, 0, 0);
+    for result in executor.execute_all(&convention.rename_operations(files)) {
+        match result {
+            Ok(OperationOutcome::Performed(op)) | Ok(OperationOutcome::Planned(op)) => {
+                renamed += 1;
+                if dry_run {
+                    println!("  Would rename {} -> {}", op.source().display(), op.destination().unwrap_or(op.source()).display());
+                }
+            }
+            Ok(OperationOutcome::Skipped { operation, reason }) => {
+                skipped += 1;
+                println!("  Skipped {}: {}", operation.source().display(), reason);
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("  Error renaming file: {}", e);
+            }
+        }
+    }
+
+    println!("{} {} files ({} skipped, {} failed)",
+        if dry_run { "Would rename" } else { "Renamed" }, renamed, skipped, failed);
+    if let Some(journal) = executor.journal_path() {
+        println!("Operation journal: {}", journal.display());
+    }
+}
+
+fn storage(scan: &ScanOpts, output: Option<&Path>) {
+    println!("=== Storage Planning ===");
+    let Ok(log) = open_scan_log(scan.jsonl_log.clone()) else { return };
+    let scan_options = ScanOptions {
+        log,
+        ..scan.to_scan_options()
+    };
+    let files = match MetadataExtractor::process_directories_with(&scan.input, &scan_options) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error processing directory: {}", e);
+            return;
+        }
+    };
+
+    let planner = StoragePlanner::new();
+    let history = planner.load_history(&scan.input).unwrap_or_else(|e| {
+        eprintln!("Error reading storage history: {}", e);
+        Vec::new()
+    });
+    let forecast = planner.forecast(&files, &history);
+    let gb = |bytes: f64| bytes / 1_073_741_824.0;
+
+    println!("\nLibrary: {} files, {:.2} GB", forecast.files, gb(forecast.bytes as f64));
+    for format in &forecast.formats {
+        println!("  {:<5} {:>7} files {:>9.2} GB  (average {:.1} MB)",
+            format.format, format.files, gb(format.bytes as f64), format.average_bytes as f64 / 1_048_576.0);
+    }
+
+    println!("\nGrowth: {:.2} GB and {:.0} files per month ({})",
+        gb(forecast.bytes_per_month), forecast.files_per_month,
+        match forecast.growth_source {
+            GrowthSource::History => "from earlier storage runs",
+            GrowthSource::FileDates => "from file dates of the last year; more accurate after a month of storage runs",
+        });
+    for projection in &forecast.projections {
+        println!("  In {:>2} months: {:.2} GB", projection.months, gb(projection.bytes as f64));
+    }
+
+    if forecast.wav_files > 0 {
+        println!("\nConverting {} WAV files ({:.2} GB) to FLAC would reclaim about {:.2} GB (FLAC at {:.0}% of PCM)",
+            forecast.wav_files, gb(forecast.wav_bytes as f64), gb(forecast.reclaimable_bytes() as f64), forecast.flac_ratio * 100.0);
+    }
+
+    if let Err(e) = planner.record(&StoragePlanner::snapshot(&scan.input, &files)) {
+        eprintln!("Error recording storage history: {}", e);
+    }
+
+    if let Some(output) = output {
+        match serde_json::to_string_pretty(&forecast) {
+            Ok(json) => match report_dir::write_atomic(output, json) {
+                Ok(()) => println!("\nStorage forecast written to: {}", output.display()),
+                Err(e) => eprintln!("Error writing storage forecast: {}", e),
+            },
+            Err(e) => eprintln!("Error serializing storage forecast: {}", e),
+        }
+    }
+
+    println!("\n=== Storage Planning Complete ===");
+}
+
+// Tracks of a Rekordbox or Serato collection, or None after reporting why
+// it couldn't be read
+fn read_collection(collection: &CollectionOpts) -> Option<Vec<CollectionTrack>> {
+    let tracks = match (&collection.rekordbox, &collection.serato, &collection.traktor) {
+        (Some(xml), _, _) => {
+            println!("Reading Rekordbox collection: {}", xml.display());
+            rekordbox::read_collection(xml)
+        }
+        (None, Some(database), _) => {
+            let root = collection.serato_root.clone().unwrap_or_else(|| serato::default_volume_root(database));
+            println!("Reading Serato database: {} (paths relative to {})", database.display(), root.display());
+            serato::read_database(database, root)
+        }
+        (None, None, Some(nml)) => {
+            println!("Reading Traktor collection: {}", nml.display());
+            traktor::read_collection(nml)
+        }
+        (None, None, None) => unreachable!("clap requires one collection"),
+    };
+    match tracks {
+        Ok(tracks) => Some(tracks),
+        Err(e) => {
+            eprintln!("Error reading collection: {}", e);
+            None
+        }
+    }
+}
+
+fn drift(
+    collection: &CollectionOpts,
+    tolerance: f64,
+    output: &Path,
+    report: &ReportOpts,
+) {
+    println!("=== Checking Track Lengths Against Analysis ===");
+    let Some(tracks) = read_collection(collection) else { return };
+
+    let present: Vec<&CollectionTrack> = tracks.iter().filter(|track| !track.is_missing()).collect();
+    let analyzed: Vec<&CollectionTrack> = present.iter()
+        .copied()
+        .filter(|track| track.duration_secs.is_some_and(|secs| secs > 0.0))
+        .collect();
+    println!("Collection has {} tracks, {} on disk, {} with a recorded length", tracks.len(), present.len(), analyzed.len());
+
+    println!("\nReading file lengths...");
+    let mut files = Vec::new();
+    for track in analyzed {
+        match MetadataExtractor::extract_metadata(&track.location) {
+            Ok(file) => files.push((track, file)),
+            Err(e) => eprintln!("  Error reading {}: {}", track.location.display(), e),
+        }
+    }
+
+    let checker = DriftChecker::new().with_tolerance(tolerance);
+    let mismatches = checker.find_mismatches(files.iter().map(|(track, file)| (*track, file)));
+    println!("\n{} tracks changed length since they were analyzed:", mismatches.len());
+    for mismatch in &mismatches {
+        println!("  {:+.1}s {} ({:.1}s -> {:.1}s)",
+            mismatch.difference_secs(), mismatch.path.display(), mismatch.collection_secs, mismatch.file_secs);
+    }
+    if !mismatches.is_empty() {
+        println!("Their beatgrids and cues are likely offset; re-analyze them in the DJ software.");
+    }
+
+    println!("\nGenerating report...");
+    let reporter = Reporter::new().with_options(ReportOptions::from(report));
+    if let Err(e) = reporter.generate_length_mismatch_report(&mismatches, output) {
+        eprintln!("Error generating report: {}", e);
+    }
+
+    println!("\n=== Length Check Complete ===");
+}
+
+fn watch(config_path: &Path, once: bool, dry_run: bool) {
+    println!("=== Watching Folders ===");
+    let config = match WatchConfig::load(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(dry_run)
+        .with_collision_policy(CollisionPolicy::Skip);
+    let executor = match executor.with_default_journal() {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return;
+        }
+    };
+
+    let mut watchers: Vec<FolderWatcher> = config.folders.iter()
+        .map(|folder| FolderWatcher::new(folder.clone(), config.settle()))
+        .collect();
+    for watcher in &watchers {
+        let steps: Vec<&str> = watcher.folder().steps.iter()
+            .map(|step| match step {
+                PipelineStep::Enrich => "enrich",
+                PipelineStep::Rename { .. } => "rename",
+                PipelineStep::Move { .. } => "move",
+            })
+            .collect();
+        println!("  {}: {}", watcher.folder().path.display(), steps.join(" -> "));
+    }
+    if !once {
+        println!("Checking every {}s; press Ctrl+C to stop", config.interval().as_secs());
+    }
+
+    loop {
+        for watcher in &mut watchers {
+            let ready = watcher.poll();
+            let steps = watcher.folder().steps.clone();
+            let pipeline = Pipeline::new(&steps, &executor);
+            for path in ready {
+                println!("\n{}", path.display());
+                match pipeline.run(&path) {
+                    Ok((final_path, outcomes)) => {
+                        for outcome in outcomes {
+                            match outcome {
+                                StepOutcome::Enriched(fields) => println!("  {} {}",
+                                    if dry_run { "Would tag" } else { "Tagged" }, fields.join(", ")),
+                                StepOutcome::Renamed(to) => println!("  {} {}",
+                                    if dry_run { "Would rename to" } else { "Renamed to" }, to.display()),
+                                StepOutcome::Moved(to) => println!("  {} {}",
+                                    if dry_run { "Would move to" } else { "Moved to" }, to.display()),
+                                StepOutcome::Unchanged { step, reason } => println!("  Skipped {}: {}", step, reason),
+                            }
+                        }
+                        watcher.mark_handled(&path, &final_path);
+                    }
+                    Err(e) => {
+                        eprintln!("  Error: {}", e);
+                        watcher.mark_handled(&path, &path);
+                    }
+                }
+            }
+        }
+        if once {
+            break;
+        }
+        std::thread::sleep(config.interval());
+    }
+
+    println!("\n=== Watch Complete ===");
+}
+
+// Indexes files as they appear in the library and flags the ones that
+// duplicate an indexed track. An event only marks a file as changed; it is
+// read once it went `settle_secs` without another, so a copy in progress is
+// read once, complete.
+#[cfg(feature = "library-watch")]
+fn watch_library(inputs: &[PathBuf], index_path: Option<PathBuf>, settle_secs: u64, jsonl_log: Option<PathBuf>, force_unlock: bool) {
+    use dj_library_manager::audio::metadata;
+    use notify::{RecursiveMode, Watcher};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::Instant;
+
+    println!("=== Watching Library ===");
+    let index_path = index_path.unwrap_or_else(LibraryIndex::default_path);
+    // Clears a stale lock if asked to; the monitor opens the index per change
+    if open_index(&index_path, force_unlock).is_none() {
+        return;
+    }
+    let Some(ignored) = load_ignored_pairs() else { return };
+    let mut monitor = match LibraryMonitor::new(&index_path) {
+        Ok(monitor) => monitor.with_ignored_pairs(ignored),
+        Err(e) => {
+            eprintln!("Error reading index: {}", e);
+            return;
+        }
+    };
+    println!("Index: {} ({} tracks to match new files against)", index_path.display(), monitor.tracked());
+    let Ok(log) = open_scan_log(jsonl_log) else { return };
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error starting file watcher: {}", e);
+            return;
+        }
+    };
+    for input in inputs {
+        if let Err(e) = watcher.watch(input, RecursiveMode::Recursive) {
+            eprintln!("Error watching {}: {}", input.display(), e);
+            return;
+        }
+        println!("Watching: {}", input.display());
+    }
+    println!("Press Ctrl+C to stop");
+
+    let extensions = ScanOptions::default().extensions;
+    let is_audio = |path: &Path| {
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        !metadata::is_resource_file(&name)
+            && !path.components().any(|part| metadata::is_software_folder(&part.as_os_str().to_string_lossy()))
+            && path.extension().is_some_and(|ext| extensions.contains(&ext.to_string_lossy().to_lowercase()))
+    };
+    let settle = Duration::from_secs(settle_secs);
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+
+    loop {
+        match events.recv_timeout(Duration::from_secs(1)) {
+            Ok(Ok(event)) => {
+                for path in event.paths {
+                    if path.is_dir() {
+                        // A folder moved in reports only itself
+                        for entry in walkdir::WalkDir::new(&path).into_iter().filter_map(|entry| entry.ok()) {
+                            if entry.file_type().is_file() && is_audio(entry.path()) {
+                                pending.insert(entry.into_path(), Instant::now());
+                            }
+                        }
+                    } else if !is_audio(&path) {
+                        continue;
+                    } else if path.is_file() {
+                        pending.insert(path, Instant::now());
+                    } else {
+                        pending.remove(&path);
+                        match monitor.removed(&path) {
+                            Ok(true) => println!("\nRemoved from index: {}", path.display()),
+                            Ok(false) => {}
+                            Err(e) => eprintln!("\nError removing {} from index: {}", path.display(), e),
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let mut ready: Vec<PathBuf> = pending.iter()
+            .filter(|(_, changed)| changed.elapsed() >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in ready {
+            pending.remove(&path);
+            let arrival = match monitor.arrived(&path) {
+                Ok(arrival) => arrival,
+                Err(e) => {
+                    eprintln!("\nSkipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            println!("\nIndexed: {} ({} kbps)", path.display(), arrival.file.bitrate.unwrap_or(0));
+            if let Some(log) = &log {
+                log.record(&LogEvent::Scanned { file: &arrival.file });
+            }
+            for dup_match in &arrival.duplicates {
+                let other = if dup_match.higher_quality.path == path { &dup_match.lower_quality } else { &dup_match.higher_quality };
+                println!("  Duplicate of: {}", other.path.display());
+                println!("  Reason: {}", dup_match.match_reason);
+                println!("  Better copy: {} ({})", dup_match.higher_quality.path.display(), dup_match.quality_difference);
+                if let Some(log) = &log {
+                    log.record(&LogEvent::Duplicate {
+                        higher_quality: &dup_match.higher_quality.path,
+                        lower_quality: &dup_match.lower_quality.path,
+                        match_reason: &dup_match.match_reason,
+                        quality_difference: &dup_match.quality_difference,
+                    });
+                }
+            }
+        }
+    }
+
+    println!("\n=== Watch Complete ===");
+}
+
+fn sync(source: &Path, target: &Path, allow_delete: bool, retention_days: u64, dry_run: bool) {
+    println!("=== Syncing {} -> {} ===", source.display(), target.display());
+    let syncer = Syncer::new()
+        .with_allow_delete(allow_delete)
+        .with_retention_days(retention_days);
+    let plan = match syncer.plan(source, target) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    println!("{} files unchanged, {} new, {} changed ({:.2} MB to copy)",
+        plan.unchanged, plan.count(SyncAction::Copy), plan.count(SyncAction::Update),
+        plan.bytes_to_copy() as f64 / 1_048_576.0);
+    let protected = plan.count(SyncAction::Protected);
+    if protected > 0 {
+        println!("{} files are no longer in the source; kept on the target (pass --allow-delete to remove them)", protected);
+    }
+    for entry in &plan.entries {
+        if let SyncAction::Retain { delete_after } = entry.action {
+            println!("  Keeping {} until {} UTC", entry.path.display(), audit::format_timestamp(delete_after / 1000));
+        }
+    }
+
+    let executor = FileOperationExecutor::new()
+        .with_dry_run(dry_run)
+        .with_verification(true)
+        .with_collision_policy(CollisionPolicy::Overwrite);
+    let executor = match executor.with_default_journal() {
+        Ok(executor) => executor,
+        Err(e) => {
+            eprintln!("Error creating operation journal: {}", e);
+            return;
+        }
+    };
+    let results = match syncer.execute(&plan, &executor) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Error saving sync state: {}", e);
+            return;
+        }
+    };
+
+    let mut failed = 0;
+    for result in &results {
+        let verb = match (result.action, dry_run) {
+            (SyncAction::Copy, false) => "Copied",
+            (SyncAction::Copy, true) => "Would copy",
+            (SyncAction::Update, false) => "Updated",
+            (SyncAction::Update, true) => "Would update",
+            (_, false) => "Deleted",
+            (_, true) => "Would delete",
+        };
+        match &result.outcome {
+            Ok(OperationOutcome::Performed(_)) | Ok(OperationOutcome::Planned(_)) => println!("  {} {}", verb, result.path.display()),
+            Ok(OperationOutcome::Skipped { reason, .. }) => println!("  Skipped {}: {}", result.path.display(), reason),
+            Err(e) => {
+                failed += 1;
+                eprintln!("  Error syncing {}: {}", result.path.display(), e);
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} files failed to sync", failed);
+    }
+    println!("\n=== Sync Complete ===");
+}
+
+fn relocate(
+    scan: &ScanOpts,
+    collection: &CollectionOpts,
+    output: &Path,
+    write_xml: Option<PathBuf>,
+    write_nml: Option<PathBuf>,
+) {
+    println!("=== Starting Relocation ===");
+
+    let Some(tracks) = read_collection(collection) else { return };
+
+    let missing: Vec<_> = tracks.iter().filter(|t| t.is_missing()).collect();
+    println!("Collection has {} tracks, {} missing", tracks.len(), missing.len());
+    if missing.is_empty() {
+        println!("Nothing to relocate.");
+        return;
+    }
+
+    println!("\nScanning library for candidates...");
+    let candidates: Vec<(PathBuf, u64)> = MetadataExtractor::list_audio_files(&scan.input, &scan.to_scan_options())
+        .into_iter()
+        .filter_map(|path| std::fs::metadata(&path).ok().map(|m| (path, m.len())))
+        .collect();
+    println!("Found {} candidate files", candidates.len());
+
+    let relocator = Relocator::new(candidates);
+    let relocations: Vec<Relocation> = missing.iter()
+        .filter_map(|track| {
+            let found = relocator.locate(track);
+            match &found {
+                Some(r) => println!("  Found: {} -> {} ({:?})", r.old_path.display(), r.new_path.display(), r.method),
+                None => println!("  Not found: {}", track.location.display()),
+            }
+            found
+        })
+        .collect();
+    println!("\nRelocated {} of {} missing tracks", relocations.len(), missing.len());
+
+    let json = match serde_json::to_string_pretty(&relocations) {
+        Ok(json) => json,
+        Err(e) => {
+            eprintln!("Error serializing relocation mapping: {}", e);
+            return;
+        }
+    };
+    match report_dir::write_atomic(output, json) {
+        Ok(()) => println!("Relocation mapping saved to: {}", output.display()),
+        Err(e) => eprintln!("Error writing relocation mapping: {}", e),
+    }
+
+    let mapping: HashMap<PathBuf, PathBuf> = relocations.into_iter()
+        .map(|r| (r.old_path, r.new_path))
+        .collect();
+    if let Some(updated_xml) = write_xml {
+        let Some(xml) = &collection.rekordbox else {
+            eprintln!("--write-xml only applies to Rekordbox collections");
+            return;
+        };
+        match rekordbox::rewrite_locations(xml, &updated_xml, &mapping) {
+            Ok(count) => println!("Updated collection with {} new locations: {}", count, updated_xml.display()),
+            Err(e) => eprintln!("Error writing updated collection: {}", e),
+        }
+    }
+    if let Some(updated_nml) = write_nml {
+        let Some(nml) = &collection.traktor else {
+            eprintln!("--write-nml only applies to Traktor collections");
+            return;
+        };
+        match traktor::rewrite_locations(nml, &updated_nml, &mapping) {
+            Ok(count) => println!("Updated collection with {} new locations: {}", count, updated_nml.display()),
+            Err(e) => eprintln!("Error writing updated collection: {}", e),
+        }
+    }
+}
+
+fn doctor(input: &[PathBuf], output: &[PathBuf]) {
+    use dj_library_manager::cli::doctor::{self, CheckStatus};
+
+    println!("=== Running Diagnostics ===\n");
+    let checks = doctor::run_checks(input, output);
+    for check in &checks {
+        let label = match check.status {
+            CheckStatus::Ok => "  OK ",
+            CheckStatus::Warning => " WARN",
+            CheckStatus::Failed => " FAIL",
+        };
+        println!("[{}] {}: {}", label, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("        -> {}", hint);
+        }
+    }
+
+    let failed = checks.iter().filter(|c| c.status == CheckStatus::Failed).count();
+    let warnings = checks.iter().filter(|c| c.status == CheckStatus::Warning).count();
+    println!("\n{} checks, {} warnings, {} failures", checks.len(), warnings, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "schema")]
+fn write_schemas(output: &Path) {
+    let dir = output.join(format!("v{}", schema::SCHEMA_VERSION));
+    match schema::write_schemas(&dir) {
+        Ok(paths) => {
+            println!("Schema version {}", schema::SCHEMA_VERSION);
+            for path in paths {
+                println!("Written: {}", path.display());
+            }
+        }
+        Err(e) => eprintln!("Error writing schemas: {}", e),
+    }
+}
+
+#[cfg(feature = "self-update")]
+fn self_update(check_only: bool) {
+    use dj_library_manager::cli::update;
+
+    let current = env!("CARGO_PKG_VERSION");
+    println!("Current version: {}", current);
+    println!("Checking for updates...");
+
+    let release = match update::latest_release() {
+        Ok(release) => release,
+        Err(e) => {
+            eprintln!("Error checking for updates: {}", e);
+            return;
+        }
+    };
+
+    if !update::is_newer(release.version(), current) {
+        println!("You are running the latest version ({}).", current);
+        return;
+    }
+
+    println!("New version available: {} ({})", release.version(), release.html_url);
+    if check_only {
+        println!("Run `dj-library-manager self-update` to install it.");
+        return;
+    }
+
+    println!("Downloading {}...", release.version());
+    match update::install(&release) {
+        Ok(path) => println!("Updated {} to {}", path.display(), release.version()),
+        Err(e) => eprintln!("Error installing update: {}", e),
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::AudioFile;
+use crate::exporters::keys::{KeyNotation, MusicalKey};
+
+// Harmonic mixing: how well one track flows into the next, judged on key
+// (Camelot wheel) and tempo. Tracks without a key or BPM tag score as a
+// neutral 0.5 on that part rather than being ruled out.
+
+// BPM difference at which a transition stops being mixable without
+// noticeable pitch change
+const MAX_BPM_DELTA_PERCENT: f64 = 8.0;
+// Weight of the key in a transition score; the rest is tempo
+const KEY_WEIGHT: f64 = 0.6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HarmonicTrack {
+    pub path: PathBuf,
+    pub key: Option<MusicalKey>,
+    pub bpm: Option<f64>,
+}
+
+impl HarmonicTrack {
+    pub fn from_file(file: &AudioFile) -> Self {
+        Self {
+            path: file.path.clone(),
+            key: file.key.as_deref().and_then(MusicalKey::parse),
+            bpm: file.bpm,
+        }
+    }
+
+    // Track with nothing known about it, e.g. a missing file
+    pub fn unknown(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), key: None, bpm: None }
+    }
+}
+
+// Tempo change from `from` to `to` in percent, allowing half- and
+// double-time mixes (70 -> 140 BPM counts as no change)
+pub fn bpm_delta_percent(from: f64, to: f64) -> f64 {
+    [to, to * 2.0, to / 2.0].into_iter()
+        .map(|to| (to - from) / from * 100.0)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+        .unwrap_or(0.0)
+}
+
+pub fn key_score(from: &HarmonicTrack, to: &HarmonicTrack) -> f64 {
+    match (from.key, to.key) {
+        (Some(from), Some(to)) => from.relation_to(&to).score(),
+        _ => 0.5,
+    }
+}
+
+pub fn bpm_score(from: &HarmonicTrack, to: &HarmonicTrack) -> f64 {
+    match (from.bpm, to.bpm) {
+        (Some(from), Some(to)) => {
+            let delta = bpm_delta_percent(from, to);
+            let closeness = (1.0 - delta.abs() / MAX_BPM_DELTA_PERCENT).max(0.0);
+            // Building the tempo up is preferred over dropping it
+            if delta < 0.0 { closeness * 0.8 } else { closeness }
+        }
+        _ => 0.5,
+    }
+}
+
+// How well `from` mixes into `to`, 0..=1
+pub fn transition_score(from: &HarmonicTrack, to: &HarmonicTrack) -> f64 {
+    KEY_WEIGHT * key_score(from, to) + (1.0 - KEY_WEIGHT) * bpm_score(from, to)
+}
+
+// Average transition score of the tracks in this order, 0..=100
+pub fn playlist_score(tracks: &[HarmonicTrack], order: &[usize]) -> f64 {
+    if order.len() < 2 {
+        return 100.0;
+    }
+    let total: f64 = order.windows(2)
+        .map(|pair| transition_score(&tracks[pair[0]], &tracks[pair[1]]))
+        .sum();
+    total / (order.len() - 1) as f64 * 100.0
+}
+
+// Greedy nearest-neighbor ordering: starts at the slowest track and always
+// continues with the remaining track that mixes best. Ties keep the
+// original playlist order. Tracks with neither key nor BPM go last.
+pub fn harmonic_order(tracks: &[HarmonicTrack]) -> Vec<usize> {
+    let (mut remaining, unknown): (Vec<usize>, Vec<usize>) = (0..tracks.len())
+        .partition(|&index| tracks[index].key.is_some() || tracks[index].bpm.is_some());
+    if remaining.is_empty() {
+        return unknown;
+    }
+    let start = remaining.iter()
+        .filter_map(|&index| Some((index, tracks[index].bpm?)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(remaining[0], |(index, _)| index);
+
+    remaining.retain(|&index| index != start);
+    let mut order = vec![start];
+    while !remaining.is_empty() {
+        let current = &tracks[order[order.len() - 1]];
+        let mut best = 0;
+        let mut best_score = f64::MIN;
+        for (position, &index) in remaining.iter().enumerate() {
+            let score = transition_score(current, &tracks[index]);
+            if score > best_score {
+                best = position;
+                best_score = score;
+            }
+        }
+        order.push(remaining.remove(best));
+    }
+    order.extend(unknown);
+    order
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SortedTrack {
+    pub path: PathBuf,
+    // Camelot notation
+    pub key: Option<String>,
+    pub bpm: Option<f64>,
+    // Score of the transition into this track; None for the first one
+    pub transition_score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SortedPlaylist {
+    pub score_before: f64,
+    pub score_after: f64,
+    // Indices into the original playlist, in the new order
+    #[serde(skip)]
+    pub order: Vec<usize>,
+    pub tracks: Vec<SortedTrack>,
+}
+
+impl SortedPlaylist {
+    pub fn sort(tracks: &[HarmonicTrack]) -> Self {
+        let original: Vec<usize> = (0..tracks.len()).collect();
+        let mut order = harmonic_order(tracks);
+        // Greedy ordering isn't optimal; never hand back something worse
+        if playlist_score(tracks, &order) < playlist_score(tracks, &original) {
+            order = original.clone();
+        }
+        let sorted = order.iter().enumerate().map(|(position, &index)| {
+            let track = &tracks[index];
+            SortedTrack {
+                path: track.path.clone(),
+                key: track.key.map(|key| key.format(KeyNotation::Camelot)),
+                bpm: track.bpm,
+                transition_score: position.checked_sub(1)
+                    .map(|previous| transition_score(&tracks[order[previous]], track) * 100.0),
+            }
+        }).collect();
+
+        Self {
+            score_before: playlist_score(tracks, &original),
+            score_after: playlist_score(tracks, &order),
+            order,
+            tracks: sorted,
+        }
+    }
+}
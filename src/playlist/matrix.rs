@@ -0,0 +1,141 @@
+use std::path::Path;
+use quick_xml::escape::escape;
+use crate::Result;
+#[cfg(feature = "reports-csv")]
+use crate::AudioError;
+use crate::exporters::keys::{KeyNotation, KeyRelation};
+use crate::playlist::harmonic::{self, HarmonicTrack};
+use crate::utils::report_dir;
+
+// Pairwise transition compatibility for planning a set: row = the track
+// playing, column = the track mixed in next. Each cell holds the key
+// relation, the tempo change and the combined transition score used by
+// playlist sorting.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixCell {
+    pub relation: Option<KeyRelation>,
+    pub bpm_delta_percent: Option<f64>,
+    // 0..=100
+    pub score: f64,
+}
+
+impl MatrixCell {
+    // "adjacent, +2.1%", with "?" for whatever isn't tagged
+    pub fn summary(&self) -> String {
+        format!("{}, {}",
+            self.relation.map_or("?", |relation| relation.label()),
+            self.bpm_delta_percent.map_or("?".to_string(), |delta| format!("{:+.1}%", delta)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompatibilityMatrix {
+    pub tracks: Vec<HarmonicTrack>,
+    // cells[from][to]; None on the diagonal
+    pub cells: Vec<Vec<Option<MatrixCell>>>,
+}
+
+impl CompatibilityMatrix {
+    pub fn build(tracks: &[HarmonicTrack]) -> Self {
+        let cells = tracks.iter().enumerate().map(|(row, from)| {
+            tracks.iter().enumerate().map(|(column, to)| {
+                (row != column).then(|| MatrixCell {
+                    relation: from.key.zip(to.key).map(|(from, to)| from.relation_to(&to)),
+                    bpm_delta_percent: from.bpm.zip(to.bpm).map(|(from, to)| harmonic::bpm_delta_percent(from, to)),
+                    score: harmonic::transition_score(from, to) * 100.0,
+                })
+            }).collect()
+        }).collect();
+        Self { tracks: tracks.to_vec(), cells }
+    }
+
+    // "3. Track Name (8A, 124.0)"
+    pub fn label(&self, index: usize) -> String {
+        let track = &self.tracks[index];
+        let name = track.path.file_stem().map_or_else(|| track.path.to_string_lossy(), |s| s.to_string_lossy());
+        format!("{}. {} ({}, {})",
+            index + 1,
+            name,
+            track.key.map_or("?".to_string(), |key| key.format(KeyNotation::Camelot)),
+            track.bpm.map_or("?".to_string(), |bpm| format!("{:.1}", bpm)))
+    }
+
+    // Score, key relation and tempo change per cell, tracks as both header
+    // row and first column
+    #[cfg(feature = "reports-csv")]
+    pub fn write_csv(&self, output: impl AsRef<Path>) -> Result<()> {
+        let output = output.as_ref();
+        let partial = report_dir::partial_path(output);
+        let mut writer = csv::Writer::from_path(&partial)?;
+        let labels: Vec<String> = (0..self.tracks.len()).map(|index| self.label(index)).collect();
+        writer.write_record(std::iter::once("From \\ To").chain(labels.iter().map(String::as_str)))?;
+        for (label, row) in labels.iter().zip(&self.cells) {
+            let cells = row.iter().map(|cell| {
+                cell.as_ref().map_or(String::new(), |cell| format!("{:.0} ({})", cell.score, cell.summary()))
+            });
+            writer.write_record(std::iter::once(label.clone()).chain(cells))?;
+        }
+        writer.flush().map_err(|e| AudioError::io(&partial, e))?;
+        drop(writer);
+        report_dir::commit_partial(output)
+    }
+
+    // Standalone page with the matrix as a red-to-green heatmap; hovering a
+    // cell names the transition
+    pub fn render_html(&self, title: &str) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape(title)));
+        html.push_str(concat!(
+            "<style>\n",
+            "body { font-family: sans-serif; margin: 2em; }\n",
+            "table { border-collapse: collapse; font-size: 12px; }\n",
+            "th, td { border: 1px solid #ccc; padding: 4px 6px; }\n",
+            "thead th { writing-mode: vertical-rl; transform: rotate(180deg); text-align: left; vertical-align: bottom; }\n",
+            "tbody th { text-align: left; white-space: nowrap; }\n",
+            "td { text-align: center; min-width: 3em; }\n",
+            "td small { display: block; color: #333; }\n",
+            "td.self { background: #eee; }\n",
+            "</style>\n</head>\n<body>\n",
+        ));
+        html.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+        html.push_str("<p>Rows are the track playing, columns the track mixed in next. Scores run from 0 (clashing keys, far-apart tempos) to 100.</p>\n");
+
+        html.push_str("<table>\n<thead>\n<tr><th></th>");
+        for index in 0..self.tracks.len() {
+            html.push_str(&format!("<th>{}</th>", escape(&self.label(index))));
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+        for (from, row) in self.cells.iter().enumerate() {
+            let from_label = self.label(from);
+            html.push_str(&format!("<tr><th>{}</th>", escape(&from_label)));
+            for (to, cell) in row.iter().enumerate() {
+                match cell {
+                    None => html.push_str("<td class=\"self\"></td>"),
+                    Some(cell) => html.push_str(&format!(
+                        "<td style=\"background: {}\" title=\"{} &#8594; {}: {}\">{:.0}<small>{}</small></td>",
+                        heat_color(cell.score),
+                        escape(&from_label),
+                        escape(&self.label(to)),
+                        escape(&cell.summary()),
+                        cell.score,
+                        escape(&cell.summary()),
+                    )),
+                }
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        html
+    }
+
+    pub fn write_html(&self, output: impl AsRef<Path>, title: &str) -> Result<()> {
+        report_dir::write_atomic(output, self.render_html(title))
+    }
+}
+
+// Red at 0 through yellow to green at 100
+fn heat_color(score: f64) -> String {
+    format!("hsl({:.0}, 70%, 70%)", score.clamp(0.0, 100.0) * 1.2)
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::{AudioError, AudioFile, Result};
+use crate::exporters::percent_decode;
+
+pub mod harmonic;
+pub mod matrix;
+
+// M3U/M3U8 playlists as written by DJ software and media players. Entries
+// keep their location as written, so relative playlists stay relative when
+// saved back next to the original.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    // As written in the playlist
+    pub location: String,
+    // Resolved against the playlist's folder
+    pub path: PathBuf,
+    // From #EXTINF
+    pub duration_secs: Option<f64>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    pub entries: Vec<PlaylistEntry>,
+    // Folder relative locations are resolved against
+    pub base_dir: PathBuf,
+}
+
+impl Playlist {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| AudioError::io(path, e))?;
+        let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(Self::parse(&String::from_utf8_lossy(&bytes), base_dir))
+    }
+
+    pub fn parse(text: &str, base_dir: impl Into<PathBuf>) -> Self {
+        let base_dir = base_dir.into();
+        let mut entries = Vec::new();
+        let mut info: Option<(Option<f64>, Option<String>)> = None;
+
+        for line in text.lines() {
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                let (duration, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+                let duration = duration.split_whitespace().next()
+                    .and_then(|d| d.parse::<f64>().ok())
+                    .filter(|d| *d >= 0.0);
+                let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+                info = Some((duration, title));
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let (duration_secs, title) = info.take().unwrap_or((None, None));
+            entries.push(PlaylistEntry {
+                location: line.to_string(),
+                path: resolve(line, &base_dir),
+                duration_secs,
+                title,
+            });
+        }
+
+        Self { entries, base_dir }
+    }
+
+    // Playlist of library files with absolute locations
+    pub fn from_files<'a>(files: impl IntoIterator<Item = &'a AudioFile>) -> Self {
+        let entries = files.into_iter().map(|file| {
+            let title = match (&file.artist, &file.title) {
+                (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+                (None, Some(title)) => Some(title.clone()),
+                _ => None,
+            };
+            PlaylistEntry {
+                location: file.path.to_string_lossy().into_owned(),
+                path: file.path.clone(),
+                duration_secs: file.duration_secs,
+                title,
+            }
+        }).collect();
+        Self { entries, base_dir: PathBuf::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Writes an extended M3U. Relative locations are kept when saving next to
+    // the original playlist and made absolute otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let same_dir = path.parent().unwrap_or(Path::new("")) == self.base_dir;
+        let mut text = String::from("#EXTM3U\n");
+        for entry in &self.entries {
+            if entry.duration_secs.is_some() || entry.title.is_some() {
+                let duration = entry.duration_secs.map_or(-1, |d| d.round() as i64);
+                text.push_str(&format!("#EXTINF:{},{}\n", duration, entry.title.as_deref().unwrap_or_default()));
+            }
+            if same_dir || is_absolute_location(&entry.location) {
+                text.push_str(&entry.location);
+            } else {
+                text.push_str(&entry.path.to_string_lossy());
+            }
+            text.push('\n');
+        }
+        fs::write(path, text).map_err(|e| AudioError::io(path, e))
+    }
+
+    // Same playlist with entries in the given order
+    pub fn reordered(&self, order: &[usize]) -> Self {
+        Self {
+            entries: order.iter().map(|&index| self.entries[index].clone()).collect(),
+            base_dir: self.base_dir.clone(),
+        }
+    }
+}
+
+fn resolve(location: &str, base_dir: &Path) -> PathBuf {
+    if let Some(url) = location.strip_prefix("file://") {
+        // file:///path or file://localhost/path
+        let path = url.strip_prefix("localhost").unwrap_or(url);
+        let path = percent_decode(path);
+        // file:///C:/Music -> C:/Music
+        let windows_drive = path.len() > 2 && path.as_bytes()[2] == b':';
+        return PathBuf::from(if windows_drive { &path[1..] } else { &path[..] });
+    }
+    let path = Path::new(location);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base_dir.join(path)
+    }
+}
+
+fn is_absolute_location(location: &str) -> bool {
+    location.starts_with("file://") || Path::new(location).is_absolute()
+}
@@ -0,0 +1,195 @@
+// pyo3 0.22 macro expansion trips this lint on every PyResult-returning function
+#![allow(clippy::useless_conversion)]
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use crate::{AudioError, AudioFile, MetadataExtractor};
+use crate::analyzers::bitrate::{BitrateAnalyzer, BitrateStats};
+use crate::analyzers::duplicate::{DuplicateAnalyzer, DuplicateMatch};
+
+// Python bindings, built with maturin (see pyproject.toml). The exposed
+// classes are plain read-only records so they behave like dataclasses in
+// notebooks.
+
+impl From<AudioError> for PyErr {
+    fn from(e: AudioError) -> Self {
+        PyRuntimeError::new_err(e.to_string())
+    }
+}
+
+#[pyclass(name = "AudioFile", frozen)]
+#[derive(Clone)]
+pub struct PyAudioFile {
+    inner: AudioFile,
+}
+
+#[pymethods]
+impl PyAudioFile {
+    #[getter]
+    fn path(&self) -> PathBuf {
+        self.inner.path.clone()
+    }
+
+    #[getter]
+    fn file_name(&self) -> &str {
+        &self.inner.file_name
+    }
+
+    #[getter]
+    fn size_bytes(&self) -> u64 {
+        self.inner.size_bytes
+    }
+
+    #[getter]
+    fn duration_secs(&self) -> Option<f64> {
+        self.inner.duration_secs
+    }
+
+    #[getter]
+    fn duration_ms(&self) -> Option<u64> {
+        self.inner.duration_ms
+    }
+
+    #[getter]
+    fn bitrate(&self) -> Option<u32> {
+        self.inner.bitrate
+    }
+
+    #[getter]
+    fn artist(&self) -> Option<&str> {
+        self.inner.artist.as_deref()
+    }
+
+    #[getter]
+    fn title(&self) -> Option<&str> {
+        self.inner.title.as_deref()
+    }
+
+    #[getter]
+    fn album(&self) -> Option<&str> {
+        self.inner.album.as_deref()
+    }
+
+    #[getter]
+    fn label(&self) -> Option<&str> {
+        self.inner.label.as_deref()
+    }
+
+    #[getter]
+    fn key(&self) -> Option<&str> {
+        self.inner.key.as_deref()
+    }
+
+    #[getter]
+    fn bpm(&self) -> Option<f64> {
+        self.inner.bpm
+    }
+
+    fn __repr__(&self) -> String {
+        format!("AudioFile(file_name={:?}, bitrate={:?}, size_bytes={})",
+            self.inner.file_name, self.inner.bitrate, self.inner.size_bytes)
+    }
+}
+
+impl From<AudioFile> for PyAudioFile {
+    fn from(inner: AudioFile) -> Self {
+        Self { inner }
+    }
+}
+
+#[pyclass(name = "DuplicateMatch", get_all, frozen)]
+pub struct PyDuplicateMatch {
+    higher_quality: PyAudioFile,
+    lower_quality: PyAudioFile,
+    match_reason: String,
+    quality_difference: String,
+    match_class: String,
+}
+
+#[pymethods]
+impl PyDuplicateMatch {
+    fn __repr__(&self) -> String {
+        format!("DuplicateMatch(higher_quality={:?}, lower_quality={:?}, match_reason={:?})",
+            self.higher_quality.inner.file_name, self.lower_quality.inner.file_name, self.match_reason)
+    }
+}
+
+impl From<DuplicateMatch> for PyDuplicateMatch {
+    fn from(dup: DuplicateMatch) -> Self {
+        Self {
+            higher_quality: Arc::unwrap_or_clone(dup.higher_quality).into(),
+            lower_quality: Arc::unwrap_or_clone(dup.lower_quality).into(),
+            match_reason: dup.match_reason,
+            quality_difference: dup.quality_difference,
+            match_class: dup.match_class.as_str().to_string(),
+        }
+    }
+}
+
+#[pyclass(name = "BitrateStats", get_all, frozen)]
+pub struct PyBitrateStats {
+    file_count: usize,
+    category_distribution: HashMap<String, usize>,
+    average_bitrate: f64,
+    min_bitrate: u32,
+    max_bitrate: u32,
+}
+
+#[pymethods]
+impl PyBitrateStats {
+    fn __repr__(&self) -> String {
+        format!("BitrateStats(file_count={}, average_bitrate={:.1}, min_bitrate={}, max_bitrate={})",
+            self.file_count, self.average_bitrate, self.min_bitrate, self.max_bitrate)
+    }
+}
+
+impl From<BitrateStats> for PyBitrateStats {
+    fn from(stats: BitrateStats) -> Self {
+        Self {
+            file_count: stats.file_count,
+            category_distribution: stats.category_distribution.into_iter()
+                .map(|(category, count)| (category.to_string(), count))
+                .collect(),
+            average_bitrate: stats.average_bitrate,
+            min_bitrate: stats.min_bitrate,
+            max_bitrate: stats.max_bitrate,
+        }
+    }
+}
+
+#[pyfunction]
+fn scan(py: Python<'_>, path: PathBuf) -> PyResult<Vec<PyAudioFile>> {
+    let files = py.allow_threads(|| MetadataExtractor::process_directory(path))?;
+    Ok(files.into_iter().map(PyAudioFile::from).collect())
+}
+
+#[pyfunction]
+fn find_duplicates(py: Python<'_>, files: Vec<PyRef<'_, PyAudioFile>>) -> Vec<PyDuplicateMatch> {
+    let files: Vec<AudioFile> = files.iter().map(|f| f.inner.clone()).collect();
+    let results = py.allow_threads(|| DuplicateAnalyzer::new(1.0).find_duplicates(files));
+    results.groups.into_iter()
+        .flat_map(|group| group.duplicates)
+        .map(PyDuplicateMatch::from)
+        .collect()
+}
+
+#[pyfunction]
+fn analyze_bitrate(py: Python<'_>, files: Vec<PyRef<'_, PyAudioFile>>) -> PyBitrateStats {
+    let files: Vec<AudioFile> = files.iter().map(|f| f.inner.clone()).collect();
+    py.allow_threads(|| BitrateAnalyzer::new().analyze(&files)).into()
+}
+
+#[pymodule]
+fn dj_library_manager(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("SCHEMA_VERSION", crate::schema::SCHEMA_VERSION)?;
+    m.add_class::<PyAudioFile>()?;
+    m.add_class::<PyDuplicateMatch>()?;
+    m.add_class::<PyBitrateStats>()?;
+    m.add_function(wrap_pyfunction!(scan, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(analyze_bitrate, m)?)?;
+    Ok(())
+}
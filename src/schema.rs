@@ -0,0 +1,76 @@
+// Public JSON schema of the documents other tools consume: the FFI and
+// Python results and the JSON the CLI writes for scans. The generated
+// schemas are checked in under `schemas/v<SCHEMA_VERSION>/` and a test
+// keeps them identical to what the types produce, so a change to the
+// serialized shape can't slip into a release unnoticed.
+//
+// Within a version, changes are additive only (new optional fields). Renaming,
+// removing or retyping a field, or making one required, bumps the version
+// and adds a new schema directory; the old one stays for existing consumers.
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[cfg(feature = "schema")]
+pub use generate::{schemas, write_schemas, Schema};
+
+#[cfg(feature = "schema")]
+mod generate {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use schemars::gen::SchemaSettings;
+    use schemars::schema::RootSchema;
+    use schemars::JsonSchema;
+    use crate::{AudioError, AudioFile, Result};
+    use crate::analyzers::bitrate::BitrateStats;
+    use crate::analyzers::duplicate::DuplicateResults;
+    use super::SCHEMA_VERSION;
+
+    const BASE_URL: &str = "https://github.com/Dalaciu/dj-library-manager/schemas";
+
+    pub struct Schema {
+        // File name without extension, e.g. "audio_file"
+        pub name: &'static str,
+        pub schema: RootSchema,
+    }
+
+    impl Schema {
+        fn of<T: JsonSchema>(name: &'static str) -> Self {
+            let mut schema = SchemaSettings::draft07().into_generator().into_root_schema_for::<T>();
+            schema.schema.metadata().id = Some(format!("{}/v{}/{}.json", BASE_URL, SCHEMA_VERSION, name));
+            Self { name, schema }
+        }
+
+        pub fn file_name(&self) -> String {
+            format!("{}.json", self.name)
+        }
+
+        pub fn to_json(&self) -> Result<String> {
+            serde_json::to_string_pretty(&self.schema)
+                .map(|json| json + "\n")
+                .map_err(|e| AudioError::Metadata(e.to_string()))
+        }
+    }
+
+    // `djlm_scan_directory` / `scan()` return an array of audio files;
+    // `djlm_find_duplicates` and `djlm_analyze_bitrate` the other two
+    pub fn schemas() -> Vec<Schema> {
+        vec![
+            Schema::of::<AudioFile>("audio_file"),
+            Schema::of::<Vec<AudioFile>>("audio_files"),
+            Schema::of::<DuplicateResults>("duplicate_results"),
+            Schema::of::<BitrateStats>("bitrate_stats"),
+        ]
+    }
+
+    // Writes every schema into `dir`; returns the files written
+    pub fn write_schemas(dir: &Path) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir).map_err(|e| AudioError::io(dir, e))?;
+        schemas().iter()
+            .map(|schema| {
+                let path = dir.join(schema.file_name());
+                fs::write(&path, schema.to_json()?).map_err(|e| AudioError::io(&path, e))?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::{AudioError, Result};
+use crate::utils::file_ops::{FileOperation, JournalEntry};
+use crate::utils::paths;
+
+// Audit trail of file operations, read back from the operation journals
+// every command writes through `FileOperationExecutor`. Moves and deletes
+// are what changed the library; copies are left out unless asked for.
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    // Unix seconds
+    pub timestamp: u64,
+    pub user: Option<String>,
+    #[serde(flatten)]
+    pub operation: FileOperation,
+    // Journal the operation was recorded in, i.e. the run
+    pub journal: PathBuf,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    // Unix seconds, inclusive
+    pub since: Option<u64>,
+    // Unix seconds, exclusive
+    pub until: Option<u64>,
+    // Source or destination at or under this path
+    pub path: Option<PathBuf>,
+    pub user: Option<String>,
+    pub include_copies: bool,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        let operation = &entry.operation;
+        (self.include_copies || !matches!(operation, FileOperation::Copy { .. }))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self.user.as_ref().is_none_or(|user| entry.user.as_ref().is_some_and(|u| u.eq_ignore_ascii_case(user)))
+            && self.path.as_ref().is_none_or(|path| {
+                operation.source().starts_with(path) || operation.destination().is_some_and(|to| to.starts_with(path))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuditLog {
+    // Oldest first
+    pub entries: Vec<AuditEntry>,
+    pub journals_read: usize,
+    // Lines that didn't parse, e.g. the last line of a run that crashed
+    pub skipped_lines: usize,
+}
+
+// Every journal in the given directories (the per-user journal directory
+// when empty), filtered
+pub fn read_audit(journal_dirs: &[PathBuf], filter: &AuditFilter) -> Result<AuditLog> {
+    let default_dirs = [paths::journal_dir()];
+    let journal_dirs = if journal_dirs.is_empty() { &default_dirs[..] } else { journal_dirs };
+
+    let mut log = AuditLog::default();
+    for dir in journal_dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            // No journal directory yet means nothing was ever done
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(AudioError::io(dir, e)),
+        };
+        let mut journals: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect();
+        journals.sort();
+        for journal in journals {
+            read_journal_into(&journal, filter, &mut log)?;
+        }
+    }
+    log.entries.sort_by_key(|entry| entry.timestamp);
+    Ok(log)
+}
+
+fn read_journal_into(journal: &Path, filter: &AuditFilter, log: &mut AuditLog) -> Result<()> {
+    let content = fs::read_to_string(journal).map_err(|e| AudioError::io(journal, e))?;
+    log.journals_read += 1;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let Ok(entry) = serde_json::from_str::<JournalEntry>(line) else {
+            log.skipped_lines += 1;
+            continue;
+        };
+        if filter.matches(&entry) {
+            log.entries.push(AuditEntry {
+                timestamp: entry.timestamp,
+                user: entry.user,
+                operation: entry.operation,
+                journal: journal.to_path_buf(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Start of a `YYYY-MM-DD` day (UTC) in Unix seconds
+pub fn parse_date(text: &str) -> Option<u64> {
+    let mut parts = text.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days).ok().map(|days| days * SECS_PER_DAY)
+}
+
+// End of the given day, for inclusive "until" dates
+pub fn end_of_day(start: u64) -> u64 {
+    start + SECS_PER_DAY
+}
+
+// `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_timestamp(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let secs_of_day = secs % SECS_PER_DAY;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, secs_of_day / 3600, secs_of_day % 3600 / 60, secs_of_day % 60)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{AudioError, AudioFile, Result};
+use crate::audio::stream;
+use crate::utils::{file_ops, hashing, unix_timestamp_millis};
+
+// Incremental library backups. A backup target holds content-addressed
+// objects (`objects/<aa>/<sha256>`) and one JSON snapshot per run
+// (`snapshots/<id>.json`) listing which object belongs to which file.
+// Files whose size and modification time match the previous snapshot are
+// not read again, and identical content is only ever stored once.
+
+const OBJECTS_DIR: &str = "objects";
+const SNAPSHOTS_DIR: &str = "snapshots";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupMode {
+    // Whole files
+    Full,
+    // Only the tag block in front of the audio (ID3v2, FLAC metadata), so
+    // cues, ratings and artwork survive a retag gone wrong at a fraction of
+    // the size. Restoring needs the audio files to still be there.
+    TagsOnly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    // Where the file was backed up from
+    pub path: PathBuf,
+    // Path below the scanned directory, starting with that directory's name
+    pub relative: PathBuf,
+    pub size_bytes: u64,
+    pub modified_secs: Option<u64>,
+    // SHA-256 of the stored object, which is also its name in the store
+    pub object: String,
+    pub object_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub created_at: u64,
+    pub mode: BackupMode,
+    pub roots: Vec<PathBuf>,
+    pub entries: Vec<SnapshotEntry>,
+    // The library index database, when it was included
+    pub index: Option<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupSummary {
+    // Objects written to the store by this run
+    pub stored: usize,
+    pub bytes_written: u64,
+    // Files whose content was already in the store
+    pub deduplicated: usize,
+    // Files skipped because size and modification time were unchanged
+    pub unchanged: usize,
+    // Files with no tag block to back up in tags-only mode (e.g. WAV)
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSummary {
+    pub restored: usize,
+    // Destinations that already matched the backup
+    pub unchanged: usize,
+    pub failed: usize,
+}
+
+pub struct BackupStore {
+    root: PathBuf,
+    dry_run: bool,
+}
+
+impl BackupStore {
+    pub fn open(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            dry_run: false,
+        }
+    }
+
+    // Hash and compare as usual but write nothing
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Snapshot IDs in the store, oldest first
+    pub fn snapshots(&self) -> Result<Vec<String>> {
+        let dir = self.root.join(SNAPSHOTS_DIR);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots: Vec<(u64, String)> = fs::read_dir(&dir)
+            .map_err(|e| AudioError::io(&dir, e))?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let id = name.strip_suffix(".json")?;
+                let created_at = id.strip_prefix("backup-")?.parse().ok()?;
+                Some((created_at, id.to_string()))
+            })
+            .collect();
+        snapshots.sort();
+        Ok(snapshots.into_iter().map(|(_, id)| id).collect())
+    }
+
+    // The given snapshot, or the latest one when `id` is None
+    pub fn load_snapshot(&self, id: Option<&str>) -> Result<Snapshot> {
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => self.snapshots()?.pop().ok_or_else(|| {
+                AudioError::Metadata(format!("No backups found in {}", self.root.display()))
+            })?,
+        };
+        let path = self.snapshot_path(&id);
+        let json = fs::read_to_string(&path).map_err(|e| AudioError::io(&path, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| AudioError::Metadata(format!("Invalid snapshot {}: {}", path.display(), e)))
+    }
+
+    // Backs up `files` (found under `roots`) and, if given, the index
+    // database, then writes the snapshot. Unchanged files are carried over
+    // from the latest snapshot taken in the same mode.
+    pub fn backup(
+        &self,
+        files: &[AudioFile],
+        roots: &[PathBuf],
+        mode: BackupMode,
+        index: Option<&Path>,
+    ) -> Result<(Snapshot, BackupSummary)> {
+        let previous: HashMap<PathBuf, SnapshotEntry> = self.latest_in_mode(mode)?
+            .map(|snapshot| snapshot.entries.into_iter().map(|e| (e.path.clone(), e)).collect())
+            .unwrap_or_default();
+
+        let created_at = unix_timestamp_millis();
+        let mut snapshot = Snapshot {
+            id: format!("backup-{}", created_at),
+            created_at,
+            mode,
+            roots: roots.to_vec(),
+            entries: Vec::with_capacity(files.len()),
+            index: None,
+        };
+        let mut summary = BackupSummary::default();
+        // Scanned paths are absolute; the roots may not be
+        let canonical_roots: Vec<PathBuf> = roots.iter()
+            .map(|root| fs::canonicalize(root).unwrap_or_else(|_| root.clone()))
+            .collect();
+
+        for (processed, file) in files.iter().enumerate() {
+            if mode == BackupMode::TagsOnly && stream::leading_tag_bytes(&file.path).is_none() {
+                summary.skipped += 1;
+            } else {
+                let relative = relative_to_roots(&file.path, &canonical_roots);
+                match self.back_up_file(&file.path, relative, mode, &previous, &mut summary) {
+                    Ok(entry) => snapshot.entries.push(entry),
+                    Err(e) => {
+                        eprintln!("Error backing up {}: {}", file.path.display(), e);
+                        summary.failed += 1;
+                    }
+                }
+            }
+            if (processed + 1) % 100 == 0 || processed + 1 == files.len() {
+                println!("Progress: {}/{} files", processed + 1, files.len());
+            }
+        }
+
+        if let Some(index) = index {
+            let relative = PathBuf::from(index.file_name().unwrap_or_default());
+            match self.back_up_file(index, relative, BackupMode::Full, &HashMap::new(), &mut summary) {
+                Ok(entry) => snapshot.index = Some(entry),
+                Err(e) => {
+                    eprintln!("Error backing up index: {}", e);
+                    summary.failed += 1;
+                }
+            }
+        }
+
+        if !self.dry_run {
+            let path = self.snapshot_path(&snapshot.id);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+            }
+            let json = serde_json::to_string_pretty(&snapshot).map_err(|e| AudioError::Metadata(e.to_string()))?;
+            fs::write(&path, json).map_err(|e| AudioError::io(&path, e))?;
+        }
+        Ok((snapshot, summary))
+    }
+
+    // Puts every file in the snapshot back. Files go to their original
+    // paths, or below `to` (keeping the scanned directory's name) if given.
+    // Objects are checked against their checksum before anything is
+    // overwritten, and destinations that already match are left alone.
+    pub fn restore(&self, snapshot: &Snapshot, to: Option<&Path>, index_to: Option<&Path>) -> Result<RestoreSummary> {
+        let mut summary = RestoreSummary::default();
+        let mut restore = |entry: &SnapshotEntry, destination: PathBuf, mode: BackupMode| {
+            match self.restore_entry(entry, &destination, mode) {
+                Ok(true) => {
+                    let verb = if self.dry_run { "Would restore" } else { "Restored" };
+                    println!("{}: {}", verb, destination.display());
+                    summary.restored += 1;
+                }
+                Ok(false) => summary.unchanged += 1,
+                Err(e) => {
+                    eprintln!("Error restoring {}: {}", destination.display(), e);
+                    summary.failed += 1;
+                }
+            }
+        };
+
+        for entry in &snapshot.entries {
+            let destination = match to {
+                Some(to) => to.join(&entry.relative),
+                None => entry.path.clone(),
+            };
+            restore(entry, destination, snapshot.mode);
+        }
+        if let (Some(entry), Some(index_to)) = (&snapshot.index, index_to) {
+            restore(entry, index_to.to_path_buf(), BackupMode::Full);
+        }
+        Ok(summary)
+    }
+
+    fn back_up_file(
+        &self,
+        path: &Path,
+        relative: PathBuf,
+        mode: BackupMode,
+        previous: &HashMap<PathBuf, SnapshotEntry>,
+        summary: &mut BackupSummary,
+    ) -> Result<SnapshotEntry> {
+        let metadata = fs::metadata(path).map_err(|e| AudioError::io(path, e))?;
+        let size_bytes = metadata.len();
+        let modified_secs = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        if let Some(entry) = previous.get(path) {
+            let same_file = entry.size_bytes == size_bytes
+                && modified_secs.is_some()
+                && entry.modified_secs == modified_secs;
+            if same_file && self.object_path(&entry.object).exists() {
+                summary.unchanged += 1;
+                return Ok(SnapshotEntry { relative, ..entry.clone() });
+            }
+        }
+
+        let (object, object_bytes) = match mode {
+            BackupMode::Full => self.store_file(path, summary)?,
+            BackupMode::TagsOnly => self.store_bytes(&stream::read_leading_tags(path)?, summary)?,
+        };
+
+        Ok(SnapshotEntry {
+            path: path.to_path_buf(),
+            relative,
+            size_bytes,
+            modified_secs,
+            object,
+            object_bytes,
+        })
+    }
+
+    // Streams the file into a temporary object while hashing it, then moves
+    // it into place under its hash
+    fn store_file(&self, path: &Path, summary: &mut BackupSummary) -> Result<(String, u64)> {
+        if self.dry_run {
+            let object = hashing::sha256_file(path)?;
+            let bytes = fs::metadata(path).map_err(|e| AudioError::io(path, e))?.len();
+            self.count_object(&object, bytes, summary);
+            return Ok((object, bytes));
+        }
+
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        let copied = copy_hashing(path, &temp);
+        let (object, bytes) = match copied {
+            Ok(copied) => copied,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        };
+        self.commit_object(&temp, &object, bytes, summary)?;
+        Ok((object, bytes))
+    }
+
+    fn store_bytes(&self, data: &[u8], summary: &mut BackupSummary) -> Result<(String, u64)> {
+        let object = hashing::sha256_bytes(data);
+        let bytes = data.len() as u64;
+        if self.dry_run {
+            self.count_object(&object, bytes, summary);
+            return Ok((object, bytes));
+        }
+
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        fs::write(&temp, data).map_err(|e| AudioError::io(&temp, e))?;
+        self.commit_object(&temp, &object, bytes, summary)?;
+        Ok((object, bytes))
+    }
+
+    fn commit_object(&self, temp: &Path, object: &str, bytes: u64, summary: &mut BackupSummary) -> Result<()> {
+        let destination = self.object_path(object);
+        if destination.exists() {
+            let _ = fs::remove_file(temp);
+            summary.deduplicated += 1;
+            return Ok(());
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        fs::rename(temp, &destination).map_err(|e| AudioError::io(&destination, e))?;
+        summary.stored += 1;
+        summary.bytes_written += bytes;
+        Ok(())
+    }
+
+    fn count_object(&self, object: &str, bytes: u64, summary: &mut BackupSummary) {
+        if self.object_path(object).exists() {
+            summary.deduplicated += 1;
+        } else {
+            summary.stored += 1;
+            summary.bytes_written += bytes;
+        }
+    }
+
+    // Ok(false) when the destination already matches
+    fn restore_entry(&self, entry: &SnapshotEntry, destination: &Path, mode: BackupMode) -> Result<bool> {
+        let object_path = self.object_path(&entry.object);
+        let stored = fs::read(&object_path).map_err(|e| AudioError::io(&object_path, e))?;
+        if hashing::sha256_bytes(&stored) != entry.object {
+            return Err(AudioError::Metadata(format!(
+                "Backup object {} is corrupt (checksum mismatch)", object_path.display()
+            )));
+        }
+
+        match mode {
+            BackupMode::Full => {
+                if destination.exists() && hashing::sha256_file(destination)? == entry.object {
+                    return Ok(false);
+                }
+                if self.dry_run {
+                    return Ok(true);
+                }
+                file_ops::ensure_writable(destination)?;
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+                }
+                // Write next to the destination and rename, so an interrupted
+                // restore never leaves a half-written track behind
+                let mut temp_name = destination.file_name().unwrap_or_default().to_os_string();
+                temp_name.push(".restore-tmp");
+                let temp = destination.with_file_name(temp_name);
+                fs::write(&temp, &stored).map_err(|e| AudioError::io(&temp, e))?;
+                fs::rename(&temp, destination).map_err(|e| AudioError::io(destination, e))?;
+            }
+            BackupMode::TagsOnly => {
+                // Keep the audio that's there now, swap in the saved tags
+                if stream::read_leading_tags(destination)? == stored {
+                    return Ok(false);
+                }
+                if !self.dry_run {
+                    stream::replace_leading_tags(destination, &stored)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn latest_in_mode(&self, mode: BackupMode) -> Result<Option<Snapshot>> {
+        for id in self.snapshots()?.iter().rev() {
+            let snapshot = self.load_snapshot(Some(id))?;
+            if snapshot.mode == mode {
+                return Ok(Some(snapshot));
+            }
+        }
+        Ok(None)
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.root.join(SNAPSHOTS_DIR).join(format!("{}.json", id))
+    }
+
+    fn object_path(&self, object: &str) -> PathBuf {
+        let prefix = object.get(..2).unwrap_or("00");
+        self.root.join(OBJECTS_DIR).join(prefix).join(object)
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.root.join(OBJECTS_DIR).join("tmp").join(format!("{}-{}", std::process::id(), unix_timestamp_millis()))
+    }
+}
+
+// Path below whichever root contains the file, prefixed with the root's own
+// name so several roots restore side by side
+fn relative_to_roots(path: &Path, roots: &[PathBuf]) -> PathBuf {
+    roots.iter()
+        .filter_map(|root| {
+            let relative = path.strip_prefix(root).ok()?;
+            Some(PathBuf::from(root.file_name().unwrap_or_default()).join(relative))
+        })
+        .min_by_key(|relative| relative.components().count())
+        .unwrap_or_else(|| PathBuf::from(path.file_name().unwrap_or_default()))
+}
+
+fn copy_hashing(from: &Path, to: &Path) -> Result<(String, u64)> {
+    let file = File::open(from).map_err(|e| AudioError::io(from, e))?;
+    let mut reader = BufReader::with_capacity(1024 * 1024, file);
+    let mut writer = File::create(to).map_err(|e| AudioError::io(to, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut total = 0u64;
+
+    loop {
+        let read = reader.read(&mut buffer).map_err(|e| AudioError::io(from, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read]).map_err(|e| AudioError::io(to, e))?;
+        total += read as u64;
+    }
+    writer.sync_all().map_err(|e| AudioError::io(to, e))?;
+
+    Ok((hashing::to_hex(&hasher.finalize()), total))
+}
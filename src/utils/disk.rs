@@ -0,0 +1,73 @@
+use std::fs::Metadata;
+use std::path::Path;
+
+// Bytes actually allocated on disk. This differs from the logical length for
+// sparse files and on compressed volumes (NTFS compression, APFS), and is what
+// the OS frees when the file is deleted.
+#[cfg(unix)]
+pub fn allocated_size(_path: &Path, metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always in 512-byte units regardless of the filesystem block size
+    Some(metadata.blocks() * 512)
+}
+
+#[cfg(windows)]
+pub fn allocated_size(path: &Path, _metadata: &Metadata) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{GetLastError, NO_ERROR};
+    use windows_sys::Win32::Storage::FileSystem::{GetCompressedFileSizeW, INVALID_FILE_SIZE};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut high: u32 = 0;
+    // SAFETY: `wide` is a NUL-terminated UTF-16 path and `high` outlives the call
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != NO_ERROR {
+        return None;
+    }
+    Some(((high as u64) << 32) | low as u64)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn allocated_size(_path: &Path, _metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+pub type DeviceId = u64;
+
+// Identifier of the volume holding `path`. Paths that don't exist yet (e.g. a
+// move destination) resolve to their nearest existing ancestor.
+pub fn device_id(path: &Path) -> Option<DeviceId> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    volume_id(existing)
+}
+
+pub fn same_device(a: &Path, b: &Path) -> bool {
+    match (device_id(a), device_id(b)) {
+        (Some(a), Some(b)) => a == b,
+        // If we can't tell, assume the worst and treat it as a cross-device operation
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn volume_id(path: &Path) -> Option<DeviceId> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn volume_id(path: &Path) -> Option<DeviceId> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::path::Component;
+
+    // Drive letter or UNC share identifies the volume
+    let canonical = std::fs::canonicalize(path).ok()?;
+    let prefix = canonical.components().find_map(|c| match c {
+        Component::Prefix(prefix) => Some(prefix.as_os_str().to_ascii_lowercase()),
+        _ => None,
+    })?;
+    let mut hasher = DefaultHasher::new();
+    prefix.hash(&mut hasher);
+    Some(hasher.finish())
+}
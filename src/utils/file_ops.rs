@@ -1,71 +1,572 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
-use std::fs;
-use crate::Result;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, Result};
+use crate::utils::disk::{self, DeviceId};
+use crate::utils::{paths, unix_timestamp_millis};
 
+// Process-wide read-only switch (--read-only). Once set, the executor and
+// every tag writer refuse to touch library files; reports, the index and
+// other files outside the library are still written.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::SeqCst);
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+// Guard for code that writes library files without going through the
+// executor, e.g. tag writers
+pub fn ensure_writable(path: &Path) -> Result<()> {
+    if is_read_only() {
+        return Err(AudioError::ReadOnly { path: path.to_path_buf() });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FileOperation {
+    Move { from: PathBuf, to: PathBuf },
+    Copy { from: PathBuf, to: PathBuf },
+    Delete { path: PathBuf },
+}
+
+impl FileOperation {
+    pub fn source(&self) -> &Path {
+        match self {
+            Self::Move { from, .. } | Self::Copy { from, .. } => from,
+            Self::Delete { path } => path,
+        }
+    }
+
+    pub fn destination(&self) -> Option<&Path> {
+        match self {
+            Self::Move { to, .. } | Self::Copy { to, .. } => Some(to),
+            Self::Delete { .. } => None,
+        }
+    }
+
+    fn with_destination(&self, destination: PathBuf) -> Self {
+        match self {
+            Self::Move { from, .. } => Self::Move { from: from.clone(), to: destination },
+            Self::Copy { from, .. } => Self::Copy { from: from.clone(), to: destination },
+            Self::Delete { .. } => self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    // Pick a free name next to the existing file
+    Rename,
+    // Leave the existing destination alone and skip the operation
+    Skip,
+    Overwrite,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperationOutcome {
+    // The operation ran; holds the operation as actually performed
+    // (destinations may differ from the request after collision handling)
+    Performed(FileOperation),
+    // Dry run: what would have been performed
+    Planned(FileOperation),
+    Skipped { operation: FileOperation, reason: String },
+}
+
+// Operations sharing a source device, executed together to avoid seek thrash
+// when several spinning disks are involved
+#[derive(Debug, Clone)]
+pub struct DeviceGroup {
+    pub device: Option<DeviceId>,
+    // (index into the original operation list, operation)
+    pub operations: Vec<(usize, FileOperation)>,
+}
+
+pub fn group_by_device(operations: &[FileOperation]) -> Vec<DeviceGroup> {
+    let mut groups: Vec<DeviceGroup> = Vec::new();
+    for (index, operation) in operations.iter().enumerate() {
+        let device = disk::device_id(operation.source());
+        match groups.iter_mut().find(|g| g.device == device) {
+            Some(group) => group.operations.push((index, operation.clone())),
+            None => groups.push(DeviceGroup {
+                device,
+                operations: vec![(index, operation.clone())],
+            }),
+        }
+    }
+    groups
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    // Account that ran the command; missing in journals from older builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(flatten)]
+    pub operation: FileOperation,
+}
+
+// Single place where commands touch library files. Every move, copy and
+// delete goes through here so dry-run, verification, collision handling and
+// journaling behave the same for all commands.
+pub struct FileOperationExecutor {
+    dry_run: bool,
+    verify: bool,
+    collision_policy: CollisionPolicy,
+    rename_label: String,
+    // Opened on the first operation performed, so a run that changes
+    // nothing leaves no journal behind
+    journal: Option<Mutex<Option<BufWriter<File>>>>,
+    journal_path: Option<PathBuf>,
+    // Destinations taken by earlier operations of this run. A dry run (or a
+    // script) leaves the disk as it was, so two files planned onto the same
+    // name would otherwise both get it.
+    reserved: Mutex<HashSet<PathBuf>>,
+}
+
+impl Default for FileOperationExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileOperationExecutor {
+    pub fn new() -> Self {
+        Self {
+            dry_run: false,
+            verify: false,
+            collision_policy: CollisionPolicy::Rename,
+            rename_label: "copy".to_string(),
+            journal: None,
+            journal_path: None,
+            reserved: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    // Label used when renaming on collision: `track_<label>_1.mp3`
+    pub fn with_rename_label(mut self, label: impl Into<String>) -> Self {
+        self.rename_label = label.into();
+        self
+    }
+
+    // Appends every performed operation to a JSON-lines journal, created
+    // with the first one. Dry runs and read-only runs never write a journal.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>) -> Result<Self> {
+        if self.dry_run || is_read_only() {
+            return Ok(self);
+        }
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        self.journal = Some(Mutex::new(None));
+        self.journal_path = Some(path);
+        Ok(self)
+    }
+
+    // Journal in the per-user journal directory, one file per run
+    pub fn with_default_journal(self) -> Result<Self> {
+        let path = paths::journal_dir().join(format!("journal-{}.jsonl", unix_timestamp_millis()));
+        self.with_journal(path)
+    }
+
+    // Default journal named after the command that ran, so `undo` can find
+    // that command's latest run: `journal-<millis>-<command>.jsonl`
+    pub fn with_command_journal(self, command: &str) -> Result<Self> {
+        let path = paths::journal_dir().join(format!("journal-{}-{}.jsonl", unix_timestamp_millis(), command));
+        self.with_journal(path)
+    }
+
+    // None until an operation has been journaled
+    pub fn journal_path(&self) -> Option<&Path> {
+        let opened = self.journal.as_ref()?.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+        self.journal_path.as_deref().filter(|_| opened)
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn execute(&self, operation: &FileOperation) -> Result<OperationOutcome> {
+        let operation = match self.resolve_collision(operation)? {
+            Some(resolved) => resolved,
+            None => {
+                return Ok(OperationOutcome::Skipped {
+                    operation: operation.clone(),
+                    reason: "Destination already exists".to_string(),
+                })
+            }
+        };
+
+        if self.dry_run {
+            return Ok(OperationOutcome::Planned(operation));
+        }
+        ensure_writable(operation.source())?;
+
+        match &operation {
+            FileOperation::Move { from, to } => self.move_file(from, to)?,
+            FileOperation::Copy { from, to } => self.copy_file(from, to)?,
+            FileOperation::Delete { path } => {
+                fs::remove_file(path).map_err(|e| AudioError::io(path, e))?
+            }
+        }
+
+        self.record(&operation)?;
+        Ok(OperationOutcome::Performed(operation))
+    }
+
+    pub fn execute_all(&self, operations: &[FileOperation]) -> Vec<Result<OperationOutcome>> {
+        operations.iter().map(|op| self.execute(op)).collect()
+    }
+
+    // Executes operations one source device at a time, reporting progress per
+    // device. Results are returned in the order of `operations`.
+    pub fn execute_grouped(&self, operations: &[FileOperation]) -> Vec<Result<OperationOutcome>> {
+        let groups = group_by_device(operations);
+        let group_count = groups.len();
+        let mut results: Vec<Option<Result<OperationOutcome>>> = operations.iter().map(|_| None).collect();
+
+        for (group_index, group) in groups.iter().enumerate() {
+            let device_label = group.device.map_or("unknown".to_string(), |d| d.to_string());
+            let total = group.operations.len();
+            if group_count > 1 {
+                println!("Device {}/{} ({}): {} operations", group_index + 1, group_count, device_label, total);
+            }
+
+            for (done, (index, operation)) in group.operations.iter().enumerate() {
+                results[*index] = Some(self.execute(operation));
+                let done = done + 1;
+                if group_count > 1 && (done % 100 == 0 || done == total) {
+                    println!("  Device {}: {}/{} operations done", device_label, done, total);
+                }
+            }
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    pub fn ensure_directory(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if self.dry_run {
+            return Ok(());
+        }
+        ensure_writable(path)?;
+        fs::create_dir_all(path).map_err(|e| AudioError::io(path, e))
+    }
+
+    // Resolves against the disk and the destinations reserved so far, then
+    // reserves the destination picked; a move frees its source again
+    fn resolve_collision(&self, operation: &FileOperation) -> Result<Option<FileOperation>> {
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        let resolved = self.resolve_against(operation, &reserved)?;
+        if let Some(resolved) = &resolved {
+            if let FileOperation::Move { from, .. } = resolved {
+                reserved.remove(from);
+            }
+            if let Some(destination) = resolved.destination() {
+                reserved.insert(destination.to_path_buf());
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn resolve_against(&self, operation: &FileOperation, reserved: &HashSet<PathBuf>) -> Result<Option<FileOperation>> {
+        let taken = |path: &Path| path.exists() || reserved.contains(path);
+        let destination = match operation.destination() {
+            Some(destination) if taken(destination) => destination,
+            _ => return Ok(Some(operation.clone())),
+        };
+
+        match self.collision_policy {
+            CollisionPolicy::Overwrite => Ok(Some(operation.clone())),
+            CollisionPolicy::Skip => Ok(None),
+            CollisionPolicy::Fail => Err(AudioError::io(destination, std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                "Destination already exists",
+            ))),
+            CollisionPolicy::Rename => {
+                let parent = destination.parent().unwrap_or_else(|| Path::new(""));
+                let file_stem = destination.file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("file");
+                let extension = destination.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| format!(".{}", ext))
+                    .unwrap_or_default();
+
+                let mut counter = 1;
+                loop {
+                    let new_name = format!("{}_{}_{}{}", file_stem, self.rename_label, counter, extension);
+                    let new_path = parent.join(new_name);
+                    if !taken(&new_path) {
+                        break Ok(Some(operation.with_destination(new_path)));
+                    }
+                    counter += 1;
+                }
+            }
+        }
+    }
+
+    fn move_file(&self, from: &Path, to: &Path) -> Result<()> {
+        if let Some(parent) = to.parent() {
+            self.ensure_directory(parent)?;
+        }
+        let expected_size = file_size(from)?;
+
+        // Renames can't cross volumes: copy, always verify, and only then delete
+        if !disk::same_device(from, to) {
+            self.copy_verified(from, to, true)?;
+            return fs::remove_file(from).map_err(|e| AudioError::io(from, e));
+        }
+
+        match fs::rename(from, to) {
+            Ok(_) => {
+                if self.verify && file_size(to)? != expected_size {
+                    return Err(verification_error(to));
+                }
+                Ok(())
+            }
+            // Device detection can be fooled (bind mounts, network shares)
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+                self.copy_verified(from, to, true)?;
+                fs::remove_file(from).map_err(|e| AudioError::io(from, e))
+            }
+            Err(e) => Err(AudioError::io(from, e)),
+        }
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path) -> Result<()> {
+        self.copy_verified(from, to, self.verify)
+    }
+
+    fn copy_verified(&self, from: &Path, to: &Path, verify: bool) -> Result<()> {
+        if let Some(parent) = to.parent() {
+            self.ensure_directory(parent)?;
+        }
+        fs::copy(from, to).map_err(|e| AudioError::io(from, e))?;
+        if verify && !files_identical(from, to)? {
+            // Don't leave a corrupt copy behind
+            let _ = fs::remove_file(to);
+            return Err(verification_error(to));
+        }
+        Ok(())
+    }
+
+    fn record(&self, operation: &FileOperation) -> Result<()> {
+        let (Some(journal), Some(path)) = (&self.journal, &self.journal_path) else {
+            return Ok(());
+        };
+        let entry = JournalEntry {
+            timestamp: unix_timestamp_millis() / 1000,
+            user: current_user(),
+            operation: operation.clone(),
+        };
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| AudioError::Metadata(e.to_string()))?;
+
+        let mut journal = journal.lock().unwrap_or_else(|e| e.into_inner());
+        let writer = match journal.as_mut() {
+            Some(writer) => writer,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| AudioError::io(path, e))?;
+                journal.insert(BufWriter::new(file))
+            }
+        };
+        writeln!(writer, "{}", line)?;
+        // Flush per entry so the journal survives a crash mid-run
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// Reads back a journal written by `FileOperationExecutor`
+pub fn read_journal(path: impl AsRef<Path>) -> Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|e| AudioError::io(path, e))?;
+    content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line)
+            .map_err(|e| AudioError::Metadata(format!("Invalid journal entry in {}: {}", path.display(), e))))
+        .collect()
+}
+
+// Most recent journal written with `with_command_journal` by any of
+// `commands`. Empty journals, left by older builds for runs that changed
+// nothing, are passed over.
+pub fn latest_command_journal(commands: &[&str]) -> Option<PathBuf> {
+    let suffixes: Vec<String> = commands.iter().map(|command| format!("-{}.jsonl", command)).collect();
+    fs::read_dir(paths::journal_dir()).ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("journal-") && suffixes.iter().any(|suffix| name.ends_with(suffix))))
+        .filter(|p| fs::metadata(p).is_ok_and(|m| m.len() > 0))
+        // Fixed-width millisecond timestamps sort by name
+        .max()
+}
+
+// Moves that put back what a journal recorded, newest first so chained
+// moves unwind in order, and the operations that can't be undone: deletes,
+// and copies, which are left alone rather than deleted
+pub fn undo_operations(entries: &[JournalEntry]) -> (Vec<FileOperation>, Vec<FileOperation>) {
+    let mut moves = Vec::new();
+    let mut kept = Vec::new();
+    for entry in entries.iter().rev() {
+        match &entry.operation {
+            FileOperation::Move { from, to } => moves.push(FileOperation::Move { from: to.clone(), to: from.clone() }),
+            operation => kept.push(operation.clone()),
+        }
+    }
+    (moves, kept)
+}
+
+fn current_user() -> Option<String> {
+    ["USER", "USERNAME"].iter()
+        .find_map(|var| std::env::var(var).ok())
+        .filter(|user| !user.is_empty())
+}
+
+fn file_size(path: &Path) -> Result<u64> {
+    fs::metadata(path)
+        .map(|m| m.len())
+        .map_err(|e| AudioError::io(path, e))
+}
+
+fn verification_error(path: &Path) -> AudioError {
+    AudioError::io(path, std::io::Error::new(
+        ErrorKind::InvalidData,
+        "Verification failed: destination does not match source",
+    ))
+}
+
+fn files_identical(a: &Path, b: &Path) -> Result<bool> {
+    if file_size(a)? != file_size(b)? {
+        return Ok(false);
+    }
+    let mut file_a = File::open(a).map_err(|e| AudioError::io(a, e))?;
+    let mut file_b = File::open(b).map_err(|e| AudioError::io(b, e))?;
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+    loop {
+        let read = file_a.read(&mut buf_a).map_err(|e| AudioError::io(a, e))?;
+        if read == 0 {
+            return Ok(true);
+        }
+        file_b.read_exact(&mut buf_b[..read]).map_err(|e| AudioError::io(b, e))?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+// Moves duplicates into a quarantine directory
 pub struct FileManager {
     duplicate_dir: PathBuf,
+    executor: FileOperationExecutor,
 }
 
 impl FileManager {
     pub fn new(duplicate_dir: impl Into<PathBuf>) -> Self {
+        Self::with_executor(duplicate_dir, FileOperationExecutor::new())
+    }
+
+    pub fn with_executor(duplicate_dir: impl Into<PathBuf>, executor: FileOperationExecutor) -> Self {
         Self {
             duplicate_dir: duplicate_dir.into(),
+            executor: executor
+                .with_collision_policy(CollisionPolicy::Rename)
+                .with_rename_label("duplicate"),
         }
     }
 
+    pub fn executor(&self) -> &FileOperationExecutor {
+        &self.executor
+    }
+
     pub fn move_duplicate(&self, file_path: impl AsRef<Path>) -> Result<PathBuf> {
         let file_path = file_path.as_ref();
+        let operation = self.plan_move(file_path)?;
+        let outcome = self.executor.execute(&operation)?;
+        Self::destination_of(file_path, outcome)
+    }
+
+    // Moves several files at once, grouped per source device. Returns the new
+    // location (or error) for each input path, in input order.
+    pub fn move_duplicates(&self, file_paths: &[&Path]) -> Vec<Result<PathBuf>> {
+        let operations: Vec<Result<FileOperation>> = file_paths.iter()
+            .map(|path| self.plan_move(path))
+            .collect();
+        let valid: Vec<FileOperation> = operations.iter()
+            .filter_map(|op| op.as_ref().ok().cloned())
+            .collect();
+        let mut outcomes = self.executor.execute_grouped(&valid).into_iter();
+
+        operations.into_iter()
+            .zip(file_paths)
+            .map(|(operation, path)| {
+                operation?;
+                let outcome = outcomes.next().expect("one outcome per planned operation")?;
+                Self::destination_of(path, outcome)
+            })
+            .collect()
+    }
+
+    pub fn ensure_directory(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.executor.ensure_directory(path)
+    }
+
+    fn plan_move(&self, file_path: &Path) -> Result<FileOperation> {
         let file_name = file_path.file_name()
-            .ok_or_else(|| std::io::Error::new(
+            .ok_or_else(|| AudioError::io(file_path, std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "Invalid file path"
-            ))?;
-
-        // Create duplicate directory if it doesn't exist
-        fs::create_dir_all(&self.duplicate_dir)?;
-
-        let destination = self.duplicate_dir.join(file_name);
-        
-        // Handle case where file already exists in destination
-        let final_destination = if destination.exists() {
-            let mut counter = 1;
-            let file_stem = file_name.to_str().unwrap_or("duplicate");
-            let extension = file_path.extension()
-                .and_then(|ext| ext.to_str())
-                .unwrap_or("");
-                
-            loop {
-                let new_name = format!("{}_{}_{}.{}", file_stem, "duplicate", counter, extension);
-                let new_path = self.duplicate_dir.join(new_name);
-                if !new_path.exists() {
-                    break new_path;
-                }
-                counter += 1;
-            }
-        } else {
-            destination
-        };
+            )))?;
 
-        // Try to move the file first
-        match fs::rename(file_path, &final_destination) {
-            Ok(_) => Ok(final_destination),
-            Err(e) => {
-                // Check if error is about cross-device move
-                if e.to_string().contains("cannot move") || 
-                   e.to_string().contains("different disk drive") {
-                    // Fall back to copy + delete
-                    fs::copy(file_path, &final_destination)?;
-                    fs::remove_file(file_path)?;
-                    Ok(final_destination)
-                } else {
-                    Err(e.into())
-                }
-            }
-        }
+        Ok(FileOperation::Move {
+            from: file_path.to_path_buf(),
+            to: self.duplicate_dir.join(file_name),
+        })
     }
 
-    pub fn ensure_directory(&self, path: impl AsRef<Path>) -> Result<()> {
-        fs::create_dir_all(path.as_ref())?;
-        Ok(())
+    fn destination_of(file_path: &Path, outcome: OperationOutcome) -> Result<PathBuf> {
+        match outcome {
+            OperationOutcome::Performed(op) | OperationOutcome::Planned(op) => {
+                Ok(op.destination().map(Path::to_path_buf).unwrap_or_default())
+            }
+            OperationOutcome::Skipped { reason, .. } => Err(AudioError::io(file_path, std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                reason,
+            ))),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use sha2::{Digest, Sha256};
+use crate::{AudioError, Result};
+
+// Hex-encoded SHA-256 of a file's contents, streamed so large files don't
+// need to fit in memory
+pub fn sha256_file(path: impl AsRef<Path>) -> Result<String> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| AudioError::io(path, e))?;
+    sha256_reader(path, file)
+}
+
+// Same for `length` bytes starting at `offset`, e.g. only the audio stream
+pub fn sha256_file_range(path: impl AsRef<Path>, offset: u64, length: u64) -> Result<String> {
+    let path = path.as_ref();
+    let mut file = File::open(path).map_err(|e| AudioError::io(path, e))?;
+    file.seek(SeekFrom::Start(offset)).map_err(|e| AudioError::io(path, e))?;
+    sha256_reader(path, file.take(length))
+}
+
+fn sha256_reader(path: &Path, reader: impl Read) -> Result<String> {
+    let mut reader = BufReader::with_capacity(1024 * 1024, reader);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = reader.read(&mut buffer).map_err(|e| AudioError::io(path, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn sha256_bytes(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,3 +1,27 @@
 pub mod parallel;
+pub mod pipeline;
 pub mod file_ops;
-pub mod reporting;
\ No newline at end of file
+pub mod audit;
+pub mod disk;
+pub mod hashing;
+pub mod backup;
+pub mod vault;
+pub mod volumes;
+pub mod paths;
+pub mod progress;
+pub mod report_dir;
+pub mod report_sink;
+pub mod scan_log;
+pub mod scan_cache;
+pub mod script;
+#[cfg(feature = "reports-csv")]
+pub mod reporting;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) fn unix_timestamp_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
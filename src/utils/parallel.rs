@@ -1,20 +1,91 @@
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::utils::pipeline;
+use crate::utils::progress::{self, Progress};
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+// Sets up the global worker pool. Without the `parallel` feature everything
+// runs on the calling thread and this is a no-op.
+pub fn init_global_pool(threads: usize) {
+    #[cfg(feature = "parallel")]
+    {
+        // Building fails when the global pool already exists, which is fine
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global();
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = threads;
+}
+
+pub fn available_threads() -> usize {
+    #[cfg(feature = "parallel")]
+    return num_cpus::get();
+    #[cfg(not(feature = "parallel"))]
+    return 1;
+}
+
+pub fn current_threads() -> usize {
+    #[cfg(feature = "parallel")]
+    return rayon::current_num_threads();
+    #[cfg(not(feature = "parallel"))]
+    return 1;
+}
+
+// Files decoded at once by the analyzers that read the audio; 0 until set,
+// meaning as many as the worker pool has threads
+static DECODE_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+// Each decoding thread holds one file's samples, so this bounds the memory
+// analyzers use as well as their share of the CPU
+pub fn set_decode_threads(threads: usize) {
+    DECODE_THREADS.store(threads, Ordering::Relaxed);
+}
+
+pub fn decode_threads() -> usize {
+    match DECODE_THREADS.load(Ordering::Relaxed) {
+        0 => current_threads(),
+        threads => threads,
+    }
+}
+
 pub trait ParallelProcessor {
     fn init_parallel_processing() {
         // Configure thread pool if not already configured
-        if rayon::current_num_threads() == 1 {
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(num_cpus::get())
-                .build_global()
-                .expect("Failed to initialize thread pool");
+        if current_threads() == 1 {
+            init_global_pool(available_threads());
         }
-        println!("Using {} CPU threads for processing", rayon::current_num_threads());
+        println!("Using {} CPU threads for processing", current_threads());
     }
 
-    fn get_progress_counter() -> AtomicUsize {
-        AtomicUsize::new(0)
+    fn get_progress_counter(total: usize) -> Progress {
+        Progress::new(total)
+    }
+
+    fn parallel_map<T, F, R>(items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Send + Sync,
+    {
+        #[cfg(feature = "parallel")]
+        return items.par_iter().map(f).collect();
+        #[cfg(not(feature = "parallel"))]
+        return items.iter().map(f).collect();
+    }
+
+    // For work that decodes audio: runs on a pool of its own with
+    // `decode_threads()` threads, apart from the walk and metadata pool
+    fn decode_map<T, F, R>(items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Send + Sync,
+    {
+        let threads = decode_threads();
+        pipeline::bounded_map(items, threads, threads, f)
     }
 
     fn process_chunks<T, F, R>(items: Vec<T>, chunk_size: usize, f: F) -> Vec<R>
@@ -23,15 +94,16 @@ pub trait ParallelProcessor {
         R: Send,
         F: Fn(&[T]) -> Vec<R> + Send + Sync,
     {
-        let chunks: Vec<_> = items.chunks(chunk_size).collect();
-        
-        chunks.par_iter()
-            .enumerate()
-            .flat_map(|(chunk_index, chunk)| {
-                println!("Processing chunk {}/{} in parallel", chunk_index + 1, chunks.len());
-                f(chunk)
-            })
-            .collect()
+        let chunks: Vec<_> = items.chunks(chunk_size).enumerate().collect();
+        let total_chunks = chunks.len();
+
+        Self::parallel_map(&chunks, |(chunk_index, chunk)| {
+            println!("Processing chunk {}/{} in parallel", chunk_index + 1, total_chunks);
+            f(chunk)
+        })
+        .into_iter()
+        .flatten()
+        .collect()
     }
 
     fn parallel_compare<T, F, R>(items: &[T], comparison_fn: F) -> Vec<R>
@@ -40,15 +112,138 @@ pub trait ParallelProcessor {
         R: Send,
         F: Fn(&T, &T) -> Option<R> + Send + Sync,
     {
-        (0..items.len())
-            .into_par_iter()
-            .flat_map(|i| {
-                let items_slice = &items[i + 1..];
-                items_slice
-                    .iter()
-                    .filter_map(|item2| comparison_fn(&items[i], item2))
-                    .collect::<Vec<_>>()
-            })
-            .collect()
-    }
-}
\ No newline at end of file
+        let indices: Vec<usize> = (0..items.len()).collect();
+        Self::parallel_map(&indices, |&i| {
+            let items_slice = &items[i + 1..];
+            items_slice
+                .iter()
+                .filter_map(|item2| comparison_fn(&items[i], item2))
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+// Caps how many workers extract files at once. Throughput is measured in
+// windows of finished files; when it stays below half the best window for a
+// while, as on a fanless laptop that throttles once it heats up, the cap is
+// lowered, and it is raised a step at a time again once throughput holds.
+// Workers over the cap wait for a slot.
+#[derive(Debug)]
+pub struct AdaptiveThreads {
+    state: Mutex<AdaptiveState>,
+    slot_free: Condvar,
+    max: usize,
+    window: Duration,
+}
+
+#[derive(Debug)]
+struct AdaptiveState {
+    limit: usize,
+    active: usize,
+    window_started: Instant,
+    window_files: usize,
+    // Files per second of the best window since the cap last changed
+    peak_rate: f64,
+    slow_windows: u32,
+    steady_windows: u32,
+}
+
+// A window slower than this share of the peak counts as collapsed
+const COLLAPSE_RATIO: f64 = 0.5;
+// Collapsed windows in a row before backing off
+const SUSTAINED_WINDOWS: u32 = 3;
+// Steady windows in a row before trying one worker more
+const RECOVERY_WINDOWS: u32 = 12;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+
+impl AdaptiveThreads {
+    // Starts with all `max` workers
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            state: Mutex::new(AdaptiveState {
+                limit: max,
+                active: 0,
+                window_started: Instant::now(),
+                window_files: 0,
+                peak_rate: 0.0,
+                slow_windows: 0,
+                steady_windows: 0,
+            }),
+            slot_free: Condvar::new(),
+            max,
+            window: DEFAULT_WINDOW,
+        }
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    // Workers currently allowed to run at once
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).limit
+    }
+
+    // Runs `f` for one file once a worker slot is free
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.active >= state.limit {
+            state = self.slot_free.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.active += 1;
+        drop(state);
+
+        let result = f();
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.active -= 1;
+        state.window_files += 1;
+        if self.finish_window(&mut state) {
+            self.slot_free.notify_all();
+        } else {
+            self.slot_free.notify_one();
+        }
+        result
+    }
+
+    // Closes the window once it is long enough and holds a file per worker;
+    // true when the cap went up
+    fn finish_window(&self, state: &mut AdaptiveState) -> bool {
+        let elapsed = state.window_started.elapsed();
+        if elapsed < self.window || state.window_files < state.limit {
+            return false;
+        }
+        let files = state.window_files;
+        let rate = files as f64 / elapsed.as_secs_f64();
+        state.window_started = Instant::now();
+        state.window_files = 0;
+
+        if rate < state.peak_rate * COLLAPSE_RATIO {
+            state.steady_windows = 0;
+            state.slow_windows += 1;
+            if state.slow_windows >= SUSTAINED_WINDOWS && state.limit > 1 {
+                state.limit -= (state.limit / 4).max(1);
+                state.peak_rate = rate;
+                state.slow_windows = 0;
+                println!("Throughput fell to {}; backing off to {} workers", progress::format_rate(files, elapsed), state.limit);
+            }
+            return false;
+        }
+
+        state.slow_windows = 0;
+        state.peak_rate = state.peak_rate.max(rate);
+        state.steady_windows += 1;
+        if state.steady_windows >= RECOVERY_WINDOWS && state.limit < self.max {
+            state.limit += 1;
+            state.steady_windows = 0;
+            println!("Throughput holding; raising to {} workers", state.limit);
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,25 @@
+use std::path::PathBuf;
+
+const APP_DIR: &str = "dj-library-manager";
+
+// Per-user directory for state that outlives a single run (journals, indexes)
+pub fn data_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR)
+}
+
+pub fn journal_dir() -> PathBuf {
+    data_dir().join("journal")
+}
+
+pub fn runs_dir() -> PathBuf {
+    data_dir().join("runs")
+}
+
+// Per-user directory for settings (~/.config/dj-library-manager on Linux)
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR)
+}
@@ -0,0 +1,65 @@
+#[cfg(feature = "parallel")]
+use std::sync::{mpsc, Arc, Mutex};
+
+// One bounded stage of a pipeline. `source` is drawn on a thread of its own
+// and `f` runs on `workers` threads of the stage's own, with at most `queue`
+// items waiting between them: a source that runs ahead (a directory walk)
+// blocks once the queue is full instead of piling items up in memory, and a
+// slow stage (decoding) only ever holds back the one feeding it. Results
+// come back in source order. A panic in `f` is raised again on the calling
+// thread once the stage has wound down. Without the `parallel` feature
+// everything runs on the calling thread.
+pub fn bounded_map<S, F, O>(source: S, workers: usize, queue: usize, f: F) -> Vec<O>
+where
+    S: IntoIterator,
+    S::IntoIter: Send,
+    S::Item: Send,
+    O: Send,
+    F: Fn(S::Item) -> O + Sync,
+{
+    #[cfg(not(feature = "parallel"))]
+    {
+        let _ = (workers, queue);
+        source.into_iter().map(f).collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    {
+        let source = source.into_iter();
+        let (sender, receiver) = mpsc::sync_channel(queue.max(1));
+        // Only the workers hold the receiver, so once they have all stopped,
+        // panicked ones included, the source's next send fails and it stops too
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (result_sender, result_receiver) = mpsc::channel();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for item in source.enumerate() {
+                    if sender.send(item).is_err() {
+                        break;
+                    }
+                }
+            });
+            let handles: Vec<_> = (0..workers.max(1)).map(|_| {
+                let (receiver, result_sender, f) = (Arc::clone(&receiver), result_sender.clone(), &f);
+                scope.spawn(move || loop {
+                    let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    let Ok((index, item)) = next else { break };
+                    if result_sender.send((index, f(item))).is_err() {
+                        break;
+                    }
+                })
+            }).collect();
+            drop((receiver, result_sender));
+            for handle in handles {
+                if let Err(panic) = handle.join() {
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        });
+
+        let mut results: Vec<(usize, O)> = result_receiver.into_iter().collect();
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Work done so far across worker threads, with the throughput and the time
+// left estimated from the rate since the counter was created. The total can
+// still be growing, as when files are extracted while the walk finds more.
+pub struct Progress {
+    total: AtomicUsize,
+    counting: AtomicBool,
+    done: AtomicUsize,
+    started: Instant,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self { total: AtomicUsize::new(total), counting: AtomicBool::new(false), done: AtomicUsize::new(0), started: Instant::now() }
+    }
+
+    // A total of 0 that `found` adds to until `counted`
+    pub fn counting() -> Self {
+        Self { counting: AtomicBool::new(true), ..Self::new(0) }
+    }
+
+    pub fn found(&self, count: usize) {
+        self.total.fetch_add(count, Ordering::SeqCst);
+    }
+
+    pub fn counted(&self) {
+        self.counting.store(false, Ordering::SeqCst);
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    pub fn is_counting(&self) -> bool {
+        self.counting.load(Ordering::SeqCst)
+    }
+
+    // Adds `count` finished items; returns how many are done now
+    pub fn advance(&self, count: usize) -> usize {
+        self.done.fetch_add(count, Ordering::SeqCst) + count
+    }
+
+    // ", 1.2k files/min, ETA 3m10s" once `done` items are finished; no ETA
+    // when everything is, or while the total is still counting
+    pub fn rate(&self, done: usize) -> String {
+        let elapsed = self.started.elapsed();
+        let total = self.total();
+        let mut text = format!(", {}", format_rate(done, elapsed));
+        if done > 0 && done < total && !self.is_counting() {
+            let left = elapsed.mul_f64((total - done) as f64 / done as f64);
+            text.push_str(&format!(", ETA {}", format_duration(left)));
+        }
+        text
+    }
+}
+
+// Wall-clock time spent in each phase of a command (walking the folders,
+// extracting metadata, analyzing, matching, moving). Phases repeated, such
+// as walking several input folders, add up.
+#[derive(Debug)]
+pub struct PhaseTimer {
+    started: Instant,
+    phases: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+pub const WALKING: &str = "walking";
+pub const EXTRACTING: &str = "extracting";
+pub const ANALYZING: &str = "analyzing";
+pub const MATCHING: &str = "matching";
+pub const MOVING: &str = "moving";
+
+impl Default for PhaseTimer {
+    fn default() -> Self {
+        Self { started: Instant::now(), phases: Mutex::new(Vec::new()) }
+    }
+}
+
+impl PhaseTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, phase: &'static str, elapsed: Duration) {
+        let mut phases = self.phases.lock().unwrap_or_else(|e| e.into_inner());
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    pub fn time<T>(&self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.add(phase, started.elapsed());
+        result
+    }
+
+    // In the order they first ran
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.phases.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // "took 12m34s, 1.2k files/min (walking 3s, extracting 10m02s, ...)"
+    pub fn summary(&self, files: usize) -> String {
+        let elapsed = self.elapsed();
+        let phases: Vec<String> = self.phases().into_iter()
+            .map(|(phase, time)| format!("{} {}", phase, format_duration(time)))
+            .collect();
+        let mut summary = format!("took {}, {}", format_duration(elapsed), format_rate(files, elapsed));
+        if !phases.is_empty() {
+            summary.push_str(&format!(" ({})", phases.join(", ")));
+        }
+        summary
+    }
+}
+
+// "850ms", "45s", "12m34s", "2h05m"
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+// Per second while that's over 100, per minute below, e.g. "1.2k files/min"
+pub fn format_rate(files: usize, elapsed: Duration) -> String {
+    let per_sec = files as f64 / elapsed.as_secs_f64().max(0.001);
+    let (rate, unit) = if per_sec >= 100.0 { (per_sec, "s") } else { (per_sec * 60.0, "min") };
+    if rate >= 1000.0 {
+        format!("{:.1}k files/{}", rate / 1000.0, unit)
+    } else {
+        format!("{:.0} files/{}", rate, unit)
+    }
+}
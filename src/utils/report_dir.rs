@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use quick_xml::escape::escape;
+use serde::Serialize;
+use crate::{AudioError, Result};
+use crate::exporters::percent_encode;
+use crate::utils::audit;
+use crate::utils::unix_timestamp_millis;
+
+// One folder per run for commands that write several artifacts, e.g.
+// `reports/bitrate-2024-05-01_21-30-00/{summary.csv,detailed.csv,...}`, so a
+// run never overwrites or mixes with an earlier one. `reports/latest` points
+// at the newest run (a symlink; a text file holding its name on platforms
+// without them).
+
+pub const LATEST: &str = "latest";
+
+// Reports are written under a `.partial` name next to their destination and
+// renamed into place once complete, so a run interrupted mid-write never
+// leaves a truncated report that looks finished
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+// Moves a complete `.partial` file onto its final name, replacing an earlier
+// report of that name; the partial file is removed if that fails
+pub fn commit_partial(path: &Path) -> Result<()> {
+    let partial = partial_path(path);
+    fs::rename(&partial, path).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        AudioError::io(path, e)
+    })
+}
+
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    let partial = partial_path(path);
+    if let Err(e) = fs::write(&partial, contents) {
+        let _ = fs::remove_file(&partial);
+        return Err(AudioError::io(path, e));
+    }
+    commit_partial(path)
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportRun {
+    dir: PathBuf,
+}
+
+impl ReportRun {
+    // Creates `<parent>/<prefix>-<UTC time>`, with a counter appended when a
+    // run already started within the same second
+    pub fn create(parent: impl AsRef<Path>, prefix: &str) -> Result<Self> {
+        let parent = parent.as_ref();
+        fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        let stamp = audit::format_timestamp(unix_timestamp_millis() / 1000)
+            .replace(' ', "_")
+            .replace(':', "-");
+        let base = format!("{}-{}", prefix, stamp);
+
+        let mut attempt = 1;
+        loop {
+            let name = if attempt == 1 { base.clone() } else { format!("{}-{}", base, attempt) };
+            let dir = parent.join(name);
+            match fs::create_dir(&dir) {
+                Ok(()) => return Ok(Self { dir }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(AudioError::io(&dir, e)),
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, file_name: &str) -> PathBuf {
+        self.dir.join(file_name)
+    }
+
+    // Points `latest` next to the run folder at it. An existing `latest`
+    // that isn't ours (a real folder) is left alone and reported.
+    pub fn mark_latest(&self) -> Result<PathBuf> {
+        let (Some(parent), Some(name)) = (self.dir.parent(), self.dir.file_name()) else {
+            return Err(AudioError::Metadata(format!("Report folder has no parent: {}", self.dir.display())));
+        };
+        let latest = parent.join(LATEST);
+        if let Ok(metadata) = fs::symlink_metadata(&latest) {
+            if metadata.is_dir() {
+                return Err(AudioError::io(&latest, std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists, "a folder with this name already exists",
+                )));
+            }
+            fs::remove_file(&latest).map_err(|e| AudioError::io(&latest, e))?;
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(name, &latest).map_err(|e| AudioError::io(&latest, e))?;
+        #[cfg(not(unix))]
+        fs::write(&latest, name.to_string_lossy().as_bytes()).map_err(|e| AudioError::io(&latest, e))?;
+        Ok(latest)
+    }
+}
+
+// Entry point to everything one run wrote: `summary.json` for automation and
+// `index.html` for people, both listing headline numbers and linking the
+// artifacts (relative links for files inside the folder)
+#[derive(Debug, Clone, Serialize)]
+pub struct RunIndex {
+    pub command: String,
+    // Unix milliseconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub inputs: Vec<PathBuf>,
+    pub metrics: Vec<Metric>,
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Metric {
+    // Stable identifier for scripts, e.g. `files_scanned`
+    pub key: String,
+    pub label: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub label: String,
+    pub path: PathBuf,
+}
+
+impl RunIndex {
+    pub fn new(command: &str, inputs: &[PathBuf]) -> Self {
+        Self {
+            command: command.to_string(),
+            started_at: unix_timestamp_millis(),
+            finished_at: 0,
+            inputs: inputs.to_vec(),
+            metrics: Vec::new(),
+            artifacts: Vec::new(),
+        }
+    }
+
+    pub fn add_metric(&mut self, key: &str, label: &str, value: impl Into<serde_json::Value>) {
+        self.metrics.push(Metric {
+            key: key.to_string(),
+            label: label.to_string(),
+            value: value.into(),
+        });
+    }
+
+    pub fn add_artifacts(&mut self, label: &str, paths: &[PathBuf]) {
+        self.artifacts.extend(paths.iter().map(|path| Artifact {
+            label: label.to_string(),
+            path: path.clone(),
+        }));
+    }
+
+    // Writes `summary.json` and `index.html` into `dir`
+    pub fn write(&mut self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        self.finished_at = unix_timestamp_millis();
+        for artifact in &mut self.artifacts {
+            if let Ok(relative) = artifact.path.strip_prefix(dir) {
+                artifact.path = relative.to_path_buf();
+            }
+        }
+
+        let json_path = dir.join("summary.json");
+        let json = serde_json::to_string_pretty(self).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        write_atomic(&json_path, json)?;
+        let html_path = dir.join("index.html");
+        write_atomic(&html_path, self.render_html())?;
+        Ok(vec![json_path, html_path])
+    }
+
+    pub fn render_html(&self) -> String {
+        let title = format!("{} run, {} UTC", self.command, audit::format_timestamp(self.started_at / 1000));
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape(&title)));
+        html.push_str(concat!(
+            "<style>\n",
+            "body { font-family: sans-serif; margin: 2em; }\n",
+            "table { border-collapse: collapse; }\n",
+            "th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n",
+            "td.value { text-align: right; }\n",
+            "</style>\n</head>\n<body>\n",
+        ));
+        html.push_str(&format!("<h1>{}</h1>\n", escape(&title)));
+
+        html.push_str("<p>Inputs:</p>\n<ul>\n");
+        for input in &self.inputs {
+            html.push_str(&format!("<li>{}</li>\n", escape(&input.to_string_lossy())));
+        }
+        html.push_str("</ul>\n");
+
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        for metric in &self.metrics {
+            let value = match &metric.value {
+                serde_json::Value::String(text) => text.clone(),
+                value => value.to_string(),
+            };
+            html.push_str(&format!("<tr><th>{}</th><td class=\"value\">{}</td></tr>\n", escape(&metric.label), escape(&value)));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Reports</h2>\n<ul>\n");
+        for artifact in &self.artifacts {
+            let path = artifact.path.to_string_lossy().replace('\\', "/");
+            let href = match (artifact.path.is_absolute(), path.starts_with('/')) {
+                (true, true) => format!("file://{}", percent_encode(&path)),
+                // Windows drive paths
+                (true, false) => format!("file:///{}", percent_encode(&path)),
+                (false, _) => percent_encode(&path),
+            };
+            html.push_str(&format!("<li>{}: <a href=\"{}\">{}</a></li>\n",
+                escape(&artifact.label), escape(&href), escape(&artifact.path.to_string_lossy())));
+        }
+        html.push_str("</ul>\n</body>\n</html>\n");
+        html
+    }
+}
@@ -1,87 +1,304 @@
-use std::path::Path;
-use csv::Writer;
-use crate::analyzers::bitrate::{BitrateStats, BitrateCategory};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use csv::{QuoteStyle, Writer, WriterBuilder};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use quick_xml::escape::escape;
+#[cfg(feature = "artwork")]
+use crate::analyzers::artwork::ArtworkReport;
+#[cfg(feature = "decode")]
+use crate::analyzers::authenticity::AuthenticityReport;
+#[cfg(feature = "decode")]
+use crate::analyzers::bpm::{BpmReport, BpmStatus};
+#[cfg(feature = "decode")]
+use crate::analyzers::explicit::ExplicitReport;
+#[cfg(feature = "decode")]
+use crate::analyzers::key::{KeyReport, KeyStatus};
+#[cfg(feature = "decode")]
+use crate::analyzers::vocals::VocalReport;
+use crate::analyzers::bitrate::{BitrateAnalyzer, BitrateStats, BitrateCategory, Grouping};
+use crate::analyzers::discs::SplitAlbum;
+use crate::analyzers::drift::LengthMismatch;
 use crate::analyzers::duplicate::DuplicateResults;
-use crate::AudioFile;
+use crate::analyzers::policy::PolicyViolation;
+use crate::analyzers::pool::DirtyOnlyTrack;
+use crate::analyzers::wishlist::{WishMatch, WishStatus};
+#[cfg(feature = "decode")]
+use crate::exporters::keys::KeyNotation;
+use crate::utils::audit::{self, AuditEntry};
+use crate::utils::file_ops::FileOperation;
+use crate::utils::report_dir;
+use crate::utils::report_sink::ReportSink;
+use crate::{AudioError, AudioFile};
 use crate::Result;
 
-pub struct Reporter;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    // An array with one object per row, keyed by column name
+    Json,
+    // A page with a sortable table, for sharing with people without a
+    // spreadsheet program
+    Html,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "html" | "htm" => Ok(Self::Html),
+            other => Err(format!("Unknown report format: {} (use csv, json or html)", other)),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    // File format of every table; the extension callers give is replaced
+    pub format: ReportFormat,
+    // Write `report.csv.gz` instead of `report.csv`
+    pub gzip: bool,
+    // Split large tables into numbered parts (`report.part001.csv`, ...)
+    // holding at most this many rows each, header repeated in every part
+    pub max_rows_per_file: Option<usize>,
+    // Also deliver every finished report here
+    pub sink: Option<Arc<dyn ReportSink>>,
+    // Start each CSV file with a UTF-8 byte order mark, without which Excel
+    // on Windows reads accented names in the local code page
+    pub bom: bool,
+}
+
+pub struct Reporter {
+    options: ReportOptions,
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Reporter {
     pub fn new() -> Self {
-        Self
+        Self {
+            options: ReportOptions::default(),
+        }
     }
 
-    pub fn generate_bitrate_report(&self, stats: &BitrateStats, files: &[AudioFile], output_path: impl AsRef<Path>) -> Result<()> {
-        let output_path_ref = output_path.as_ref();
-        let mut summary_path = output_path_ref.to_path_buf();
-        let mut detailed_path = output_path_ref.to_path_buf();
-        
-        // Create summary and detailed report paths
-        let file_stem = output_path_ref.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("bitrate");
-        
-        summary_path.set_file_name(format!("{}_summary.csv", file_stem));
-        detailed_path.set_file_name(format!("{}_detailed.csv", file_stem));
-
-        // Generate summary report
-        self.generate_summary_report(stats, &summary_path)?;
-        
-        // Generate detailed report
-        self.generate_detailed_report(files, &detailed_path)?;
+    pub fn with_options(mut self, options: ReportOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-        Ok(())
+    // Hands finished files to the sink, if any. A failed delivery is
+    // reported but the local files are still returned.
+    fn publish(&self, written: Vec<PathBuf>) -> Vec<PathBuf> {
+        if let Some(sink) = &self.options.sink {
+            for path in &written {
+                match sink.publish(path) {
+                    Ok(destination) => println!("Report delivered to: {}", destination),
+                    Err(e) => eprintln!("Error delivering report {}: {}", path.display(), e),
+                }
+            }
+        }
+        written
+    }
+
+    // `summary.csv` and `detailed.csv` in the run folder `dir`. Returns the
+    // paths of every file written
+    pub fn generate_bitrate_report(&self, stats: &BitrateStats, files: &[AudioFile], dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        // `summary.json` is the run summary's name
+        let summary = match self.options.format {
+            ReportFormat::Json => "bitrate_summary.json",
+            _ => "summary.csv",
+        };
+        let mut written = self.generate_summary_report(stats, &dir.join(summary))?;
+        written.extend(self.generate_detailed_report(files, &dir.join("detailed.csv"))?);
+        Ok(written)
     }
 
-    fn generate_summary_report(&self, stats: &BitrateStats, path: &Path) -> Result<()> {
-        let mut writer = Writer::from_path(path)?;
-        
-        // Write header
-        writer.write_record(&["Category", "File Count", "Percentage"])?;
+    // Average quality per artist or label (`by_artist.csv`) in the run folder
+    // `dir`, worst first
+    pub fn generate_group_report(&self, files: &[AudioFile], grouping: Grouping, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let path = dir.as_ref().join(format!("by_{}.csv", grouping.as_str().to_lowercase()));
 
-        let total_files: usize = stats.category_distribution.values().sum();
+        let mut writer = TableWriter::create(&path, [
+            grouping.as_str(),
+            "File Count",
+            "Average Bitrate (kbps)",
+            "Min Bitrate (kbps)",
+            "Low Quality Files",
+            "Low Quality (%)",
+            "Lossless Files",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for group in BitrateAnalyzer::quality_by(files, grouping) {
+            writer.write_record([
+                group.name.clone(),
+                group.file_count.to_string(),
+                format!("{:.1}", group.average_bitrate),
+                group.min_bitrate.to_string(),
+                group.low_count.to_string(),
+                format!("{:.1}", group.low_percent()),
+                group.lossless_count.to_string(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Per-{} report generated: {}", grouping.as_str().to_lowercase(), path.display());
+        }
+        Ok(written)
+    }
 
-        // Sort categories for consistent output (highest to lowest quality)
+    // Files below the minimum quality their folder or genre requires
+    pub fn generate_policy_report(&self, violations: &[PolicyViolation], path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(path.as_ref(), [
+            "File",
+            "Bitrate (kbps)",
+            "Required",
+            "Rule",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for violation in violations {
+            writer.write_record([
+                violation.path.display().to_string(),
+                violation.bitrate.to_string(),
+                violation.minimum.to_string(),
+                violation.rule.clone(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Quality policy report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // Files a scan couldn't read, with the error
+    pub fn generate_scan_error_report(&self, failures: &[(PathBuf, String)], path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(path.as_ref(), ["File", "Error"], self.options.max_rows_per_file, &self.options)?;
+        for (file, error) in failures {
+            writer.write_record([file.to_string_lossy().as_ref(), error.as_str()])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Scan error report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // Placeholder-size files (failed downloads) a scan left out, with where
+    // each was quarantined to, if anywhere
+    pub fn generate_placeholder_report(&self, placeholders: &[(PathBuf, u64, Option<PathBuf>)], path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(path.as_ref(), ["File", "Size (bytes)", "Quarantined To"], self.options.max_rows_per_file, &self.options)?;
+        for (file, size_bytes, quarantined) in placeholders {
+            writer.write_record([
+                file.display().to_string(),
+                size_bytes.to_string(),
+                quarantined.as_ref().map(|path| path.display().to_string()).unwrap_or_default(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Placeholder file report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    fn generate_summary_report(&self, stats: &BitrateStats, path: &Path) -> Result<Vec<PathBuf>> {
+        // The summary is always a single file
+        let mut writer = TableWriter::create(path, ["Category", "File Count", "Percentage"], None, &self.options)?;
+
+        // Sort categories for consistent output (highest to lowest quality),
+        // then files without a bitrate by cause
         let mut categories: Vec<_> = stats.category_distribution.iter().collect();
         categories.sort_by(|a, b| b.0.cmp(a.0));
+        let rows: Vec<(String, usize)> = categories.iter()
+            .map(|(category, count)| (category.to_string(), **count))
+            .chain(stats.unknown_causes.iter().map(|(cause, count)| (format!("Unknown Bitrate ({})", cause.label()), *count)))
+            .collect();
+        let total_files: usize = rows.iter().map(|(_, count)| count).sum();
 
         // Write distribution by category
-        for (category, count) in categories {
+        for (category, count) in &rows {
             let percentage = ((*count as f64 / total_files as f64) * 100.0).round();
-            writer.write_record(&[
-                category.to_string(),
+            writer.write_record([
+                category.clone(),
                 count.to_string(),
                 format!("{:.1}%", percentage),
             ])?;
         }
+        writer.set_chart("Bitrate Distribution", rows.iter()
+            .map(|(category, count)| (category.clone(), *count as f64))
+            .collect());
 
-        // Write summary
-        writer.write_record(&["", "", ""])?;
-        writer.write_record(&["Summary Statistics", "", ""])?;
-        writer.write_record(&["Total Files", &stats.file_count.to_string(), ""])?;
-        writer.write_record(&["Average Bitrate", &format!("{:.1} kbps", stats.average_bitrate), ""])?;
-        writer.write_record(&["Min Bitrate", &format!("{} kbps", stats.min_bitrate), ""])?;
-        writer.write_record(&["Max Bitrate", &format!("{} kbps", stats.max_bitrate), ""])?;
+        // Write summary, set apart by a blank line and heading in CSV; the
+        // other formats have no use for filler rows
+        if self.options.format == ReportFormat::Csv {
+            writer.write_record(["", "", ""])?;
+            writer.write_record(["Summary Statistics", "", ""])?;
+        }
+        writer.write_record(["Total Files", &stats.file_count.to_string(), ""])?;
+        writer.write_record(["Average Bitrate", &format!("{:.1} kbps", stats.average_bitrate), ""])?;
+        writer.write_record(["Min Bitrate", &format!("{} kbps", stats.min_bitrate), ""])?;
+        writer.write_record(["Max Bitrate", &format!("{} kbps", stats.max_bitrate), ""])?;
+        writer.write_record(["Total Size", &format!("{:.2} MB", stats.total_size_bytes as f64 / 1_048_576.0), ""])?;
+        writer.write_record(["Total Size on Disk", &format!("{:.2} MB", stats.total_allocated_bytes as f64 / 1_048_576.0), ""])?;
 
-        writer.flush()?;
-        println!("Summary report generated: {}", path.display());
-        Ok(())
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Summary report generated: {}", path.display());
+        }
+        Ok(written)
     }
 
-    fn generate_detailed_report(&self, files: &[AudioFile], path: &Path) -> Result<()> {
-        let mut writer = Writer::from_path(path)?;
-        
-        // Write header
-        writer.write_record(&[
+    fn generate_detailed_report(&self, files: &[AudioFile], path: &Path) -> Result<Vec<PathBuf>> {
+        let mut header = vec![
             "File Name",
             "Bitrate (kbps)",
             "Quality Category",
             "Size (MB)",
+            "Size on Disk (MB)",
             "Artist",
             "Title",
-            "Album"
-        ])?;
+            "Album",
+            "Label"
+        ];
+        // Tempo columns only when stability was measured for this run
+        let with_tempo = files.iter().any(|file| file.tempo_stability.is_some());
+        if with_tempo {
+            header.extend(["Tempo Stability (%)", "Beatgrid"]);
+        }
+        let mut writer = TableWriter::create(path, header, self.options.max_rows_per_file, &self.options)?;
 
         // Sort files by bitrate (highest to lowest)
         let mut sorted_files: Vec<&AudioFile> = files.iter().collect();
@@ -89,62 +306,774 @@ impl Reporter {
             b.bitrate.unwrap_or(0).cmp(&a.bitrate.unwrap_or(0))
         });
 
-        // Write file details
+        // Write file details; files without a bitrate say why, last
         for file in sorted_files {
-            if let Some(bitrate) = file.bitrate {
-                let category = BitrateCategory::from_bitrate(bitrate);
+            let category = match (file.bitrate, file.bitrate_unknown) {
+                (Some(bitrate), _) => Some(BitrateCategory::from_bitrate(bitrate).as_str().to_string()),
+                (None, Some(cause)) => Some(format!("Unknown Bitrate ({})", cause.label())),
+                (None, None) => None,
+            };
+            if let Some(category) = category {
                 let size_mb = file.size_bytes as f64 / 1_048_576.0; // Convert bytes to MB
+                let on_disk_mb = file.size_on_disk() as f64 / 1_048_576.0;
                 
-                writer.write_record(&[
-                    &file.file_name,
-                    &bitrate.to_string(),
-                    category.as_str(),
-                    &format!("{:.2}", size_mb),
-                    file.artist.as_deref().unwrap_or("Unknown"),
-                    file.title.as_deref().unwrap_or("Unknown"),
-                    file.album.as_deref().unwrap_or("Unknown"),
-                ])?;
+                let mut record = vec![
+                    file.file_name.clone(),
+                    file.bitrate.map_or(String::new(), |bitrate| bitrate.to_string()),
+                    category,
+                    format!("{:.2}", size_mb),
+                    format!("{:.2}", on_disk_mb),
+                    file.artist.as_deref().unwrap_or("Unknown").to_string(),
+                    file.title.as_deref().unwrap_or("Unknown").to_string(),
+                    file.album.as_deref().unwrap_or("Unknown").to_string(),
+                    file.label.as_deref().unwrap_or("Unknown").to_string(),
+                ];
+                if with_tempo {
+                    record.push(file.tempo_stability.map_or("Unknown".to_string(), |s| format!("{:.1}", s)));
+                    record.push(match file.needs_manual_grid() {
+                        Some(true) => "Needs manual grid",
+                        Some(false) => "Static",
+                        None => "Unknown",
+                    }.to_string());
+                }
+                writer.write_record(record)?;
             }
         }
 
-        writer.flush()?;
-        println!("Detailed report generated: {}", path.display());
-        Ok(())
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Detailed report generated: {}", path.display());
+        }
+        Ok(written)
     }
 
-    pub fn generate_duplicate_report(&self, results: &DuplicateResults, output_path: impl AsRef<Path>) -> Result<()> {
+    // Returns the paths of every file written
+    pub fn generate_duplicate_report(&self, results: &DuplicateResults, output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
         let output_path_ref = output_path.as_ref();
-        let mut writer = Writer::from_path(output_path_ref)?;
-        
-        writer.write_record(&[
+        let mut writer = TableWriter::create(output_path_ref, [
+            "Group",
             "Higher Quality File",
             "Higher Quality Size (MB)",
             "Higher Quality Bitrate",
             "Lower Quality File",
             "Lower Quality Size (MB)",
+            "Lower Quality Size on Disk (MB)",
             "Lower Quality Bitrate",
             "Match Reason",
+            "Match Class",
             "Quality Difference"
-        ])?;
+        ], self.options.max_rows_per_file, &self.options)?;
 
-        for dup_match in &results.matches {
+        // One row per duplicate, next to the copy its group keeps
+        let rows = results.groups.iter().enumerate()
+            .flat_map(|(number, group)| group.duplicates.iter().map(move |dup_match| (number + 1, dup_match)));
+        for (group, dup_match) in rows {
             let higher_size_mb = dup_match.higher_quality.size_bytes as f64 / 1_048_576.0;
             let lower_size_mb = dup_match.lower_quality.size_bytes as f64 / 1_048_576.0;
+            let lower_on_disk_mb = dup_match.lower_quality.size_on_disk() as f64 / 1_048_576.0;
 
-            writer.write_record(&[
+            writer.write_record([
+                &group.to_string(),
                 &dup_match.higher_quality.file_name,
                 &format!("{:.2}", higher_size_mb),
                 &dup_match.higher_quality.bitrate.map_or("Unknown".to_string(), |b| format!("{} kbps", b)),
                 &dup_match.lower_quality.file_name,
                 &format!("{:.2}", lower_size_mb),
+                &format!("{:.2}", lower_on_disk_mb),
                 &dup_match.lower_quality.bitrate.map_or("Unknown".to_string(), |b| format!("{} kbps", b)),
                 &dup_match.match_reason,
+                dup_match.match_class.as_str(),
                 &dup_match.quality_difference,
             ])?;
         }
 
-        writer.flush()?;
-        println!("Duplicate report generated: {}", output_path_ref.display());
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Duplicate report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per file, flagged ones first
+    #[cfg(feature = "artwork")]
+    pub fn generate_artwork_report(&self, reports: &[ArtworkReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Image Type",
+            "Width",
+            "Height",
+            "Cover Size (KB)",
+            "Findings",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&ArtworkReport> = reports.iter().collect();
+        sorted.sort_by_key(|report| report.flags.is_empty());
+
+        let optional = |value: Option<u32>| value.map_or("Unknown".to_string(), |v| v.to_string());
+        for report in sorted {
+            let findings: Vec<String> = report.flags.iter().map(ToString::to_string).collect();
+            let art = report.artwork.as_ref();
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                art.map_or("", |a| a.media_type.as_str()),
+                &art.map_or(String::new(), |a| optional(a.width)),
+                &art.map_or(String::new(), |a| optional(a.height)),
+                &art.map_or(String::new(), |a| format!("{:.1}", a.size_bytes as f64 / 1024.0)),
+                &findings.join("; "),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Artwork report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per checked file, suspicious ones first
+    #[cfg(feature = "decode")]
+    pub fn generate_authenticity_report(&self, reports: &[AuthenticityReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Sample Rate (Hz)",
+            "Claimed Bits",
+            "Effective Bits",
+            "Spectral Cutoff (Hz)",
+            "Suspicious",
+            "Findings",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&AuthenticityReport> = reports.iter().collect();
+        sorted.sort_by_key(|report| !report.is_suspicious());
+
+        let optional = |value: Option<u32>| value.map_or("Unknown".to_string(), |v| v.to_string());
+        for report in sorted {
+            let findings: Vec<String> = report.flags.iter().map(ToString::to_string).collect();
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                &report.sample_rate.to_string(),
+                &optional(report.claimed_bits),
+                &optional(report.effective_bits),
+                &optional(report.cutoff_hz),
+                if report.is_suspicious() { "Yes" } else { "No" },
+                &findings.join("; "),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Authenticity report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per analyzed file, vocal tracks first
+    #[cfg(feature = "decode")]
+    pub fn generate_vocal_report(&self, reports: &[VocalReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Vocal Score (%)",
+            "Classification",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&VocalReport> = reports.iter().collect();
+        sorted.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        for report in sorted {
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                &format!("{:.1}", report.score),
+                report.class.label(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Vocal report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per analyzed file, disagreements with the tag first
+    #[cfg(feature = "decode")]
+    pub fn generate_bpm_report(&self, reports: &[BpmReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "BPM",
+            "Tag BPM",
+            "Status",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&BpmReport> = reports.iter().collect();
+        sorted.sort_by_key(|report| (report.status() != BpmStatus::DiffersFromTag, report.path.clone()));
+
+        let format_bpm = |bpm: Option<f64>| bpm.map_or(String::new(), |bpm| format!("{:.2}", bpm));
+        for report in sorted {
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                &format_bpm(report.bpm),
+                &format_bpm(report.tag_bpm),
+                report.status().label(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("BPM report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per analyzed file in standard and Camelot notation,
+    // disagreements with the tag first
+    #[cfg(feature = "decode")]
+    pub fn generate_key_report(&self, reports: &[KeyReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Key",
+            "Camelot",
+            "Confidence (%)",
+            "Tag Key",
+            "Status",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&KeyReport> = reports.iter().collect();
+        sorted.sort_by_key(|report| (report.status() != KeyStatus::DiffersFromTag, report.path.clone()));
+
+        for report in sorted {
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                &report.key.map_or(String::new(), |key| key.format(KeyNotation::Classical)),
+                &report.key.map_or(String::new(), |key| key.format(KeyNotation::Camelot)),
+                &report.confidence.map_or(String::new(), |confidence| format!("{:.1}", confidence)),
+                &report.tag_key.map_or(String::new(), |key| key.format(KeyNotation::Classical)),
+                report.status().label(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Key report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per journaled operation, oldest first
+    pub fn generate_audit_report(&self, entries: &[AuditEntry], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "Time (UTC)",
+            "User",
+            "Operation",
+            "Source",
+            "Destination",
+            "Journal",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for entry in entries {
+            let operation = match entry.operation {
+                FileOperation::Move { .. } => "Move",
+                FileOperation::Copy { .. } => "Copy",
+                FileOperation::Delete { .. } => "Delete",
+            };
+            writer.write_record([
+                audit::format_timestamp(entry.timestamp).as_str(),
+                entry.user.as_deref().unwrap_or("Unknown"),
+                operation,
+                &entry.operation.source().display().to_string(),
+                &entry.operation.destination().map_or(String::new(), |to| to.display().to_string()),
+                &entry.journal.display().to_string(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Audit report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per dirty version of each track without a clean one
+    pub fn generate_dirty_only_report(&self, tracks: &[DirtyOnlyTrack], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "Artist",
+            "Title",
+            "Version",
+            "File",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for track in tracks {
+            for version in &track.versions {
+                writer.write_record([
+                    &track.artist,
+                    &track.title,
+                    version.version.as_deref().unwrap_or(""),
+                    &version.path.display().to_string(),
+                ])?;
+            }
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Dirty-only report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    pub fn generate_length_mismatch_report(&self, mismatches: &[LengthMismatch], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Artist",
+            "Title",
+            "Analyzed Length (s)",
+            "File Length (s)",
+            "Difference (s)",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for mismatch in mismatches {
+            writer.write_record([
+                &mismatch.path.display().to_string(),
+                mismatch.artist.as_deref().unwrap_or(""),
+                mismatch.title.as_deref().unwrap_or(""),
+                &format!("{:.1}", mismatch.collection_secs),
+                &format!("{:.1}", mismatch.file_secs),
+                &format!("{:+.1}", mismatch.difference_secs()),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Length mismatch report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // The shopping list: wish-list tracks not in the library, then those
+    // only there in another version
+    pub fn generate_wishlist_report(&self, matches: &[WishMatch], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "Artist",
+            "Title",
+            "Status",
+            "Library Copy",
+            "Link",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for status in [WishStatus::Missing, WishStatus::OtherVersion] {
+            for wish in matches.iter().filter(|wish| wish.status == status) {
+                writer.write_record([
+                    wish.entry.artist.as_str(),
+                    wish.entry.title.as_str(),
+                    status.as_str(),
+                    &wish.library_path().map(|path| path.display().to_string()).unwrap_or_default(),
+                    wish.entry.link.as_deref().unwrap_or(""),
+                ])?;
+            }
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Wish-list report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // Every track of an imported playlist in playlist order, with the best
+    // local copy and its quality
+    pub fn generate_playlist_gap_report(&self, matches: &[WishMatch], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "Position",
+            "Artist",
+            "Title",
+            "Status",
+            "Bitrate (kbps)",
+            "Quality Category",
+            "Library Copy",
+            "Link",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for (position, wish) in matches.iter().enumerate() {
+            let bitrate = wish.library_copy.as_ref().and_then(|file| file.bitrate);
+            writer.write_record([
+                (position + 1).to_string(),
+                wish.entry.artist.clone(),
+                wish.entry.title.clone(),
+                wish.status.as_str().to_string(),
+                bitrate.map(|bitrate| bitrate.to_string()).unwrap_or_default(),
+                bitrate.map(|bitrate| BitrateCategory::from_bitrate(bitrate).as_str().to_string()).unwrap_or_default(),
+                wish.library_path().map(|path| path.display().to_string()).unwrap_or_default(),
+                wish.entry.link.clone().unwrap_or_default(),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Playlist report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per disc folder of each split album
+    pub fn generate_split_album_report(&self, albums: &[SplitAlbum], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "Album",
+            "Merge Into",
+            "Disc",
+            "Folder",
+            "Tracks",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        for album in albums {
+            for disc in &album.discs {
+                writer.write_record([
+                    &album.name,
+                    &album.target.display().to_string(),
+                    &disc.disc.to_string(),
+                    &disc.path.display().to_string(),
+                    &disc.tracks.len().to_string(),
+                ])?;
+            }
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Split album report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+
+    // One row per checked file, explicit tracks first
+    #[cfg(feature = "decode")]
+    pub fn generate_explicit_report(&self, reports: &[ExplicitReport], output_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut writer = TableWriter::create(output_path.as_ref(), [
+            "File",
+            "Status",
+            "Source",
+            "Matched Words",
+        ], self.options.max_rows_per_file, &self.options)?;
+
+        let mut sorted: Vec<&ExplicitReport> = reports.iter().collect();
+        sorted.sort_by_key(|report| (report.status, report.path.clone()));
+
+        for report in sorted {
+            writer.write_record([
+                report.path.display().to_string().as_str(),
+                report.status.label(),
+                report.source.map_or("", |source| source.label()),
+                &report.matched_words.join(" "),
+            ])?;
+        }
+
+        let written = self.publish(writer.finish()?);
+        for path in &written {
+            println!("Explicit content report generated: {}", path.display());
+        }
+        Ok(written)
+    }
+}
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Sink {
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut w) => w.flush(),
+            Self::Gzip(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(w) => w.write(buf),
+            Self::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(w) => w.flush(),
+            Self::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+// Table that rolls over into numbered part files past a row limit and
+// optionally gzips its output. Parts are only numbered once a second one is
+// needed; small tables keep the plain file name. In CSV, fields holding
+// commas, quotes or line breaks (titles and file names do) are quoted, and
+// a row with a different number of fields than the header is an error
+// rather than a table whose columns shift. JSON and HTML parts are kept in
+// memory and rendered when the part closes.
+struct TableWriter {
+    base: PathBuf,
+    header: Vec<String>,
+    max_rows: Option<usize>,
+    format: ReportFormat,
+    gzip: bool,
+    bom: bool,
+    // Bar chart above an HTML table: title, and a label and value per bar
+    chart: Option<(String, Vec<(String, f64)>)>,
+    part: usize,
+    rows_in_part: usize,
+    current: Option<(PathBuf, Part)>,
+    written: Vec<PathBuf>,
+}
+
+enum Part {
+    Csv(Box<Writer<Sink>>),
+    Rendered(Sink, Vec<Vec<String>>),
+}
+
+impl TableWriter {
+    fn create<I, T>(base: &Path, header: I, max_rows: Option<usize>, options: &ReportOptions) -> Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut writer = Self {
+            base: base.with_extension(options.format.extension()),
+            header: header.into_iter().map(|h| h.as_ref().to_string()).collect(),
+            max_rows: max_rows.filter(|&rows| rows > 0),
+            format: options.format,
+            gzip: options.gzip,
+            bom: options.bom,
+            chart: None,
+            part: 0,
+            rows_in_part: 0,
+            current: None,
+            written: Vec::new(),
+        };
+        writer.open_part()?;
+        Ok(writer)
+    }
+
+    // Only HTML shows it; every part gets the whole chart
+    fn set_chart(&mut self, title: &str, bars: Vec<(String, f64)>) {
+        self.chart = Some((title.to_string(), bars));
+    }
+
+    fn write_record<I, T>(&mut self, record: I) -> Result<()>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        if self.max_rows.is_some_and(|max| self.rows_in_part >= max) {
+            self.close_part()?;
+            self.open_part()?;
+        }
+        match self.current.as_mut() {
+            Some((_, Part::Csv(writer))) => writer.write_record(record)?,
+            Some((path, Part::Rendered(_, rows))) => {
+                let row: Vec<String> = record.into_iter()
+                    .map(|field| String::from_utf8_lossy(field.as_ref()).into_owned())
+                    .collect();
+                if row.len() != self.header.len() {
+                    return Err(AudioError::Metadata(format!(
+                        "Report row has {} fields but {} has {} columns", row.len(), path.display(), self.header.len()
+                    )));
+                }
+                rows.push(row);
+            }
+            None => {}
+        }
+        self.rows_in_part += 1;
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Parts are only renamed to their final names here, so an interrupted
+    // run leaves `.partial` files rather than truncated reports
+    fn finish(mut self) -> Result<Vec<PathBuf>> {
+        self.close_part()?;
+
+        // A single part keeps the unnumbered name
+        let single = self.written.len() == 1 && self.part == 1;
+        let mut finished = Vec::with_capacity(self.written.len());
+        while !self.written.is_empty() {
+            let path = self.written.remove(0);
+            let partial = report_dir::partial_path(&path);
+            let target = if single { self.path_for(None) } else { path };
+            std::fs::rename(&partial, &target).map_err(|e| {
+                let _ = std::fs::remove_file(&partial);
+                AudioError::io(&target, e)
+            })?;
+            finished.push(target);
+        }
+        Ok(finished)
+    }
+
+    fn open_part(&mut self) -> Result<()> {
+        self.part += 1;
+        let path = if self.max_rows.is_some() {
+            self.path_for(Some(self.part))
+        } else {
+            self.path_for(None)
+        };
+
+        let partial = report_dir::partial_path(&path);
+        let file = File::create(&partial).map_err(|e| AudioError::io(&partial, e))?;
+        let mut sink = if self.gzip {
+            Sink::Gzip(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Sink::Plain(BufWriter::new(file))
+        };
+        if self.format != ReportFormat::Csv {
+            self.current = Some((path, Part::Rendered(sink, Vec::new())));
+            self.rows_in_part = 0;
+            return Ok(());
+        }
+        // In every part, as each may be opened on its own
+        if self.bom {
+            sink.write_all("\u{feff}".as_bytes()).map_err(|e| AudioError::io(&partial, e))?;
+        }
+        let mut writer = WriterBuilder::new()
+            .quote_style(QuoteStyle::Necessary)
+            .flexible(false)
+            .from_writer(sink);
+        writer.write_record(&self.header)?;
+        self.current = Some((path, Part::Csv(Box::new(writer))));
+        self.rows_in_part = 0;
+        Ok(())
+    }
+
+    fn close_part(&mut self) -> Result<()> {
+        let Some((path, part)) = self.current.take() else {
+            return Ok(());
+        };
+        self.written.push(path.clone());
+        let sink = match part {
+            Part::Csv(writer) => (*writer).into_inner().map_err(|e| AudioError::io(&path, e.into_error()))?,
+            Part::Rendered(mut sink, rows) => {
+                let text = match self.format {
+                    ReportFormat::Html => self.render_html(&path, &rows),
+                    _ => self.render_json(&rows),
+                };
+                sink.write_all(text.as_bytes()).map_err(|e| AudioError::io(&path, e))?;
+                sink
+            }
+        };
+        sink.finish().map_err(|e| AudioError::io(&path, e))
+    }
+
+    // One object per line, keys in column order
+    fn render_json(&self, rows: &[Vec<String>]) -> String {
+        let objects: Vec<String> = rows.iter()
+            .map(|row| {
+                let fields: Vec<String> = self.header.iter()
+                    .zip(row)
+                    .map(|(column, value)| format!("{}: {}", json_string(column), json_string(value)))
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+        if objects.is_empty() {
+            return "[]\n".to_string();
+        }
+        format!("[\n{}\n]\n", objects.join(",\n"))
+    }
+
+    fn render_html(&self, path: &Path, rows: &[Vec<String>]) -> String {
+        let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().trim_end_matches(".gz").to_string());
+        let title = Path::new(&name).file_stem().map_or(name.clone(), |stem| stem.to_string_lossy().replace('_', " "));
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape(&title)));
+        html.push_str(concat!(
+            "<style>\n",
+            "body { font-family: sans-serif; margin: 2em; }\n",
+            "table { border-collapse: collapse; }\n",
+            "th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n",
+            "th { cursor: pointer; background: #f4f4f4; user-select: none; }\n",
+            "th[data-order=asc]::after { content: \" \\25B2\"; }\n",
+            "th[data-order=desc]::after { content: \" \\25BC\"; }\n",
+            ".bar { display: flex; align-items: center; margin: 2px 0; }\n",
+            ".bar .label { width: 16em; }\n",
+            ".bar .fill { background: #4a7fb5; height: 1.2em; margin-right: 6px; }\n",
+            "</style>\n</head>\n<body>\n",
+        ));
+        html.push_str(&format!("<h1>{}</h1>\n", escape(&title)));
+
+        if let Some((chart_title, bars)) = &self.chart {
+            let highest = bars.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+            html.push_str(&format!("<h2>{}</h2>\n<div class=\"chart\">\n", escape(chart_title)));
+            for (label, value) in bars {
+                let width = if highest > 0.0 { value / highest * 30.0 } else { 0.0 };
+                html.push_str(&format!(
+                    "<div class=\"bar\"><span class=\"label\">{}</span><span class=\"fill\" style=\"width: {:.2}em\"></span>{}</div>\n",
+                    escape(label), width, value,
+                ));
+            }
+            html.push_str("</div>\n");
+        }
+
+        html.push_str("<table>\n<thead>\n<tr>");
+        for column in &self.header {
+            html.push_str(&format!("<th>{}</th>", escape(column)));
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+        for row in rows {
+            html.push_str("<tr>");
+            for value in row {
+                html.push_str(&format!("<td>{}</td>", escape(value)));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n");
+        // Click a column heading to sort by it, again to reverse; numbers
+        // (with a unit such as "kbps" or "%") sort by value
+        html.push_str(concat!(
+            "<script>\n",
+            "const number = text => /^-?\\d+(\\.\\d+)?\\s*(%|kbps|MB)?$/.test(text.trim()) ? parseFloat(text) : NaN;\n",
+            "document.querySelectorAll('th').forEach(th => th.addEventListener('click', () => {\n",
+            "  const table = th.closest('table'), body = table.tBodies[0], column = th.cellIndex;\n",
+            "  const ascending = th.dataset.order !== 'asc';\n",
+            "  table.querySelectorAll('th').forEach(other => delete other.dataset.order);\n",
+            "  th.dataset.order = ascending ? 'asc' : 'desc';\n",
+            "  const rows = Array.from(body.rows).sort((a, b) => {\n",
+            "    const x = a.cells[column].textContent, y = b.cells[column].textContent;\n",
+            "    const nx = number(x), ny = number(y);\n",
+            "    const order = isNaN(nx) || isNaN(ny) ? x.localeCompare(y) : nx - ny;\n",
+            "    return ascending ? order : -order;\n",
+            "  });\n",
+            "  body.append(...rows);\n",
+            "}));\n",
+            "</script>\n</body>\n</html>\n",
+        ));
+        html
+    }
+
+    // report.csv -> report.csv / report.part002.csv, plus .gz when compressing
+    fn path_for(&self, part: Option<usize>) -> PathBuf {
+        let stem = self.base.file_stem().and_then(|s| s.to_str()).unwrap_or("report");
+        let extension = self.base.extension().and_then(|e| e.to_str()).unwrap_or("csv");
+        let mut name = match part {
+            Some(part) => format!("{}.part{:03}.{}", stem, part, extension),
+            None => format!("{}.{}", stem, extension),
+        };
+        if self.gzip {
+            name.push_str(".gz");
+        }
+        self.base.with_file_name(name)
+    }
+}
+
+// A writer dropped before `finish`, say after a failed write, takes its
+// partial files with it
+impl Drop for TableWriter {
+    fn drop(&mut self) {
+        if let Some((path, part)) = self.current.take() {
+            drop(part);
+            self.written.push(path);
+        }
+        for path in &self.written {
+            let _ = std::fs::remove_file(report_dir::partial_path(path));
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| "\"\"".to_string())
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{AudioError, AudioFile, Result};
+use crate::utils::{hashing, paths, unix_timestamp_millis};
+
+// Whole-folder scan results, reused while the folder is unchanged. A folder's
+// fingerprint covers the name, size and modification time of every entry
+// under it plus the scan options, so any added, removed, renamed or rewritten
+// file (a retag included) invalidates it. Meant for archive drives that get
+// analyzed again and again without changing.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    root: PathBuf,
+    fingerprint: String,
+    scanned_at: u64,
+    files: Vec<AudioFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanCache {
+    dir: PathBuf,
+}
+
+impl Default for ScanCache {
+    fn default() -> Self {
+        Self::new(paths::data_dir().join("scan-cache"))
+    }
+}
+
+impl ScanCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // One file per scanned root
+    fn entry_path(&self, root: &Path) -> PathBuf {
+        let key = hashing::sha256_bytes(root.to_string_lossy().as_bytes());
+        self.dir.join(format!("{}.json", &key[..16]))
+    }
+
+    // The files of the last scan of `root`, if it had the same fingerprint.
+    // Unreadable entries (e.g. from an older version) are misses.
+    pub fn lookup(&self, root: &Path, fingerprint: &str) -> Option<Vec<AudioFile>> {
+        let json = fs::read_to_string(self.entry_path(root)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&json).ok()?;
+        (entry.root == root && entry.fingerprint == fingerprint).then_some(entry.files)
+    }
+
+    pub fn store(&self, root: &Path, fingerprint: &str, files: &[AudioFile]) -> Result<()> {
+        fs::create_dir_all(&self.dir).map_err(|e| AudioError::io(&self.dir, e))?;
+        let entry = CacheEntry {
+            root: root.to_path_buf(),
+            fingerprint: fingerprint.to_string(),
+            scanned_at: unix_timestamp_millis(),
+            files: files.to_vec(),
+        };
+        let path = self.entry_path(root);
+        let json = serde_json::to_string(&entry).map_err(|e| AudioError::Metadata(e.to_string()))?;
+        // Written aside and renamed so an interrupted write can't leave a
+        // truncated entry behind
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, json).map_err(|e| AudioError::io(&partial, e))?;
+        fs::rename(&partial, &path).map_err(|e| AudioError::io(&path, e))
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::{AudioError, AudioFile, Result};
+use crate::utils::unix_timestamp_millis;
+
+// Events appended to the JSON-lines scan log as they happen
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LogEvent<'a> {
+    Scanned { file: &'a AudioFile },
+    Failed { path: &'a Path, error: String, transient: bool },
+    // Too small to be audio (see `PLACEHOLDER_BYTES`)
+    Placeholder { path: &'a Path, size_bytes: u64 },
+    Duplicate {
+        higher_quality: &'a Path,
+        lower_quality: &'a Path,
+        match_reason: &'a str,
+        quality_difference: &'a str,
+    },
+    Moved { from: &'a Path, to: &'a Path, dry_run: bool },
+}
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a LogEvent<'a>,
+}
+
+// Append-only log with one JSON object per line, flushed line by line so it
+// can be tailed while a scan is still running
+#[derive(Debug)]
+pub struct ScanLog {
+    path: PathBuf,
+    writer: Mutex<LineWriter<File>>,
+    // Files that failed, with the error, for error reports at the end of a run
+    failures: Mutex<Vec<(PathBuf, String)>>,
+    // Placeholder-size files with their size, reported apart from failures
+    placeholders: Mutex<Vec<(PathBuf, u64)>>,
+}
+
+impl ScanLog {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| AudioError::io(parent, e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| AudioError::io(&path, e))?;
+        Ok(Self {
+            path,
+            writer: Mutex::new(LineWriter::new(file)),
+            failures: Mutex::new(Vec::new()),
+            placeholders: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Every failure recorded so far, in order. A file that failed with a
+    // transient error and was recovered on retry shows up here too.
+    pub fn failures(&self) -> Vec<(PathBuf, String)> {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // Every placeholder-size file recorded so far, in order
+    pub fn placeholders(&self) -> Vec<(PathBuf, u64)> {
+        self.placeholders.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // Logging never interrupts a scan; failures are reported and dropped
+    pub fn record(&self, event: &LogEvent<'_>) {
+        match event {
+            LogEvent::Failed { path, error, .. } => {
+                self.failures.lock().unwrap_or_else(|e| e.into_inner()).push((path.to_path_buf(), error.clone()));
+            }
+            LogEvent::Placeholder { path, size_bytes } => {
+                self.placeholders.lock().unwrap_or_else(|e| e.into_inner()).push((path.to_path_buf(), *size_bytes));
+            }
+            _ => {}
+        }
+        let line = LogLine {
+            timestamp: unix_timestamp_millis(),
+            event,
+        };
+        let json = match serde_json::to_string(&line) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("Failed to serialize scan log entry: {}", e);
+                return;
+            }
+        };
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(writer, "{}", json) {
+            eprintln!("Failed to write scan log {}: {}", self.path.display(), e);
+        }
+    }
+}
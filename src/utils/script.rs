@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::{AudioError, Result};
+use crate::utils::file_ops::FileOperation;
+use crate::utils::report_dir;
+
+// Planned file operations written out as a script instead of performed, for
+// users who review and run changes with their own tooling. Commands never
+// overwrite: moves and copies onto an existing file are skipped in the script too.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    // POSIX sh
+    Shell,
+    PowerShell,
+}
+
+impl ScriptFormat {
+    // PowerShell for `.ps1` files, sh otherwise
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ps1") => Self::PowerShell,
+            _ => Self::Shell,
+        }
+    }
+
+    fn quote(&self, path: &Path) -> String {
+        let text = path.to_string_lossy();
+        match self {
+            Self::Shell => format!("'{}'", text.replace('\'', r"'\''")),
+            Self::PowerShell => format!("'{}'", text.replace('\'', "''")),
+        }
+    }
+}
+
+impl FromStr for ScriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sh" | "shell" | "bash" => Ok(Self::Shell),
+            "powershell" | "ps1" | "pwsh" => Ok(Self::PowerShell),
+            other => Err(format!("Unknown script format: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for ScriptFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Shell => "sh",
+            Self::PowerShell => "powershell",
+        })
+    }
+}
+
+pub struct OperationScript {
+    format: ScriptFormat,
+    lines: Vec<String>,
+    // Destination folders already created earlier in the script
+    directories: HashSet<PathBuf>,
+    operations: usize,
+}
+
+impl OperationScript {
+    pub fn new(format: ScriptFormat) -> Self {
+        let header = match format {
+            ScriptFormat::Shell => "#!/bin/sh",
+            ScriptFormat::PowerShell => "#Requires -Version 5.1",
+        };
+        Self {
+            format,
+            lines: vec![header.to_string()],
+            directories: HashSet::new(),
+            operations: 0,
+        }
+    }
+
+    pub fn format(&self) -> ScriptFormat {
+        self.format
+    }
+
+    // Operations added so far, comments and folder creation not counted
+    pub fn len(&self) -> usize {
+        self.operations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations == 0
+    }
+
+    // One comment line per line of text
+    pub fn comment(&mut self, text: &str) {
+        self.lines.extend(text.lines().map(|line| format!("# {}", line).trim_end().to_string()));
+    }
+
+    pub fn blank_line(&mut self) {
+        self.lines.push(String::new());
+    }
+
+    pub fn push(&mut self, operation: &FileOperation) {
+        if let Some(parent) = operation.destination().and_then(Path::parent) {
+            if !parent.as_os_str().is_empty() && self.directories.insert(parent.to_path_buf()) {
+                let parent = self.format.quote(parent);
+                self.lines.push(match self.format {
+                    ScriptFormat::Shell => format!("mkdir -p -- {}", parent),
+                    ScriptFormat::PowerShell => format!("New-Item -ItemType Directory -Force -Path {} | Out-Null", parent),
+                });
+            }
+        }
+
+        let q = |path: &Path| self.format.quote(path);
+        let line = match (self.format, operation) {
+            (ScriptFormat::Shell, FileOperation::Move { from, to }) => format!("mv -n -- {} {}", q(from), q(to)),
+            (ScriptFormat::Shell, FileOperation::Copy { from, to }) => format!("cp -n -p -- {} {}", q(from), q(to)),
+            (ScriptFormat::Shell, FileOperation::Delete { path }) => format!("rm -- {}", q(path)),
+            (ScriptFormat::PowerShell, FileOperation::Move { from, to }) => format!(
+                "if (-not (Test-Path -LiteralPath {to})) {{ Move-Item -LiteralPath {} -Destination {to} }}", q(from), to = q(to)),
+            (ScriptFormat::PowerShell, FileOperation::Copy { from, to }) => format!(
+                "if (-not (Test-Path -LiteralPath {to})) {{ Copy-Item -LiteralPath {} -Destination {to} }}", q(from), to = q(to)),
+            (ScriptFormat::PowerShell, FileOperation::Delete { path }) => format!("Remove-Item -LiteralPath {}", q(path)),
+        };
+        self.lines.push(line);
+        self.operations += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+
+    // Shell scripts are made executable where the platform has the notion
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        report_dir::write_atomic(path, self.render())?;
+        #[cfg(unix)]
+        if self.format == ScriptFormat::Shell {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|e| AudioError::io(path, e))?;
+        }
+        Ok(())
+    }
+}
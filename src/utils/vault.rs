@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::{AudioError, AudioFile, Result};
+use crate::audio::stream;
+use crate::utils::parallel::{self, ParallelProcessor};
+use crate::utils::{hashing, unix_timestamp_millis};
+
+// Metadata vault: the tag block of every MP3 and FLAC file (tags, cues,
+// ratings, artwork), keyed by a hash of the audio stream alone. Retagging
+// or moving a file doesn't change that hash, so a vault can put tags back
+// on the right tracks even after the library was reorganized.
+//
+// Stored as a small binary file rather than JSON since tag blocks are
+// mostly binary (artwork, Serato GEOB frames).
+
+const MAGIC: &[u8; 7] = b"DJVAULT";
+const VAULT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultEntry {
+    pub audio_sha256: String,
+    // Where the file was when the vault was saved, for reporting only
+    pub path: PathBuf,
+    // Lowercase extension; a tag block only fits files of the same format
+    pub extension: String,
+    pub tags: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VaultApplySummary {
+    pub restored: usize,
+    // Files whose tags already match the vault
+    pub unchanged: usize,
+    // Files whose audio isn't in the vault
+    pub unmatched: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetadataVault {
+    pub created_at: u64,
+    pub entries: Vec<VaultEntry>,
+}
+
+struct VaultBuilder;
+
+impl ParallelProcessor for VaultBuilder {}
+
+impl MetadataVault {
+    // Reads the tag block and audio hash of every file that has one. Files
+    // without a tag block (WAV, DSD, ...) are left out.
+    pub fn build(files: &[AudioFile]) -> Self {
+        println!("Saving tags of {} files using {} threads", files.len(), parallel::current_threads());
+        let entries = VaultBuilder::parallel_map(files, |file| {
+            let path = file.path.as_path();
+            stream::leading_tag_bytes(path)?;
+            match read_entry(path) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    eprintln!("Error reading tags: {}", e);
+                    None
+                }
+            }
+        });
+
+        let mut entries: Vec<VaultEntry> = entries.into_iter().flatten().collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Self {
+            created_at: unix_timestamp_millis(),
+            entries,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| AudioError::io(path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| AudioError::io(path, e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| AudioError::io(path, e))?;
+        Self::read_from(&mut BufReader::new(file)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => {
+                AudioError::Metadata(format!("Invalid vault {}: {}", path.display(), e))
+            }
+            _ => AudioError::io(path, e),
+        })
+    }
+
+    // Re-applies saved tags to every file whose audio is in the vault and
+    // whose tags differ. When the same audio was saved more than once, the
+    // entry saved from the file's current path wins, then one with the same
+    // file name.
+    pub fn apply(&self, files: &[AudioFile], dry_run: bool) -> VaultApplySummary {
+        let mut by_audio: HashMap<&str, Vec<&VaultEntry>> = HashMap::new();
+        for entry in &self.entries {
+            by_audio.entry(entry.audio_sha256.as_str()).or_default().push(entry);
+        }
+
+        println!("Matching {} files against {} saved tag sets using {} threads",
+            files.len(),
+            self.entries.len(),
+            parallel::current_threads()
+        );
+        let outcomes = VaultBuilder::parallel_map(files, |file| {
+            let path = file.path.as_path();
+            let Some((offset, length)) = stream::audio_stream_range(path) else {
+                return ApplyOutcome::Unmatched;
+            };
+            let audio_sha256 = match hashing::sha256_file_range(path, offset, length) {
+                Ok(hash) => hash,
+                Err(e) => return ApplyOutcome::Failed(e),
+            };
+            let extension = extension_of(path);
+            let Some(candidates) = by_audio.get(audio_sha256.as_str()) else {
+                return ApplyOutcome::Unmatched;
+            };
+            let Some(entry) = candidates.iter()
+                .filter(|entry| entry.extension == extension)
+                .max_by_key(|entry| (entry.path == path, entry.path.file_name() == path.file_name()))
+            else {
+                return ApplyOutcome::Unmatched;
+            };
+
+            match stream::read_leading_tags(path) {
+                Ok(current) if current == entry.tags => ApplyOutcome::Unchanged,
+                Ok(_) if dry_run => ApplyOutcome::Restored,
+                Ok(_) => match stream::replace_leading_tags(path, &entry.tags) {
+                    Ok(()) => ApplyOutcome::Restored,
+                    Err(e) => ApplyOutcome::Failed(e),
+                },
+                Err(e) => ApplyOutcome::Failed(e),
+            }
+        });
+
+        let mut summary = VaultApplySummary::default();
+        for (file, outcome) in files.iter().zip(outcomes) {
+            match outcome {
+                ApplyOutcome::Restored => {
+                    let verb = if dry_run { "Would restore tags" } else { "Restored tags" };
+                    println!("{}: {}", verb, file.path.display());
+                    summary.restored += 1;
+                }
+                ApplyOutcome::Unchanged => summary.unchanged += 1,
+                ApplyOutcome::Unmatched => summary.unmatched += 1,
+                ApplyOutcome::Failed(e) => {
+                    eprintln!("Error restoring tags: {}", e);
+                    summary.failed += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    // Layout: magic, version, created_at (u64), entry count (u32), then per
+    // entry the raw 32-byte audio hash, extension and path (u16 length +
+    // UTF-8) and the tag block (u32 length + bytes). Integers are little-endian.
+    fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VAULT_VERSION])?;
+        writer.write_all(&self.created_at.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for entry in &self.entries {
+            writer.write_all(&from_hex(&entry.audio_sha256)?)?;
+            write_string(writer, &entry.extension)?;
+            write_string(writer, &entry.path.to_string_lossy())?;
+            writer.write_all(&(entry.tags.len() as u32).to_le_bytes())?;
+            writer.write_all(&entry.tags)?;
+        }
+        Ok(())
+    }
+
+    fn read_from(reader: &mut impl Read) -> std::io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic[..7] != MAGIC {
+            return Err(invalid_data("not a metadata vault"));
+        }
+        if magic[7] != VAULT_VERSION {
+            return Err(invalid_data(&format!("unsupported vault version {}", magic[7])));
+        }
+
+        let created_at = u64::from_le_bytes(read_array(reader)?);
+        let count = u32::from_le_bytes(read_array(reader)?) as usize;
+        let mut entries = Vec::with_capacity(count.min(100_000));
+        for _ in 0..count {
+            let hash: [u8; 32] = read_array(reader)?;
+            let extension = read_string(reader)?;
+            let path = PathBuf::from(read_string(reader)?);
+            let length = u32::from_le_bytes(read_array(reader)?) as u64;
+            let mut tags = Vec::new();
+            reader.take(length).read_to_end(&mut tags)?;
+            if tags.len() as u64 != length {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            entries.push(VaultEntry {
+                audio_sha256: hashing::to_hex(&hash),
+                path,
+                extension,
+                tags,
+            });
+        }
+        Ok(Self { created_at, entries })
+    }
+}
+
+enum ApplyOutcome {
+    Restored,
+    Unchanged,
+    Unmatched,
+    Failed(AudioError),
+}
+
+fn read_entry(path: &Path) -> Result<VaultEntry> {
+    let (offset, length) = stream::audio_stream_range(path).ok_or_else(|| AudioError::TagRead {
+        path: path.to_path_buf(),
+        reason: "can't locate the audio stream".to_string(),
+    })?;
+    Ok(VaultEntry {
+        audio_sha256: hashing::sha256_file_range(path, offset, length)?,
+        path: path.to_path_buf(),
+        extension: extension_of(path),
+        tags: stream::read_leading_tags(path)?,
+    })
+}
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> std::io::Result<()> {
+    let length = u16::try_from(value.len()).map_err(|_| invalid_data("string too long"))?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_string(reader: &mut impl Read) -> std::io::Result<String> {
+    let length = u16::from_le_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid_data("string is not UTF-8"))
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn from_hex(hex: &str) -> std::io::Result<[u8; 32]> {
+    let mut bytes = [0u8; 32];
+    if hex.len() != 64 {
+        return Err(invalid_data("malformed hash"));
+    }
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid_data("malformed hash"))?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid_data("malformed hash"))?;
+    }
+    Ok(bytes)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
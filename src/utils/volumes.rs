@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+// Stable identity of the drive a file lives on, so a library spread over
+// external drives survives them being unplugged or mounted somewhere else
+// (another drive letter, /media/<user>/<label> vs /mnt/usb).
+//
+// The OS volume UUID (serial number on Windows) is used when available. A
+// directory can also declare itself a volume root with a marker file
+// holding an ID; that covers network shares and filesystems without UUIDs.
+
+pub const VOLUME_MARKER: &str = ".dj-library-volume";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volume {
+    pub id: String,
+    pub label: Option<String>,
+    pub mount_point: PathBuf,
+}
+
+impl Volume {
+    // Path of `path` below the mount point
+    pub fn relative_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.mount_point).ok()
+    }
+}
+
+// Resolves volumes for many files, querying each directory and each mount
+// point only once
+#[derive(Default)]
+pub struct VolumeResolver {
+    directories: HashMap<PathBuf, Option<Volume>>,
+    mount_points: HashMap<PathBuf, Option<Volume>>,
+}
+
+impl VolumeResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resolve(&mut self, path: &Path) -> Option<Volume> {
+        let directory = path.parent().unwrap_or(path).to_path_buf();
+        if let Some(volume) = self.directories.get(&directory) {
+            return volume.clone();
+        }
+
+        let volume = match marker_volume(&directory) {
+            Some(volume) => Some(volume),
+            None => {
+                let mount_point = mount_point(&directory);
+                match mount_point {
+                    Some(mount_point) => self.mount_points
+                        .entry(mount_point.clone())
+                        .or_insert_with(|| os_volume(&mount_point))
+                        .clone(),
+                    None => None,
+                }
+            }
+        };
+        self.directories.insert(directory, volume.clone());
+        volume
+    }
+}
+
+// Volume holding `path`, if it can be identified
+pub fn volume_of(path: &Path) -> Option<Volume> {
+    VolumeResolver::new().resolve(path)
+}
+
+// Identifiable volumes mounted right now. Marker volumes aren't listed;
+// they are found again when a scan walks into them.
+pub fn mounted_volumes() -> Vec<Volume> {
+    let mut volumes: Vec<Volume> = Vec::new();
+    for mount_point in mount_points() {
+        if let Some(volume) = os_volume(&mount_point) {
+            if !volumes.iter().any(|v| v.id == volume.id) {
+                volumes.push(volume);
+            }
+        }
+    }
+    volumes
+}
+
+// Writes a marker so `directory` is tracked as a volume of its own, with
+// a generated ID unless one is given. Returns the ID.
+pub fn mark_volume(directory: &Path, id: Option<&str>) -> std::io::Result<String> {
+    if crate::utils::file_ops::is_read_only() {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read-only mode"));
+    }
+    let id = id.map_or_else(|| format!("vol-{}", crate::utils::unix_timestamp_millis()), str::to_string);
+    std::fs::write(directory.join(VOLUME_MARKER), format!("{}\n", id))?;
+    Ok(id)
+}
+
+// Nearest ancestor directory holding a marker file
+fn marker_volume(directory: &Path) -> Option<Volume> {
+    let canonical = std::fs::canonicalize(directory).ok()?;
+    canonical.ancestors().find_map(|dir| {
+        let contents = std::fs::read_to_string(dir.join(VOLUME_MARKER)).ok()?;
+        let id = contents.lines().next()?.trim();
+        if id.is_empty() {
+            return None;
+        }
+        Some(Volume {
+            id: id.to_string(),
+            label: dir.file_name().map(|name| name.to_string_lossy().into_owned()),
+            mount_point: dir.to_path_buf(),
+        })
+    })
+}
+
+// Topmost ancestor on the same device as `path`
+#[cfg(unix)]
+fn mount_point(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let canonical = std::fs::canonicalize(path).ok()?;
+    let device = std::fs::metadata(&canonical).ok()?.dev();
+    let mut mount_point = canonical.as_path();
+    for ancestor in canonical.ancestors().skip(1) {
+        match std::fs::metadata(ancestor) {
+            Ok(metadata) if metadata.dev() == device => mount_point = ancestor,
+            _ => break,
+        }
+    }
+    Some(mount_point.to_path_buf())
+}
+
+#[cfg(windows)]
+fn mount_point(path: &Path) -> Option<PathBuf> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows_sys::Win32::Storage::FileSystem::GetVolumePathNameW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut buffer = [0u16; 261];
+    // SAFETY: `wide` is NUL-terminated and the buffer length is passed along
+    let ok = unsafe { GetVolumePathNameW(wide.as_ptr(), buffer.as_mut_ptr(), buffer.len() as u32) };
+    if ok == 0 {
+        return None;
+    }
+    let length = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(PathBuf::from(std::ffi::OsString::from_wide(&buffer[..length])))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn mount_point(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    mountinfo.lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|field| PathBuf::from(unescape_mountinfo(field)))
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn mount_points() -> Vec<PathBuf> {
+    let mut mount_points = vec![PathBuf::from("/")];
+    if let Ok(entries) = std::fs::read_dir("/Volumes") {
+        mount_points.extend(entries.flatten().map(|entry| entry.path()));
+    }
+    mount_points
+}
+
+#[cfg(windows)]
+fn mount_points() -> Vec<PathBuf> {
+    use windows_sys::Win32::Storage::FileSystem::GetLogicalDrives;
+    // SAFETY: no arguments
+    let drives = unsafe { GetLogicalDrives() };
+    (0..26u8)
+        .filter(|bit| drives & (1 << bit) != 0)
+        .map(|bit| PathBuf::from(format!("{}:\\", (b'A' + bit) as char)))
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn mount_points() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+// UUID and label of the filesystem mounted at `mount_point`, matched through
+// the /dev/disk/by-uuid and by-label symlinks
+#[cfg(target_os = "linux")]
+fn os_volume(mount_point: &Path) -> Option<Volume> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let source = mountinfo.lines().rev().find_map(|line| {
+        let mut fields = line.split(' ');
+        let mounted_at = fields.nth(4)?;
+        if Path::new(&unescape_mountinfo(mounted_at)) != mount_point {
+            return None;
+        }
+        // Optional fields end at " - ", followed by fstype and source
+        let rest = line.split(" - ").nth(1)?;
+        rest.split(' ').nth(1).map(unescape_mountinfo)
+    })?;
+    let device = std::fs::canonicalize(source).ok()?;
+
+    let link_named = |dir: &str| -> Option<String> {
+        std::fs::read_dir(dir).ok()?
+            .flatten()
+            .find(|entry| std::fs::canonicalize(entry.path()).ok().as_ref() == Some(&device))
+            .map(|entry| unescape_udev(&entry.file_name().to_string_lossy()))
+    };
+    Some(Volume {
+        id: link_named("/dev/disk/by-uuid")?,
+        label: link_named("/dev/disk/by-label"),
+        mount_point: mount_point.to_path_buf(),
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn os_volume(mount_point: &Path) -> Option<Volume> {
+    let output = std::process::Command::new("diskutil")
+        .arg("info")
+        .arg("-plist")
+        .arg(mount_point)
+        .output()
+        .ok()?;
+    let plist = String::from_utf8_lossy(&output.stdout);
+    // <key>VolumeUUID</key> followed by <string>...</string>
+    let value = |key: &str| -> Option<String> {
+        let after = plist.split(&format!("<key>{}</key>", key)).nth(1)?;
+        let start = after.find("<string>")? + "<string>".len();
+        let end = after[start..].find("</string>")? + start;
+        Some(after[start..end].to_string()).filter(|v| !v.is_empty())
+    };
+    Some(Volume {
+        id: value("VolumeUUID")?,
+        label: value("VolumeName"),
+        mount_point: mount_point.to_path_buf(),
+    })
+}
+
+// Volume serial number and label
+#[cfg(windows)]
+fn os_volume(mount_point: &Path) -> Option<Volume> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeInformationW;
+
+    let wide: Vec<u16> = mount_point.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let mut label = [0u16; 261];
+    let mut serial: u32 = 0;
+    // SAFETY: `wide` is NUL-terminated, buffer lengths are passed along and
+    // unused outputs are null
+    let ok = unsafe {
+        GetVolumeInformationW(
+            wide.as_ptr(),
+            label.as_mut_ptr(),
+            label.len() as u32,
+            &mut serial,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    let length = label.iter().position(|&c| c == 0).unwrap_or(label.len());
+    let label = String::from_utf16_lossy(&label[..length]);
+    Some(Volume {
+        id: format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff),
+        label: (!label.is_empty()).then_some(label),
+        mount_point: mount_point.to_path_buf(),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn os_volume(_mount_point: &Path) -> Option<Volume> {
+    None
+}
+
+// mountinfo escapes space, tab, newline and backslash as octal (\040)
+#[cfg(target_os = "linux")]
+fn unescape_mountinfo(field: &str) -> String {
+    unescape(field, "\\", 3, 8)
+}
+
+// udev escapes symlink names as \xNN
+#[cfg(target_os = "linux")]
+fn unescape_udev(name: &str) -> String {
+    unescape(name, "\\x", 2, 16)
+}
+
+#[cfg(target_os = "linux")]
+fn unescape(text: &str, marker: &str, digits: usize, radix: u32) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find(marker) {
+        bytes.extend_from_slice(&rest.as_bytes()[..index]);
+        let start = index + marker.len();
+        let code = rest.get(start..start + digits)
+            .and_then(|digits| u8::from_str_radix(digits, radix).ok());
+        match code {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &rest[start + digits..];
+            }
+            None => {
+                bytes.extend_from_slice(marker.as_bytes());
+                rest = &rest[start..];
+            }
+        }
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "cli")]
+
+use clap::Parser;
+use dj_library_manager::cli::commands::{Cli, Commands};
+
+fn parse(args: &[&str]) -> Result<Commands, clap::Error> {
+    Cli::try_parse_from(std::iter::once("dj-library-manager").chain(args.iter().copied())).map(|cli| cli.command)
+}
+
+#[test]
+fn retry_delay_must_be_a_finite_number_of_seconds() {
+    let dir = tempfile::tempdir().unwrap();
+    let library = dir.path().to_str().unwrap();
+    for bad in ["-1", "inf", "NaN"] {
+        let arg = format!("--retry-delay={}", bad);
+        let Err(error) = parse(&["bitrate", "-i", library, "-o", "/out", &arg]) else { panic!("{} accepted", bad) };
+        assert!(error.to_string().contains("--retry-delay"), "{}", error);
+    }
+    let Commands::Bitrate { scan, .. } = parse(&["bitrate", "-i", library, "-o", "/out", "--retry-delay", "0.5"]).unwrap() else { panic!() };
+    assert_eq!(scan.to_scan_options().retry_delay, std::time::Duration::from_millis(500));
+}
+
+#[test]
+fn cover_names_are_one_plain_file_name() {
+    use dj_library_manager::cli::options::parse_cover_name;
+
+    assert_eq!(parse_cover_name("cover.png").as_deref(), Ok("cover.png"));
+    for bad in ["/tmp/cover.jpg", "../folder.jpg", "art/folder.jpg", "folder.gif", ".."] {
+        assert!(parse_cover_name(bad).is_err(), "{} accepted", bad);
+    }
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "cli")]
+
+use std::fs;
+use clap::{CommandFactory, FromArgMatches};
+use dj_library_manager::cli::commands::{Cli, Commands};
+use dj_library_manager::cli::config::Config;
+use dj_library_manager::utils::reporting::ReportFormat;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn settings_fill_in_flags_left_off_the_command_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let (library, moved) = (dir.path().join("library"), dir.path().join("moved"));
+    fs::create_dir_all(&library).unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, format!(
+        "input = [{:?}]\noutput = {:?}\nformats = [\"mp3\"]\nthreads = 3\nreport_format = \"json\"\n",
+        library, moved,
+    )).unwrap();
+    let config = Config::load(&path).unwrap();
+
+    let parse = |args: &[&str]| {
+        let matches = config.apply(Cli::command()).try_get_matches_from(args).unwrap();
+        Cli::from_arg_matches(&matches).unwrap().command
+    };
+    let Commands::Bitrate { scan, output, report, .. } = parse(&["dj-library-manager", "bitrate"]) else { panic!() };
+    assert_eq!(scan.input, [library]);
+    assert_eq!(output, moved);
+    assert_eq!(scan.format, ["mp3"]);
+    assert_eq!(scan.threads, Some(3));
+    assert_eq!(report.report_format, ReportFormat::Json);
+
+    // The command line wins
+    let Commands::Bitrate { scan, .. } = parse(&["dj-library-manager", "bitrate", "-t", "1", "--format", "flac"]) else { panic!() };
+    assert_eq!((scan.threads, scan.format), (Some(1), vec!["flac".to_string()]));
+
+    // A report file isn't a folder: --output stays required where it names one
+    assert!(config.apply(Cli::command()).try_get_matches_from(["dj-library-manager", "storage"]).is_ok());
+    assert!(config.apply(Cli::command()).try_get_matches_from(["dj-library-manager", "vocals"]).is_err());
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    fs::write(&path, "inputs = [\"/music\"]\n").unwrap();
+    let error = Config::load(&path).unwrap_err().to_string();
+    assert!(error.contains("unknown field `inputs`"), "{}", error);
+}
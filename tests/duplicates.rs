@@ -0,0 +1,184 @@
+use std::sync::Arc;
+use dj_library_manager::AudioFile;
+use dj_library_manager::analyzers::approval::ApprovalList;
+use dj_library_manager::analyzers::duplicate::{DuplicateAnalyzer, MatchChain, MatchClass, MatchSource};
+use dj_library_manager::analyzers::ignore::IgnoreList;
+use pretty_assertions::assert_eq;
+
+fn file(path: &str, bitrate: u32, tags: Option<(&str, &str)>) -> Arc<AudioFile> {
+    let mut file = AudioFile::new(path, 1024);
+    file.bitrate = Some(bitrate);
+    if let Some((artist, title)) = tags {
+        file.artist = Some(artist.to_string());
+        file.title = Some(title.to_string());
+    }
+    Arc::new(file)
+}
+
+#[test]
+fn tags_identify_files_with_meaningless_names() {
+    let analyzer = DuplicateAnalyzer::new(1.0);
+    let tagged = file("/music/01 track.mp3", 320, Some(("Daft Punk", "One More Time")));
+    let named = file("/music/Daft Punk - One More Time.mp3", 128, None);
+
+    let dup = analyzer.match_pair(&tagged, &named).expect("tags and file name agree");
+    assert_eq!(dup.higher_quality.path, tagged.path);
+    assert!(dup.match_reason.ends_with("(from tags and file name)"), "{}", dup.match_reason);
+
+    let names_only = DuplicateAnalyzer::new(1.0).with_match_chain("filename".parse().unwrap());
+    assert!(names_only.match_pair(&tagged, &named).is_none());
+}
+
+#[test]
+fn fuzzy_pairs_typos_but_not_numbered_parts() {
+    let analyzer = DuplicateAnalyzer::new(1.0).with_match_chain("tags,filename,fuzzy".parse().unwrap());
+    let typo = file("/music/a.mp3", 320, Some(("Daft Punk", "One More Tme")));
+    let correct = file("/music/b.mp3", 128, Some(("Daft Punk", "One More Time")));
+    assert!(analyzer.match_pair(&typo, &correct).is_some());
+    assert!(DuplicateAnalyzer::new(1.0).match_pair(&typo, &correct).is_none());
+
+    let part1 = file("/music/c.mp3", 320, Some(("Artist", "Journey Part 1")));
+    let part2 = file("/music/d.mp3", 128, Some(("Artist", "Journey Part 2")));
+    assert!(analyzer.match_pair(&part1, &part2).is_none());
+}
+
+#[test]
+fn match_chain_parsing() {
+    assert_eq!(MatchChain::default().sources, [MatchSource::Tags, MatchSource::Filename]);
+    let chain: MatchChain = "filename, tags".parse().unwrap();
+    assert_eq!(chain.sources, [MatchSource::Filename, MatchSource::Tags]);
+    assert!(!chain.fuzzy);
+    assert!("fuzzy".parse::<MatchChain>().is_err());
+    assert!("fuzzy,tags".parse::<MatchChain>().is_err());
+    assert!("tags,tags".parse::<MatchChain>().is_err());
+    assert!("artist".parse::<MatchChain>().is_err());
+}
+
+#[test]
+fn similarity_threshold_is_honored() {
+    let typo = file("/music/a.mp3", 320, Some(("Daft Punk", "One More Tme")));
+    let punctuated = file("/music/b.mp3", 128, Some(("Daft-Punk", "One More Time!")));
+    let other = file("/music/c.mp3", 128, Some(("Daft Punk", "Digital Love")));
+
+    let loose = DuplicateAnalyzer::new(0.9);
+    let dup = loose.match_pair(&typo, &punctuated).expect("one letter apart");
+    assert!(dup.match_reason.starts_with("Similar title match (96%)"), "{}", dup.match_reason);
+    assert!(loose.match_pair(&typo, &other).is_none());
+    assert!(DuplicateAnalyzer::new(0.99).match_pair(&typo, &punctuated).is_none());
+    assert!(DuplicateAnalyzer::new(1.0).match_pair(&typo, &punctuated).is_none());
+
+    let results = loose.find_duplicates(vec![
+        Arc::unwrap_or_clone(typo),
+        Arc::unwrap_or_clone(punctuated),
+        Arc::unwrap_or_clone(other),
+    ]);
+    assert_eq!(results.groups.len(), 1);
+    assert_eq!(results.duplicate_count(), 1);
+}
+
+#[test]
+fn copies_of_a_track_form_one_group_with_the_best_kept() {
+    let copies = [("/music/a/Artist - Track.mp3", 128), ("/music/b/Artist - Track.mp3", 320), ("/music/c/Artist - Track.mp3", 192)];
+    let results = DuplicateAnalyzer::new(1.0).find_duplicates(
+        copies.iter().map(|&(path, bitrate)| Arc::unwrap_or_clone(file(path, bitrate, None))).collect()
+    );
+
+    assert_eq!(results.groups.len(), 1);
+    let group = &results.groups[0];
+    assert_eq!(group.original.bitrate, Some(320));
+    let mut removed: Vec<u32> = group.duplicates.iter().filter_map(|dup| dup.lower_quality.bitrate).collect();
+    removed.sort();
+    assert_eq!(removed, [128, 192]);
+    assert!(results.matches().all(|dup| dup.higher_quality.path == group.original.path));
+}
+
+#[test]
+fn ignored_pairs_are_not_matched() {
+    let keeper = file("/music/a/Artist - Track.mp3", 320, None);
+    let remix = file("/music/b/Artist - Track.mp3", 128, None);
+    let copy = file("/music/c/Artist - Track.mp3", 192, None);
+    let mut ignored = IgnoreList::default();
+    assert!(ignored.add(&remix.path, &keeper.path, None));
+    assert!(!ignored.add(&keeper.path, &remix.path, None));
+
+    let analyzer = DuplicateAnalyzer::new(1.0).with_ignored_pairs(ignored.clone());
+    assert!(analyzer.match_pair(&keeper, &remix).is_none());
+    assert!(analyzer.match_pair(&keeper, &copy).is_some());
+
+    let results = analyzer.find_duplicates(vec![
+        Arc::unwrap_or_clone(keeper.clone()),
+        Arc::unwrap_or_clone(remix.clone()),
+        Arc::unwrap_or_clone(copy.clone()),
+    ]);
+    assert_eq!(results.groups.len(), 1);
+    let removed: Vec<&std::path::Path> = results.matches().map(|dup| dup.lower_quality.path.as_path()).collect();
+    assert_eq!(removed, [copy.path.as_path()]);
+
+    assert!(ignored.remove(&keeper.path, &remix.path));
+    assert!(ignored.is_empty());
+}
+
+#[test]
+fn matches_are_classed_for_approval() {
+    let analyzer = DuplicateAnalyzer::new(0.9);
+    let tagged = file("/music/a.mp3", 320, Some(("Daft Punk", "One More Time")));
+    let retagged = file("/music/b.mp3", 128, Some(("Daft Punk", "One More Time")));
+    let named = file("/music/Daft Punk - One More Time.mp3", 192, None);
+    let typo = file("/music/c.mp3", 128, Some(("Daft Punk", "One More Tme")));
+    assert_eq!(analyzer.match_pair(&tagged, &retagged).unwrap().match_class, MatchClass::Tags);
+    assert_eq!(analyzer.match_pair(&tagged, &named).unwrap().match_class, MatchClass::Filename);
+    assert_eq!(analyzer.match_pair(&tagged, &typo).unwrap().match_class, MatchClass::Similar);
+
+    // Bytes are only compared when grouping, never by the pairwise check
+    let dir = tempfile::tempdir().unwrap();
+    let copies: Vec<AudioFile> = ["a", "b", "c"].iter().map(|name| {
+        let path = dir.path().join(format!("{}.mp3", name));
+        std::fs::write(&path, [u8::from(*name == "c"); 1024]).unwrap();
+        Arc::unwrap_or_clone(file(path.to_str().unwrap(), if *name == "a" { 320 } else { 192 }, Some(("Daft Punk", "One More Time"))))
+    }).collect();
+    let shared: Vec<Arc<AudioFile>> = copies.iter().cloned().map(Arc::new).collect();
+    assert_eq!(analyzer.match_pair(&shared[0], &shared[1]).unwrap().match_class, MatchClass::Tags);
+    let results = analyzer.find_duplicates(copies);
+    let classes: Vec<(&str, MatchClass)> = results.matches()
+        .map(|m| (m.lower_quality.file_name.as_str(), m.match_class))
+        .collect();
+    assert_eq!(classes, [("b.mp3", MatchClass::Identical), ("c.mp3", MatchClass::Tags)]);
+    let identical = results.matches().next().unwrap();
+
+    let approval: ApprovalList = serde_json::from_str(r#"{ "act_on": ["identical", "tags"] }"#).unwrap();
+    assert!(approval.approves(identical));
+    assert!(!approval.approves(&analyzer.match_pair(&tagged, &named).unwrap()));
+    assert_eq!("Fingerprint".parse::<MatchClass>(), Ok(MatchClass::Fingerprint));
+    assert_eq!("ISRC".parse::<MatchClass>(), Ok(MatchClass::Isrc));
+    assert!("upc".parse::<MatchClass>().is_err());
+}
+
+#[test]
+fn isrc_matches_the_recording_whatever_the_titles_say() {
+    let analyzer = DuplicateAnalyzer::new(0.9).with_match_chain("isrc,tags,filename".parse().unwrap());
+    let isrc = |path: &str, code: &str, title: &str| {
+        let mut file = AudioFile::new(path, 1024);
+        file.bitrate = Some(320);
+        file.artist = Some("Daft Punk".to_string());
+        file.title = Some(title.to_string());
+        file.isrc = Some(code.to_string());
+        Arc::new(file)
+    };
+    let original = isrc("/music/a.mp3", "GBDUW0000059", "One More Time");
+    let retitled = isrc("/music/b.mp3", "GBDUW0000059", "One More Time (Album Version)");
+    let other = isrc("/music/c.mp3", "GBDUW0000060", "One More Time");
+    let untagged = file("/music/d.mp3", 320, Some(("Daft Punk", "One More Time")));
+
+    let dup = analyzer.match_pair(&original, &retitled).expect("same code");
+    assert_eq!(dup.match_class, MatchClass::Isrc);
+    assert_eq!(dup.match_reason, "Same ISRC: GBDUW0000059");
+    // Codes are never alike enough, and a code doesn't meet a title
+    assert!(analyzer.match_pair(&original, &other).is_none());
+    assert!(analyzer.match_pair(&original, &untagged).is_none());
+
+    let files = [original, retitled, other, untagged].map(Arc::unwrap_or_clone);
+    let results = analyzer.find_duplicates(files.to_vec());
+    assert_eq!(results.duplicate_count(), 1);
+    let approval: ApprovalList = serde_json::from_str(r#"{ "act_on": ["identical", "isrc"] }"#).unwrap();
+    assert!(results.matches().all(|m| approval.approves(m)));
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "codecs-wav")]
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use dj_library_manager::MetadataExtractor;
+use pretty_assertions::assert_eq;
+
+// Writes a silent 16-bit mono PCM WAV with exactly `frames` samples
+fn write_wav(dir: &Path, name: &str, sample_rate: u32, frames: u32) -> PathBuf {
+    let data_len = frames * 2;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    bytes.resize(44 + data_len as usize, 0);
+
+    let path = dir.join(name);
+    File::create(&path).unwrap().write_all(&bytes).unwrap();
+    path
+}
+
+#[test]
+fn whole_second_duration() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_wav(dir.path(), "two_seconds.wav", 44_100, 88_200);
+
+    let file = MetadataExtractor::extract_metadata(&path).unwrap();
+    assert_eq!(file.duration_ms, Some(2_000));
+    assert_eq!(file.duration_secs, Some(2.0));
+}
+
+#[test]
+fn fractional_duration_is_not_truncated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_wav(dir.path(), "one_and_a_half.wav", 44_100, 66_150);
+
+    let file = MetadataExtractor::extract_metadata(&path).unwrap();
+    assert_eq!(file.duration_ms, Some(1_500));
+    assert!((file.duration_secs.unwrap() - 1.5).abs() < 1e-9);
+}
+
+#[test]
+fn duration_is_sample_accurate() {
+    let dir = tempfile::tempdir().unwrap();
+    // 12_345 frames at 48 kHz = 257.1875 ms
+    let path = write_wav(dir.path(), "odd_length.wav", 48_000, 12_345);
+
+    let file = MetadataExtractor::extract_metadata(&path).unwrap();
+    assert_eq!(file.duration_ms, Some(257));
+    assert!((file.duration_secs.unwrap() - 0.2571875).abs() < 1e-9);
+}
+
+#[test]
+fn sub_second_file_has_duration() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_wav(dir.path(), "short.wav", 22_050, 11_025);
+
+    let file = MetadataExtractor::extract_metadata(&path).unwrap();
+    assert_eq!(file.duration_ms, Some(500));
+}
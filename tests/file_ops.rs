@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+use dj_library_manager::utils::file_ops::{FileManager, FileOperationExecutor};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn same_named_duplicates_get_distinct_planned_destinations() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a/track.mp3"), dir.path().join("b/track.mp3"));
+    for path in [&a, &b] {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"audio").unwrap();
+    }
+    let duplicates = dir.path().join("duplicates");
+
+    // A dry run leaves the disk alone, so the first planned move doesn't make the name taken there
+    let manager = FileManager::with_executor(&duplicates, FileOperationExecutor::new().with_dry_run(true));
+    let planned: Vec<_> = manager.move_duplicates(&[a.as_path(), b.as_path()]).into_iter().map(Result::unwrap).collect();
+    assert_eq!(planned, [duplicates.join("track.mp3"), duplicates.join("track_duplicate_1.mp3")]);
+
+    // A real run picks the same names
+    let manager = FileManager::new(&duplicates);
+    let moved: Vec<_> = manager.move_duplicates(&[a.as_path(), b.as_path()]).into_iter().map(Result::unwrap).collect();
+    assert_eq!(moved, planned);
+    assert!(moved.iter().all(|path| Path::new(path).exists()));
+}
+
+// `undo` finds journals under the data directory, which follows XDG_DATA_HOME on Linux
+#[cfg(target_os = "linux")]
+#[test]
+fn runs_that_move_nothing_leave_no_journal_for_undo_to_pick() {
+    use dj_library_manager::utils::file_ops::{self, FileOperation};
+
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", dir.path().join("data"));
+    let library = dir.path().join("library");
+    fs::create_dir_all(&library).unwrap();
+    fs::write(library.join("a.mp3"), b"audio").unwrap();
+
+    let run = FileOperationExecutor::new().with_command_journal("run").unwrap();
+    run.execute(&FileOperation::Move { from: library.join("a.mp3"), to: library.join("b.mp3") }).unwrap();
+    let journal = run.journal_path().expect("journaled").to_path_buf();
+    assert_eq!(file_ops::latest_command_journal(&["duplicates", "run"]), Some(journal.clone()));
+
+    // A later run that finds nothing to move
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let idle = FileOperationExecutor::new().with_command_journal("duplicates").unwrap();
+    assert_eq!(idle.journal_path(), None);
+    // and an empty journal an older build left for one
+    fs::write(journal.with_file_name("journal-9999999999999-duplicates.jsonl"), "").unwrap();
+    assert_eq!(file_ops::latest_command_journal(&["duplicates", "run"]), Some(journal));
+    assert_eq!(file_ops::latest_command_journal(&["duplicates"]), None);
+}
@@ -0,0 +1,23 @@
+use std::fs;
+use dj_library_manager::UnknownBitrate;
+use dj_library_manager::analyzers::bitrate::BitrateAnalyzer;
+use dj_library_manager::audio::formats::FormatRegistry;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn tracker_modules_report_an_unknown_bitrate() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("song.xm");
+    let mut header = b"Extended Module: Space Debris\0\0\0\0\0\0\0\0\x1a".to_vec();
+    header.resize(64, 0);
+    fs::write(&path, header).unwrap();
+
+    let file = FormatRegistry::builtin().extract(&path).expect("registered").unwrap();
+    assert_eq!(file.title.as_deref(), Some("Space Debris"));
+    assert_eq!((file.bitrate, file.bitrate_unknown), (None, Some(UnknownBitrate::NoStreamBitrate)));
+
+    // Counted as unknown, not as a low-quality 0 kbps file
+    let stats = BitrateAnalyzer::new().analyze(&[file]);
+    assert!(stats.category_distribution.is_empty());
+    assert_eq!(stats.unknown_causes.get(&UnknownBitrate::NoStreamBitrate), Some(&1));
+}
@@ -0,0 +1,38 @@
+#![cfg(all(feature = "index", feature = "codecs-mp3"))]
+
+use std::fs;
+use std::path::Path;
+use dj_library_manager::index::monitor::LibraryMonitor;
+use pretty_assertions::assert_eq;
+
+// Silent MPEG-1 Layer III frames, about 2.6 s at 128 kbps
+fn write_mp3(path: &Path) {
+    let frame: Vec<u8> = [0xFF, 0xFB, 0x90, 0x64].into_iter().chain(std::iter::repeat_n(0, 413)).collect();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, frame.repeat(100)).unwrap();
+}
+
+#[test]
+fn new_files_are_indexed_and_flagged_against_indexed_copies() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut monitor = LibraryMonitor::new(dir.path().join("index.db")).unwrap();
+    assert_eq!(monitor.tracked(), 0);
+
+    let first = dir.path().join("music/a/Artist - Track.mp3");
+    let second = dir.path().join("music/b/Artist - Track.mp3");
+    write_mp3(&first);
+    write_mp3(&second);
+
+    assert!(monitor.arrived(&first).unwrap().duplicates.is_empty());
+    let arrival = monitor.arrived(&second).unwrap();
+    assert_eq!(arrival.duplicates.len(), 1);
+    assert_eq!(monitor.tracked(), 2);
+
+    // Reading a changed file again doesn't match it with itself
+    assert_eq!(monitor.arrived(&second).unwrap().duplicates.len(), 1);
+
+    assert!(monitor.removed(&first).unwrap());
+    assert!(monitor.arrived(&second).unwrap().duplicates.is_empty());
+    // Indexed files are picked up by the next monitor
+    assert_eq!(LibraryMonitor::new(dir.path().join("index.db")).unwrap().tracked(), 1);
+}
@@ -0,0 +1,52 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use dj_library_manager::utils::parallel::AdaptiveThreads;
+
+fn work_for(adaptive: &AdaptiveThreads, per_file: Duration, total: Duration) {
+    let started = Instant::now();
+    while started.elapsed() < total {
+        adaptive.run(|| thread::sleep(per_file));
+    }
+}
+
+#[test]
+fn workers_back_off_when_throughput_collapses() {
+    let adaptive = AdaptiveThreads::new(4).with_window(Duration::from_millis(20));
+    work_for(&adaptive, Duration::from_millis(1), Duration::from_millis(150));
+    assert_eq!(adaptive.limit(), 4);
+
+    // Ten times slower per file, well past three windows
+    work_for(&adaptive, Duration::from_millis(10), Duration::from_millis(400));
+    assert!(adaptive.limit() < 4, "still at {} workers", adaptive.limit());
+}
+
+#[test]
+fn bounded_stages_keep_source_order_and_hold_back_the_source() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use dj_library_manager::utils::pipeline;
+
+    let drawn = AtomicUsize::new(0);
+    let in_flight = AtomicUsize::new(0);
+    let source = (0..200).inspect(|_| { drawn.fetch_add(1, Ordering::SeqCst); });
+    let results = pipeline::bounded_map(source, 3, 4, |n| {
+        // Never more drawn than the queue, the workers and the one waiting to be sent allow
+        let started = in_flight.fetch_add(1, Ordering::SeqCst);
+        let ahead = drawn.load(Ordering::SeqCst) - started;
+        assert!(ahead <= 4 + 3 + 1, "source ran {} items ahead", ahead);
+        thread::sleep(Duration::from_micros(200 * (n % 3)));
+        n * 2
+    });
+    assert_eq!(results, (0..200).map(|n| n * 2).collect::<Vec<_>>());
+}
+
+#[test]
+fn a_panicking_worker_fails_the_stage_instead_of_hanging() {
+    use dj_library_manager::utils::pipeline;
+
+    // One worker and a queue of one: the source blocks on its second send
+    let outcome = std::panic::catch_unwind(|| {
+        pipeline::bounded_map(0..1000, 1, 1, |n: i32| if n == 3 { panic!("bad file") } else { n })
+    });
+    let panic = outcome.expect_err("the worker's panic is raised again");
+    assert_eq!(panic.downcast_ref::<&str>(), Some(&"bad file"));
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+use dj_library_manager::utils::progress::{self, PhaseTimer, Progress};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn durations_and_rates_read_naturally() {
+    assert_eq!(progress::format_duration(Duration::from_millis(850)), "850ms");
+    assert_eq!(progress::format_duration(Duration::from_secs(45)), "45s");
+    assert_eq!(progress::format_duration(Duration::from_secs(754)), "12m34s");
+    assert_eq!(progress::format_duration(Duration::from_secs(7500)), "2h05m");
+
+    assert_eq!(progress::format_rate(1200, Duration::from_secs(60)), "1.2k files/min");
+    assert_eq!(progress::format_rate(30, Duration::from_secs(60)), "30 files/min");
+    assert_eq!(progress::format_rate(5000, Duration::from_secs(2)), "2.5k files/s");
+}
+
+#[test]
+fn phases_add_up_in_first_run_order() {
+    let timer = PhaseTimer::new();
+    timer.add(progress::WALKING, Duration::from_secs(2));
+    timer.add(progress::EXTRACTING, Duration::from_secs(60));
+    timer.add(progress::WALKING, Duration::from_secs(3));
+    assert_eq!(timer.phases(), [(progress::WALKING, Duration::from_secs(5)), (progress::EXTRACTING, Duration::from_secs(60))]);
+    assert!(timer.summary(10).ends_with("(walking 5s, extracting 1m00s)"), "{}", timer.summary(10));
+
+    let counter = Progress::new(4);
+    assert_eq!(counter.advance(1), 1);
+    assert!(counter.rate(1).contains("ETA"));
+    assert_eq!(counter.advance(3), 4);
+    assert!(!counter.rate(4).contains("ETA"));
+}
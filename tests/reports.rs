@@ -0,0 +1,167 @@
+#![cfg(feature = "reports-csv")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use dj_library_manager::AudioFile;
+use dj_library_manager::analyzers::bitrate::BitrateAnalyzer;
+use dj_library_manager::utils::reporting::{ReportFormat, ReportOptions, Reporter};
+use pretty_assertions::assert_eq;
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+// Names that break naive CSV writing or reading
+const ADVERSARIAL: &[&str] = &[
+    "Artist, The - Track, Part 1",
+    "12\" Mix \"Extended\"",
+    "Line one\nline two",
+    "Carriage\r\nreturn",
+    "  padded  ",
+    "=HYPERLINK(\"http://example.com\")",
+    "Beyoncé – Déjà Vu (Ωmega Remix) 日本",
+    "trailing comma,",
+    "\"",
+    "",
+];
+
+fn read_rows(path: &Path) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path).unwrap();
+    let header = reader.headers().unwrap().iter().map(str::to_string).collect();
+    let rows = reader.records()
+        .map(|record| record.unwrap().iter().map(str::to_string).collect())
+        .collect();
+    (header, rows)
+}
+
+#[test]
+fn adversarial_tags_keep_their_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<AudioFile> = ADVERSARIAL.iter()
+        .enumerate()
+        .map(|(index, text)| {
+            let mut file = AudioFile::new(format!("/music/{}.mp3", index), 1024);
+            file.file_name = format!("{}.mp3", text);
+            file.bitrate = Some(320);
+            file.artist = Some(text.to_string());
+            file.title = Some(format!("{} (title)", text));
+            file
+        })
+        .collect();
+    let stats = BitrateAnalyzer::new().analyze(&files);
+
+    Reporter::new().generate_bitrate_report(&stats, &files, dir.path()).unwrap();
+
+    let (header, rows) = read_rows(&dir.path().join("detailed.csv"));
+    assert_eq!(rows.len(), ADVERSARIAL.len());
+    for row in &rows {
+        assert_eq!(row.len(), header.len());
+    }
+    let artist = header.iter().position(|column| column == "Artist").unwrap();
+    let title = header.iter().position(|column| column == "Title").unwrap();
+    let name = header.iter().position(|column| column == "File Name").unwrap();
+    for file in &files {
+        let row = rows.iter().find(|row| row[name] == file.file_name)
+            .unwrap_or_else(|| panic!("no row for {:?}", file.file_name));
+        assert_eq!(Some(&row[artist]), file.artist.as_ref());
+        assert_eq!(Some(&row[title]), file.title.as_ref());
+    }
+}
+
+#[test]
+fn adversarial_paths_keep_their_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let failures: Vec<(PathBuf, String)> = ADVERSARIAL.iter()
+        .map(|text| (PathBuf::from(format!("/music/{}.flac", text)), format!("Error, \"{}\"", text)))
+        .collect();
+    let path = dir.path().join("errors.csv");
+
+    Reporter::new().generate_scan_error_report(&failures, &path).unwrap();
+
+    let (header, rows) = read_rows(&path);
+    assert_eq!(header, ["File", "Error"]);
+    let expected: Vec<Vec<String>> = failures.iter()
+        .map(|(file, error)| vec![file.display().to_string(), error.clone()])
+        .collect();
+    assert_eq!(rows, expected);
+}
+
+#[test]
+fn bom_starts_every_part() {
+    let dir = tempfile::tempdir().unwrap();
+    let failures: Vec<(PathBuf, String)> = ["Déjà Vu.mp3", "Ωmega.mp3"].iter()
+        .map(|name| (PathBuf::from(name), "unreadable".to_string()))
+        .collect();
+    let options = ReportOptions { bom: true, max_rows_per_file: Some(1), ..ReportOptions::default() };
+
+    let written = Reporter::new()
+        .with_options(options)
+        .generate_scan_error_report(&failures, dir.path().join("errors.csv"))
+        .unwrap();
+
+    assert_eq!(written.len(), 2);
+    for (part, (file, _)) in written.iter().zip(&failures) {
+        let bytes = fs::read(part).unwrap();
+        assert!(bytes.starts_with(BOM), "{} has no BOM", part.display());
+        assert!(!bytes[BOM.len()..].starts_with(BOM), "{} has two BOMs", part.display());
+        let (header, rows) = read_rows(part);
+        assert_eq!(header, ["File", "Error"]);
+        assert_eq!(rows, [vec![file.display().to_string(), "unreadable".to_string()]]);
+    }
+}
+
+#[test]
+fn no_bom_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("errors.csv");
+
+    Reporter::new().generate_scan_error_report(&[(PathBuf::from("Déjà Vu.mp3"), "unreadable".to_string())], &path).unwrap();
+
+    assert!(fs::read(&path).unwrap().starts_with(b"File,Error"));
+}
+
+#[test]
+fn json_rows_keep_adversarial_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let failures: Vec<(PathBuf, String)> = ADVERSARIAL.iter()
+        .map(|text| (PathBuf::from(format!("/music/{}.flac", text)), format!("Error, \"{}\"", text)))
+        .collect();
+    let options = ReportOptions { format: ReportFormat::Json, ..ReportOptions::default() };
+
+    let written = Reporter::new()
+        .with_options(options)
+        .generate_scan_error_report(&failures, dir.path().join("errors.csv"))
+        .unwrap();
+
+    assert_eq!(written, [dir.path().join("errors.json")]);
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_slice(&fs::read(&written[0]).unwrap()).unwrap();
+    assert_eq!(rows.len(), failures.len());
+    for (row, (file, error)) in rows.iter().zip(&failures) {
+        assert_eq!(row["File"], file.display().to_string());
+        assert_eq!(row["Error"], *error);
+    }
+}
+
+#[test]
+fn html_escapes_names_and_charts_the_distribution() {
+    let dir = tempfile::tempdir().unwrap();
+    let files: Vec<AudioFile> = [128, 320, 320]
+        .iter()
+        .enumerate()
+        .map(|(index, bitrate)| {
+            let mut file = AudioFile::new(format!("/music/{}.mp3", index), 1024);
+            file.file_name = format!("<b>Track & {}</b>.mp3", index);
+            file.bitrate = Some(*bitrate);
+            file
+        })
+        .collect();
+    let stats = BitrateAnalyzer::new().analyze(&files);
+    let options = ReportOptions { format: ReportFormat::Html, ..ReportOptions::default() };
+
+    Reporter::new().with_options(options).generate_bitrate_report(&stats, &files, dir.path()).unwrap();
+
+    let summary = fs::read_to_string(dir.path().join("summary.html")).unwrap();
+    assert!(summary.contains("Bitrate Distribution"));
+    assert_eq!(summary.matches("class=\"bar\"").count(), 2);
+    let detailed = fs::read_to_string(dir.path().join("detailed.html")).unwrap();
+    assert!(detailed.contains("&lt;b&gt;Track &amp; 0&lt;/b&gt;.mp3"));
+    assert!(!detailed.contains("<b>Track"));
+}
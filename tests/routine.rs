@@ -0,0 +1,21 @@
+#![cfg(feature = "cli")]
+
+use dj_library_manager::cli::routine::{Routine, RoutineStep};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn routine_parsing() {
+    let routine: Routine = "scan -> quality -> duplicates -> report".parse().unwrap();
+    assert_eq!(routine.steps, [RoutineStep::Scan, RoutineStep::Quality, RoutineStep::Duplicates, RoutineStep::Report]);
+    assert_eq!(routine, "scan,quality,duplicates,report".parse().unwrap());
+    assert_eq!(routine.to_string(), "scan -> quality -> duplicates -> report");
+
+    let routine: Routine = "duplicates → quality".parse().unwrap();
+    assert_eq!(routine.steps, [RoutineStep::Duplicates, RoutineStep::Quality]);
+
+    assert!("quality,scan".parse::<Routine>().is_err());
+    assert!("report,quality".parse::<Routine>().is_err());
+    assert!("quality,quality".parse::<Routine>().is_err());
+    assert!("scan,report".parse::<Routine>().is_err());
+    assert!("quality,tidy".parse::<Routine>().is_err());
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "decode")]
+
+use std::fs::{self, File};
+use std::sync::Arc;
+use dj_library_manager::{AudioFile, MetadataExtractor, Result, ScanOptions};
+use dj_library_manager::audio::formats::{FormatParser, FormatRegistry};
+use dj_library_manager::utils::scan_cache::ScanCache;
+use dj_library_manager::utils::scan_log::ScanLog;
+use pretty_assertions::assert_eq;
+
+// Reads modules without looking at them, to tell which registry scanned a file
+struct NamingParser;
+
+impl FormatParser for NamingParser {
+    fn name(&self) -> &'static str {
+        "naming"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["xm"]
+    }
+
+    fn read(&self, _: &mut File, audio_file: &mut AudioFile) -> Result<()> {
+        audio_file.title = Some("Named".to_string());
+        Ok(())
+    }
+}
+
+fn titles(files: &[AudioFile]) -> Vec<Option<&str>> {
+    files.iter().map(|file| file.title.as_deref()).collect()
+}
+
+#[test]
+fn cached_scans_follow_the_parsers_and_still_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let library = dir.path().join("library");
+    fs::create_dir_all(&library).unwrap();
+    // Past the placeholder size, so it counts as audio
+    let mut module = b"Extended Module: Space Debris\0".to_vec();
+    module.resize(8192, 0);
+    fs::write(library.join("song.xm"), module).unwrap();
+    let options = ScanOptions { cache: Some(ScanCache::new(dir.path().join("cache"))), ..ScanOptions::default() };
+
+    let scanned = MetadataExtractor::process_directory_with(&library, &options).unwrap();
+    assert_eq!(titles(&scanned), [Some("Space Debris")]);
+
+    // Another parser for the same files isn't served the first one's results
+    let mut formats = FormatRegistry::default();
+    formats.register(Arc::new(NamingParser));
+    let renamed = MetadataExtractor::process_directory_with(&library, &ScanOptions { formats, ..options.clone() }).unwrap();
+    assert_eq!(titles(&renamed), [Some("Named")]);
+
+    // A cache hit still writes the files to the scan log
+    let log_path = dir.path().join("scan.jsonl");
+    let log = Arc::new(ScanLog::open(&log_path).unwrap());
+    let cached = MetadataExtractor::process_directory_with(&library, &ScanOptions { log: Some(log), ..options }).unwrap();
+    assert_eq!(titles(&cached), [Some("Space Debris")]);
+    let logged = fs::read_to_string(&log_path).unwrap();
+    assert_eq!(logged.lines().filter(|line| line.contains(r#""event":"scanned""#)).count(), 1, "{}", logged);
+}
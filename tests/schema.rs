@@ -0,0 +1,38 @@
+#![cfg(feature = "schema")]
+
+use std::fs;
+use std::path::PathBuf;
+use dj_library_manager::schema::{self, SCHEMA_VERSION};
+use pretty_assertions::assert_eq;
+
+fn schema_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schemas").join(format!("v{}", SCHEMA_VERSION))
+}
+
+// A failure here means the serialized shape of a public type changed. If the
+// change is additive, regenerate with `dj-library-manager schema`; otherwise
+// bump SCHEMA_VERSION first.
+#[test]
+fn checked_in_schemas_match_types() {
+    for generated in schema::schemas() {
+        let path = schema_dir().join(generated.file_name());
+        let checked_in = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("missing schema {}: {}", path.display(), e));
+        assert_eq!(checked_in, generated.to_json().unwrap(), "{} is out of date", path.display());
+    }
+}
+
+#[test]
+fn audio_file_matches_its_schema_fields() {
+    let file = dj_library_manager::AudioFile::new("/music/track.mp3", 1024);
+    let value = serde_json::to_value(&file).unwrap();
+    let schema: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(schema_dir().join("audio_file.json")).unwrap()
+    ).unwrap();
+
+    let mut fields: Vec<&String> = value.as_object().unwrap().keys().collect();
+    let mut properties: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+    fields.sort();
+    properties.sort();
+    assert_eq!(fields, properties);
+}
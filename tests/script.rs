@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+use dj_library_manager::utils::file_ops::FileOperation;
+use dj_library_manager::utils::script::{OperationScript, ScriptFormat};
+use pretty_assertions::assert_eq;
+
+fn operations() -> [FileOperation; 3] {
+    [
+        FileOperation::Move { from: PathBuf::from("/music/It's On.mp3"), to: PathBuf::from("/dupes/It's On.mp3") },
+        FileOperation::Copy { from: PathBuf::from("/music/b.mp3"), to: PathBuf::from("/backup/b.mp3") },
+        FileOperation::Delete { path: PathBuf::from("/music/c.mp3") },
+    ]
+}
+
+fn render(format: ScriptFormat) -> Vec<String> {
+    let mut script = OperationScript::new(format);
+    for operation in &operations() {
+        script.push(operation);
+    }
+    assert_eq!(script.len(), 3);
+    script.render().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn shell_script_quotes_paths_and_never_overwrites() {
+    assert_eq!(render(ScriptFormat::Shell), [
+        "#!/bin/sh",
+        "mkdir -p -- '/dupes'",
+        r"mv -n -- '/music/It'\''s On.mp3' '/dupes/It'\''s On.mp3'",
+        "mkdir -p -- '/backup'",
+        "cp -n -p -- '/music/b.mp3' '/backup/b.mp3'",
+        "rm -- '/music/c.mp3'",
+    ]);
+}
+
+#[test]
+fn powershell_script_quotes_paths_and_never_overwrites() {
+    assert_eq!(render(ScriptFormat::PowerShell), [
+        "#Requires -Version 5.1",
+        "New-Item -ItemType Directory -Force -Path '/dupes' | Out-Null",
+        "if (-not (Test-Path -LiteralPath '/dupes/It''s On.mp3')) { Move-Item -LiteralPath '/music/It''s On.mp3' -Destination '/dupes/It''s On.mp3' }",
+        "New-Item -ItemType Directory -Force -Path '/backup' | Out-Null",
+        "if (-not (Test-Path -LiteralPath '/backup/b.mp3')) { Copy-Item -LiteralPath '/music/b.mp3' -Destination '/backup/b.mp3' }",
+        "Remove-Item -LiteralPath '/music/c.mp3'",
+    ]);
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use dj_library_manager::exporters::serato;
+use pretty_assertions::assert_eq;
+
+fn record(tag: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut record = tag.to_vec();
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+fn write_crate(dir: &Path, name: &str, tracks: &[&str]) -> PathBuf {
+    let mut data = record(b"vrsn", &utf16("1.0/Serato ScratchLive Crate"));
+    for track in tracks {
+        data.extend(record(b"otrk", &record(b"ptrk", &utf16(track))));
+    }
+    let path = dir.join("Subcrates").join(format!("{}.crate", name));
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn crates_are_read_and_pointed_at_kept_copies() {
+    let serato_dir = tempfile::tempdir().unwrap();
+    let root = Path::new("/Volumes/USB");
+    write_crate(serato_dir.path(), "House%%Deep", &["Music/a.mp3", "Music/b.mp3", "Music/keep.mp3"]);
+    let other = write_crate(serato_dir.path(), "Warmup", &["Music/c.mp3"]);
+
+    let crates = serato::read_crates(serato_dir.path(), root).unwrap();
+    let names: Vec<&str> = crates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, ["House / Deep", "Warmup"]);
+    assert_eq!(crates[0].tracks[0], root.join("Music/a.mp3"));
+
+    let relocations = HashMap::from([
+        (root.join("Music/a.mp3"), root.join("Music/Kept/a.flac")),
+        (root.join("Music/b.mp3"), root.join("Music/keep.mp3")),
+        (root.join("Music/c.mp3"), PathBuf::from("/elsewhere/c.mp3")),
+    ]);
+    assert_eq!(serato::rewrite_crate(&crates[0].path, root, &relocations).unwrap(), 2);
+    let rewritten = serato::read_crate(&crates[0].path, root).unwrap();
+    assert_eq!(rewritten.tracks, [root.join("Music/Kept/a.flac"), root.join("Music/keep.mp3")]);
+
+    // Off the volume: can't be listed, so the crate stays as it was
+    let before = fs::read(&other).unwrap();
+    assert_eq!(serato::rewrite_crate(&other, root, &relocations).unwrap(), 0);
+    assert_eq!(fs::read(&other).unwrap(), before);
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "decode")]
+
+use std::fs;
+use std::path::Path;
+use dj_library_manager::exporters::sync::{SyncAction, Syncer};
+use pretty_assertions::assert_eq;
+
+fn action_for_removed(syncer: Syncer, source: &Path, target: &Path) -> SyncAction {
+    let plan = syncer.plan(source, target).unwrap();
+    plan.entries.iter().find(|entry| entry.path == Path::new("gone.mp3")).unwrap().action
+}
+
+#[test]
+fn huge_retention_never_comes_due() {
+    let dir = tempfile::tempdir().unwrap();
+    let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+    for folder in [&source, &target] {
+        fs::create_dir_all(folder).unwrap();
+        fs::write(folder.join("kept.mp3"), b"audio").unwrap();
+    }
+    fs::write(target.join("gone.mp3"), b"audio").unwrap();
+
+    let now_due = action_for_removed(Syncer::new().with_allow_delete(true).with_retention_days(0), &source, &target);
+    assert_eq!(now_due, SyncAction::Delete);
+    let forever = action_for_removed(Syncer::new().with_allow_delete(true).with_retention_days(u64::MAX), &source, &target);
+    assert_eq!(forever, SyncAction::Retain { delete_after: u64::MAX });
+}
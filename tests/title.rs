@@ -0,0 +1,57 @@
+use dj_library_manager::analyzers::title::TitleParser;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn strips_common_numbering_formats() {
+    let parser = TitleParser::new();
+    for name in [
+        "01 - Daft Punk - One More Time.mp3",
+        "01. Daft Punk - One More Time.mp3",
+        "1) Daft Punk - One More Time.mp3",
+        "[01] Daft Punk - One More Time.mp3",
+        "(01) Daft Punk - One More Time.mp3",
+        "01 Daft Punk - One More Time.mp3",
+        "01_Daft_Punk_-_One_More_Time.mp3",
+        "01-Daft Punk - One More Time.mp3",
+        "1-03 Daft Punk - One More Time.mp3",
+        "123456 - Daft Punk - One More Time.mp3",
+    ] {
+        let parsed = parser.parse(name);
+        assert_eq!((parsed.artist.as_str(), parsed.title.as_str()), ("daft punk", "one more time"), "{}", name);
+    }
+}
+
+#[test]
+fn keeps_numbers_that_belong_to_the_name() {
+    let parser = TitleParser::new();
+    for (name, artist) in [
+        ("1999 - Prince.mp3", "1999"),
+        ("2 Unlimited - Get Ready For This.mp3", "2 unlimited"),
+        ("808 State - Pacific State.mp3", "808 state"),
+        ("10cc - Dreadlock Holiday.mp3", "10cc"),
+        ("50 Cent - In Da Club.mp3", "50 cent"),
+        ("2000 And One - Voodoo Magic.mp3", "2000 and one"),
+    ] {
+        assert_eq!(parser.parse(name).artist, artist, "{}", name);
+    }
+    assert_eq!(parser.track_number_prefix("99 Luftballons"), "");
+    assert_eq!(parser.track_number_prefix("1.5 Hours - Someone"), "");
+}
+
+#[test]
+fn prefix_is_the_number_and_its_separator() {
+    let parser = TitleParser::new();
+    assert_eq!(parser.track_number_prefix("01 - Artist - Title"), "01 - ");
+    assert_eq!(parser.track_number_prefix("07. Artist - Title"), "07. ");
+    assert_eq!(parser.track_number_prefix("[12] Artist - Title"), "[12] ");
+    assert_eq!(parser.track_number_prefix("2-01 Intro"), "2-01 ");
+}
+
+#[test]
+fn stripping_can_be_turned_off() {
+    let parser = TitleParser::new().with_track_number_stripping(false);
+    assert_eq!(parser.track_number_prefix("01 - Artist - Title"), "");
+    let parsed = parser.parse("01 - Daft Punk - One More Time.mp3");
+    assert_eq!(parsed.artist, "01");
+    assert_eq!(parsed.title, "daft punk - one more time");
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use dj_library_manager::exporters::cues::{CueKind, CuePoint, TrackCues};
+use dj_library_manager::exporters::traktor::{self, CueType, NmlLocation};
+use pretty_assertions::assert_eq;
+
+const COLLECTION: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<NML VERSION="19"><HEAD COMPANY="www.native-instruments.com" PROGRAM="Traktor"></HEAD>
+<COLLECTION ENTRIES="2">
+<ENTRY TITLE="Deep &amp; Low" ARTIST="Someone"><LOCATION DIR="/:Users/:dj/:Music/:" FILE="deep.mp3" VOLUME="Macintosh HD" VOLUMEID="Macintosh HD"></LOCATION>
+<INFO BITRATE="320000" PLAYCOUNT="12" PLAYTIME="301" PLAYTIME_FLOAT="300.512" FILESIZE="11750"></INFO>
+<TEMPO BPM="124.000000" BPM_QUALITY="100.000000"></TEMPO>
+<CUE_V2 NAME="AutoGrid" DISPL_ORDER="0" TYPE="4" START="52.5" LEN="0" REPEATS="-1" HOTCUE="-1"></CUE_V2>
+<CUE_V2 NAME="Drop" DISPL_ORDER="0" TYPE="0" START="64000" LEN="0" REPEATS="-1" HOTCUE="1"></CUE_V2>
+</ENTRY>
+<ENTRY TITLE="Other" ARTIST="Else"><LOCATION DIR="/:Music/:" FILE="other.mp3" VOLUME="USB" VOLUMEID="1a2b3c"></LOCATION>
+<INFO PLAYTIME="200"></INFO>
+</ENTRY>
+</COLLECTION>
+<PLAYLISTS><NODE TYPE="FOLDER" NAME="$ROOT"><SUBNODES COUNT="1"><NODE TYPE="PLAYLIST" NAME="Warmup"><PLAYLIST ENTRIES="2" TYPE="LIST" UUID="x">
+<ENTRY><PRIMARYKEY TYPE="TRACK" KEY="Macintosh HD/:Users/:dj/:Music/:deep.mp3"></PRIMARYKEY></ENTRY>
+<ENTRY><PRIMARYKEY TYPE="TRACK" KEY="USB/:Music/:other.mp3"></PRIMARYKEY></ENTRY>
+</PLAYLIST></NODE></SUBNODES></NODE></PLAYLISTS>
+</NML>
+"#;
+
+#[test]
+fn entries_keep_play_counts_bpm_and_cues() {
+    let dir = tempfile::tempdir().unwrap();
+    let nml = dir.path().join("collection.nml");
+    fs::write(&nml, COLLECTION).unwrap();
+
+    let entries = traktor::read_entries(&nml).unwrap();
+    assert_eq!(entries.len(), 2);
+    let deep = &entries[0];
+    assert_eq!(deep.track.location, PathBuf::from("/Users/dj/Music/deep.mp3"));
+    assert_eq!(deep.track.title.as_deref(), Some("Deep & Low"));
+    assert_eq!(deep.track.duration_secs, Some(300.512));
+    assert_eq!(deep.play_count, Some(12));
+    assert_eq!(deep.bpm, Some(124.0));
+    let cues: Vec<(CueType, f64, Option<u8>)> = deep.cues.iter().map(|c| (c.cue_type, c.start_secs, c.hotcue)).collect();
+    assert_eq!(cues, [(CueType::Grid, 0.0525, None), (CueType::Cue, 64.0, Some(1))]);
+    assert_eq!(entries[1].track.location, PathBuf::from("/Volumes/USB/Music/other.mp3"));
+    assert_eq!(entries[1].track.duration_secs, Some(200.0));
+}
+
+#[test]
+fn relocations_update_entries_and_playlists() {
+    let dir = tempfile::tempdir().unwrap();
+    let (input, output) = (dir.path().join("collection.nml"), dir.path().join("updated.nml"));
+    fs::write(&input, COLLECTION).unwrap();
+
+    let relocations = HashMap::from([
+        (PathBuf::from("/Users/dj/Music/deep.mp3"), PathBuf::from("/Volumes/USB/Sorted/deep.mp3")),
+    ]);
+    assert_eq!(traktor::rewrite_locations(&input, &output, &relocations).unwrap(), 1);
+
+    let entries = traktor::read_entries(&output).unwrap();
+    assert_eq!(entries[0].track.location, PathBuf::from("/Volumes/USB/Sorted/deep.mp3"));
+    assert_eq!(entries[0].play_count, Some(12));
+    assert_eq!(entries[0].cues.len(), 2);
+    let written = fs::read_to_string(&output).unwrap();
+    assert!(written.contains(r#"KEY="USB/:Sorted/:deep.mp3""#));
+    assert!(!written.contains("Macintosh HD/:Users"));
+    // The moved entry's volume ID was for the old volume; the other keeps its own
+    assert!(!written.contains(r#"VOLUMEID="Macintosh HD""#));
+    assert!(written.contains(r#"VOLUMEID="1a2b3c""#));
+}
+
+#[test]
+fn cue_collection_reads_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let nml = dir.path().join("cues.nml");
+    let tracks = [TrackCues {
+        path: PathBuf::from("/Volumes/USB/Music/a.mp3"),
+        artist: Some("Artist".to_string()),
+        title: None,
+        duration_secs: 180.0,
+        bpm: Some(128.0),
+        cues: vec![CuePoint { kind: CueKind::Drop, position_secs: 30.0 }],
+    }];
+    traktor::write_cue_collection(&nml, &tracks).unwrap();
+
+    let entries = traktor::read_entries(&nml).unwrap();
+    assert_eq!(entries[0].track.location, tracks[0].path);
+    assert_eq!(entries[0].track.title.as_deref(), Some("a"));
+    assert_eq!(entries[0].bpm, Some(128.0));
+    let cue = &entries[0].cues[0];
+    assert_eq!((cue.name.as_str(), cue.cue_type, cue.start_secs, cue.hotcue), ("Drop", CueType::Cue, 30.0, Some(1)));
+}
+
+#[test]
+fn windows_paths_use_the_drive_as_volume() {
+    let location = traktor::path_to_location(Path::new(r"C:\Music\House\a.mp3"));
+    assert_eq!(location, NmlLocation {
+        volume: "C:".to_string(),
+        dir: "/:Music/:House/:".to_string(),
+        file: "a.mp3".to_string(),
+    });
+    assert_eq!(traktor::location_to_path(&location), PathBuf::from("C:/Music/House/a.mp3"));
+}